@@ -0,0 +1,93 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use kubewarden_policy_sdk::request::KubernetesAdmissionRequest;
+use trusted_repos_policy::api::Decision;
+use trusted_repos_policy::benchmarking::{parse_image, Policy};
+
+const DIGEST: &str = "sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb";
+
+/// Settings with `size` entries in each list, all of them allowing the
+/// images of `pod_request`.
+fn settings(size: usize) -> serde_json::Value {
+    let registries: Vec<String> = (0..size)
+        .map(|i| format!("registry-{}.my-corp.com", i))
+        .collect();
+    let images: Vec<String> = (0..size)
+        .map(|i| format!("registry-{}.my-corp.com/team-{}/", i, i))
+        .collect();
+    let tags: Vec<String> = (0..size).map(|i| format!("dev-{}", i)).collect();
+    serde_json::json!({
+        "registries": {"allow": registries},
+        "images": {"allow": images},
+        "tags": {"reject": tags},
+        "blockedDigests": [],
+    })
+}
+
+/// Pod with `containers` containers, whose images are spread over the
+/// registries of `settings(size)`.
+fn pod_request(containers: usize, size: usize) -> KubernetesAdmissionRequest {
+    let containers: Vec<serde_json::Value> = (0..containers)
+        .map(|i| {
+            let registry = i * size / containers.max(1);
+            serde_json::json!({
+                "name": format!("container-{}", i),
+                "image": format!(
+                    "registry-{}.my-corp.com/team-{}/service-{}:1.{}.0@{}",
+                    registry, registry, i, i, DIGEST
+                ),
+            })
+        })
+        .collect();
+    serde_json::from_value(serde_json::json!({
+        "operation": "CREATE",
+        "kind": {"group": "", "version": "v1", "kind": "Pod"},
+        "object": {
+            "metadata": {"name": "app"},
+            "spec": {"containers": containers}
+        }
+    }))
+    .expect("cannot build request")
+}
+
+fn image_parsing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_image");
+    for image in [
+        "nginx",
+        "quay.io/etcd/etcd:v3.4.12",
+        "registry.my-corp.com:5000/team/service:1.2.3@sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb",
+    ] {
+        group.bench_with_input(BenchmarkId::from_parameter(image), image, |b, image| {
+            b.iter(|| parse_image(black_box(image)))
+        });
+    }
+    group.finish();
+}
+
+/// Evaluates the request the way the policy does on admission, and
+/// returns true when it is accepted.
+fn is_accepted(policy: &Policy, request: &KubernetesAdmissionRequest) -> bool {
+    matches!(
+        policy
+            .evaluate(request)
+            .map(|evaluation| evaluation.decision),
+        Ok(Decision::Accept) | Ok(Decision::Mutate(_))
+    )
+}
+
+fn pod_evaluation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("evaluate_pod");
+    for (size, containers) in [(10, 1), (100, 10), (500, 50)] {
+        let policy = Policy::new(settings(size)).expect("cannot build policy");
+        let request = pod_request(containers, size);
+        assert!(is_accepted(&policy, &request));
+        group.bench_with_input(
+            BenchmarkId::new(format!("{} patterns", size), containers),
+            &request,
+            |b, request| b.iter(|| is_accepted(&policy, black_box(request))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, image_parsing, pod_evaluation);
+criterion_main!(benches);
@@ -0,0 +1,2 @@
+//! Benchmarks of the policy live under benches/, run them with
+//! `cargo bench`.
@@ -0,0 +1,114 @@
+#!/usr/bin/env bats
+#
+# Evaluates the recorded requests of test_data/requests with the
+# settings of test_data/settings through the compiled policy, so that
+# the waPC entry points and the (de)serialization of the settings and
+# responses are covered along with the rules.
+
+bats_require_minimum_version 1.5.0
+
+# Evaluates a recorded request with a settings file, leaving the
+# response in $output. The logs of the policy are left in $stderr.
+evaluate() {
+  run --separate-stderr kwctl run annotated-policy.wasm \
+    --request-path "test_data/requests/$1" \
+    --settings-path "test_data/settings/$2"
+  # Printed when one of the checks of the test fails
+  echo "output = ${output}"
+  echo "stderr = ${stderr}"
+  [ "$status" -eq 0 ]
+}
+
+# Checks that the JSON patch of the response sets the field at the
+# given pointer to the given value.
+assert_patched() {
+  [ "$(jq -r .allowed <<<"$output")" = "true" ]
+  jq -r .patch <<<"$output" | base64 --decode |
+    jq -e --arg path "$1" --arg value "$2" \
+      'any(.[]; .path == $path and .value == $value)'
+}
+
+assert_accepted() {
+  [ "$(jq -r .allowed <<<"$output")" = "true" ]
+  [ "$(jq -r '.patch // empty' <<<"$output")" = "" ]
+}
+
+assert_rejected() {
+  [ "$(jq -r .allowed <<<"$output")" = "false" ]
+  [[ "$(jq -r .status.message <<<"$output")" == *"$1"* ]]
+}
+
+@test "accept images from allowed registries" {
+  evaluate pod-quay.json registries.yaml
+  assert_accepted
+}
+
+@test "accept pinned images away from the Docker Hub" {
+  evaluate pod-quay.json strict.yaml
+  assert_accepted
+}
+
+@test "reject Docker Hub short names outside the allowed registries" {
+  evaluate pod-docker-hub-short.json registries.yaml
+  assert_rejected "registries not allowed: docker.io"
+}
+
+@test "reject the Docker Hub with a reject list" {
+  evaluate pod-docker-hub-short.json strict.yaml
+  assert_rejected "registries not allowed: docker.io"
+}
+
+@test "reject the latest tag" {
+  evaluate pod-latest-tag.json registries.yaml
+  assert_rejected "tags not allowed: latest"
+}
+
+@test "reject malformed tags" {
+  evaluate pod-invalid-tag.json registries.yaml
+  assert_rejected "invalid tag syntax"
+}
+
+@test "reject registries addressed by IP" {
+  evaluate pod-ip-registry.json strict.yaml
+  assert_rejected "registries addressed by IP: 10.0.0.100:5000"
+}
+
+@test "accept updates leaving the images unchanged" {
+  evaluate pod-update-unchanged-images.json registries.yaml
+  assert_accepted
+}
+
+@test "reject deployments through a custom resource rule" {
+  evaluate deployment.json workloads.yaml
+  assert_rejected "registries not allowed: docker.io"
+}
+
+@test "canonicalize Docker Hub short names" {
+  evaluate pod-docker-hub-short.json mutating.yaml
+  assert_patched /spec/containers/0/image docker.io/library/nginx:latest
+}
+
+@test "set the pull policy of pod templates" {
+  evaluate podtemplate.json mutating.yaml
+  assert_patched /template/spec/containers/0/imagePullPolicy Always
+}
+
+@test "pull short names from the default registry" {
+  evaluate pod-docker-hub-short.json air-gapped.yaml
+  assert_patched /spec/containers/0/image registry.internal/nginx
+}
+
+@test "replace floating tags" {
+  evaluate pod-latest-tag.json frozen-tags.yaml
+  assert_patched /spec/containers/0/image \
+    quay.io/my-org/app:1.4.2@sha256:9b2f3a1de8c4a0d9b7e5c61f3a2b4d8e0c7f1a6b3d5e9c2f4a8b0d1e6c3f7a29
+}
+
+@test "reject invalid settings" {
+  run kwctl run annotated-policy.wasm \
+    --request-path test_data/requests/pod-quay.json \
+    --settings-json '{"registries": {"allow": ["quay.io"], "reject": ["docker.io"]}}'
+  echo "output = ${output}"
+  [ "$status" -ne 0 ]
+  [[ "$output" == *"only one of registries allow or reject can be provided"* ]]
+}
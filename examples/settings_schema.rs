@@ -0,0 +1,8 @@
+//! Prints the JSON Schema of the settings, the one returned by the
+//! `settings_schema` entry point, for `make settings.schema.json`.
+
+fn main() {
+    let schema = trusted_repos_policy::api::settings_schema();
+    let schema = serde_json::to_string_pretty(&schema).expect("cannot serialize schema");
+    println!("{}", schema);
+}
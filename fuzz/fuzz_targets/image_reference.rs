@@ -0,0 +1,7 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|image: &str| {
+    trusted_repos_policy::fuzzing::parse_image(image);
+});
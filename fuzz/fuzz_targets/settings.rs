@@ -0,0 +1,7 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|settings: &[u8]| {
+    trusted_repos_policy::fuzzing::parse_settings(settings);
+});
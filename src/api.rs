@@ -0,0 +1,108 @@
+//! Image parsing, matchers and evaluation of the policy, only built
+//! with the `lib` feature, for the policies sharing its rules.
+
+use kubewarden::request::KubernetesAdmissionRequest;
+use kubewarden::settings::Validatable;
+
+pub use crate::evaluation::{Decision, Evaluation};
+pub use crate::image::{Image, ImageParseError, ImagePattern, Transport};
+pub use crate::registry::RegistryPattern;
+pub use crate::review::parse_admission_request;
+pub use crate::tag::TagPattern;
+
+use crate::context::merge_cluster_settings;
+use crate::evaluation::evaluate;
+use crate::settings::Settings;
+
+/// Settings of the policy, ready to evaluate requests.
+pub struct Policy {
+    settings: Settings,
+}
+
+impl Policy {
+    /// Parses and validates the settings, in any of the supported
+    /// versions of their format.
+    pub fn new(settings: serde_json::Value) -> Result<Policy, String> {
+        let settings = Settings::from_value(settings)?;
+        settings.validate()?;
+        Ok(Policy { settings })
+    }
+
+    /// Parses and validates the settings from a raw payload, given either
+    /// as JSON or as YAML.
+    pub fn from_slice(settings: &[u8]) -> Result<Policy, String> {
+        let settings = Settings::from_slice(settings)?;
+        settings.validate()?;
+        Ok(Policy { settings })
+    }
+
+    /// Returns the warnings about settings that are valid but likely
+    /// mistaken.
+    pub fn warnings(&self) -> Vec<String> {
+        self.settings.warnings()
+    }
+
+    /// Merges the rules stored in the cluster, if the settings refer to
+    /// any. Requires the policy to be context aware.
+    pub fn merge_cluster_settings(&mut self) -> Result<(), String> {
+        merge_cluster_settings(&mut self.settings)
+    }
+
+    /// Evaluates the request the way the policy does on admission,
+    /// returning the decision along with its audit annotations.
+    pub fn evaluate(&self, request: &KubernetesAdmissionRequest) -> anyhow::Result<Evaluation> {
+        evaluate(&self.settings, request)
+    }
+}
+
+/// Returns the JSON Schema of the settings accepted by `Policy::new`.
+pub fn settings_schema() -> serde_json::Value {
+    Settings::json_schema()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluate_requests() {
+        let policy = Policy::new(serde_json::json!({"registries": {"allow": ["quay.io"]}}))
+            .expect("cannot create policy");
+        let request: KubernetesAdmissionRequest = serde_json::from_value(serde_json::json!({
+            "operation": "CREATE",
+            "kind": {"group": "", "version": "v1", "kind": "Pod"},
+            "object": {"spec": {"containers": [{"name": "app", "image": "nginx:1.25"}]}}
+        }))
+        .expect("cannot parse request");
+        let evaluation = policy.evaluate(&request).expect("cannot evaluate request");
+        assert!(matches!(evaluation.decision, Decision::Reject(_)));
+        assert_eq!(
+            evaluation
+                .audit_annotations
+                .get("decision")
+                .map(String::as_str),
+            Some("rejected")
+        );
+        assert!(Policy::new(serde_json::json!({"registries": {}})).is_err());
+        let policy = Policy::from_slice(b"registries:\n  allow:\n  - docker.io\n")
+            .expect("cannot create policy");
+        assert!(matches!(
+            policy
+                .evaluate(&request)
+                .map(|evaluation| evaluation.decision),
+            Ok(Decision::Accept)
+        ));
+    }
+
+    #[test]
+    fn match_images() {
+        let image = Image::new("quay.io/prometheus/prometheus:v2.45.0").expect("cannot parse");
+        assert!(ImagePattern::new("quay.io/prometheus/*").matches(&image));
+        assert!(RegistryPattern::parse("quay.io:*")
+            .expect("cannot parse registry")
+            .matches(image.registry.as_deref().unwrap_or_default()));
+        assert!(TagPattern::parse(">=2.0.0")
+            .expect("cannot parse tag")
+            .matches(image.tag.as_deref().unwrap_or_default()));
+    }
+}
@@ -0,0 +1,10 @@
+//! Entry points of the benchmarks, only built with the `benchmarking`
+//! feature.
+
+pub use crate::api::Policy;
+use crate::image::Image;
+
+/// Parses an image reference, returning true when it is valid.
+pub fn parse_image(image: &str) -> bool {
+    Image::new(image).is_ok()
+}
@@ -0,0 +1,306 @@
+use anyhow::{anyhow, Result};
+use k8s_openapi::api::core::v1 as apicore;
+use kubewarden::host_capabilities::kubernetes::{get_resource, GetResourceRequest};
+use kubewarden::settings::Validatable;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+const DEFAULT_API_VERSION: &str = "policies.kubewarden.io/v1alpha1";
+const DEFAULT_KIND: &str = "TrustedRepositories";
+
+/// Reference to the custom resource holding the trusted repositories
+/// of the cluster. Its `spec` uses the same format as the settings of
+/// the policy.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TrustedRepositoriesReference {
+    #[serde(default = "default_api_version")]
+    pub(crate) api_version: String,
+    #[serde(default = "default_kind")]
+    pub(crate) kind: String,
+    pub(crate) name: String,
+    /// Must be omitted for cluster wide resources.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) namespace: Option<String>,
+}
+
+fn default_api_version() -> String {
+    DEFAULT_API_VERSION.to_string()
+}
+
+fn default_kind() -> String {
+    DEFAULT_KIND.to_string()
+}
+
+impl TrustedRepositoriesReference {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("trustedRepositories must provide the name of the resource".to_string());
+        }
+        if self.api_version.is_empty() || self.kind.is_empty() {
+            return Err(format!(
+                "trustedRepositories {} must provide an apiVersion and a kind",
+                self.name
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Fetches the referenced resource through the Kubernetes capability
+/// of the host, and builds settings out of its `spec`.
+pub(crate) fn fetch_trusted_repositories(
+    reference: &TrustedRepositoriesReference,
+) -> Result<Settings> {
+    let resource: serde_json::Value = get_resource(&GetResourceRequest {
+        api_version: reference.api_version.clone(),
+        kind: reference.kind.clone(),
+        name: reference.name.clone(),
+        namespace: reference.namespace.clone(),
+        disable_cache: false,
+    })?;
+    let spec = resource
+        .get("spec")
+        .cloned()
+        .unwrap_or(serde_json::Value::Null);
+    Settings::from_value(spec)
+        .and_then(mergeable)
+        .map_err(|e| anyhow!("invalid {} {}: {}", reference.kind, reference.name, e))
+}
+
+/// Rejects the settings stored in the cluster that set fields ignored
+/// by `Settings::merge`, so that they are not silently dropped.
+fn mergeable(settings: Settings) -> Result<Settings, String> {
+    let unmerged = settings.unmerged_settings();
+    if unmerged.is_empty() {
+        Ok(settings)
+    } else {
+        Err(format!(
+            "{} can only be set in the policy settings",
+            unmerged.join(", ")
+        ))
+    }
+}
+
+/// Source of additional rules, stored in the cluster.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RulesFrom {
+    pub(crate) config_map: ConfigMapReference,
+    #[serde(default)]
+    pub(crate) on_missing: OnMissing,
+}
+
+/// Key of a ConfigMap holding rules, written in YAML or JSON with the
+/// same format as the settings of the policy.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ConfigMapReference {
+    pub(crate) namespace: String,
+    pub(crate) name: String,
+    pub(crate) key: String,
+    /// The host caches the ConfigMap for a few seconds, to avoid
+    /// querying the API server on every evaluation.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) disable_cache: bool,
+}
+
+/// What to do when the ConfigMap, or its key, cannot be found. Rules
+/// that cannot be parsed always reject the pods, so that a typo does
+/// not silently drop them.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum OnMissing {
+    /// Reject all the pods.
+    #[default]
+    Reject,
+    /// Evaluate pods with the inline settings only.
+    Ignore,
+}
+
+impl RulesFrom {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let config_map = &self.config_map;
+        if config_map.namespace.is_empty()
+            || config_map.name.is_empty()
+            || config_map.key.is_empty()
+        {
+            return Err(
+                "rulesFrom configMap must provide a namespace, a name and a key".to_string(),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Why the rules of a ConfigMap cannot be loaded.
+#[derive(Debug)]
+enum RulesError {
+    /// The ConfigMap, or its key, cannot be found.
+    Missing(anyhow::Error),
+    /// The key holds rules that cannot be parsed.
+    Invalid(anyhow::Error),
+}
+
+/// Fetches the referenced ConfigMap through the Kubernetes capability
+/// of the host, and builds settings out of its key.
+fn fetch_config_map_rules(config_map: &ConfigMapReference) -> Result<Settings, RulesError> {
+    let resource: apicore::ConfigMap = get_resource(&GetResourceRequest {
+        api_version: "v1".to_string(),
+        kind: "ConfigMap".to_string(),
+        name: config_map.name.clone(),
+        namespace: Some(config_map.namespace.clone()),
+        disable_cache: config_map.disable_cache,
+    })
+    .map_err(RulesError::Missing)?;
+    config_map_rules(config_map, resource)
+}
+
+/// Builds settings out of the key of the ConfigMap.
+fn config_map_rules(
+    config_map: &ConfigMapReference,
+    resource: apicore::ConfigMap,
+) -> Result<Settings, RulesError> {
+    let rules = resource
+        .data
+        .and_then(|mut data| data.remove(&config_map.key))
+        .ok_or_else(|| {
+            RulesError::Missing(anyhow!(
+                "ConfigMap {}/{} has no key {}",
+                config_map.namespace,
+                config_map.name,
+                config_map.key
+            ))
+        })?;
+    parse_rules(&rules).map_err(|e| {
+        RulesError::Invalid(anyhow!(
+            "invalid rules in ConfigMap {}/{}: {}",
+            config_map.namespace,
+            config_map.name,
+            e
+        ))
+    })
+}
+
+fn parse_rules(rules: &str) -> Result<Settings, String> {
+    let rules: serde_json::Value = serde_yaml::from_str(rules).map_err(|e| e.to_string())?;
+    Settings::from_value(rules).and_then(mergeable)
+}
+
+/// Merges the settings stored in the cluster, if any, into `settings`.
+pub(crate) fn merge_cluster_settings(settings: &mut Settings) -> Result<(), String> {
+    let mut merged = false;
+    if let Some(reference) = &settings.trusted_repositories {
+        let cluster_settings = fetch_trusted_repositories(reference)
+            .map_err(|e| format!("cannot load trusted repositories: {}", e))?;
+        settings.merge(cluster_settings);
+        merged = true;
+    }
+    if let Some(rules_from) = &settings.rules_from {
+        match fetch_config_map_rules(&rules_from.config_map) {
+            Ok(cluster_settings) => {
+                settings.merge(cluster_settings);
+                merged = true;
+            }
+            Err(RulesError::Missing(_)) if rules_from.on_missing == OnMissing::Ignore => {}
+            Err(RulesError::Missing(e)) | Err(RulesError::Invalid(e)) => {
+                return Err(format!("cannot load rules: {}", e))
+            }
+        }
+    }
+    if merged {
+        settings
+            .validate()
+            .map_err(|e| format!("invalid settings loaded from the cluster: {}", e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rules() {
+        let settings = parse_rules(
+            r#"
+registries:
+  allow:
+  - quay.io
+tags:
+  reject:
+  - latest
+"#,
+        )
+        .expect("cannot parse rules");
+        assert_eq!(settings.validate(), Ok(()));
+        assert!(parse_rules(r#"{"registries": {"allow": ["quay.io"]}}"#).is_ok());
+        assert!(parse_rules("registries: [").is_err());
+        assert_eq!(
+            parse_rules(
+                "registries: {allow: [quay.io]}\nrejectIpRegistries: true\nmaxImageAge: 90d"
+            )
+            .map(|_| ()),
+            Err(
+                "maxImageAge, rejectIpRegistries can only be set in the policy settings"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn rules_from() {
+        let rules_from: RulesFrom = serde_json::from_str(
+            r#"{"configMap": {"namespace": "kubewarden", "name": "trusted-repos", "key": "rules.yaml"}}"#,
+        )
+        .expect("cannot parse rulesFrom");
+        assert_eq!(rules_from.on_missing, OnMissing::Reject);
+        assert!(!rules_from.config_map.disable_cache);
+        assert_eq!(rules_from.validate(), Ok(()));
+
+        let rules_from: RulesFrom = serde_json::from_str(
+            r#"{"configMap": {"namespace": "kubewarden", "name": "trusted-repos", "key": ""}, "onMissing": "ignore"}"#,
+        )
+        .expect("cannot parse rulesFrom");
+        assert_eq!(rules_from.on_missing, OnMissing::Ignore);
+        assert_eq!(
+            rules_from.validate(),
+            Err("rulesFrom configMap must provide a namespace, a name and a key".to_string())
+        );
+    }
+
+    #[test]
+    fn config_map_rules_errors() {
+        let config_map = ConfigMapReference {
+            namespace: "kubewarden".to_string(),
+            name: "trusted-repos".to_string(),
+            key: "rules.yaml".to_string(),
+            disable_cache: false,
+        };
+        let resource = |rules: &str| apicore::ConfigMap {
+            data: Some([("rules.yaml".to_string(), rules.to_string())].into()),
+            ..Default::default()
+        };
+        assert!(config_map_rules(&config_map, resource("tags: {reject: [latest]}")).is_ok());
+        match config_map_rules(&config_map, apicore::ConfigMap::default()) {
+            Err(RulesError::Missing(e)) => assert_eq!(
+                e.to_string(),
+                "ConfigMap kubewarden/trusted-repos has no key rules.yaml"
+            ),
+            result => panic!("unexpected result {:?}", result),
+        }
+        for rules in ["registries: [", "registries: {allow: quay.io}"] {
+            match config_map_rules(&config_map, resource(rules)) {
+                Err(RulesError::Invalid(e)) => assert!(
+                    e.to_string()
+                        .starts_with("invalid rules in ConfigMap kubewarden/trusted-repos: "),
+                    "{}",
+                    e
+                ),
+                result => panic!("unexpected result for {}: {:?}", rules, result),
+            }
+        }
+    }
+}
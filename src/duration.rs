@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+const UNITS: &[(char, u64)] = &[
+    ('w', 7 * 24 * 60 * 60),
+    ('d', 24 * 60 * 60),
+    ('h', 60 * 60),
+    ('m', 60),
+    ('s', 1),
+];
+
+/// Parses durations written as a number followed by a unit, like
+/// `30d`, where the unit is one of `w`, `d`, `h`, `m` or `s`.
+pub(crate) fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let invalid_duration = || {
+        format!(
+            "invalid duration {}, expected a number followed by w, d, h, m or s",
+            duration
+        )
+    };
+    let unit = duration.chars().last().ok_or_else(invalid_duration)?;
+    let seconds = UNITS
+        .iter()
+        .find(|(name, _)| *name == unit)
+        .map(|(_, seconds)| *seconds)
+        .ok_or_else(invalid_duration)?;
+    let value: u64 = duration[..duration.len() - 1]
+        .parse()
+        .map_err(|_| invalid_duration())?;
+    Ok(Duration::from_secs(value * seconds))
+}
+
+/// Formats the duration with the largest unit it spans, truncating the
+/// rest, like `400d`.
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    UNITS
+        .iter()
+        .filter(|(unit, _)| *unit != 'w')
+        .find(|(_, unit_seconds)| seconds >= *unit_seconds)
+        .map(|(unit, unit_seconds)| format!("{}{}", seconds / unit_seconds, unit))
+        .unwrap_or_else(|| "0s".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse_duration("30d"), Ok(Duration::from_secs(30 * 86400)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * 86400)));
+        assert_eq!(parse_duration("90m"), Ok(Duration::from_secs(5400)));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("1.5h").is_err());
+        assert!(parse_duration("-1d").is_err());
+    }
+
+    #[test]
+    fn format_durations() {
+        assert_eq!(
+            format_duration(Duration::from_secs(400 * 86400 + 3600)),
+            "400d"
+        );
+        assert_eq!(format_duration(Duration::from_secs(5400)), "1h");
+        assert_eq!(format_duration(Duration::from_secs(59)), "59s");
+        assert_eq!(format_duration(Duration::from_secs(0)), "0s");
+    }
+}
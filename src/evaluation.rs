@@ -0,0 +1,1408 @@
+use anyhow::Result;
+use k8s_openapi::api::core::v1 as apicore;
+use kubewarden::request::{GroupVersionKind, KubernetesAdmissionRequest};
+use slog::{debug, info, o, Logger};
+
+use std::collections::BTreeMap;
+
+use crate::settings::{
+    container_images, is_valid_annotation_key, MirrorPods, PodEvaluationResult,
+    PodRejectionReasons, Quarantine, RequestScope, Settings,
+};
+use crate::template::MessageTemplate;
+
+/// Annotation set by the kubelets on the mirror pods of their static
+/// pods.
+const MIRROR_POD_ANNOTATION_KEY: &str = "kubernetes.io/config.mirror";
+
+/// Prefix of the usernames of the kubelets, followed by their node name.
+const NODE_USERNAME_PREFIX: &str = "system:node:";
+
+/// Outcome of the evaluation of an admission request.
+#[derive(Debug, PartialEq)]
+pub enum Decision {
+    Accept,
+    /// Accept the request, replacing its object with the given one.
+    Mutate(serde_json::Value),
+    /// Reject the request with the given message.
+    Reject(String),
+}
+
+/// Decision taken on an admission request, along with the audit
+/// annotations recording it in the audit log of the cluster.
+#[derive(Debug, PartialEq)]
+pub struct Evaluation {
+    pub decision: Decision,
+    /// Outcome of the evaluation under `decision`, the evaluated images
+    /// under `images` and the identifiers of the matched allow rules, or
+    /// of the violated ones, under `rules`. Empty when the request was
+    /// not evaluated.
+    pub audit_annotations: BTreeMap<String, String>,
+    /// Warnings returned to the client that made the request, like the
+    /// injected containers that were skipped. They are also recorded
+    /// under `warnings` in the audit annotations of evaluated requests.
+    pub warnings: Vec<String>,
+}
+
+impl Evaluation {
+    fn new(decision: Decision, outcome: &str, images: &[&str], rules: &[String]) -> Evaluation {
+        let mut audit_annotations = BTreeMap::new();
+        audit_annotations.insert("decision".to_string(), outcome.to_string());
+        if !images.is_empty() {
+            audit_annotations.insert("images".to_string(), images.join(","));
+        }
+        if !rules.is_empty() {
+            audit_annotations.insert("rules".to_string(), rules.join(","));
+        }
+        Evaluation {
+            decision,
+            audit_annotations,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Evaluation of a request that the policy does not look into.
+    fn skipped() -> Evaluation {
+        Evaluation {
+            decision: Decision::Accept,
+            audit_annotations: BTreeMap::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn with_warnings(mut self, warnings: Vec<String>) -> Evaluation {
+        if !self.audit_annotations.is_empty() && !warnings.is_empty() {
+            self.audit_annotations
+                .insert("warnings".to_string(), warnings.join("; "));
+        }
+        Evaluation { warnings, ..self }
+    }
+
+    /// Outcome recorded under `decision`, or `skipped` when the request
+    /// was not evaluated.
+    fn outcome(&self) -> &str {
+        self.audit_annotations
+            .get("decision")
+            .map(String::as_str)
+            .unwrap_or("skipped")
+    }
+}
+
+/// Evaluates an admission request against the settings, which must
+/// already include the rules stored in the cluster, if any.
+///
+/// The evaluation does not depend on the waPC protocol, so it can run
+/// natively. The host capabilities are only queried by the features
+/// that need them, like signature verification.
+pub(crate) fn evaluate(
+    settings: &Settings,
+    request: &KubernetesAdmissionRequest,
+) -> Result<Evaluation> {
+    let namespace_settings = settings
+        .for_namespace(&request.namespace)
+        .map_err(|e| anyhow::anyhow!("invalid settings: {}", e))?;
+    let settings = namespace_settings.as_ref().unwrap_or(settings);
+    let evaluation = evaluate_request(settings, request)?;
+    if settings.log_decisions {
+        log_decision(settings, request, &evaluation);
+    }
+    Ok(evaluation)
+}
+
+fn evaluate_request(
+    settings: &Settings,
+    request: &KubernetesAdmissionRequest,
+) -> Result<Evaluation> {
+    if !settings.is_operation_enforced(&request.operation)
+        || !settings.is_resource_evaluated(&request.kind)
+    {
+        return Ok(Evaluation::skipped());
+    }
+
+    let labels = object_labels(&request.object);
+    let scope = RequestScope {
+        namespace: &request.namespace,
+        labels: &labels,
+    };
+    let mut compiled_settings = settings
+        .compile(Some(&scope))
+        .map_err(|e| anyhow::anyhow!("invalid settings: {}", e))?;
+    compiled_settings.dry_run = request.dry_run;
+    compiled_settings.log = compiled_settings.log.new(o!(
+        "operation" => request.operation.clone(),
+        "namespace" => request.namespace.clone(),
+        "name" => request.name.clone(),
+    ));
+    let log = &compiled_settings.log;
+    // Dry runs are not persisted, so they are not worth the queries to
+    // the registries
+    let mut warnings = Vec::new();
+    let registry_checks = compiled_settings.registry_checks();
+    if request.dry_run && !registry_checks.is_empty() {
+        warnings.push(format!(
+            "dry run: {} not evaluated",
+            registry_checks.join(", ")
+        ));
+    }
+
+    if let Some(rule) = settings.custom_resource_rule_for(&request.kind) {
+        let unchanged_images = rule.images(&request.old_object);
+        let located_images: Vec<(String, &str)> = rule
+            .located_images(&request.object)
+            .into_iter()
+            .filter(|(_, image)| !unchanged_images.contains(image))
+            .collect();
+        let images: Vec<&str> = located_images.iter().map(|(_, image)| *image).collect();
+        warnings.extend(compiled_settings.image_warnings(&images));
+        let evaluation = match compiled_settings.are_images_accepted(
+            located_images
+                .iter()
+                .map(|(path, image)| (path.as_str(), *image)),
+        ) {
+            PodEvaluationResult::Allowed(matched_rules) => {
+                log_acceptance(log, &matched_rules);
+                Evaluation::new(Decision::Accept, "accepted", &images, &matched_rules)
+            }
+            PodEvaluationResult::NotAllowed(rejection_reasons) => {
+                reject(log, settings, *rejection_reasons, &request.object, &images)?
+            }
+        };
+        return Ok(evaluation.with_warnings(warnings));
+    }
+
+    let pod_template = is_pod_template(&request.kind);
+    let old_pod = parse_pod(&request.old_object, pod_template);
+    let mut pod = match parse_pod(&request.object, pod_template) {
+        Some(pod) => pod,
+        None => return Ok(Evaluation::skipped()),
+    };
+    let mirror_pods = if !pod_template && is_mirror_pod(&pod, &request.user_info.username) {
+        settings.mirror_pods
+    } else {
+        MirrorPods::Evaluate
+    };
+    if mirror_pods == MirrorPods::Accept {
+        debug!(log, "mirror pod not evaluated");
+        return Ok(Evaluation::skipped());
+    }
+    if let Some(controller) = settings
+        .evaluated_controller(&pod)
+        .filter(|_| !pod_template)
+    {
+        debug!(log, "pod not evaluated, its controller is"; "controller" => controller);
+        return Ok(Evaluation::skipped());
+    }
+    let original_pod = pod.clone();
+    let images = evaluated_images(settings, &original_pod, old_pod.as_ref());
+    warnings.extend(injected_container_warnings(
+        log,
+        settings,
+        &original_pod,
+        old_pod.as_ref(),
+    ));
+    // The images are evaluated as they are going to be pulled, with the
+    // replaced tags and from the default registry
+    let replaced = settings.replace_tags(&mut pod, old_pod.as_ref());
+    let defaulted = settings.apply_default_registry(&mut pod, old_pod.as_ref()) || replaced;
+    warnings.extend(compiled_settings.image_warnings(&evaluated_images(
+        settings,
+        &pod,
+        old_pod.as_ref(),
+    )));
+    let matched_rules = match compiled_settings.is_pod_accepted(&pod, old_pod.as_ref()) {
+        PodEvaluationResult::Allowed(matched_rules) => matched_rules,
+        // Rejecting a mirror pod does not stop its static pod, so the
+        // violations are only reported
+        PodEvaluationResult::NotAllowed(rejection_reasons) if mirror_pods == MirrorPods::Warn => {
+            let message = rejection_message(*rejection_reasons);
+            info!(log, "mirror pod accepted"; "decision" => "allowed", "reason" => &message);
+            warnings.push(format!(
+                "mirror pod accepted despite violations: {}",
+                message
+            ));
+            return Ok(
+                Evaluation::new(Decision::Accept, "accepted", &images, &[]).with_warnings(warnings)
+            );
+        }
+        PodEvaluationResult::NotAllowed(mut rejection_reasons) => {
+            // The paths of the images are relative to the pod, which is
+            // under `template` in pod templates
+            if pod_template {
+                for violation in &mut rejection_reasons.violations {
+                    violation.path = format!("template.{}", violation.path);
+                }
+            }
+            return reject(log, settings, *rejection_reasons, &request.object, &images)
+                .map(|evaluation| evaluation.with_warnings(warnings));
+        }
+    };
+    log_acceptance(log, &matched_rules);
+
+    // The pull policy of the containers cannot be changed once the pod
+    // is created, unlike the one of pod templates
+    let mut mutated = settings.canonicalize_images(&mut pod, old_pod.as_ref()) || defaulted;
+    if (pod_template || old_pod.is_none()) && settings.mutate_pull_policies(&mut pod) {
+        mutated = true;
+    }
+    if let Some(decision_annotation) = &settings.decision_annotation {
+        let decision = settings.decision_annotation_value(&matched_rules);
+        let annotations = pod
+            .metadata
+            .annotations
+            .get_or_insert_with(Default::default);
+        if annotations.get(&decision_annotation.key) != Some(&decision) {
+            annotations.insert(decision_annotation.key.clone(), decision);
+            mutated = true;
+        }
+    }
+    let evaluation = if mutated {
+        record_original_images(&original_pod, &mut pod);
+        Evaluation::new(
+            Decision::Mutate(pod_object(&request.object, pod, pod_template)?),
+            "mutated",
+            &images,
+            &matched_rules,
+        )
+    } else {
+        Evaluation::new(Decision::Accept, "accepted", &images, &matched_rules)
+    };
+    Ok(evaluation.with_warnings(warnings))
+}
+
+/// Returns a warning for every injected container that is skipped,
+/// leaving out the ones unchanged since the old pod.
+fn injected_container_warnings(
+    log: &Logger,
+    settings: &Settings,
+    pod: &apicore::Pod,
+    old_pod: Option<&apicore::Pod>,
+) -> Vec<String> {
+    let unchanged_images = old_pod.map(container_images).unwrap_or_default();
+    container_images(pod)
+        .into_iter()
+        .filter(|(container, image)| {
+            settings.is_container_selected(container)
+                && settings.is_injected_container(pod, container)
+                && !unchanged_images.contains(&(container, image))
+        })
+        .map(|(container, image)| {
+            info!(log, "injected container skipped"; "container" => container, "image" => image);
+            format!(
+                "image {} of the injected container {} was not evaluated",
+                image, container
+            )
+        })
+        .collect()
+}
+
+/// Returns the images of the containers that are evaluated, leaving out
+/// the containers not selected and the ones unchanged since the old pod,
+/// without duplicates.
+fn evaluated_images<'a>(
+    settings: &Settings,
+    pod: &'a apicore::Pod,
+    old_pod: Option<&apicore::Pod>,
+) -> Vec<&'a str> {
+    let unchanged_images = old_pod.map(container_images).unwrap_or_default();
+    let mut images = Vec::new();
+    for (container, image) in container_images(pod) {
+        if settings.is_container_selected(container)
+            && !settings.is_injected_container(pod, container)
+            && !unchanged_images.contains(&(container, image))
+            && !images.contains(&image)
+        {
+            images.push(image);
+        }
+    }
+    images
+}
+
+/// Returns the labels of the object, ignoring the malformed ones.
+fn object_labels(object: &serde_json::Value) -> BTreeMap<String, String> {
+    object
+        .get("metadata")
+        .and_then(|metadata| metadata.get("labels"))
+        .and_then(|labels| serde_json::from_value(labels.clone()).ok())
+        .unwrap_or_default()
+}
+
+/// Returns true when the pod is the mirror of a static pod: it carries
+/// the mirror annotation, and the request comes from a kubelet. Anyone
+/// creating pods can set the annotation, which alone would let them
+/// skip the evaluation.
+fn is_mirror_pod(pod: &apicore::Pod, username: &str) -> bool {
+    username.starts_with(NODE_USERNAME_PREFIX)
+        && pod
+            .metadata
+            .annotations
+            .as_ref()
+            .map(|annotations| annotations.contains_key(MIRROR_POD_ANNOTATION_KEY))
+            .unwrap_or(false)
+}
+
+/// Returns true for the core/v1 PodTemplate objects, which hold the pod
+/// stamped out by controllers under `template`.
+fn is_pod_template(gvk: &GroupVersionKind) -> bool {
+    gvk.group.is_empty() && gvk.kind == "PodTemplate"
+}
+
+/// Parses the pod of the object, which is the pod itself unless the
+/// object is a pod template.
+fn parse_pod(object: &serde_json::Value, pod_template: bool) -> Option<apicore::Pod> {
+    if !pod_template {
+        return serde_json::from_value(object.clone()).ok();
+    }
+    let template = serde_json::from_value::<apicore::PodTemplate>(object.clone())
+        .ok()?
+        .template?;
+    Some(apicore::Pod {
+        metadata: template.metadata.unwrap_or_default(),
+        spec: template.spec,
+        ..Default::default()
+    })
+}
+
+/// Returns the object holding the given pod, the reverse of
+/// `parse_pod`.
+fn pod_object(
+    object: &serde_json::Value,
+    pod: apicore::Pod,
+    pod_template: bool,
+) -> Result<serde_json::Value> {
+    if !pod_template {
+        return Ok(serde_json::to_value(&pod)?);
+    }
+    let mut object = object.clone();
+    object["template"] = serde_json::to_value(&apicore::PodTemplateSpec {
+        metadata: Some(pod.metadata),
+        spec: pod.spec,
+    })?;
+    Ok(object)
+}
+
+/// Prefix of the annotations recording the image of a container before
+/// the policy rewrote it, followed by the name of the container.
+const ORIGINAL_IMAGE_ANNOTATION_PREFIX: &str = "trusted-repos.policy/original-image.";
+
+/// Annotates the pod with the original image of every container whose
+/// image was rewritten, so that the change can be audited and reverted.
+/// The containers whose name does not fit in an annotation key are
+/// skipped.
+fn record_original_images(original_pod: &apicore::Pod, pod: &mut apicore::Pod) {
+    let images = |pod: &apicore::Pod| -> Vec<(String, Option<String>)> {
+        pod.spec
+            .iter()
+            .flat_map(|spec| {
+                spec.init_containers
+                    .iter()
+                    .flatten()
+                    .chain(spec.containers.iter())
+                    .map(|container| (container.name.clone(), container.image.clone()))
+                    .chain(
+                        spec.ephemeral_containers
+                            .iter()
+                            .flatten()
+                            .map(|container| (container.name.clone(), container.image.clone())),
+                    )
+            })
+            .collect()
+    };
+    let rewritten_images = images(pod);
+    for (name, original_image) in images(original_pod) {
+        let rewritten = rewritten_images
+            .iter()
+            .any(|(rewritten_name, image)| rewritten_name == &name && image != &original_image);
+        let key = format!("{}{}", ORIGINAL_IMAGE_ANNOTATION_PREFIX, name);
+        if let (true, Some(original_image)) = (rewritten, original_image) {
+            if is_valid_annotation_key(&key) {
+                pod.metadata
+                    .annotations
+                    .get_or_insert_with(Default::default)
+                    .insert(key, original_image);
+            }
+        }
+    }
+}
+
+/// Logs the outcome of the request and the rules that decided it, one
+/// event per request, for the log pipelines turning them into metrics.
+fn log_decision(
+    settings: &Settings,
+    request: &KubernetesAdmissionRequest,
+    evaluation: &Evaluation,
+) {
+    let rules = evaluation
+        .audit_annotations
+        .get("rules")
+        .map(String::as_str)
+        .unwrap_or_default();
+    info!(settings.logger(), "policy decision";
+        "operation" => &request.operation,
+        "namespace" => &request.namespace,
+        "name" => &request.name,
+        "kind" => &request.kind.kind,
+        "decision" => evaluation.outcome(),
+        "rules" => rules);
+}
+
+fn log_acceptance(log: &Logger, matched_rules: &[String]) {
+    debug!(log, "request accepted";
+        "decision" => "allowed",
+        "rule" => matched_rules.join(","));
+}
+
+/// Rejects the request, or accepts its object annotated with the
+/// violations when the quarantine mode is enabled.
+fn reject(
+    log: &Logger,
+    settings: &Settings,
+    rejection_reasons: PodRejectionReasons,
+    object: &serde_json::Value,
+    images: &[&str],
+) -> Result<Evaluation> {
+    let violations = rejection_reasons.violations.clone();
+    let mut violated_rules: Vec<String> = violations
+        .iter()
+        .map(|violation| violation.rule.clone())
+        .collect();
+    violated_rules.sort();
+    violated_rules.dedup();
+    let message = match settings.message_template() {
+        Some(message_template) if !violations.is_empty() => {
+            templated_rejection_message(&rejection_reasons, &message_template)
+        }
+        _ => rejection_message(rejection_reasons),
+    };
+    if let Some(quarantine) = &settings.quarantine {
+        info!(log, "request quarantined"; "decision" => "quarantined", "reason" => &message);
+        let record = serde_json::json!({"message": message, "violations": violations});
+        return Ok(Evaluation::new(
+            Decision::Mutate(quarantined_object(
+                object,
+                quarantine,
+                serde_json::to_string(&record)?,
+            )),
+            "quarantined",
+            images,
+            &violated_rules,
+        ));
+    }
+    info!(log, "request rejected"; "decision" => "rejected", "reason" => &message);
+    Ok(Evaluation::new(
+        Decision::Reject(message),
+        "rejected",
+        images,
+        &violated_rules,
+    ))
+}
+
+/// Returns a copy of the object with the quarantine annotation, holding
+/// the given record, and the quarantine label, if any.
+fn quarantined_object(
+    object: &serde_json::Value,
+    quarantine: &Quarantine,
+    record: String,
+) -> serde_json::Value {
+    let mut object = object.clone();
+    if let Some(object) = object.as_object_mut() {
+        let metadata = object
+            .entry("metadata")
+            .or_insert_with(|| serde_json::json!({}));
+        if let Some(metadata) = metadata.as_object_mut() {
+            insert_metadata_entry(metadata, "annotations", &quarantine.annotation, record);
+            if let Some(label) = &quarantine.label {
+                insert_metadata_entry(metadata, "labels", &label.key, label.value.clone());
+            }
+        }
+    }
+    object
+}
+
+fn insert_metadata_entry(
+    metadata: &mut serde_json::Map<String, serde_json::Value>,
+    field: &str,
+    key: &str,
+    value: String,
+) {
+    let entries = metadata
+        .entry(field)
+        .or_insert_with(|| serde_json::json!({}));
+    if !entries.is_object() {
+        *entries = serde_json::json!({});
+    }
+    if let Some(entries) = entries.as_object_mut() {
+        entries.insert(key.to_string(), serde_json::Value::String(value));
+    }
+}
+
+/// Renders the message template for every violation, skipping the
+/// duplicates.
+fn templated_rejection_message(
+    rejection_reasons: &PodRejectionReasons,
+    message_template: &MessageTemplate,
+) -> String {
+    let mut messages: Vec<String> = Vec::new();
+    for violation in &rejection_reasons.violations {
+        let message = message_template.render(|name| violation.placeholder(name));
+        if !messages.contains(&message) {
+            messages.push(message);
+        }
+    }
+    messages.join("; ")
+}
+
+fn rejection_message(rejection_reasons: PodRejectionReasons) -> String {
+    let mut paths: Vec<String> = Vec::new();
+    for violation in &rejection_reasons.violations {
+        if !violation.path.is_empty() && !paths.contains(&violation.path) {
+            paths.push(violation.path.clone());
+        }
+    }
+    let mut errors = Vec::new();
+    if !rejection_reasons.digests_blocked.is_empty() {
+        errors.push(format!(
+            "digests blocked: {}",
+            rejection_reasons.digests_blocked.join(", ")
+        ));
+    }
+    if !rejection_reasons.digests_not_allowed.is_empty() {
+        errors.push(format!(
+            "digests not allowed: {}",
+            rejection_reasons.digests_not_allowed.join(", ")
+        ));
+    }
+    if !rejection_reasons.digests_not_listed.is_empty() {
+        errors.push(format!(
+            "images not listed by digest: {}",
+            rejection_reasons.digests_not_listed.join(", ")
+        ));
+    }
+    if !rejection_reasons.insecure_registries.is_empty() {
+        errors.push(format!(
+            "registries marked as insecure: {}",
+            rejection_reasons.insecure_registries.join(", ")
+        ));
+    }
+    if !rejection_reasons.ip_registries.is_empty() {
+        errors.push(format!(
+            "registries addressed by IP: {}",
+            rejection_reasons.ip_registries.join(", ")
+        ));
+    }
+    if !rejection_reasons.unofficial_images.is_empty() {
+        errors.push(format!(
+            "Docker Hub images outside of the official library: {}",
+            rejection_reasons.unofficial_images.join(", ")
+        ));
+    }
+    if !rejection_reasons.registry_ports_not_allowed.is_empty() {
+        errors.push(format!(
+            "registry ports not allowed: {}",
+            rejection_reasons.registry_ports_not_allowed.join(", ")
+        ));
+    }
+    if !rejection_reasons.registries_not_allowed.is_empty() {
+        errors.push(format!(
+            "registries not allowed: {}",
+            rejection_reasons.registries_not_allowed.join(", ")
+        ));
+    }
+    if !rejection_reasons.tags_not_allowed.is_empty() {
+        errors.push(format!(
+            "tags not allowed: {}",
+            rejection_reasons.tags_not_allowed.join(", ")
+        ))
+    }
+    if !rejection_reasons.pre_release_tags.is_empty() {
+        errors.push(format!(
+            "pre-release tags not allowed: {}",
+            rejection_reasons.pre_release_tags.join(", ")
+        ))
+    }
+    if !rejection_reasons.invalid_tags.is_empty() {
+        errors.push(format!(
+            "invalid tag syntax: {}",
+            rejection_reasons.invalid_tags.join(", ")
+        ))
+    }
+    if !rejection_reasons.unparseable_images.is_empty() {
+        errors.push(format!(
+            "image references that cannot be parsed: {}",
+            rejection_reasons.unparseable_images.join(", ")
+        ))
+    }
+    if !rejection_reasons.incomplete_references.is_empty() {
+        errors.push(format!(
+            "tag and digest required: {}",
+            rejection_reasons.incomplete_references.join(", ")
+        ))
+    }
+    if !rejection_reasons.digest_only_references.is_empty() {
+        errors.push(format!(
+            "digest-only references not allowed: {}",
+            rejection_reasons.digest_only_references.join(", ")
+        ))
+    }
+    if !rejection_reasons.unqualified_references.is_empty() {
+        errors.push(format!(
+            "fully qualified references required: {}",
+            rejection_reasons.unqualified_references.join(", ")
+        ))
+    }
+    if !rejection_reasons.ambiguous_short_names.is_empty() {
+        errors.push(format!(
+            "ambiguous short names: {}",
+            rejection_reasons.ambiguous_short_names.join(", ")
+        ))
+    }
+    if !rejection_reasons.images_not_allowed.is_empty() {
+        errors.push(format!(
+            "images not allowed: {}",
+            rejection_reasons.images_not_allowed.join(", ")
+        ))
+    }
+    if !rejection_reasons.images_not_found.is_empty() {
+        errors.push(format!(
+            "image not found in registry: {}",
+            rejection_reasons.images_not_found.join(", ")
+        ))
+    }
+    if !rejection_reasons.image_lookups_failed.is_empty() {
+        errors.push(format!(
+            "cannot look up images in registry: {}",
+            rejection_reasons.image_lookups_failed.join(", ")
+        ))
+    }
+    if !rejection_reasons.platforms_missing.is_empty() {
+        errors.push(format!(
+            "images missing required platforms: {}",
+            rejection_reasons.platforms_missing.join(", ")
+        ))
+    }
+    if !rejection_reasons.root_images.is_empty() {
+        errors.push(format!(
+            "images running as root: {}",
+            rejection_reasons.root_images.join(", ")
+        ))
+    }
+    if !rejection_reasons.image_ages_not_allowed.is_empty() {
+        errors.push(format!(
+            "image age not allowed: {}",
+            rejection_reasons.image_ages_not_allowed.join(", ")
+        ))
+    }
+    if !rejection_reasons.vulnerable_images.is_empty() {
+        errors.push(format!(
+            "images with vulnerabilities: {}",
+            rejection_reasons.vulnerable_images.join(", ")
+        ))
+    }
+    if !rejection_reasons.signatures_not_verified.is_empty() {
+        errors.push(format!(
+            "signatures not verified: {}",
+            rejection_reasons.signatures_not_verified.join(", ")
+        ))
+    }
+    if !rejection_reasons.pull_policies_not_allowed.is_empty() {
+        errors.push(format!(
+            "image pull policy not allowed for containers: {}",
+            rejection_reasons.pull_policies_not_allowed.join(", ")
+        ))
+    }
+    if !rejection_reasons.pull_secrets_missing.is_empty() {
+        errors.push(format!(
+            "image pull secrets missing: {}",
+            rejection_reasons.pull_secrets_missing.join(", ")
+        ))
+    }
+    if !rejection_reasons.attestations_missing.is_empty() {
+        errors.push(format!(
+            "attestations missing: {}",
+            rejection_reasons.attestations_missing.join(", ")
+        ))
+    }
+    if !rejection_reasons.attestations_not_verified.is_empty() {
+        errors.push(format!(
+            "attestations not verified: {}",
+            rejection_reasons.attestations_not_verified.join(", ")
+        ))
+    }
+    if !rejection_reasons.attestation_lookups_failed.is_empty() {
+        errors.push(format!(
+            "cannot look up attestations in registry: {}",
+            rejection_reasons.attestation_lookups_failed.join(", ")
+        ))
+    }
+    for message in rejection_reasons.messages {
+        if !errors.contains(&message) {
+            errors.push(message);
+        }
+    }
+    let message = format!("not allowed, reported errors: {}", errors.join("; "));
+    if paths.is_empty() {
+        message
+    } else {
+        format!("{}; offending fields: {}", message, paths.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pod_request(operation: &str, image: &str) -> KubernetesAdmissionRequest {
+        serde_json::from_value(serde_json::json!({
+            "operation": operation,
+            "kind": {"group": "", "version": "v1", "kind": "Pod"},
+            "namespace": "default",
+            "name": "app",
+            "object": {
+                "metadata": {"name": "app"},
+                "spec": {"containers": [{"name": "app", "image": image}]}
+            }
+        }))
+        .expect("cannot parse request")
+    }
+
+    fn settings(settings: serde_json::Value) -> Settings {
+        Settings::from_value(settings).expect("cannot parse settings")
+    }
+
+    #[test]
+    fn decision_outcomes() {
+        let settings = settings(serde_json::json!({
+            "registries": {"allow": ["quay.io"]},
+            "operations": ["CREATE"],
+            "logDecisions": true,
+        }));
+        let outcome = |operation: &str, image: &str| {
+            evaluate(&settings, &pod_request(operation, image))
+                .unwrap()
+                .outcome()
+                .to_string()
+        };
+        assert_eq!(outcome("CREATE", "quay.io/etcd:v3.4.12"), "accepted");
+        assert_eq!(outcome("CREATE", "docker.io/nginx:1.25"), "rejected");
+        assert_eq!(outcome("UPDATE", "docker.io/nginx:1.25"), "skipped");
+    }
+
+    #[test]
+    fn evaluate_pods() {
+        let settings = settings(serde_json::json!({"registries": {"allow": ["quay.io"]}}));
+        assert_eq!(
+            evaluate(&settings, &pod_request("CREATE", "quay.io/etcd:v3.4.12"))
+                .unwrap()
+                .decision,
+            Decision::Accept
+        );
+        assert_eq!(
+            evaluate(&settings, &pod_request("CREATE", "docker.io/nginx:1.25"))
+                .unwrap()
+                .decision,
+            Decision::Reject(
+                "not allowed, reported errors: registries not allowed: docker.io; \
+                 offending fields: spec.containers[0].image"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn evaluate_with_message_template() {
+        let settings = settings(serde_json::json!({
+            "registries": {"allow": ["quay.io"]},
+            "tags": {"reject": ["latest"]},
+            "messageTemplate": "{{path}}: {{image}} violates {{rule}}, see https://wiki.example.com/{{registry}}"
+        }));
+        assert_eq!(
+            evaluate(&settings, &pod_request("CREATE", "nginx:latest"))
+                .unwrap()
+                .decision,
+            Decision::Reject(
+                "spec.containers[0].image: nginx:latest violates registries, see https://wiki.example.com/docker.io; \
+                 spec.containers[0].image: nginx:latest violates tags, see https://wiki.example.com/docker.io"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn evaluate_invalid_tags() {
+        let settings = settings(serde_json::json!({"registries": {"reject": ["docker.io"]}}));
+        assert_eq!(
+            evaluate(&settings, &pod_request("CREATE", "quay.io/app:1.0:alpine"))
+                .unwrap()
+                .decision,
+            Decision::Reject(
+                "not allowed, reported errors: invalid tag syntax: quay.io/app:1.0:alpine; \
+                 offending fields: spec.containers[0].image"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn evaluate_parse_errors() {
+        let rejecting_settings =
+            settings(serde_json::json!({"registries": {"reject": ["docker.io"]}}));
+        assert_eq!(
+            evaluate(
+                &rejecting_settings,
+                &pod_request("CREATE", "quay.io/-app:1.0")
+            )
+            .unwrap()
+            .decision,
+            Decision::Reject(
+                "not allowed, reported errors: image references that cannot be parsed: \
+                 quay.io/-app:1.0 (invalid repository); \
+                 offending fields: spec.containers[0].image"
+                    .to_string()
+            )
+        );
+
+        let warning_settings = settings(serde_json::json!({
+            "registries": {"reject": ["docker.io"]},
+            "onParseError": "allowWithWarning"
+        }));
+        for (image, reason) in [
+            ("quay.io/-app:1.0", "invalid repository"),
+            ("quay.io/app:1.0:alpine", "invalid tag syntax"),
+        ] {
+            let evaluation = evaluate(&warning_settings, &pod_request("CREATE", image)).unwrap();
+            assert_eq!(evaluation.decision, Decision::Accept);
+            assert_eq!(
+                evaluation.warnings,
+                vec![format!(
+                    "image {} cannot be parsed ({}), accepted without evaluation",
+                    image, reason
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn evaluate_enforced_operations() {
+        let settings = settings(serde_json::json!({
+            "registries": {"allow": ["quay.io"]},
+            "operations": ["CREATE"]
+        }));
+        assert_eq!(
+            evaluate(&settings, &pod_request("UPDATE", "docker.io/nginx:1.25"))
+                .unwrap()
+                .decision,
+            Decision::Accept
+        );
+    }
+
+    #[test]
+    fn evaluate_mirror_pods() {
+        let mut request = pod_request("CREATE", "docker.io/nginx:1.25");
+        request.object["metadata"]["annotations"] =
+            serde_json::json!({"kubernetes.io/config.mirror": "0123456789abcdef"});
+        request.user_info.username = "system:node:worker-1".to_string();
+        for (mirror_pods, outcome, warnings) in [
+            (None, "skipped", 0),
+            (Some("warn"), "accepted", 1),
+            (Some("evaluate"), "rejected", 0),
+        ] {
+            let mut value = serde_json::json!({"registries": {"allow": ["quay.io"]}});
+            if let Some(mirror_pods) = mirror_pods {
+                value["mirrorPods"] = serde_json::json!(mirror_pods);
+            }
+            let evaluation = evaluate(&settings(value), &request).unwrap();
+            assert_eq!(evaluation.outcome(), outcome, "{:?}", mirror_pods);
+            assert_eq!(evaluation.warnings.len(), warnings, "{:?}", mirror_pods);
+        }
+
+        // The annotation set by anyone else than a kubelet is ignored
+        request.user_info.username = "jane".to_string();
+        let evaluation = evaluate(
+            &settings(serde_json::json!({"registries": {"allow": ["quay.io"]}})),
+            &request,
+        )
+        .unwrap();
+        assert_eq!(evaluation.outcome(), "rejected");
+    }
+
+    #[test]
+    fn evaluate_owned_pods() {
+        let mut request = pod_request("CREATE", "docker.io/nginx:1.25");
+        request.object["metadata"]["ownerReferences"] = serde_json::json!([{
+            "apiVersion": "apps/v1",
+            "kind": "ReplicaSet",
+            "name": "app-5d4f8",
+            "uid": "6f1d2c3b-0000-4000-8000-000000000000",
+            "controller": true
+        }]);
+        let rule = serde_json::json!([{
+            "apiVersion": "apps/v1",
+            "kind": "ReplicaSet",
+            "paths": ["spec.template.spec.containers[*].image"]
+        }]);
+        for (skip_owned_pods, resources, outcome) in [
+            (
+                true,
+                serde_json::json!(["Pod", "apps/ReplicaSet"]),
+                "skipped",
+            ),
+            (true, serde_json::json!(["Pod"]), "rejected"),
+            (
+                false,
+                serde_json::json!(["Pod", "apps/ReplicaSet"]),
+                "rejected",
+            ),
+        ] {
+            let settings = settings(serde_json::json!({
+                "registries": {"allow": ["quay.io"]},
+                "customResourceRules": rule,
+                "resources": resources,
+                "skipOwnedPods": skip_owned_pods,
+            }));
+            assert_eq!(
+                evaluate(&settings, &request).unwrap().outcome(),
+                outcome,
+                "{} {}",
+                skip_owned_pods,
+                resources
+            );
+        }
+    }
+
+    #[test]
+    fn evaluate_dry_run() {
+        let settings = settings(serde_json::json!({
+            "registries": {"allow": ["quay.io"]},
+            "verifyImageExists": true,
+            "requiredPlatforms": ["linux/arm64"]
+        }));
+        let mut request = pod_request("CREATE", "quay.io/etcd:v3.4.12");
+        request.dry_run = true;
+        let evaluation = evaluate(&settings, &request).unwrap();
+        assert_eq!(evaluation.decision, Decision::Accept);
+        assert_eq!(
+            evaluation.warnings,
+            vec!["dry run: verifyImageExists, requiredPlatforms not evaluated"]
+        );
+
+        // The other rules still apply
+        let mut request = pod_request("CREATE", "docker.io/nginx:1.25");
+        request.dry_run = true;
+        assert!(matches!(
+            evaluate(&settings, &request).unwrap().decision,
+            Decision::Reject(_)
+        ));
+        let settings = self::settings(serde_json::json!({"registries": {"allow": ["quay.io"]}}));
+        assert!(evaluate(&settings, &request).unwrap().warnings.is_empty());
+    }
+
+    #[test]
+    fn evaluate_injected_containers() {
+        let settings = settings(serde_json::json!({
+            "registries": {"allow": ["quay.io"]},
+            "skipInjectedContainers": {"names": ["istio-proxy"]}
+        }));
+        let request = |operation: &str, old_object: serde_json::Value| {
+            serde_json::from_value::<KubernetesAdmissionRequest>(serde_json::json!({
+                "operation": operation,
+                "kind": {"group": "", "version": "v1", "kind": "Pod"},
+                "object": {"spec": {"containers": [
+                    {"name": "app", "image": "quay.io/app:1.0"},
+                    {"name": "istio-proxy", "image": "docker.io/istio/proxyv2:1.20.0"}
+                ]}},
+                "oldObject": old_object
+            }))
+            .expect("cannot parse request")
+        };
+        let evaluation = evaluate(&settings, &request("CREATE", serde_json::Value::Null)).unwrap();
+        assert_eq!(evaluation.decision, Decision::Accept);
+        assert_eq!(
+            evaluation.warnings,
+            vec![
+                "image docker.io/istio/proxyv2:1.20.0 of the injected container istio-proxy \
+                 was not evaluated"
+            ]
+        );
+        assert_eq!(
+            evaluation
+                .audit_annotations
+                .get("images")
+                .map(String::as_str),
+            Some("quay.io/app:1.0")
+        );
+        let old_object = serde_json::json!({"spec": {"containers": [
+            {"name": "app", "image": "quay.io/app:0.9"},
+            {"name": "istio-proxy", "image": "docker.io/istio/proxyv2:1.20.0"}
+        ]}});
+        assert!(evaluate(&settings, &request("UPDATE", old_object))
+            .unwrap()
+            .warnings
+            .is_empty());
+    }
+
+    #[test]
+    fn evaluate_audit_annotations() {
+        let audit_annotations = |settings: &Settings, request: &KubernetesAdmissionRequest| {
+            evaluate(settings, request)
+                .unwrap()
+                .audit_annotations
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+        let annotation = |key: &str, value: &str| (key.to_string(), value.to_string());
+        let rules = settings(serde_json::json!({
+            "registries": {"allow": ["quay.io"]},
+            "tags": {"reject": ["latest"]}
+        }));
+        assert_eq!(
+            audit_annotations(&rules, &pod_request("CREATE", "quay.io/etcd:v3.4.12")),
+            vec![
+                annotation("decision", "accepted"),
+                annotation("images", "quay.io/etcd:v3.4.12"),
+                annotation("rules", "registries.allow[0]"),
+            ]
+        );
+        assert_eq!(
+            audit_annotations(&rules, &pod_request("CREATE", "nginx:latest")),
+            vec![
+                annotation("decision", "rejected"),
+                annotation("images", "nginx:latest"),
+                annotation("rules", "registries,tags"),
+            ]
+        );
+
+        let quarantine = settings(serde_json::json!({
+            "registries": {"allow": ["quay.io"]},
+            "quarantine": {}
+        }));
+        assert_eq!(
+            audit_annotations(&quarantine, &pod_request("CREATE", "nginx:1.25"))[0],
+            annotation("decision", "quarantined")
+        );
+        let operations = settings(serde_json::json!({
+            "registries": {"allow": ["quay.io"]},
+            "operations": ["CREATE"]
+        }));
+        assert!(audit_annotations(&operations, &pod_request("UPDATE", "nginx:1.25")).is_empty());
+    }
+
+    #[test]
+    fn evaluate_rule_enforcement() {
+        let settings = settings(serde_json::json!({
+            "tags": {"reject": [
+                {"pattern": "latest", "enforcement": "warn", "message": "use a release tag"},
+                "dev"
+            ]}
+        }));
+        let evaluation = evaluate(&settings, &pod_request("CREATE", "nginx:latest")).unwrap();
+        assert_eq!(evaluation.decision, Decision::Accept);
+        assert_eq!(
+            evaluation.warnings,
+            vec![
+                "image nginx:latest: use a release tag \
+                 (would be rejected by tags.reject entry `latest`)"
+            ]
+        );
+        assert_eq!(
+            evaluation
+                .audit_annotations
+                .get("warnings")
+                .map(String::as_str),
+            Some(evaluation.warnings[0].as_str())
+        );
+
+        let evaluation = evaluate(&settings, &pod_request("CREATE", "nginx:dev")).unwrap();
+        assert!(matches!(evaluation.decision, Decision::Reject(_)));
+        assert!(evaluation.warnings.is_empty());
+    }
+
+    #[test]
+    fn evaluate_mutations() {
+        let settings = settings(serde_json::json!({
+            "registries": {"allow": ["quay.io"]},
+            "decisionAnnotation": {}
+        }));
+        match evaluate(&settings, &pod_request("CREATE", "quay.io/etcd:v3.4.12"))
+            .unwrap()
+            .decision
+        {
+            Decision::Mutate(object) => assert_eq!(
+                object["metadata"]["annotations"]["trusted-repos.policy/decision"]
+                    .as_str()
+                    .map(|decision| decision.starts_with("allowed; rules=registries.allow[0];")),
+                Some(true)
+            ),
+            decision => panic!("unexpected decision {:?}", decision),
+        }
+    }
+
+    #[test]
+    fn evaluate_pod_templates() {
+        let settings = settings(serde_json::json!({
+            "registries": {"allow": ["quay.io"]},
+            "imagePullPolicy": {"mode": "mutate"}
+        }));
+        let request = |image: &str| -> KubernetesAdmissionRequest {
+            serde_json::from_value(serde_json::json!({
+                "operation": "UPDATE",
+                "kind": {"group": "", "version": "v1", "kind": "PodTemplate"},
+                "namespace": "default",
+                "name": "app",
+                "object": {
+                    "apiVersion": "v1",
+                    "kind": "PodTemplate",
+                    "metadata": {"name": "app"},
+                    "template": {
+                        "metadata": {"labels": {"app": "app"}},
+                        "spec": {"containers": [{"name": "app", "image": image}]}
+                    }
+                }
+            }))
+            .expect("cannot parse request")
+        };
+        assert_eq!(
+            evaluate(&settings, &request("docker.io/nginx:1.25"))
+                .unwrap()
+                .decision,
+            Decision::Reject(
+                "not allowed, reported errors: registries not allowed: docker.io; \
+                 offending fields: template.spec.containers[0].image"
+                    .to_string()
+            )
+        );
+        match evaluate(&settings, &request("quay.io/etcd:v3.4.12"))
+            .unwrap()
+            .decision
+        {
+            Decision::Mutate(object) => {
+                assert_eq!(object["kind"], "PodTemplate");
+                assert_eq!(object["template"]["metadata"]["labels"]["app"], "app");
+                assert_eq!(
+                    object["template"]["spec"]["containers"][0]["imagePullPolicy"],
+                    "Always"
+                );
+            }
+            decision => panic!("unexpected decision {:?}", decision),
+        }
+    }
+
+    #[test]
+    fn original_images() {
+        let container = |name: &str, image: &str| apicore::Container {
+            name: name.to_string(),
+            image: Some(image.to_string()),
+            ..Default::default()
+        };
+        let original_pod = apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                init_containers: Some(vec![container("init", "busybox:1.36")]),
+                containers: vec![
+                    container("app", "nginx:1.25"),
+                    container(&"a".repeat(60), "envoy:1.28"),
+                ],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut pod = original_pod.clone();
+        record_original_images(&original_pod, &mut pod);
+        assert_eq!(pod, original_pod);
+
+        let spec = pod.spec.as_mut().unwrap();
+        spec.containers[0].image = Some("mirror.example.com/nginx:1.25".to_string());
+        spec.containers[1].image = Some("mirror.example.com/envoy:1.28".to_string());
+        record_original_images(&original_pod, &mut pod);
+        assert_eq!(
+            pod.metadata.annotations,
+            Some(
+                vec![(
+                    "trusted-repos.policy/original-image.app".to_string(),
+                    "nginx:1.25".to_string()
+                )]
+                .into_iter()
+                .collect()
+            )
+        );
+    }
+
+    #[test]
+    fn evaluate_quarantine() {
+        let settings = settings(serde_json::json!({
+            "registries": {"allow": ["quay.io"]},
+            "quarantine": {"label": {}}
+        }));
+        assert_eq!(
+            evaluate(&settings, &pod_request("CREATE", "quay.io/etcd:v3.4.12"))
+                .unwrap()
+                .decision,
+            Decision::Accept
+        );
+        let object = match evaluate(&settings, &pod_request("CREATE", "nginx:1.25"))
+            .unwrap()
+            .decision
+        {
+            Decision::Mutate(object) => object,
+            decision => panic!("unexpected decision {:?}", decision),
+        };
+        assert_eq!(
+            object["metadata"]["labels"]["trusted-repos.policy/quarantined"],
+            "true"
+        );
+        let record: serde_json::Value = serde_json::from_str(
+            object["metadata"]["annotations"]["trusted-repos.policy/violations"]
+                .as_str()
+                .expect("missing violations annotation"),
+        )
+        .expect("cannot parse violations annotation");
+        assert_eq!(
+            record,
+            serde_json::json!({
+                "message": "not allowed, reported errors: registries not allowed: docker.io; offending fields: spec.containers[0].image",
+                "violations": [{
+                    "container": "app",
+                    "path": "spec.containers[0].image",
+                    "image": "nginx:1.25",
+                    "rule": "registries",
+                    "code": "REGISTRY_NOT_ALLOWED",
+                    "registry": "docker.io"
+                }]
+            })
+        );
+        assert_eq!(object["spec"]["containers"][0]["image"], "nginx:1.25");
+    }
+
+    enum Expected {
+        Accept,
+        /// The mutated object holds the value at the JSON pointer.
+        Mutate(&'static str, &'static str),
+        /// The rejection message contains the text.
+        Reject(&'static str),
+    }
+
+    fn fixture(path: &str) -> Vec<u8> {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("test_data")
+            .join(path);
+        std::fs::read(&path).unwrap_or_else(|e| panic!("cannot read {}: {}", path.display(), e))
+    }
+
+    #[test]
+    fn evaluate_fixtures() {
+        use crate::review::parse_admission_request;
+        use kubewarden::settings::Validatable;
+        use Expected::*;
+        let cases = vec![
+            ("pod-quay.json", "registries.yaml", Accept),
+            ("pod-quay.json", "strict.yaml", Accept),
+            ("pod-quay.json", "mutating.yaml", Accept),
+            (
+                "pod-v1beta1.json",
+                "registries.yaml",
+                Reject("registries not allowed: docker.io"),
+            ),
+            (
+                "pod-docker-hub-short.json",
+                "registries.yaml",
+                Reject("registries not allowed: docker.io"),
+            ),
+            (
+                "pod-docker-hub-short.json",
+                "strict.yaml",
+                Reject("registries not allowed: docker.io"),
+            ),
+            (
+                "pod-docker-hub-short.json",
+                "mutating.yaml",
+                Mutate("/spec/containers/0/image", "docker.io/library/nginx:latest"),
+            ),
+            (
+                "pod-latest-tag.json",
+                "registries.yaml",
+                Reject("tags not allowed: latest"),
+            ),
+            (
+                "pod-invalid-tag.json",
+                "registries.yaml",
+                Reject("invalid tag syntax"),
+            ),
+            (
+                "pod-ip-registry.json",
+                "registries.yaml",
+                Reject("registries not allowed: 10.0.0.100:5000"),
+            ),
+            (
+                "pod-ip-registry.json",
+                "strict.yaml",
+                Reject("registries addressed by IP: 10.0.0.100:5000"),
+            ),
+            (
+                "pod-update-unchanged-images.json",
+                "registries.yaml",
+                Accept,
+            ),
+            ("deployment.json", "registries.yaml", Accept),
+            (
+                "deployment.json",
+                "workloads.yaml",
+                Reject("registries not allowed: docker.io"),
+            ),
+            ("podtemplate.json", "registries.yaml", Accept),
+            (
+                "pod-docker-hub-short.json",
+                "air-gapped.yaml",
+                Mutate("/spec/containers/0/image", "registry.internal/nginx"),
+            ),
+            (
+                "pod-latest-tag.json",
+                "air-gapped.yaml",
+                Reject("registries not allowed: quay.io"),
+            ),
+            (
+                "podtemplate.json",
+                "mutating.yaml",
+                Mutate("/template/spec/containers/0/imagePullPolicy", "Always"),
+            ),
+            ("pod-quay.json", "frozen-tags.yaml", Accept),
+            (
+                "pod-latest-tag.json",
+                "frozen-tags.yaml",
+                Mutate(
+                    "/spec/containers/0/image",
+                    "quay.io/my-org/app:1.4.2@sha256:9b2f3a1de8c4a0d9b7e5c61f3a2b4d8e0c7f1a6b3d5e9c2f4a8b0d1e6c3f7a29",
+                ),
+            ),
+        ];
+        for (request, profile, expected) in cases {
+            let settings = Settings::from_slice(&fixture(&format!("settings/{}", profile)))
+                .unwrap_or_else(|e| panic!("{}: {}", profile, e));
+            settings
+                .validate()
+                .unwrap_or_else(|e| panic!("{}: {}", profile, e));
+            let review: serde_json::Value =
+                serde_json::from_slice(&fixture(&format!("requests/{}", request)))
+                    .unwrap_or_else(|e| panic!("{}: {}", request, e));
+            let request_object =
+                parse_admission_request(review).unwrap_or_else(|e| panic!("{}: {}", request, e));
+            let decision = evaluate(&settings, &request_object)
+                .unwrap_or_else(|e| panic!("{} with {}: {}", request, profile, e))
+                .decision;
+            match (expected, decision) {
+                (Accept, Decision::Accept) => {}
+                (Mutate(pointer, value), Decision::Mutate(object)) => assert_eq!(
+                    object.pointer(pointer),
+                    Some(&serde_json::Value::from(value)),
+                    "{} with {}",
+                    request,
+                    profile
+                ),
+                (Reject(message), Decision::Reject(reason)) => assert!(
+                    reason.contains(message),
+                    "{} with {}: {}",
+                    request,
+                    profile,
+                    reason
+                ),
+                (_, decision) => panic!("{} with {}: {:?}", request, profile, decision),
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use hmac::{Hmac, Mac};
+use k8s_openapi::chrono::DateTime;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::time::SystemTime;
+
+use crate::settings::is_valid_annotation_key;
+
+const DEFAULT_EXCEPTION_ANNOTATION_KEY: &str = "trusted-repos.policy/exception";
+
+/// Shortest secret accepted, as the tokens are only as strong as it.
+const MIN_SECRET_LENGTH: usize = 32;
+
+/// Break-glass exceptions: an image of a namespace is accepted, whatever
+/// the rules, while the object carries an unexpired token signed with
+/// the shared secret.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq)]
+pub(crate) struct Exceptions {
+    /// Secret shared with the people allowed to grant exceptions.
+    secret: String,
+    /// Key of the annotation holding the tokens, separated by commas.
+    #[serde(default = "default_exception_annotation_key")]
+    pub(crate) annotation: String,
+}
+
+fn default_exception_annotation_key() -> String {
+    DEFAULT_EXCEPTION_ANNOTATION_KEY.to_string()
+}
+
+impl Exceptions {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.secret.len() < MIN_SECRET_LENGTH {
+            return Err(format!(
+                "exceptions secret must be at least {} characters long",
+                MIN_SECRET_LENGTH
+            ));
+        }
+        if !is_valid_annotation_key(&self.annotation) {
+            return Err(format!(
+                "exceptions annotation {} is not a valid annotation key",
+                self.annotation
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the expiry of the first token of the annotation value
+    /// granting an exception to the image in the namespace at `now`.
+    pub(crate) fn granted_until<'a>(
+        &self,
+        tokens: &'a str,
+        namespace: &str,
+        image: &str,
+        now: SystemTime,
+    ) -> Option<&'a str> {
+        tokens
+            .split(',')
+            .map(str::trim)
+            .filter_map(|token| token.rsplit_once('.'))
+            .find(|(expiry, signature)| self.verify(namespace, image, expiry, signature, now))
+            .map(|(expiry, _)| expiry)
+    }
+
+    /// Returns true when the hex encoded signature is the one of the
+    /// namespace, image and expiry, and the expiry is after `now`.
+    fn verify(
+        &self,
+        namespace: &str,
+        image: &str,
+        expiry: &str,
+        signature: &str,
+        now: SystemTime,
+    ) -> bool {
+        let expires_at = match DateTime::parse_from_rfc3339(expiry) {
+            Ok(expires_at) => SystemTime::from(expires_at),
+            Err(_) => return false,
+        };
+        if now >= expires_at {
+            return false;
+        }
+        match decode_hex(signature) {
+            Some(signature) => self
+                .mac(namespace, image, expiry)
+                .verify_slice(&signature)
+                .is_ok(),
+            None => false,
+        }
+    }
+
+    /// HMAC-SHA256 of the namespace, image and expiry, separated by
+    /// newlines.
+    fn mac(&self, namespace: &str, image: &str, expiry: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(format!("{}\n{}\n{}", namespace, image, expiry).as_bytes());
+        mac
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    fn sign(exceptions: &Exceptions, namespace: &str, image: &str, expiry: &str) -> String {
+        let signature = exceptions.mac(namespace, image, expiry).finalize();
+        format!("{}.{:x}", expiry, signature.into_bytes())
+    }
+
+    #[test]
+    fn signed_tokens() {
+        let exceptions = Exceptions {
+            secret: SECRET.to_string(),
+            annotation: default_exception_annotation_key(),
+        };
+        assert!(exceptions.validate().is_ok());
+        let expiry = "2025-06-30T12:00:00Z";
+        let before = SystemTime::from(DateTime::parse_from_rfc3339(expiry).unwrap())
+            - Duration::from_secs(60);
+        let token = sign(&exceptions, "team-a", "docker.io/nginx:1.25", expiry);
+
+        for (tokens, namespace, image, now, granted) in [
+            (
+                token.clone(),
+                "team-a",
+                "docker.io/nginx:1.25",
+                before,
+                true,
+            ),
+            (
+                format!("garbage, {}", token),
+                "team-a",
+                "docker.io/nginx:1.25",
+                before,
+                true,
+            ),
+            (
+                token.clone(),
+                "team-b",
+                "docker.io/nginx:1.25",
+                before,
+                false,
+            ),
+            (
+                token.clone(),
+                "team-a",
+                "docker.io/nginx:1.26",
+                before,
+                false,
+            ),
+            (
+                token.clone(),
+                "team-a",
+                "docker.io/nginx:1.25",
+                before + Duration::from_secs(120),
+                false,
+            ),
+            (
+                token.replace("2025-06-30", "2099-06-30"),
+                "team-a",
+                "docker.io/nginx:1.25",
+                before,
+                false,
+            ),
+            (
+                format!("{}.zz", expiry),
+                "team-a",
+                "docker.io/nginx:1.25",
+                before,
+                false,
+            ),
+        ] {
+            assert_eq!(
+                exceptions
+                    .granted_until(&tokens, namespace, image, now)
+                    .is_some(),
+                granted,
+                "{} {} {}",
+                tokens,
+                namespace,
+                image
+            );
+        }
+
+        let other = Exceptions {
+            secret: SECRET.replace('0', "1"),
+            annotation: default_exception_annotation_key(),
+        };
+        assert!(other
+            .granted_until(&token, "team-a", "docker.io/nginx:1.25", before)
+            .is_none());
+    }
+
+    #[test]
+    fn validation() {
+        for (secret, annotation, valid) in [
+            (SECRET, DEFAULT_EXCEPTION_ANNOTATION_KEY, true),
+            ("short", DEFAULT_EXCEPTION_ANNOTATION_KEY, false),
+            (SECRET, "not a key", false),
+        ] {
+            let exceptions = Exceptions {
+                secret: secret.to_string(),
+                annotation: annotation.to_string(),
+            };
+            assert_eq!(exceptions.validate().is_ok(), valid, "{}", annotation);
+        }
+    }
+}
@@ -0,0 +1,397 @@
+use kubewarden::request::GroupVersionKind;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Kind of resource, other than pods, embedding images in its own
+/// fields.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CustomResourceRule {
+    pub(crate) api_version: String,
+    pub(crate) kind: String,
+    /// Paths of the fields holding images, like
+    /// `spec.components[*].image`.
+    pub(crate) paths: Vec<String>,
+}
+
+impl CustomResourceRule {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.api_version.is_empty() || self.kind.is_empty() {
+            return Err("customResourceRules must provide an apiVersion and a kind".to_string());
+        }
+        if self.paths.is_empty() {
+            return Err(format!(
+                "customResourceRules entry for {} must provide at least one path",
+                self.kind
+            ));
+        }
+        for path in &self.paths {
+            FieldPath::parse(path)
+                .map_err(|e| format!("invalid path {} for {}: {}", path, self.kind, e))?;
+        }
+        Ok(())
+    }
+
+    /// Returns true when the rule applies to resources of the given
+    /// kind.
+    pub(crate) fn applies_to(&self, gvk: &GroupVersionKind) -> bool {
+        let api_version = if gvk.group.is_empty() {
+            gvk.version.clone()
+        } else {
+            format!("{}/{}", gvk.group, gvk.version)
+        };
+        self.api_version == api_version && self.kind == gvk.kind
+    }
+
+    /// Kind of the resources the rule applies to.
+    pub(crate) fn group_version_kind(&self) -> GroupVersionKind {
+        let (group, version) = self
+            .api_version
+            .rsplit_once('/')
+            .unwrap_or(("", &self.api_version));
+        GroupVersionKind {
+            group: group.to_string(),
+            version: version.to_string(),
+            kind: self.kind.clone(),
+        }
+    }
+
+    /// Collects the images found at the paths of the rule in `object`.
+    /// Fields that are missing, or that do not hold a string, are
+    /// skipped.
+    pub(crate) fn images<'a>(&self, object: &'a serde_json::Value) -> Vec<&'a str> {
+        self.located_images(object)
+            .into_iter()
+            .map(|(_, image)| image)
+            .collect()
+    }
+
+    /// Collects the images found at the paths of the rule in `object`,
+    /// like `images`, along with the path of the field holding them,
+    /// like `spec.components[2].image`.
+    pub(crate) fn located_images<'a>(
+        &self,
+        object: &'a serde_json::Value,
+    ) -> Vec<(String, &'a str)> {
+        let mut images = Vec::new();
+        for path in self
+            .paths
+            .iter()
+            .filter_map(|path| FieldPath::parse(path).ok())
+        {
+            images.extend(
+                path.extract(object)
+                    .into_iter()
+                    .filter_map(|(path, v)| v.as_str().map(|image| (path, image))),
+            );
+        }
+        images
+    }
+}
+
+/// Popular workloads embedding images, whose rules are built into the
+/// policy.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum CustomWorkload {
+    /// Knative `Service` and `Configuration`, from
+    /// `serving.knative.dev/v1`.
+    KnativeService,
+    /// Argo `Rollout`, from `argoproj.io/v1alpha1`.
+    ArgoRollout,
+    /// Tekton `Task`, `TaskRun` and `Pipeline`, from `tekton.dev/v1`
+    /// and `tekton.dev/v1beta1`.
+    Tekton,
+}
+
+/// Paths of the images of a Tekton task spec, relative to the spec.
+const TEKTON_TASK_SPEC_PATHS: &[&str] =
+    &["steps[*].image", "sidecars[*].image", "stepTemplate.image"];
+
+impl CustomWorkload {
+    /// Returns the rules extracting the images of the workload, one
+    /// for each of its kinds and versions.
+    pub(crate) fn rules(&self) -> Vec<CustomResourceRule> {
+        let rule = |api_version: &str, kind: &str, paths: Vec<String>| CustomResourceRule {
+            api_version: api_version.to_string(),
+            kind: kind.to_string(),
+            paths,
+        };
+        let pod_template_paths = || {
+            vec![
+                "spec.template.spec.initContainers[*].image".to_string(),
+                "spec.template.spec.containers[*].image".to_string(),
+            ]
+        };
+        let task_spec_paths = |prefixes: &[&str]| -> Vec<String> {
+            prefixes
+                .iter()
+                .flat_map(|prefix| {
+                    TEKTON_TASK_SPEC_PATHS
+                        .iter()
+                        .map(move |path| format!("{}.{}", prefix, path))
+                })
+                .collect()
+        };
+        match self {
+            CustomWorkload::KnativeService => vec![
+                rule("serving.knative.dev/v1", "Service", pod_template_paths()),
+                rule(
+                    "serving.knative.dev/v1",
+                    "Configuration",
+                    pod_template_paths(),
+                ),
+            ],
+            CustomWorkload::ArgoRollout => {
+                vec![rule(
+                    "argoproj.io/v1alpha1",
+                    "Rollout",
+                    pod_template_paths(),
+                )]
+            }
+            CustomWorkload::Tekton => ["tekton.dev/v1", "tekton.dev/v1beta1"]
+                .iter()
+                .flat_map(|api_version| {
+                    vec![
+                        rule(api_version, "Task", task_spec_paths(&["spec"])),
+                        rule(api_version, "TaskRun", task_spec_paths(&["spec.taskSpec"])),
+                        rule(
+                            api_version,
+                            "Pipeline",
+                            task_spec_paths(&[
+                                "spec.tasks[*].taskSpec",
+                                "spec.finally[*].taskSpec",
+                            ]),
+                        ),
+                    ]
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Field(String),
+    Index(usize),
+    /// `[*]`, every item of an array.
+    Wildcard,
+}
+
+/// Path to the fields of a JSON object, made of field names separated
+/// by dots, each optionally followed by array indexes like `[0]` or by
+/// `[*]`. The leading `$.` of JSONPath expressions is accepted.
+#[derive(Debug, PartialEq)]
+struct FieldPath(Vec<Segment>);
+
+impl FieldPath {
+    fn parse(path: &str) -> Result<FieldPath, String> {
+        let path = path.strip_prefix("$.").unwrap_or(path);
+        let mut segments = Vec::new();
+        for part in path.split('.') {
+            let (name, mut indexes) = match part.find('[') {
+                Some(position) => part.split_at(position),
+                None => (part, ""),
+            };
+            if name.is_empty() {
+                return Err("field names cannot be empty".to_string());
+            }
+            segments.push(Segment::Field(name.to_string()));
+            while !indexes.is_empty() {
+                let (index, rest) = indexes
+                    .strip_prefix('[')
+                    .and_then(|indexes| indexes.split_once(']'))
+                    .ok_or_else(|| format!("malformed index in {}", part))?;
+                segments.push(match index {
+                    "*" => Segment::Wildcard,
+                    index => Segment::Index(
+                        index
+                            .parse()
+                            .map_err(|_| format!("invalid index {}", index))?,
+                    ),
+                });
+                indexes = rest;
+            }
+        }
+        Ok(FieldPath(segments))
+    }
+
+    /// Returns the values found at the path, along with the path of
+    /// each of them, where `[*]` is replaced by the index of the item.
+    fn extract<'a>(&self, object: &'a serde_json::Value) -> Vec<(String, &'a serde_json::Value)> {
+        let mut values = vec![(String::new(), object)];
+        for segment in &self.0 {
+            values = values
+                .into_iter()
+                .flat_map(|(path, value)| match segment {
+                    Segment::Field(name) => value
+                        .get(name)
+                        .map(|value| {
+                            if path.is_empty() {
+                                (name.clone(), value)
+                            } else {
+                                (format!("{}.{}", path, name), value)
+                            }
+                        })
+                        .into_iter()
+                        .collect::<Vec<_>>(),
+                    Segment::Index(index) => value
+                        .get(index)
+                        .map(|value| (format!("{}[{}]", path, index), value))
+                        .into_iter()
+                        .collect(),
+                    Segment::Wildcard => value
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .enumerate()
+                        .map(|(index, value)| (format!("{}[{}]", path, index), value))
+                        .collect(),
+                })
+                .collect();
+        }
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_field_path() {
+        assert_eq!(
+            FieldPath::parse("$.spec.components[*].images[0]"),
+            Ok(FieldPath(vec![
+                Segment::Field("spec".to_string()),
+                Segment::Field("components".to_string()),
+                Segment::Wildcard,
+                Segment::Field("images".to_string()),
+                Segment::Index(0),
+            ]))
+        );
+        assert!(FieldPath::parse("spec..image").is_err());
+        assert!(FieldPath::parse("spec.images[").is_err());
+        assert!(FieldPath::parse("spec.images[first]").is_err());
+    }
+
+    #[test]
+    fn extract_images() {
+        let rule: CustomResourceRule = serde_json::from_str(
+            r#"{"apiVersion": "apps.example.com/v1", "kind": "AppDeployment", "paths": ["spec.image", "spec.components[*].image", "spec.sidecars[1]"]}"#,
+        )
+        .expect("cannot parse rule");
+        assert_eq!(rule.validate(), Ok(()));
+        let object = serde_json::json!({
+            "spec": {
+                "image": "quay.io/app:1.0",
+                "components": [
+                    {"image": "quay.io/worker:1.0"},
+                    {"name": "no-image"},
+                    {"image": 42},
+                ],
+                "sidecars": ["envoy:1.0", "ghcr.io/proxy:2.0"],
+            }
+        });
+        assert_eq!(
+            rule.images(&object),
+            vec!["quay.io/app:1.0", "quay.io/worker:1.0", "ghcr.io/proxy:2.0"]
+        );
+        assert_eq!(
+            rule.located_images(&object),
+            vec![
+                ("spec.image".to_string(), "quay.io/app:1.0"),
+                ("spec.components[0].image".to_string(), "quay.io/worker:1.0"),
+                ("spec.sidecars[1]".to_string(), "ghcr.io/proxy:2.0"),
+            ]
+        );
+        assert!(rule.images(&serde_json::Value::Null).is_empty());
+    }
+
+    #[test]
+    fn applies_to() {
+        let gvk = |group: &str, version: &str, kind: &str| GroupVersionKind {
+            group: group.to_string(),
+            version: version.to_string(),
+            kind: kind.to_string(),
+        };
+        let rule = CustomResourceRule {
+            api_version: "apps.example.com/v1".to_string(),
+            kind: "AppDeployment".to_string(),
+            paths: vec!["spec.image".to_string()],
+        };
+        assert!(rule.applies_to(&gvk("apps.example.com", "v1", "AppDeployment")));
+        assert!(!rule.applies_to(&gvk("apps.example.com", "v2", "AppDeployment")));
+        assert!(!rule.applies_to(&gvk("", "v1", "Pod")));
+
+        let rules = CustomWorkload::KnativeService.rules();
+        assert!(rules[0].applies_to(&gvk("serving.knative.dev", "v1", "Service")));
+        assert!(!rules[0].applies_to(&gvk("", "v1", "Service")));
+        assert!(rules[1].applies_to(&gvk("serving.knative.dev", "v1", "Configuration")));
+        assert!(CustomWorkload::ArgoRollout.rules()[0].applies_to(&gvk(
+            "argoproj.io",
+            "v1alpha1",
+            "Rollout"
+        )));
+        let rules = CustomWorkload::Tekton.rules();
+        for (version, kind) in [("v1", "Task"), ("v1beta1", "TaskRun"), ("v1", "Pipeline")] {
+            assert!(
+                rules
+                    .iter()
+                    .any(|rule| rule.applies_to(&gvk("tekton.dev", version, kind))),
+                "no rule for {}/{}",
+                version,
+                kind
+            );
+        }
+    }
+
+    #[test]
+    fn extract_custom_workload_images() {
+        let object = serde_json::json!({
+            "spec": {
+                "template": {
+                    "spec": {
+                        "initContainers": [{"name": "init", "image": "busybox:1.36"}],
+                        "containers": [{"name": "app", "image": "quay.io/app:1.0"}]
+                    }
+                }
+            }
+        });
+        assert_eq!(
+            CustomWorkload::ArgoRollout.rules()[0].images(&object),
+            vec!["busybox:1.36", "quay.io/app:1.0"]
+        );
+
+        let pipeline = serde_json::json!({
+            "spec": {
+                "tasks": [
+                    {"name": "fetch", "taskRef": {"name": "git-clone"}},
+                    {
+                        "name": "build",
+                        "taskSpec": {
+                            "stepTemplate": {"image": "quay.io/base:1.0"},
+                            "steps": [{"name": "build", "image": "gcr.io/kaniko:1.9"}],
+                            "sidecars": [{"name": "registry", "image": "registry:2"}]
+                        }
+                    }
+                ],
+                "finally": [{"taskSpec": {"steps": [{"image": "alpine:3.18"}]}}]
+            }
+        });
+        let rule = CustomWorkload::Tekton
+            .rules()
+            .into_iter()
+            .find(|rule| rule.kind == "Pipeline")
+            .expect("rule not found");
+        assert_eq!(
+            rule.images(&pipeline),
+            vec![
+                "gcr.io/kaniko:1.9",
+                "registry:2",
+                "quay.io/base:1.0",
+                "alpine:3.18"
+            ]
+        );
+    }
+}
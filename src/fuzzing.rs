@@ -0,0 +1,23 @@
+//! Entry points of the fuzz targets, only built with the `fuzzing`
+//! feature.
+
+use kubewarden::settings::Validatable;
+
+use crate::image::Image;
+use crate::settings::Settings;
+
+/// Parses an image reference, as found in the pod specs.
+pub fn parse_image(image: &str) {
+    let _ = Image::new(image);
+}
+
+/// Parses, validates and compiles settings given as JSON or YAML.
+pub fn parse_settings(settings: &[u8]) {
+    let settings = match Settings::from_slice(settings) {
+        Ok(settings) => settings,
+        Err(_) => return,
+    };
+    if settings.validate().is_ok() {
+        let _ = settings.compile(None);
+    }
+}
@@ -1,35 +1,231 @@
 use anyhow::{anyhow, Result};
 use core::fmt::Display;
-use regex::Regex;
-use url::{Host, Url};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::net::Ipv6Addr;
+use std::str::FromStr;
+use thiserror::Error;
 
-#[derive(Default, Debug)]
-pub(crate) struct Image {
-    pub(crate) image: String,
-    pub(crate) registry: Option<String>,
-    pub(crate) name: String,
-    pub(crate) tag: Option<String>,
-    pub(crate) sha256: Option<String>,
+use crate::normalize::{is_domain, normalize_registry, normalize_repository, split_name};
+use crate::pattern::glob_matches;
+use crate::rule::{literal_characters, Component, Covers, Specific, Specificity};
+
+/// Registry used when the reference does not include a domain.
+pub(crate) const DEFAULT_REGISTRY: &str = "docker.io";
+
+/// Other hostnames of the Docker Hub, treated as `DEFAULT_REGISTRY`
+/// unless registry aliases are disabled.
+const DOCKER_HUB_ALIASES: &[&str] = &["index.docker.io", "registry-1.docker.io"];
+
+/// Returns true when the registry is an alias of the Docker Hub.
+pub(crate) fn is_docker_hub_alias(registry: &str) -> bool {
+    DOCKER_HUB_ALIASES.contains(&registry)
 }
 
-impl ToString for Image {
-    fn to_string(&self) -> String {
-        format!(
-            "{}{}{}{}",
-            self.registry
-                .as_ref()
-                .map(|registry| format!("{}/", registry))
-                .unwrap_or_default(),
-            self.name,
-            self.tag
-                .as_ref()
-                .map(|tag| format!(":{}", tag))
-                .unwrap_or_default(),
-            self.sha256
-                .as_ref()
-                .map(|sha256| format!("@sha256:{}", sha256))
-                .unwrap_or_default(),
-        )
+/// Transport of an image reference, given by a scheme prefix like in
+/// `docker://quay.io/app:1.0`. References without a prefix use the
+/// `Docker` transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Docker,
+    Oci,
+}
+
+const TRANSPORTS: &[(&str, Transport)] =
+    &[("docker://", Transport::Docker), ("oci://", Transport::Oci)];
+
+impl Display for Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Transport::Docker => write!(f, "docker"),
+            Transport::Oci => write!(f, "oci"),
+        }
+    }
+}
+
+/// Splits the transport prefix, if any, from an image reference.
+pub(crate) fn split_transport(reference: &str) -> (Option<Transport>, &str) {
+    TRANSPORTS
+        .iter()
+        .find_map(|(prefix, transport)| {
+            reference
+                .strip_prefix(prefix)
+                .map(|reference| (Some(*transport), reference))
+        })
+        .unwrap_or((None, reference))
+}
+
+/// Returns true when the domain follows the reference grammar: dot
+/// separated labels, or an IPv6 address between brackets, optionally
+/// followed by a port.
+fn is_valid_domain(domain: &str) -> bool {
+    let (valid_host, port) = match domain.strip_prefix('[') {
+        Some(rest) => match rest.split_once(']') {
+            Some((address, port)) => (
+                !address.is_empty() && address.chars().all(|c| c.is_ascii_hexdigit() || c == ':'),
+                port,
+            ),
+            None => return false,
+        },
+        None => {
+            let (host, port) = domain
+                .find(':')
+                .map(|index| domain.split_at(index))
+                .unwrap_or((domain, ""));
+            (host.split('.').all(is_domain_label), port)
+        }
+    };
+    valid_host
+        && (port.is_empty()
+            || port
+                .strip_prefix(':')
+                .map(|port| !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()))
+                .unwrap_or(false))
+}
+
+/// Returns true for the alphanumeric labels of a domain, which can
+/// contain dashes but neither start nor end with one.
+fn is_domain_label(label: &str) -> bool {
+    !label.is_empty()
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Returns true for the lowercase alphanumeric components of a
+/// repository path, whose runs can be separated by `.`, `_`, `__` or
+/// any number of dashes.
+fn is_valid_path_component(component: &str) -> bool {
+    let is_alphanumeric = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit();
+    component.starts_with(is_alphanumeric)
+        && component.ends_with(is_alphanumeric)
+        && component
+            .split(is_alphanumeric)
+            .filter(|separator| !separator.is_empty())
+            .all(|separator| {
+                matches!(separator, "." | "_" | "__") || separator.chars().all(|c| c == '-')
+            })
+}
+
+/// Returns true for the tags made of ASCII word characters, dots and
+/// dashes, starting with a word character. Their length is checked
+/// apart, see `TAG_LENGTH_MAX`.
+fn is_valid_tag(tag: &str) -> bool {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    tag.starts_with(is_word) && tag.chars().all(|c| is_word(c) || c == '.' || c == '-')
+}
+
+/// Returns the limit of the distribution reference exceeded by the
+/// domain of a reference following its grammar, if any: labels are at
+/// most 63 characters long, and ports at most 65535.
+fn exceeded_domain_limit(domain: &str) -> Option<String> {
+    let (host, port) = match domain.strip_prefix('[') {
+        Some(rest) => rest.split_once(']').unwrap_or((rest, "")),
+        None => domain
+            .find(':')
+            .map(|index| domain.split_at(index))
+            .unwrap_or((domain, "")),
+    };
+    if !domain.starts_with('[')
+        && host
+            .split('.')
+            .any(|label| label.len() > DOMAIN_LABEL_LENGTH_MAX)
+    {
+        return Some(format!(
+            "registry label longer than {} characters",
+            DOMAIN_LABEL_LENGTH_MAX
+        ));
+    }
+    match port.strip_prefix(':') {
+        Some(port) if port.parse::<u16>().is_err() => {
+            Some(format!("registry port greater than {}", u16::MAX))
+        }
+        _ => None,
+    }
+}
+
+/// Returns true when the digest follows the `algorithm:encoded` syntax,
+/// where the algorithm is made of lowercase alphanumeric components
+/// separated by one of `+._-`.
+fn is_valid_digest_syntax(digest: &str) -> bool {
+    let (algorithm, encoded) = match digest.split_once(':') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    algorithm.split(['+', '.', '_', '-']).all(|component| {
+        !component.is_empty()
+            && component
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+    }) && !encoded.is_empty()
+        && encoded
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '=' || c == '_' || c == '-')
+}
+
+/// Length of the hex encoded digests of the algorithms registered by
+/// the OCI image specification.
+const KNOWN_DIGEST_ALGORITHMS: &[(&str, usize)] = &[("sha256", 64), ("sha512", 128)];
+
+/// Parsed image reference. Images are displayed, and serialized, in
+/// their canonical form, which always includes the registry and the
+/// `library/` prefix of Docker Hub official images, like
+/// `docker.io/library/nginx:1.25`, preceded by the transport prefix of
+/// the reference, if any. Parsing the canonical form gives back the
+/// same image.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    /// Reference of the image, as written, without its transport
+    /// prefix.
+    pub image: String,
+    /// Transport given by the scheme prefix of the reference, if any.
+    pub transport: Option<Transport>,
+    pub registry: Option<String>,
+    /// Repository path inside the registry, e.g. `prometheus/prometheus`.
+    /// Docker Hub official images are prefixed by `library/`.
+    pub repository: String,
+    pub tag: Option<String>,
+    /// Digest of the image, including its algorithm, e.g.
+    /// `sha256:3fc9b689...`.
+    pub digest: Option<String>,
+}
+
+impl Display for Image {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(transport) = &self.transport {
+            write!(f, "{}://", transport)?;
+        }
+        if let Some(registry) = &self.registry {
+            write!(f, "{}/", registry)?;
+        }
+        write!(f, "{}", self.repository)?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{}", tag)?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Image {
+    type Err = ImageParseError;
+
+    fn from_str(image: &str) -> Result<Image, ImageParseError> {
+        Image::new(image)
+    }
+}
+
+impl Serialize for Image {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Image {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Image, D::Error> {
+        let image = String::deserialize(deserializer)?;
+        Image::new(image.as_str()).map_err(de::Error::custom)
     }
 }
 
@@ -45,67 +241,532 @@ impl PartialEq<String> for Image {
     }
 }
 
+/// Image rule entry. Entries can be:
+///
+/// * a reference, whose registry, repository, tag and digest are
+///   compared one by one with the ones of the image. The tag and the
+///   digest are optional, and match any value when absent; the tag can
+///   contain `*` wildcards, e.g. `quay.io/org/app:1.*`
+/// * a repository path prefix ending in `/` or `/*`, e.g.
+///   `gcr.io/my-project/*`, matching any repository under that path
+#[derive(Debug)]
+pub struct ImagePattern {
+    /// Transport required by the entry, when it has a prefix like
+    /// `oci://`.
+    transport: Option<Transport>,
+    /// Components of the entry, when it is a reference that can be
+    /// parsed.
+    reference: Option<ReferencePattern>,
+    /// Registry and repository path prefix of the entry, ending in `/`,
+    /// when the entry is a prefix.
+    prefix: Option<String>,
+    /// First component of the repository path, on any registry, for
+    /// the organization rules.
+    organization: Option<String>,
+}
+
+/// Components of a reference entry of the image rules.
+#[derive(Debug)]
+struct ReferencePattern {
+    full_repository: String,
+    /// Glob pattern of the tag.
+    tag: Option<String>,
+    digest: Option<String>,
+}
+
+impl ReferencePattern {
+    /// Splits the entry into its name, tag and digest, the name being
+    /// parsed like image references are.
+    fn parse(entry: &str, registry_aliases: bool) -> Option<ReferencePattern> {
+        let (name, digest) = match entry.split_once('@') {
+            Some((name, digest)) => (name, Some(digest)),
+            None => (entry, None),
+        };
+        let (name, tag) = match name.rsplit_once(':') {
+            Some((name, tag)) if !tag.contains('/') => (name, Some(tag)),
+            _ => (name, None),
+        };
+        if let Some(digest) = digest {
+            validate_digest(digest).ok()?;
+        }
+        if tag.map(str::is_empty).unwrap_or(false) {
+            return None;
+        }
+        let mut image = Image::new(name).ok()?;
+        if registry_aliases {
+            image.normalize_registry_alias();
+        }
+        Some(ReferencePattern {
+            full_repository: image.full_repository(),
+            tag: tag.map(String::from),
+            digest: digest.map(String::from),
+        })
+    }
+
+    /// Returns true when every component of the entry matches the one
+    /// of the image. Images referenced by neither a tag nor a digest
+    /// use the `latest` tag.
+    fn matches(&self, image: &Image) -> bool {
+        if !image.has_full_repository(&self.full_repository) {
+            return false;
+        }
+        if let Some(tag) = &self.tag {
+            let image_tag = match (&image.tag, &image.digest) {
+                (Some(image_tag), _) => image_tag.as_str(),
+                (None, None) => "latest",
+                (None, Some(_)) => return false,
+            };
+            if !glob_matches(tag, image_tag) {
+                return false;
+            }
+        }
+        self.digest.is_none() || self.digest == image.digest
+    }
+}
+
+impl ImagePattern {
+    pub fn new(entry: &str) -> ImagePattern {
+        ImagePattern::parse(entry, false)
+    }
+
+    /// Builds the entry like `new`, treating the aliases of the Docker
+    /// Hub as `docker.io`, the way images are evaluated.
+    pub fn new_normalized(entry: &str) -> ImagePattern {
+        ImagePattern::parse(entry, true)
+    }
+
+    /// Builds the entry in the normal form of the image names, so that
+    /// `nginx` and `docker.io/library/nginx` are the same entry.
+    fn parse(entry: &str, registry_aliases: bool) -> ImagePattern {
+        let (transport, entry) = split_transport(entry);
+        let prefix = entry
+            .strip_suffix('*')
+            .unwrap_or(entry)
+            .strip_suffix('/')
+            .map(|prefix| match prefix.split_once('/') {
+                Some((domain, path)) if is_domain(domain) => {
+                    format!("{}/{}/", normalize_registry(domain, registry_aliases), path)
+                }
+                _ if is_domain(prefix) => {
+                    format!("{}/", normalize_registry(prefix, registry_aliases))
+                }
+                _ => format!("{}/{}/", DEFAULT_REGISTRY, prefix),
+            });
+        let reference = match prefix {
+            None => ReferencePattern::parse(entry, registry_aliases),
+            Some(_) => None,
+        };
+        ImagePattern {
+            transport,
+            reference,
+            prefix,
+            organization: None,
+        }
+    }
+
+    /// Entry matching the images of an organization, like `bitnami`
+    /// for `docker.io/bitnami/redis` and `quay.io/bitnami/redis`.
+    pub(crate) fn for_organization(organization: &str) -> ImagePattern {
+        ImagePattern {
+            transport: None,
+            reference: None,
+            prefix: None,
+            organization: Some(organization.to_string()),
+        }
+    }
+
+    /// Returns true when the image is matched by the entry. Entries with
+    /// a transport prefix only match the images using that transport.
+    pub fn matches(&self, image: &Image) -> bool {
+        if let Some(transport) = self.transport {
+            if image.transport.unwrap_or(Transport::Docker) != transport {
+                return false;
+            }
+        }
+        if let Some(prefix) = &self.prefix {
+            // The prefix ends with a `/`, so that it only matches whole
+            // path components
+            return image.full_repository_starts_with(prefix);
+        }
+        if let Some(organization) = &self.organization {
+            return image.organization() == Some(organization.as_str());
+        }
+        self.reference
+            .as_ref()
+            .map(|reference| reference.matches(image))
+            .unwrap_or(false)
+    }
+}
+
+/// Entries are ranked by the most specific component they pin down:
+/// digest, tag, repository, repository prefix, organization and
+/// registry. Between entries pinning down the same component, the
+/// longest repository prefixes, and the tags with the most literal
+/// characters, win.
+impl Specific for ImagePattern {
+    fn specificity(&self) -> Specificity {
+        if let Some(organization) = &self.organization {
+            return Specificity::new(Component::Organization, organization.len());
+        }
+        if let Some(prefix) = &self.prefix {
+            return if prefix.matches('/').count() == 1 {
+                Specificity::new(Component::Registry, prefix.len())
+            } else {
+                Specificity::new(Component::RepositoryPrefix, prefix.len())
+            };
+        }
+        match &self.reference {
+            Some(ReferencePattern {
+                digest: Some(_),
+                tag,
+                ..
+            }) => Specificity::new(Component::Digest, usize::from(tag.is_some())),
+            Some(ReferencePattern { tag: Some(tag), .. }) => {
+                Specificity::new(Component::Tag, literal_characters(tag))
+            }
+            Some(reference) => {
+                Specificity::new(Component::Repository, reference.full_repository.len())
+            }
+            None => Specificity::new(Component::Pattern, 0),
+        }
+    }
+
+    fn organization(organization: &str) -> Option<ImagePattern> {
+        Some(ImagePattern::for_organization(organization))
+    }
+}
+
+/// Prefixes cover the references and the longer prefixes under them,
+/// and references the references of the same repository with a tag
+/// matched by their own tag glob, and the same digest if they have one.
+/// Organizations cover the prefixes and references of their
+/// repositories, on any registry.
+impl Covers for ImagePattern {
+    fn covers(&self, other: &ImagePattern) -> bool {
+        if self.transport.is_some() && self.transport != other.transport {
+            return false;
+        }
+        if let Some(organization) = &self.organization {
+            let in_organization = |full_repository: &str| {
+                full_repository
+                    .split_once('/')
+                    .and_then(|(_, path)| path.split_once('/'))
+                    .map(|(other_organization, _)| other_organization == organization)
+                    .unwrap_or(false)
+            };
+            return match (&other.organization, &other.prefix, &other.reference) {
+                (Some(other_organization), _, _) => other_organization == organization,
+                (None, Some(prefix), _) => in_organization(prefix),
+                (None, None, Some(reference)) => in_organization(&reference.full_repository),
+                (None, None, None) => false,
+            };
+        }
+        match (
+            &self.prefix,
+            &other.prefix,
+            &self.reference,
+            &other.reference,
+        ) {
+            (Some(prefix), Some(other_prefix), _, _) => other_prefix.starts_with(prefix),
+            (Some(prefix), None, _, Some(other)) => {
+                format!("{}/", other.full_repository).starts_with(prefix)
+            }
+            (None, None, Some(reference), Some(other)) => {
+                reference.full_repository == other.full_repository
+                    && match (&reference.tag, &other.tag) {
+                        (None, _) => true,
+                        (Some(tag), Some(other_tag)) => glob_matches(tag, other_tag),
+                        (Some(_), None) => false,
+                    }
+                    && (reference.digest.is_none() || reference.digest == other.digest)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Maximum length of the name of an image, registry included, as
+/// defined by the distribution reference.
+const NAME_TOTAL_LENGTH_MAX: usize = 255;
+
+/// Maximum length of a tag, as defined by the distribution reference.
+const TAG_LENGTH_MAX: usize = 128;
+
+/// Maximum length of the labels of a registry domain name.
+const DOMAIN_LABEL_LENGTH_MAX: usize = 63;
+
+/// Reason why an image reference cannot be parsed. Every variant holds
+/// the reference as written, without its transport prefix.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ImageParseError {
+    #[error("could not parse an empty reference as an image")]
+    EmptyReference,
+    /// The name, registry included, is longer than 255 characters.
+    #[error("could not parse {0} as an image: name longer than 255 characters")]
+    TooLong(String),
+    /// The tag does not follow the tag grammar: word characters, dots
+    /// and dashes, not starting with a dot or a dash.
+    #[error("could not parse {0} as an image: invalid tag syntax")]
+    InvalidTag(String),
+    /// The reference follows the grammar, but exceeds one of the limits
+    /// of the distribution reference, like the 128 characters of tags.
+    #[error("could not parse {image} as an image: {limit}")]
+    LimitExceeded { image: String, limit: String },
+    #[error("could not parse {0} as an image: invalid registry")]
+    InvalidHost(String),
+    #[error("could not parse {0} as an image: invalid IPv6 registry address")]
+    InvalidIpv6Address(String),
+    #[error("could not parse {0} as an image: invalid repository")]
+    InvalidRepository(String),
+    #[error("could not parse {image} as an image: malformed digest {digest}: {reason}")]
+    InvalidDigest {
+        image: String,
+        digest: String,
+        reason: String,
+    },
+}
+
+impl ImageParseError {
+    /// Describes why the reference cannot be parsed, without repeating
+    /// the reference, like `invalid registry`.
+    pub fn reason(&self) -> String {
+        match self {
+            ImageParseError::EmptyReference => "empty reference".to_string(),
+            ImageParseError::TooLong(_) => "name longer than 255 characters".to_string(),
+            ImageParseError::InvalidTag(_) => "invalid tag syntax".to_string(),
+            ImageParseError::LimitExceeded { limit, .. } => limit.clone(),
+            ImageParseError::InvalidHost(_) => "invalid registry".to_string(),
+            ImageParseError::InvalidIpv6Address(_) => "invalid IPv6 registry address".to_string(),
+            ImageParseError::InvalidRepository(_) => "invalid repository".to_string(),
+            ImageParseError::InvalidDigest { digest, reason, .. } => {
+                format!("malformed digest {}: {}", digest, reason)
+            }
+        }
+    }
+}
+
 impl Image {
-    pub(crate) fn new<T>(image: T) -> Result<Image>
+    /// Rewrites the registry of images hosted on an alias of the Docker
+    /// Hub to `DEFAULT_REGISTRY`, prefixing official images with
+    /// `library/`. The reference of the image is kept as written.
+    pub fn normalize_registry_alias(&mut self) {
+        if self.registry.as_deref().map(is_docker_hub_alias) != Some(true) {
+            return;
+        }
+        self.registry = Some(DEFAULT_REGISTRY.to_string());
+        self.repository = normalize_repository(DEFAULT_REGISTRY, &self.repository);
+    }
+
+    /// Canonical form of the image, with the `latest` tag when the
+    /// reference has neither a tag nor a digest, like
+    /// `docker.io/library/nginx:latest` for `nginx`.
+    pub fn fully_qualified(&self) -> String {
+        if self.tag.is_none() && self.digest.is_none() {
+            format!("{}:latest", self)
+        } else {
+            self.to_string()
+        }
+    }
+
+    /// Registry and repository path of the image, without tag nor
+    /// digest, e.g. `gcr.io/my-project/team/service`.
+    pub fn full_repository(&self) -> String {
+        format!(
+            "{}/{}",
+            self.registry.as_deref().unwrap_or(DEFAULT_REGISTRY),
+            self.repository
+        )
+    }
+
+    /// Returns true when `full_repository` equals the one of the image,
+    /// without building it.
+    pub(crate) fn has_full_repository(&self, full_repository: &str) -> bool {
+        full_repository
+            .strip_prefix(self.registry.as_deref().unwrap_or(DEFAULT_REGISTRY))
+            .and_then(|rest| rest.strip_prefix('/'))
+            == Some(self.repository.as_str())
+    }
+
+    /// Returns true when the full repository of the image, followed by
+    /// a `/`, starts with `prefix`, without building it.
+    pub(crate) fn full_repository_starts_with(&self, prefix: &str) -> bool {
+        let mut prefix = prefix;
+        for segment in [
+            self.registry.as_deref().unwrap_or(DEFAULT_REGISTRY),
+            "/",
+            self.repository.as_str(),
+            "/",
+        ] {
+            match prefix.strip_prefix(segment) {
+                Some(rest) => prefix = rest,
+                None => return segment.starts_with(prefix),
+            }
+        }
+        prefix.is_empty()
+    }
+
+    /// First component of the repository path, like `bitnami` for
+    /// `docker.io/bitnami/redis`, or `library` for the official images
+    /// of the Docker Hub. Repositories with a single component, like
+    /// `quay.io/app`, have no organization.
+    pub fn organization(&self) -> Option<&str> {
+        self.repository
+            .split_once('/')
+            .map(|(organization, _)| organization)
+    }
+
+    /// Returns true for the official images of the Docker Hub, in the
+    /// `library/` namespace, like `nginx` or `docker.io/library/nginx`.
+    /// On the aliases of the Docker Hub kept as written, the
+    /// repositories with a single component are official too.
+    pub fn is_official_image(&self) -> bool {
+        match self.registry.as_deref().unwrap_or(DEFAULT_REGISTRY) {
+            DEFAULT_REGISTRY => self.organization() == Some("library"),
+            registry if is_docker_hub_alias(registry) => {
+                !self.repository.contains('/') || self.organization() == Some("library")
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true when the reference names its registry instead of
+    /// relying on the Docker Hub default, like `docker.io/nginx:1.25`
+    /// but unlike `nginx:1.25`.
+    pub fn has_explicit_registry(&self) -> bool {
+        self.image
+            .split_once('/')
+            .map(|(domain, _)| is_domain(domain))
+            .unwrap_or(false)
+    }
+
+    /// Parses an image reference following the grammar of the
+    /// [distribution reference](https://github.com/distribution/reference):
+    ///
+    /// ```text
+    /// reference := name [ ":" tag ] [ "@" digest ]
+    /// name      := [domain '/'] path-component ['/' path-component]*
+    /// ```
+    ///
+    /// The first component of the name is considered a domain only if
+    /// it contains a `.` or a `:`, is `localhost`, or contains
+    /// uppercase characters. Otherwise the image is hosted on the
+    /// Docker Hub.
+    pub fn new<T>(image: T) -> Result<Image, ImageParseError>
     where
         T: Into<String> + Display + Copy + Clone,
     {
-        let orig_image = image.into();
-        let image_with_scheme = format!("registry://{}", image);
-        let url = Url::parse(&image_with_scheme);
-
-        let image_has_slash = image.into().chars().any(|c| c == '/');
+        let mut orig_image: String = image.into();
+        let (transport, reference) = split_transport(&orig_image);
+        // The reference is kept without its transport prefix, removed in
+        // place
+        let prefix_length = orig_image.len() - reference.len();
+        orig_image.replace_range(..prefix_length, "");
+        if orig_image.is_empty() {
+            return Err(ImageParseError::EmptyReference);
+        }
 
-        let parse_image_reference = if image_has_slash {
-            Regex::new(
-                r"^(registry://)?([^/]+/)*(?P<image>[^:@]+)(:(?P<tag>[^@]+))?(@sha256:(?P<sha256>[A-Fa-f0-9]{64}))?$"
-            ).unwrap()
-        } else {
-            Regex::new(
-                r"^(?P<image>[^:@]+)(:(?P<tag>[^@]+))?(@sha256:(?P<sha256>[A-Fa-f0-9]{64}))?$",
-            )
-            .unwrap()
+        let (name, digest) = match orig_image.split_once('@') {
+            Some((name, digest)) => (name, Some(digest)),
+            None => (orig_image.as_str(), None),
         };
-
-        let registry = if image_has_slash {
-            url.clone()
-                .and_then(|url| {
-                    url.host()
-                        .map(|host| match host {
-                            Host::Domain(domain) => domain.into(),
-                            Host::Ipv4(address) => format!("{}", address),
-                            Host::Ipv6(address) => format!("{}", address),
-                        })
-                        .ok_or(url::ParseError::EmptyHost)
-                })
-                .and_then(|host| {
-                    url.clone().map(|url| {
-                        url.port()
-                            .map_or(host.clone(), |port| format!("{}:{}", host, port))
-                    })
-                })
-        } else {
-            Ok("docker.io".into())
+        let (name, tag) = match name.rfind(':') {
+            Some(index) if !name[index + 1..].contains('/') => {
+                (&name[..index], Some(&name[index + 1..]))
+            }
+            _ => (name, None),
         };
+        let (registry, path) = split_name(name);
+        // A colon left in the last component of the name can only come
+        // from a malformed tag, like in `nginx:1.25:alpine`
+        let last_component = &name[name.rfind('/').map(|index| index + 1).unwrap_or(0)..];
+        let tag_in_name = !last_component.starts_with('[') && last_component.contains(':');
+        if tag_in_name || !tag.map(is_valid_tag).unwrap_or(true) {
+            return Err(ImageParseError::InvalidTag(orig_image.clone()));
+        }
+        if name.len() > NAME_TOTAL_LENGTH_MAX {
+            return Err(ImageParseError::TooLong(orig_image.clone()));
+        }
+        if tag.map(str::len).unwrap_or(0) > TAG_LENGTH_MAX {
+            return Err(ImageParseError::LimitExceeded {
+                image: orig_image.clone(),
+                limit: format!("tag longer than {} characters", TAG_LENGTH_MAX),
+            });
+        }
+        if !is_valid_domain(registry) {
+            return Err(ImageParseError::InvalidHost(orig_image.clone()));
+        }
+        if let Some(limit) = exceeded_domain_limit(registry) {
+            return Err(ImageParseError::LimitExceeded {
+                image: orig_image.clone(),
+                limit,
+            });
+        }
+        if let Some(address) = registry
+            .strip_prefix('[')
+            .and_then(|registry| registry.split_once(']'))
+            .map(|(address, _)| address)
+        {
+            if address.parse::<Ipv6Addr>().is_err() {
+                return Err(ImageParseError::InvalidIpv6Address(orig_image.clone()));
+            }
+        }
+        if !path.split('/').all(is_valid_path_component) {
+            return Err(ImageParseError::InvalidRepository(orig_image.clone()));
+        }
+        if let Some(digest) = digest {
+            validate_digest(digest).map_err(|e| ImageParseError::InvalidDigest {
+                image: orig_image.clone(),
+                digest: digest.to_string(),
+                reason: e.to_string(),
+            })?;
+        }
 
-        parse_image_reference
-            .captures(&image.into())
-            .map(|captures| {
-                (
-                    captures.name("image").map(|image| image.as_str()),
-                    captures.name("tag").map(|tag| tag.as_str()),
-                    captures.name("sha256").map(|sha256| sha256.as_str()),
-                )
-            })
-            .map(|(image, tag, sha256)| Image {
-                image: orig_image,
-                name: String::from(image.unwrap_or_default()),
-                registry: registry.ok(),
-                tag: tag.map(|tag| tag.to_string()),
-                sha256: sha256.map(|sha256| sha256.to_string()),
-            })
-            .ok_or_else(|| anyhow!("could not parse {} as an image", &image))
+        let registry = normalize_registry(registry, false);
+        let repository = normalize_repository(&registry, path);
+        let tag = tag.map(String::from);
+        let digest = digest.map(String::from);
+
+        Ok(Image {
+            registry: Some(registry),
+            repository,
+            tag,
+            digest,
+            image: orig_image,
+            transport,
+        })
+    }
+}
+
+/// Validates a digest following the OCI `algorithm:encoded` syntax. The
+/// encoded part of known algorithms must be hex of the right length.
+pub(crate) fn validate_digest(digest: &str) -> Result<()> {
+    let (algorithm, encoded) = digest
+        .split_once(':')
+        .filter(|_| is_valid_digest_syntax(digest))
+        .ok_or_else(|| anyhow!("expected algorithm:encoded"))?;
+    if let Some((_, length)) = KNOWN_DIGEST_ALGORITHMS
+        .iter()
+        .find(|(known_algorithm, _)| *known_algorithm == algorithm)
+    {
+        if encoded.len() != *length || !encoded.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(anyhow!(
+                "{} digests must be {} hex characters",
+                algorithm,
+                length
+            ));
+        }
     }
+    Ok(())
+}
+
+/// Returns true when the repository path of an image entry, like
+/// `quay.io/MyOrg/app:1.0`, contains uppercase characters. Repositories
+/// are always lowercase, so such entries never match any image.
+pub(crate) fn has_uppercase_repository(entry: &str) -> bool {
+    let (_, entry) = split_transport(entry);
+    let (_, path) = split_name(entry);
+    let path = path.split(['@', ':']).next().unwrap_or(path);
+    path.chars().any(|c| c.is_ascii_uppercase())
 }
 
 #[cfg(test)]
@@ -194,35 +855,592 @@ mod tests {
     #[test]
     fn parse_image() -> Result<()> {
         let image = Image::new("image")?;
-        assert_eq!(image.name, "image");
+        assert_eq!(image.repository, "library/image");
 
         let image = Image::new("image:tag")?;
-        assert_eq!(image.name, "image");
+        assert_eq!(image.repository, "library/image");
 
         let image = Image::new("example.com/image")?;
-        assert_eq!(image.name, "image");
+        assert_eq!(image.repository, "image");
 
         let image = Image::new("example.com/image:tag")?;
-        assert_eq!(image.name, "image");
+        assert_eq!(image.repository, "image");
 
         let image = Image::new("example.com:5000/image")?;
-        assert_eq!(image.name, "image");
+        assert_eq!(image.repository, "image");
 
         let image = Image::new("example.com:5000/image:tag")?;
-        assert_eq!(image.name, "image");
+        assert_eq!(image.repository, "image");
 
         let image = Image::new("10.0.0.100/image")?;
-        assert_eq!(image.name, "image");
+        assert_eq!(image.repository, "image");
 
         let image = Image::new("10.0.0.100/image:tag")?;
-        assert_eq!(image.name, "image");
+        assert_eq!(image.repository, "image");
 
         let image = Image::new("10.0.0.100:5000/image")?;
-        assert_eq!(image.name, "image");
+        assert_eq!(image.repository, "image");
 
         let image = Image::new("10.0.0.100:5000/image:tag")?;
-        assert_eq!(image.name, "image");
+        assert_eq!(image.repository, "image");
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_repository() -> Result<()> {
+        let image = Image::new("image")?;
+        assert_eq!(image.repository, "library/image");
+
+        let image = Image::new("docker.io/image:tag")?;
+        assert_eq!(image.repository, "library/image");
+
+        let image = Image::new("docker.io/org/image:tag")?;
+        assert_eq!(image.repository, "org/image");
+
+        let image = Image::new("example.com/image:tag")?;
+        assert_eq!(image.repository, "image");
+
+        let image = Image::new("example.com:5000/org/team/image:tag@sha256:73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049")?;
+        assert_eq!(image.repository, "org/team/image");
+
+        let image = Image::new("quay.io/prometheus/prometheus@sha256:73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049")?;
+        assert_eq!(image.repository, "prometheus/prometheus");
+
+        Ok(())
+    }
+
+    #[test]
+    fn canonical_form() -> Result<()> {
+        let image: Image = "nginx:1.25".parse()?;
+        assert_eq!(image.to_string(), "docker.io/library/nginx:1.25");
+        let image: Image = "Quay.IO/prometheus/prometheus@sha256:73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049".parse()?;
+        assert_eq!(
+            image.to_string(),
+            "quay.io/prometheus/prometheus@sha256:73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049"
+        );
+
+        assert_eq!(
+            serde_json::to_value(Image::new("registry.internal:5000/app:1.0")?)?,
+            serde_json::json!("registry.internal:5000/app:1.0")
+        );
+        let image: Image = serde_json::from_value(serde_json::json!("busybox"))?;
+        assert_eq!(image.to_string(), "docker.io/library/busybox");
+        assert!(serde_json::from_value::<Image>(serde_json::json!("nginx:1.25:alpine")).is_err());
+        Ok(())
+    }
+
+    /// Parsing the canonical form of any valid reference gives back the
+    /// same image, whose canonical form is unchanged.
+    #[test]
+    fn canonical_form_round_trip() -> Result<()> {
+        let digest = "sha256:73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049";
+        let registries = [
+            "",
+            "docker.io/",
+            "index.docker.io/",
+            "Example.COM/",
+            "localhost:5000/",
+            "10.0.0.100:5000/",
+            "[2001:db8::1]:5000/",
+        ];
+        let transports = ["", "docker://", "oci://"];
+        let repositories = ["nginx", "library/nginx", "team/app", "a/b_c/d__e/f-g.h"];
+        let tags = ["", ":latest", ":v1.2.3", ":Upper_case-tag.1"];
+        let digests = ["".to_string(), format!("@{}", digest)];
+        let mut references = 0;
+        for registry in registries.iter() {
+            for repository in repositories.iter() {
+                for tag in tags.iter() {
+                    for digest in digests.iter() {
+                        let reference = format!(
+                            "{}{}{}{}{}",
+                            transports[references % transports.len()],
+                            registry,
+                            repository,
+                            tag,
+                            digest
+                        );
+                        let image: Image = reference.parse()?;
+                        let canonical: Image = image.to_string().parse()?;
+                        assert_eq!(
+                            (&canonical.transport, &canonical.registry),
+                            (&image.transport, &image.registry),
+                            "{}",
+                            reference
+                        );
+                        assert_eq!(
+                            (&canonical.repository, &canonical.tag, &canonical.digest),
+                            (&image.repository, &image.tag, &image.digest)
+                        );
+                        assert_eq!(canonical.to_string(), image.to_string());
+                        assert_eq!(canonical.to_string().parse::<Image>()?, canonical);
+                        let serialized = serde_json::to_value(&canonical)?;
+                        assert_eq!(serde_json::from_value::<Image>(serialized)?, canonical);
+                        references += 1;
+                    }
+                }
+            }
+        }
+        assert_eq!(references, 224);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_reference_grammar() {
+        // reference, registry, repository, tag, digest
+        let valid_references = [
+            ("test_com", "docker.io", "library/test_com", None, None),
+            ("test.com:tag", "docker.io", "library/test.com", Some("tag"), None),
+            ("test.com:5000", "docker.io", "library/test.com", Some("5000"), None),
+            ("test.com/repo:tag", "test.com", "repo", Some("tag"), None),
+            ("test:5000/repo", "test:5000", "repo", None, None),
+            ("test:5000/repo:tag", "test:5000", "repo", Some("tag"), None),
+            (
+                "test:5000/repo@sha256:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+                "test:5000",
+                "repo",
+                None,
+                Some("ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"),
+            ),
+            (
+                "test:5000/repo:tag@sha256:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+                "test:5000",
+                "repo",
+                Some("tag"),
+                Some("ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"),
+            ),
+            ("test:5000/repo", "test:5000", "repo", None, None),
+            ("lowercase:Uppercase", "docker.io", "library/lowercase", Some("Uppercase"), None),
+            ("sub-dom1.foo.com/bar/baz/quux", "sub-dom1.foo.com", "bar/baz/quux", None, None),
+            (
+                "sub-dom1.foo.com/bar/baz/quux:some-long-tag",
+                "sub-dom1.foo.com",
+                "bar/baz/quux",
+                Some("some-long-tag"),
+                None,
+            ),
+            (
+                "b.gcr.io/test.example.com/my-app:test.example.com",
+                "b.gcr.io",
+                "test.example.com/my-app",
+                Some("test.example.com"),
+                None,
+            ),
+            (
+                "xn--n3h.com/myimage:xn--n3h.com",
+                "xn--n3h.com",
+                "myimage",
+                Some("xn--n3h.com"),
+                None,
+            ),
+            ("foo_bar.com:8080", "docker.io", "library/foo_bar.com", Some("8080"), None),
+            ("foo/foo_bar.com:8080", "docker.io", "foo/foo_bar.com", Some("8080"), None),
+            ("localhost/foo", "localhost", "foo", None, None),
+            ("localhost:5000/foo:tag", "localhost:5000", "foo", Some("tag"), None),
+            ("library/nginx", "docker.io", "library/nginx", None, None),
+            ("bitnami/redis:7.0", "docker.io", "bitnami/redis", Some("7.0"), None),
+            ("Uppercase/lowercase:tag", "uppercase", "lowercase", Some("tag"), None),
+            ("Quay.IO/app", "quay.io", "app", None, None),
+            ("[2001:db8::1]:5000/repo:tag", "[2001:db8::1]:5000", "repo", Some("tag"), None),
+            ("[2001:db8::1]/repo", "[2001:db8::1]", "repo", None, None),
+            ("[::1]:5000/team/app", "[::1]:5000", "team/app", None, None),
+            (
+                "[2001:db8::1]:5000/app:1.0@sha256:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+                "[2001:db8::1]:5000",
+                "app",
+                Some("1.0"),
+                Some("ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"),
+            ),
+            ("a/b__c/d-e--f/g.h", "docker.io", "a/b__c/d-e--f/g.h", None, None),
+        ];
+        for (reference, registry, repository, tag, digest) in valid_references {
+            let image = Image::new(reference)
+                .unwrap_or_else(|e| panic!("cannot parse {}: {}", reference, e));
+            assert_eq!(image.registry.as_deref(), Some(registry), "{}", reference);
+            assert_eq!(image.repository, repository, "{}", reference);
+            assert_eq!(image.tag.as_deref(), tag, "{}", reference);
+            assert_eq!(
+                image.digest.as_deref(),
+                digest.map(|digest| format!("sha256:{}", digest)).as_deref(),
+                "{}",
+                reference
+            );
+        }
+
+        let invalid_references = [
+            "",
+            ":justtag",
+            "@sha256:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+            "repo@sha256:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+            "validname@sha256:ecc7f4d6e3b9c9e2b1a0e0c5d3f0a8a4",
+            "validname@:ecc7f4d6e3b9c9e2b1a0e0c5d3f0a8a4",
+            "Uppercase:tag",
+            "test:5000/Uppercase/lowercase:tag",
+            "aa/asdf$$^/aa",
+            "foo/-bar",
+            "foo//bar",
+            "foo/bar/",
+            "foo/bar:",
+            "foo/bar:-tag",
+            "foo/bar:tag:tag",
+            "-example.com/foo",
+            "example.com:port/foo",
+            "[2001:db8::1:5000/app",
+            "[2001:db8:::1]:5000/app",
+            "[fffff::1]/app",
+        ];
+        for reference in invalid_references {
+            assert!(
+                Image::new(reference).is_err(),
+                "{} should not be a valid reference",
+                reference
+            );
+        }
+    }
+
+    #[test]
+    fn uppercase_repository() {
+        assert!(has_uppercase_repository("quay.io/MyOrg/app"));
+        assert!(has_uppercase_repository("myorg/App:1.0"));
+        assert!(!has_uppercase_repository("Quay.IO/myorg/app:Latest"));
+        assert!(!has_uppercase_repository("myorg/app@sha256:ABC"));
+        assert!(!has_uppercase_repository("registry.internal:5000/app"));
+        assert!(!has_uppercase_repository("docker://Quay.IO/myorg/app"));
+        assert!(has_uppercase_repository("oci://quay.io/MyOrg/app"));
+    }
+
+    #[test]
+    fn normalize_registry_alias() -> Result<()> {
+        for reference in [
+            "index.docker.io/nginx:1.25",
+            "registry-1.docker.io/library/nginx:1.25",
+        ] {
+            let mut image = Image::new(reference)?;
+            image.normalize_registry_alias();
+            assert_eq!(image.full_repository(), "docker.io/library/nginx");
+            assert_eq!(image.image, reference);
+        }
+        let mut image = Image::new("index.docker.io/bitnami/redis")?;
+        image.normalize_registry_alias();
+        assert_eq!(image.full_repository(), "docker.io/bitnami/redis");
+        let mut image = Image::new("quay.io/nginx")?;
+        image.normalize_registry_alias();
+        assert_eq!(image.full_repository(), "quay.io/nginx");
+        Ok(())
+    }
+
+    #[test]
+    fn parse_invalid_tags() {
+        for reference in [
+            "nginx:1.25:alpine",
+            "quay.io/team/app:v1:v2@sha256:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+            "foo/bar:",
+            "foo/bar:-tag",
+            "foo/bar:.tag",
+            "foo/bar:tag$",
+            "foo/bar:v1.0é",
+        ] {
+            let error = Image::new(reference).unwrap_err();
+            assert_eq!(
+                error,
+                ImageParseError::InvalidTag(reference.to_string()),
+                "{} should have an invalid tag",
+                reference
+            );
+            assert_eq!(
+                error.to_string(),
+                format!("could not parse {} as an image: invalid tag syntax", reference)
+            );
+        }
+        assert!(Image::new("localhost:5000/app:1.0").is_ok());
+        assert_eq!(
+            Image::new("foo/-bar:tag").unwrap_err(),
+            ImageParseError::InvalidRepository("foo/-bar:tag".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_limits() {
+        let long_tag = format!("app:{}", "a".repeat(129));
+        let long_label = format!("{}.example.com/app", "a".repeat(64));
+        for (reference, limit) in [
+            (long_tag.as_str(), "tag longer than 128 characters"),
+            (
+                long_label.as_str(),
+                "registry label longer than 63 characters",
+            ),
+            (
+                "registry.example.com:65536/app",
+                "registry port greater than 65535",
+            ),
+            ("[::1]:99999/app", "registry port greater than 65535"),
+        ] {
+            let error = Image::new(reference).unwrap_err();
+            assert_eq!(
+                error,
+                ImageParseError::LimitExceeded {
+                    image: reference.to_string(),
+                    limit: limit.to_string(),
+                },
+                "{}",
+                reference
+            );
+            assert_eq!(error.reason(), limit);
+        }
+        for reference in [
+            format!("app:{}", "a".repeat(128)),
+            format!("{}.example.com/app", "a".repeat(63)),
+            "registry.example.com:65535/app".to_string(),
+            "[::1]:5000/app".to_string(),
+        ] {
+            assert!(Image::new(reference.as_str()).is_ok(), "{}", reference);
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        let long_name = format!("quay.io/{}:1.0", "a".repeat(248));
+        let long_name_with_transport = format!("docker://{}", long_name);
+        let cases = [
+            ("", ImageParseError::EmptyReference),
+            ("docker://", ImageParseError::EmptyReference),
+            (
+                long_name.as_str(),
+                ImageParseError::TooLong(long_name.clone()),
+            ),
+            (
+                long_name_with_transport.as_str(),
+                ImageParseError::TooLong(long_name.clone()),
+            ),
+            (
+                "-example.com/foo",
+                ImageParseError::InvalidHost("-example.com/foo".to_string()),
+            ),
+            (
+                "[fffff::1]/app",
+                ImageParseError::InvalidIpv6Address("[fffff::1]/app".to_string()),
+            ),
+            (
+                "oci://foo//bar",
+                ImageParseError::InvalidRepository("foo//bar".to_string()),
+            ),
+            (
+                "quay.io/app@sha256:abc",
+                ImageParseError::InvalidDigest {
+                    image: "quay.io/app@sha256:abc".to_string(),
+                    digest: "sha256:abc".to_string(),
+                    reason: "sha256 digests must be 64 hex characters".to_string(),
+                },
+            ),
+        ];
+        for (reference, error) in cases {
+            assert_eq!(Image::new(reference).unwrap_err(), error, "{}", reference);
+        }
+        assert!(Image::new(format!("quay.io/{}:1.0", "a".repeat(247)).as_str()).is_ok());
+        assert_eq!(
+            "".parse::<Image>().unwrap_err().to_string(),
+            "could not parse an empty reference as an image"
+        );
+    }
+
+    #[test]
+    fn parse_deep_repository() -> Result<()> {
+        let image = Image::new("gcr.io/my-project/team/service:1.2")?;
+        assert_eq!(image.registry, Some("gcr.io".into()));
+        assert_eq!(image.repository, "my-project/team/service");
+        assert_eq!(image.tag, Some("1.2".into()));
+        assert_eq!(image.full_repository(), "gcr.io/my-project/team/service");
+        assert_eq!(image.to_string(), "gcr.io/my-project/team/service:1.2");
+
+        let image = Image::new("nginx")?;
+        assert_eq!(image.full_repository(), "docker.io/library/nginx");
+        Ok(())
+    }
+
+    #[test]
+    fn compare_full_repository() -> Result<()> {
+        let image = Image::new("gcr.io/my-project/team/service:1.2")?;
+        assert!(image.has_full_repository("gcr.io/my-project/team/service"));
+        assert!(!image.has_full_repository("gcr.io/my-project/team"));
+        assert!(!image.has_full_repository("gcr.io/my-project/team/service/"));
+        for prefix in [
+            "",
+            "gcr",
+            "gcr.io/",
+            "gcr.io/my-project/",
+            "gcr.io/my-project/team/service/",
+        ] {
+            assert!(image.full_repository_starts_with(prefix), "{}", prefix);
+        }
+        for prefix in [
+            "quay.io/",
+            "gcr.io/my-project/team/service/app/",
+            "gcr.io//",
+        ] {
+            assert!(!image.full_repository_starts_with(prefix), "{}", prefix);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn image_matches() -> Result<()> {
+        let matches = |image: &Image, entry: &str| ImagePattern::new(entry).matches(image);
+        let image = Image::new("gcr.io/my-project/team/service:1.2")?;
+        assert!(matches(&image, "gcr.io/my-project/team/service:1.2"));
+        assert!(matches(&image, "gcr.io/my-project/team/service"));
+        assert!(matches(&image, "gcr.io/my-project/"));
+        assert!(matches(&image, "gcr.io/my-project/team/"));
+        assert!(matches(&image, "gcr.io/"));
+        assert!(!matches(&image, "gcr.io/my-project/team/service:1.3"));
+        assert!(!matches(&image, "gcr.io/my-project/team"));
+        assert!(!matches(&image, "gcr.io/my-proj/"));
+        assert!(!matches(&image, "quay.io/my-project/"));
+
+        assert!(matches(&image, "gcr.io/my-project/*"));
+        assert!(matches(&image, "gcr.io/my-project/team/*"));
+        assert!(matches(&image, "gcr.io/*"));
+        assert!(!matches(&image, "gcr.io/my-proj/*"));
+        assert!(!matches(&image, "gcr.io/my-project/team/serv/*"));
+        let image = Image::new("myorg/app:1.0")?;
+        assert!(matches(&image, "myorg/*"));
+        assert!(matches(&image, "docker.io/myorg/*"));
+        assert!(matches(&image, "docker.io/*"));
+        assert!(!matches(&image, "myor/*"));
+        assert!(matches(&image, "Docker.IO/myorg/*"));
+        assert!(matches(&image, "Docker.IO/myorg/app"));
+        assert!(!matches(&image, "library/*"));
+
+        assert!(matches(&image, "docker.io/myorg/app:1.0"));
+        assert!(matches(&image, "myorg/app:1.*"));
+        assert!(matches(&image, "myorg/app:*"));
+        assert!(!matches(&image, "myorg/app:2.*"));
+        assert!(!matches(&image, "myorg/app:1.0-*"));
+
+        let digest = "sha256:7ed2739c96eb16de3d7169e2a0aa4ccf3a1f44af24f2bb6cad826935a51bcb3d";
+        let other_digest =
+            "sha256:82dfd9ac433eacb5f89e5bf2601659bbc78893c1a9e3e830c5ef4eb489fde079";
+        let image = Image::new(format!("quay.io/org/app:1.2@{}", digest).as_str())?;
+        assert!(matches(&image, "quay.io/org/app"));
+        assert!(matches(&image, "quay.io/org/app:1.2"));
+        assert!(matches(&image, &format!("quay.io/org/app@{}", digest)));
+        assert!(matches(&image, &format!("quay.io/org/app:1.*@{}", digest)));
+        assert!(!matches(
+            &image,
+            &format!("quay.io/org/app@{}", other_digest)
+        ));
+        assert!(!matches(&image, &format!("quay.io/org/app:2.*@{}", digest)));
+        assert!(!matches(&image, "quay.io/org/app@sha256:invalid"));
+        let image = Image::new(format!("quay.io/org/app@{}", digest).as_str())?;
+        assert!(!matches(&image, "quay.io/org/app:*"));
+        let image = Image::new("quay.io/org/app")?;
+        assert!(matches(&image, "quay.io/org/app:latest"));
+        let image = Image::new("localhost:5000/app:1.0")?;
+        assert!(matches(&image, "localhost:5000/app:1.0"));
+        assert!(matches(&image, "localhost:5000/app"));
+        assert!(!matches(&image, "localhost/app:1.0"));
+
+        let image = Image::new("myorg/app:1.0")?;
+        assert!(matches(&image, "docker://myorg/*"));
+        assert!(!matches(&image, "oci://myorg/*"));
+        let image = Image::new("oci://quay.io/myorg/app:1.0")?;
+        assert!(matches(&image, "quay.io/myorg/*"));
+        assert!(matches(&image, "oci://quay.io/myorg/app:1.0"));
+        assert!(matches(&image, "oci://quay.io/myorg/app"));
+        assert!(!matches(&image, "docker://quay.io/myorg/*"));
+        Ok(())
+    }
+
+    #[test]
+    fn fully_qualified() -> Result<()> {
+        let digest = "sha256:73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049";
+        for (reference, fully_qualified) in [
+            ("nginx", "docker.io/library/nginx:latest".to_string()),
+            ("nginx:1.25", "docker.io/library/nginx:1.25".to_string()),
+            ("myorg/app", "docker.io/myorg/app:latest".to_string()),
+            ("quay.io/app:1.0", "quay.io/app:1.0".to_string()),
+            (
+                &format!("nginx@{}", digest),
+                format!("docker.io/library/nginx@{}", digest),
+            ),
+            (
+                &format!("quay.io/app:1.0@{}", digest),
+                format!("quay.io/app:1.0@{}", digest),
+            ),
+        ] {
+            assert_eq!(Image::new(reference)?.fully_qualified(), fully_qualified);
+        }
+        for (reference, explicit_registry) in [
+            ("nginx:1.25", false),
+            ("myorg/app:1.0", false),
+            ("docker.io/nginx:1.25", true),
+            ("localhost/app", true),
+            ("registry.my-corp.com:5000/app", true),
+            ("docker://quay.io/app:1.0", true),
+        ] {
+            assert_eq!(
+                Image::new(reference)?.has_explicit_registry(),
+                explicit_registry,
+                "{}",
+                reference
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parse_transport() -> Result<()> {
+        let image = Image::new("docker://quay.io/app:1.0")?;
+        assert_eq!(image.transport, Some(Transport::Docker));
+        assert_eq!(image.image, "quay.io/app:1.0");
+        assert_eq!(image.full_repository(), "quay.io/app");
+        assert_eq!(image.to_string(), "docker://quay.io/app:1.0");
+
+        let image = Image::new("oci://nginx")?;
+        assert_eq!(image.transport, Some(Transport::Oci));
+        assert_eq!(image.full_repository(), "docker.io/library/nginx");
+        assert_eq!(image.to_string(), "oci://docker.io/library/nginx");
+
+        assert_eq!(Image::new("quay.io/app")?.transport, None);
+        assert!(Image::new("docker://").is_err());
+        assert!(Image::new("https://quay.io/app").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_digest() -> Result<()> {
+        let image = Image::new("example.com/image@sha512:ebc5c1eee6b4bd1e2b0c4e5d3b0e4e0eebc5c1eee6b4bd1e2b0c4e5d3b0e4e0eebc5c1eee6b4bd1e2b0c4e5d3b0e4e0eebc5c1eee6b4bd1e2b0c4e5d3b0e4e0e")?;
+        assert_eq!(
+            image.digest,
+            Some("sha512:ebc5c1eee6b4bd1e2b0c4e5d3b0e4e0eebc5c1eee6b4bd1e2b0c4e5d3b0e4e0eebc5c1eee6b4bd1e2b0c4e5d3b0e4e0eebc5c1eee6b4bd1e2b0c4e5d3b0e4e0e".into())
+        );
+
+        let image = Image::new(
+            "example.com/image:tag@multihash+base58:QmRZxt2b1FVZPNqd8hsiykDL3TdBDeTSPX9Kv46HmX4Gx8",
+        )?;
+        assert_eq!(
+            image.digest,
+            Some("multihash+base58:QmRZxt2b1FVZPNqd8hsiykDL3TdBDeTSPX9Kv46HmX4Gx8".into())
+        );
 
+        assert_eq!(
+            Image::new("example.com/image@sha512:abc")
+                .unwrap_err()
+                .to_string(),
+            "could not parse example.com/image@sha512:abc as an image: malformed digest sha512:abc: sha512 digests must be 128 hex characters"
+        );
+        assert_eq!(
+            Image::new("example.com/image@sha256:zz5c1eee6b4bd1e2b0c4e5d3b0e4e0e7e6f1b7c0c1a4d2f3e5a7b9c1d3e5f7a9")
+                .unwrap_err()
+                .to_string(),
+            "could not parse example.com/image@sha256:zz5c1eee6b4bd1e2b0c4e5d3b0e4e0e7e6f1b7c0c1a4d2f3e5a7b9c1d3e5f7a9 as an image: malformed digest sha256:zz5c1eee6b4bd1e2b0c4e5d3b0e4e0e7e6f1b7c0c1a4d2f3e5a7b9c1d3e5f7a9: sha256 digests must be 64 hex characters"
+        );
+        assert_eq!(
+            Image::new("example.com/image@SHA256:abc")
+                .unwrap_err()
+                .to_string(),
+            "could not parse example.com/image@SHA256:abc as an image: malformed digest SHA256:abc: expected algorithm:encoded"
+        );
         Ok(())
     }
 }
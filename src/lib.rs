@@ -1,66 +1,144 @@
+#[cfg(feature = "policy")]
 use guest::prelude::*;
+#[cfg(feature = "policy")]
 use kubewarden_policy_sdk::wapc_guest as guest;
 
 extern crate kubewarden_policy_sdk as kubewarden;
-use kubewarden::{protocol_version_guest, request::ValidationRequest, validate_settings};
+#[cfg(feature = "policy")]
+use kubewarden::{
+    protocol_version_guest,
+    response::ValidationResponse,
+    settings::{SettingsValidationResponse, Validatable},
+};
 
+#[cfg(feature = "regex")]
 extern crate regex;
-extern crate url;
-
-use k8s_openapi::api::core::v1 as apicore;
 
 mod settings;
+#[cfg(feature = "policy")]
 use settings::Settings;
 
 mod image;
 use image::Image;
 
-use settings::PodEvaluationResult;
+mod evaluation;
+#[cfg(feature = "policy")]
+use evaluation::{evaluate, Decision};
+
+mod context;
+mod duration;
+mod exception;
+mod extract;
+mod logging;
+mod matcher;
+mod migration;
+mod normalize;
+mod oci;
+
+mod pattern;
+mod registry;
+mod review;
+#[cfg(feature = "policy")]
+use review::parse_validation_request;
+mod rule;
+mod scan;
+mod selector;
+mod tag;
+mod template;
+mod verification;
+
+#[cfg(feature = "lib")]
+pub mod api;
+#[cfg(feature = "benchmarking")]
+pub mod benchmarking;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
 
+// The entry points are only built with the policy feature, so that the
+// policies depending on this crate can register their own
+#[cfg(feature = "policy")]
 #[no_mangle]
 pub extern "C" fn wapc_init() {
     register_function("validate", validate);
-    register_function("validate_settings", validate_settings::<Settings>);
+    register_function("validate_settings", validate_settings);
+    register_function("settings_schema", settings_schema);
     register_function("protocol_version", protocol_version_guest);
 }
 
-fn validate(payload: &[u8]) -> CallResult {
-    let validation_request: ValidationRequest<Settings> = ValidationRequest::new(payload)?;
-
-    match serde_json::from_value::<apicore::Pod>(validation_request.request.object) {
-        Ok(pod) => match validation_request.settings.is_pod_accepted(&pod) {
-            PodEvaluationResult::Allowed => kubewarden::accept_request(),
-            PodEvaluationResult::NotAllowed(rejection_reasons) => {
-                let mut errors = Vec::new();
-                if !rejection_reasons.registries_not_allowed.is_empty() {
-                    errors.push(format!(
-                        "registries not allowed: {}",
-                        rejection_reasons.registries_not_allowed.join(", ")
-                    ));
-                }
-                if !rejection_reasons.tags_not_allowed.is_empty() {
-                    errors.push(format!(
-                        "tags not allowed: {}",
-                        rejection_reasons.tags_not_allowed.join(", ")
-                    ))
-                }
-                if !rejection_reasons.images_not_allowed.is_empty() {
-                    errors.push(format!(
-                        "images not allowed: {}",
-                        rejection_reasons.images_not_allowed.join(", ")
-                    ))
-                }
-                kubewarden::reject_request(
-                    Some(format!(
-                        "not allowed, reported errors: {}",
-                        errors.join("; ")
-                    )),
-                    None,
-                    None,
-                    None,
-                )
+#[cfg(feature = "policy")]
+fn validate_settings(payload: &[u8]) -> CallResult {
+    let response = match Settings::from_slice(payload)
+        .and_then(|settings| settings.validate().map(|_| settings))
+    {
+        Ok(settings) => {
+            let warnings = settings.warnings();
+            let log = settings.logger();
+            for warning in &warnings {
+                slog::warn!(log, "settings warning"; "warning" => warning);
             }
+            SettingsValidationResponse {
+                valid: true,
+                message: if warnings.is_empty() {
+                    None
+                } else {
+                    Some(format!("warnings: {}", warnings.join("; ")))
+                },
+            }
+        }
+        Err(e) => SettingsValidationResponse {
+            valid: false,
+            message: Some(e),
         },
-        Err(_) => kubewarden::accept_request(),
+    };
+
+    Ok(serde_json::to_vec(&response)?)
+}
+
+#[cfg(feature = "policy")]
+fn settings_schema(_payload: &[u8]) -> CallResult {
+    Ok(serde_json::to_vec(&Settings::json_schema())?)
+}
+
+#[cfg(feature = "policy")]
+fn validate(payload: &[u8]) -> CallResult {
+    let validation_request = parse_validation_request(payload)?;
+    let mut settings = Settings::from_value(validation_request.settings)
+        .map_err(|e| anyhow::anyhow!("invalid settings: {}", e))?;
+
+    if settings.is_operation_enforced(&validation_request.request.operation)
+        && settings.is_resource_evaluated(&validation_request.request.kind)
+    {
+        if let Err(e) = context::merge_cluster_settings(&mut settings) {
+            // Fail closed: pods cannot be evaluated without the rules
+            // stored in the cluster
+            return kubewarden::reject_request(Some(e), None, None, None);
+        }
     }
+
+    let evaluation = evaluate(&settings, &validation_request.request)?;
+    let warnings = if evaluation.warnings.is_empty() {
+        None
+    } else {
+        Some(evaluation.warnings)
+    };
+    let audit_annotations = if evaluation.audit_annotations.is_empty() {
+        None
+    } else {
+        Some(evaluation.audit_annotations.into_iter().collect())
+    };
+    // The accept and mutate helpers of the SDK cannot carry audit
+    // annotations nor warnings, so the response is built here
+    let (accepted, message, mutated_object) = match evaluation.decision {
+        Decision::Accept => (true, None, None),
+        Decision::Mutate(object) => (true, None, Some(object)),
+        Decision::Reject(message) => (false, Some(message), None),
+    };
+    Ok(serde_json::to_vec(&ValidationResponse {
+        accepted,
+        message,
+        code: None,
+        mutated_object,
+        audit_annotations,
+        warnings,
+    })?)
 }
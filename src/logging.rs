@@ -0,0 +1,48 @@
+use kubewarden::logging::KubewardenDrain;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slog::{o, Drain, Level, Logger};
+
+/// Most verbose level of the events logged by the policy.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum LogLevel {
+    Error,
+    Warning,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for Level {
+    fn from(level: LogLevel) -> Level {
+        match level {
+            LogLevel::Error => Level::Error,
+            LogLevel::Warning => Level::Warning,
+            LogLevel::Info => Level::Info,
+            LogLevel::Debug => Level::Debug,
+            LogLevel::Trace => Level::Trace,
+        }
+    }
+}
+
+/// Builds a logger sending the events up to `level` to the host, which
+/// adds them to the logs of the policy server.
+pub(crate) fn logger(level: LogLevel) -> Logger {
+    let drain = KubewardenDrain::new().filter_level(level.into()).fuse();
+    Logger::root(drain, o!("policy" => "trusted-repos"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_levels() {
+        let level: LogLevel = serde_json::from_str(r#""warning""#).expect("cannot parse level");
+        assert_eq!(Level::from(level), Level::Warning);
+        assert_eq!(LogLevel::default(), LogLevel::Info);
+        assert!(serde_json::from_str::<LogLevel>(r#""verbose""#).is_err());
+    }
+}
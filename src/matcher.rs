@@ -0,0 +1,132 @@
+#[cfg(feature = "regex")]
+use regex::RegexBuilder;
+
+use crate::pattern::glob_matches;
+
+/// Engine matching the patterns of the `prefix`, `glob` and `regex`
+/// rules against whole values.
+pub(crate) trait PatternEngine: Sized {
+    fn compile(pattern: &str) -> Result<Self, String>;
+    fn is_match(&self, value: &str) -> bool;
+    /// Pattern the engine was compiled from.
+    fn as_str(&self) -> &str;
+}
+
+/// Glob pattern, where `*` matches any sequence of characters.
+#[derive(Debug)]
+pub(crate) struct Glob(String);
+
+impl PatternEngine for Glob {
+    fn compile(pattern: &str) -> Result<Self, String> {
+        Ok(Glob(pattern.to_string()))
+    }
+
+    fn is_match(&self, value: &str) -> bool {
+        glob_matches(&self.0, value)
+    }
+
+    fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Path prefix, matching the values equal to it, or continuing it with
+/// a `/`, a `:` or a `@`: `quay.io/org` matches `quay.io/org/app` and
+/// `quay.io/org:1.0`, but not `quay.io/organization`.
+#[derive(Debug)]
+pub(crate) struct Prefix(String);
+
+impl PatternEngine for Prefix {
+    fn compile(pattern: &str) -> Result<Self, String> {
+        Ok(Prefix(pattern.trim_end_matches('/').to_string()))
+    }
+
+    fn is_match(&self, value: &str) -> bool {
+        match value.strip_prefix(self.0.as_str()) {
+            Some(rest) => rest.is_empty() || rest.starts_with(['/', ':', '@']),
+            None => false,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Longest regular expression accepted in the rules.
+#[cfg(feature = "regex")]
+const MAX_REGEX_LENGTH: usize = 1024;
+
+/// Deepest nesting of groups and repetitions accepted in the regular
+/// expressions of the rules.
+#[cfg(feature = "regex")]
+const MAX_REGEX_NESTING: u32 = 32;
+
+/// Largest size, in bytes, of the compiled regular expressions of the
+/// rules, and of the cache of their lazy DFA. Counted repetitions, like
+/// `(a{100}){100}`, can otherwise take megabytes of memory.
+#[cfg(feature = "regex")]
+const MAX_REGEX_SIZE: usize = 1 << 20;
+
+/// Regular expression matching the whole value. Matching takes linear
+/// time, but the patterns are untrusted, so their length, nesting and
+/// compiled size are bounded.
+#[cfg(feature = "regex")]
+#[derive(Debug)]
+pub(crate) struct Regex {
+    regex: regex::Regex,
+    pattern: String,
+}
+
+#[cfg(feature = "regex")]
+impl PatternEngine for Regex {
+    fn compile(pattern: &str) -> Result<Self, String> {
+        if pattern.len() > MAX_REGEX_LENGTH {
+            return Err(format!("longer than {} characters", MAX_REGEX_LENGTH));
+        }
+        RegexBuilder::new(&format!("^(?:{})$", pattern))
+            .nest_limit(MAX_REGEX_NESTING)
+            .size_limit(MAX_REGEX_SIZE)
+            .dfa_size_limit(MAX_REGEX_SIZE)
+            .build()
+            .map(|regex| Regex {
+                regex,
+                pattern: pattern.to_string(),
+            })
+            .map_err(|e| match e {
+                regex::Error::CompiledTooBig(limit) => {
+                    format!("compiled regex exceeds the limit of {} bytes", limit)
+                }
+                e => e.to_string(),
+            })
+    }
+
+    fn is_match(&self, value: &str) -> bool {
+        self.regex.is_match(value)
+    }
+
+    fn as_str(&self) -> &str {
+        &self.pattern
+    }
+}
+
+/// Builds without the `regex` feature only match globs: the `regex`
+/// rules are rejected when the settings are validated.
+#[cfg(not(feature = "regex"))]
+#[derive(Debug)]
+pub(crate) enum Regex {}
+
+#[cfg(not(feature = "regex"))]
+impl PatternEngine for Regex {
+    fn compile(_: &str) -> Result<Self, String> {
+        Err("regex rules are not supported by this build of the policy".to_string())
+    }
+
+    fn is_match(&self, _: &str) -> bool {
+        match *self {}
+    }
+
+    fn as_str(&self) -> &str {
+        match *self {}
+    }
+}
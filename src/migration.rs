@@ -0,0 +1,149 @@
+use serde_json::{Map, Value};
+
+/// Version of the settings format used by the matchers.
+pub(crate) const CURRENT_API_VERSION: &str = "v1";
+
+/// Version assumed for settings that do not provide an `apiVersion`.
+const DEFAULT_API_VERSION: &str = "v1";
+
+type RawSettings = Map<String, Value>;
+
+/// A migration converts settings from the `from` version into the next
+/// version of the format, `to`.
+struct Migration {
+    from: &'static str,
+    to: &'static str,
+    migrate: fn(RawSettings) -> Result<RawSettings, String>,
+}
+
+/// Migrations between settings versions. Every time the format of the
+/// settings changes in a non backwards compatible way, a new version
+/// is introduced, together with the migration from the previous one.
+const MIGRATIONS: &[Migration] = &[];
+
+/// Converts the raw settings, whatever their `apiVersion`, into the
+/// current settings format.
+pub(crate) fn migrate(settings: Value) -> Result<Value, String> {
+    migrate_with(settings, MIGRATIONS)
+}
+
+/// Converts the raw settings into the current settings format, applying
+/// the given migrations one after the other.
+fn migrate_with(settings: Value, migrations: &[Migration]) -> Result<Value, String> {
+    let mut settings: RawSettings = match settings {
+        Value::Null => Map::new(),
+        Value::Object(settings) => settings,
+        _ => return Err("settings must be an object".to_string()),
+    };
+
+    let mut api_version = match settings.get("apiVersion") {
+        None => DEFAULT_API_VERSION.to_string(),
+        Some(Value::String(api_version)) => api_version.clone(),
+        Some(_) => return Err("settings apiVersion must be a string".to_string()),
+    };
+    while api_version != CURRENT_API_VERSION {
+        let migration = migrations
+            .iter()
+            .find(|migration| migration.from == api_version)
+            .ok_or_else(|| format!("unsupported settings apiVersion {}", api_version))?;
+        settings = (migration.migrate)(settings).map_err(|e| {
+            format!(
+                "cannot migrate settings from {} to {}: {}",
+                migration.from, migration.to, e
+            )
+        })?;
+        api_version = migration.to.to_string();
+    }
+
+    settings.insert(
+        "apiVersion".to_string(),
+        Value::String(CURRENT_API_VERSION.to_string()),
+    );
+    Ok(Value::Object(settings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn migrate_unversioned_settings() {
+        assert_eq!(
+            migrate(json!({"registries": {"allow": ["quay.io"]}})),
+            Ok(json!({"apiVersion": "v1", "registries": {"allow": ["quay.io"]}}))
+        );
+        assert_eq!(migrate(Value::Null), Ok(json!({"apiVersion": "v1"})));
+    }
+
+    #[test]
+    fn migrate_current_settings() {
+        assert_eq!(
+            migrate(json!({"apiVersion": "v1", "tags": {"reject": ["latest"]}})),
+            Ok(json!({"apiVersion": "v1", "tags": {"reject": ["latest"]}}))
+        );
+    }
+
+    /// Renames the `registryList` of the v0 settings to the `allow` list
+    /// of `registries`.
+    fn migrate_v0(mut settings: RawSettings) -> Result<RawSettings, String> {
+        if let Some(registry_list) = settings.remove("registryList") {
+            if !registry_list.is_array() {
+                return Err("registryList must be a list".to_string());
+            }
+            settings.insert("registries".to_string(), json!({ "allow": registry_list }));
+        }
+        Ok(settings)
+    }
+
+    const TEST_MIGRATIONS: &[Migration] = &[Migration {
+        from: "v0",
+        to: "v1",
+        migrate: migrate_v0,
+    }];
+
+    #[test]
+    fn migrate_previous_settings() {
+        assert_eq!(
+            migrate_with(
+                json!({"apiVersion": "v0", "registryList": ["quay.io"]}),
+                TEST_MIGRATIONS
+            ),
+            Ok(json!({"apiVersion": "v1", "registries": {"allow": ["quay.io"]}}))
+        );
+        assert_eq!(
+            migrate_with(
+                json!({"apiVersion": "v1", "registryList": ["quay.io"]}),
+                TEST_MIGRATIONS
+            ),
+            Ok(json!({"apiVersion": "v1", "registryList": ["quay.io"]}))
+        );
+        assert_eq!(
+            migrate_with(
+                json!({"apiVersion": "v0", "registryList": "quay.io"}),
+                TEST_MIGRATIONS
+            ),
+            Err("cannot migrate settings from v0 to v1: registryList must be a list".to_string())
+        );
+        assert_eq!(
+            migrate_with(json!({"apiVersion": "v2"}), TEST_MIGRATIONS),
+            Err("unsupported settings apiVersion v2".to_string())
+        );
+    }
+
+    #[test]
+    fn migrate_unsupported_settings() {
+        assert_eq!(
+            migrate(json!({"apiVersion": "v0"})),
+            Err("unsupported settings apiVersion v0".to_string())
+        );
+        assert_eq!(
+            migrate(json!({"apiVersion": 1})),
+            Err("settings apiVersion must be a string".to_string())
+        );
+        assert_eq!(
+            migrate(json!([])),
+            Err("settings must be an object".to_string())
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use crate::image::{is_docker_hub_alias, DEFAULT_REGISTRY};
+
+/// Returns true when the first component of an image name is a domain.
+pub(crate) fn is_domain(component: &str) -> bool {
+    component.contains(['.', ':'])
+        || component == "localhost"
+        || component.chars().any(|c| c.is_ascii_uppercase())
+}
+
+/// Splits an image name, without tag nor digest, into its registry and
+/// repository path. Names whose first component is not a domain, like
+/// `nginx` or `bitnami/redis`, are hosted on the Docker Hub.
+pub(crate) fn split_name(name: &str) -> (&str, &str) {
+    match name.split_once('/') {
+        Some((domain, path)) if is_domain(domain) => (domain, path),
+        _ => (DEFAULT_REGISTRY, name),
+    }
+}
+
+/// Normal form of a registry, which is lowercase since hosts are case
+/// insensitive. The aliases of the Docker Hub are replaced by
+/// `DEFAULT_REGISTRY` when `registry_aliases` is true.
+pub(crate) fn normalize_registry(registry: &str, registry_aliases: bool) -> String {
+    let registry = registry.to_ascii_lowercase();
+    if registry_aliases && is_docker_hub_alias(&registry) {
+        DEFAULT_REGISTRY.to_string()
+    } else {
+        registry
+    }
+}
+
+/// Normal form of a repository path on a normalized registry: the
+/// official images of the Docker Hub, like `nginx`, get the `library/`
+/// prefix.
+pub(crate) fn normalize_repository(registry: &str, path: &str) -> String {
+    if registry == DEFAULT_REGISTRY && !path.contains('/') {
+        format!("library/{}", path)
+    } else {
+        path.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normal_form() {
+        for (name, aliases, registry, repository) in [
+            ("nginx", true, "docker.io", "library/nginx"),
+            ("library/nginx", true, "docker.io", "library/nginx"),
+            ("docker.io/nginx", true, "docker.io", "library/nginx"),
+            ("Docker.IO/nginx", true, "docker.io", "library/nginx"),
+            ("index.docker.io/nginx", true, "docker.io", "library/nginx"),
+            ("index.docker.io/nginx", false, "index.docker.io", "nginx"),
+            ("bitnami/redis", true, "docker.io", "bitnami/redis"),
+            ("Quay.IO/org/app", true, "quay.io", "org/app"),
+            ("localhost/app", true, "localhost", "app"),
+        ] {
+            let (domain, path) = split_name(name);
+            let normalized_registry = normalize_registry(domain, aliases);
+            assert_eq!(normalized_registry, registry, "{}", name);
+            assert_eq!(
+                normalize_repository(&normalized_registry, path),
+                repository,
+                "{}",
+                name
+            );
+        }
+    }
+}
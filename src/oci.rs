@@ -0,0 +1,382 @@
+use anyhow::{anyhow, Result};
+use k8s_openapi::chrono::DateTime;
+use kubewarden::host_capabilities::oci::ManifestDigestResponse;
+use kubewarden_policy_sdk::wapc_guest;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+/// Fragments of the errors of the host telling that the manifest, or
+/// the repository, does not exist, as opposed to the registry not being
+/// reachable.
+const NOT_FOUND_ERRORS: &[&str] = &[
+    "manifest unknown",
+    "manifest_unknown",
+    "manifestunknown",
+    "name unknown",
+    "name_unknown",
+    "nameunknown",
+    "not found",
+];
+
+/// Time during which a resolved digest is reused instead of querying
+/// the registry again.
+const DIGEST_CACHE_TTL: Duration = Duration::from_secs(60);
+
+thread_local! {
+    static DIGEST_CACHE: RefCell<DigestCache> = RefCell::new(DigestCache::new(DIGEST_CACHE_TTL));
+}
+
+/// Digests resolved by this instance of the policy, by image
+/// reference.
+struct DigestCache {
+    ttl: Duration,
+    entries: HashMap<String, (String, SystemTime)>,
+}
+
+impl DigestCache {
+    fn new(ttl: Duration) -> DigestCache {
+        DigestCache {
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    fn get(&self, image: &str, now: SystemTime) -> Option<&String> {
+        self.entries
+            .get(image)
+            .filter(|(_, expires_at)| now < *expires_at)
+            .map(|(digest, _)| digest)
+    }
+
+    /// Caches the digest of the image, dropping the expired entries so
+    /// that the cache does not grow unbounded.
+    fn insert(&mut self, image: &str, digest: String, now: SystemTime) {
+        self.entries.retain(|_, (_, expires_at)| now < *expires_at);
+        self.entries
+            .insert(image.to_string(), (digest, now + self.ttl));
+    }
+}
+
+/// Resolves the digest of the manifest referenced by `image` through
+/// the OCI capability of the host.
+pub(crate) fn resolve_digest(image: &str) -> Result<String> {
+    let now = SystemTime::now();
+    if let Some(digest) = DIGEST_CACHE.with(|cache| cache.borrow().get(image, now).cloned()) {
+        return Ok(digest);
+    }
+    let msg = serde_json::to_vec(&serde_json::json!(image))?;
+    // The error of the host is displayed, and not debugged like the
+    // SDK does, to keep its message readable
+    let response = wapc_guest::host_call("kubewarden", "oci", "v1/manifest_digest", &msg)
+        .map_err(|e| anyhow!("error invoking wapc oci.manifest_digest: {}", e))?;
+    let digest = serde_json::from_slice::<ManifestDigestResponse>(&response)?.digest;
+    DIGEST_CACHE.with(|cache| cache.borrow_mut().insert(image, digest.clone(), now));
+    Ok(digest)
+}
+
+/// Looks `image` up in its registry, returning false when its manifest
+/// cannot be found, and an error when the registry cannot be queried.
+pub(crate) fn image_exists(image: &str) -> Result<bool> {
+    match resolve_digest(image) {
+        Ok(_) => Ok(true),
+        Err(e) if is_not_found_error(&e.to_string()) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns true when the error of the host tells that the image does not
+/// exist.
+fn is_not_found_error(error: &str) -> bool {
+    let error = error.to_lowercase();
+    NOT_FOUND_ERRORS
+        .iter()
+        .any(|fragment| error.contains(fragment))
+}
+
+/// Platform an image is built for, written like `linux/arm64/v8`. The
+/// variant is optional.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Platform {
+    pub(crate) os: String,
+    pub(crate) architecture: String,
+    #[serde(default)]
+    pub(crate) variant: Option<String>,
+}
+
+impl Platform {
+    pub(crate) fn parse(platform: &str) -> Result<Platform, String> {
+        let mut parts = platform.split('/');
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(os), Some(architecture), variant, None)
+                if !os.is_empty()
+                    && !architecture.is_empty()
+                    && variant.map(|variant| !variant.is_empty()).unwrap_or(true) =>
+            {
+                Ok(Platform {
+                    os: os.to_string(),
+                    architecture: architecture.to_string(),
+                    variant: variant.map(String::from),
+                })
+            }
+            _ => Err(format!(
+                "platform {} must be written like os/architecture or os/architecture/variant",
+                platform
+            )),
+        }
+    }
+
+    /// Returns true when `other` is this platform. A platform without
+    /// variant matches all the variants of its architecture.
+    pub(crate) fn matches(&self, other: &Platform) -> bool {
+        self.os == other.os
+            && self.architecture == other.architecture
+            && (self.variant.is_none() || self.variant == other.variant)
+    }
+}
+
+#[derive(Deserialize)]
+struct ImageIndex {
+    #[serde(default)]
+    manifests: Vec<IndexEntry>,
+}
+
+#[derive(Deserialize)]
+struct IndexEntry {
+    platform: Option<Platform>,
+}
+
+/// Fetches the manifest referenced by `image` through the OCI
+/// capability of the host. The SDK only wraps `v1/manifest_digest`, so
+/// the `v1/oci_manifest` operation, available from Kubewarden 1.10, is
+/// called directly.
+fn oci_manifest<T: DeserializeOwned>(image: &str) -> Result<T> {
+    let msg = serde_json::to_vec(&serde_json::json!(image))?;
+    let response = wapc_guest::host_call("kubewarden", "oci", "v1/oci_manifest", &msg)
+        .map_err(|e| anyhow!("error invoking wapc oci.oci_manifest: {}", e))?;
+    Ok(serde_json::from_slice(&response)?)
+}
+
+/// Returns the platforms listed by the manifest list, or image index,
+/// referenced by `image`. Images referencing a single manifest have no
+/// platforms listed.
+pub(crate) fn image_platforms(image: &str) -> Result<Vec<Platform>> {
+    let index: ImageIndex = oci_manifest(image)?;
+    Ok(index
+        .manifests
+        .into_iter()
+        .filter_map(|entry| entry.platform)
+        .collect())
+}
+
+#[derive(Deserialize)]
+struct ImageManifest {
+    #[serde(default)]
+    layers: Vec<Descriptor>,
+}
+
+#[derive(Deserialize)]
+struct Descriptor {
+    #[serde(default)]
+    annotations: BTreeMap<String, String>,
+}
+
+/// Returns the reference of the attestations attached by cosign to the
+/// image with the given repository and digest.
+pub(crate) fn attestation_reference(repository: &str, digest: &str) -> String {
+    format!("{}:{}.att", repository, digest.replacen(':', "-", 1))
+}
+
+/// Returns the predicate types of the attestations at the given
+/// reference, see `attestation_reference`. Images without attestations
+/// have none, and an error is returned when the registry cannot be
+/// queried.
+pub(crate) fn attestation_predicate_types(reference: &str) -> Result<Vec<String>> {
+    match oci_manifest::<ImageManifest>(reference) {
+        Ok(manifest) => Ok(predicate_types(manifest.layers)),
+        Err(e) if is_not_found_error(&e.to_string()) => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn predicate_types(layers: Vec<Descriptor>) -> Vec<String> {
+    layers
+        .into_iter()
+        .filter_map(|mut layer| layer.annotations.remove("predicateType"))
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct ManifestAndConfig {
+    config: ImageConfiguration,
+}
+
+#[derive(Deserialize)]
+struct ImageConfiguration {
+    #[serde(default)]
+    created: Option<String>,
+    #[serde(default)]
+    config: Option<ExecutionConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ExecutionConfig {
+    #[serde(default)]
+    user: Option<String>,
+}
+
+/// Fetches the OCI configuration of the image referenced by `image`
+/// through the OCI capability of the host. Like `oci_manifest`, the
+/// `v1/oci_manifest_config` operation, available from Kubewarden 1.11,
+/// is called directly.
+fn image_configuration(image: &str) -> Result<ImageConfiguration> {
+    let msg = serde_json::to_vec(&serde_json::json!(image))?;
+    let response = wapc_guest::host_call("kubewarden", "oci", "v1/oci_manifest_config", &msg)
+        .map_err(|e| anyhow!("error invoking wapc oci.oci_manifest_config: {}", e))?;
+    let response: ManifestAndConfig = serde_json::from_slice(&response)?;
+    Ok(response.config)
+}
+
+/// Returns the user the image runs as by default, according to its
+/// OCI configuration. None when the configuration sets no user.
+pub(crate) fn image_user(image: &str) -> Result<Option<String>> {
+    Ok(image_configuration(image)?
+        .config
+        .and_then(|config| config.user)
+        .filter(|user| !user.is_empty()))
+}
+
+/// Returns the creation time of the image, according to its OCI
+/// configuration.
+pub(crate) fn image_created(image: &str) -> Result<SystemTime> {
+    let created = image_configuration(image)?
+        .created
+        .ok_or_else(|| anyhow!("image {} has no creation time", image))?;
+    parse_created(&created)
+}
+
+fn parse_created(created: &str) -> Result<SystemTime> {
+    Ok(DateTime::parse_from_rfc3339(created)
+        .map_err(|e| anyhow!("invalid creation time {}: {}", created, e))?
+        .into())
+}
+
+/// Returns true when the user of an image configuration, written like
+/// `user`, `uid`, `user:group` or `uid:gid`, is root.
+pub(crate) fn is_root_user(user: Option<&str>) -> bool {
+    match user.map(|user| user.split(':').next().unwrap_or_default()) {
+        None | Some("") | Some("0") | Some("root") => true,
+        Some(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_cache_expiry() {
+        let mut cache = DigestCache::new(Duration::from_secs(60));
+        let now = SystemTime::UNIX_EPOCH;
+        cache.insert("nginx:1.25", "sha256:abc".to_string(), now);
+        assert_eq!(
+            cache.get("nginx:1.25", now + Duration::from_secs(59)),
+            Some(&"sha256:abc".to_string())
+        );
+        assert_eq!(cache.get("nginx:1.25", now + Duration::from_secs(60)), None);
+        assert_eq!(cache.get("redis:7", now), None);
+
+        cache.insert(
+            "redis:7",
+            "sha256:def".to_string(),
+            now + Duration::from_secs(61),
+        );
+        assert_eq!(cache.entries.len(), 1);
+    }
+
+    #[test]
+    fn not_found_errors() {
+        for error in [
+            "error invoking wapc oci.manifest_digest: Host error: OCI API errors: [OciError { code: ManifestUnknown, message: \"manifest unknown\" }]",
+            "error invoking wapc oci.manifest_digest: Host error: repository name not known to registry: NAME_UNKNOWN",
+            "error invoking wapc oci.manifest_digest: Host error: 404 Not Found",
+        ] {
+            assert!(is_not_found_error(error), "{}", error);
+        }
+        for error in [
+            "error invoking wapc oci.manifest_digest: Host error: error sending request: dns error: failed to lookup address information",
+            "error invoking wapc oci.manifest_digest: Host error: 503 Service Unavailable",
+            "error invoking wapc oci.manifest_digest: Host error: 401 Unauthorized",
+        ] {
+            assert!(!is_not_found_error(error), "{}", error);
+        }
+    }
+
+    #[test]
+    fn platforms() {
+        let arm64 = Platform::parse("linux/arm64").expect("cannot parse platform");
+        let arm64_v8 = Platform::parse("linux/arm64/v8").expect("cannot parse platform");
+        assert_eq!(arm64_v8.variant.as_deref(), Some("v8"));
+        assert!(arm64.matches(&arm64_v8));
+        assert!(arm64_v8.matches(&arm64_v8));
+        assert!(!arm64_v8.matches(&arm64));
+        assert!(!arm64.matches(&Platform::parse("linux/amd64").unwrap()));
+        assert!(Platform::parse("linux").is_err());
+        assert!(Platform::parse("linux//v8").is_err());
+        assert!(Platform::parse("linux/arm64/v8/extra").is_err());
+    }
+
+    #[test]
+    fn root_user() {
+        assert!(is_root_user(None));
+        assert!(is_root_user(Some("0")));
+        assert!(is_root_user(Some("root")));
+        assert!(is_root_user(Some("0:1000")));
+        assert!(!is_root_user(Some("1000")));
+        assert!(!is_root_user(Some("nobody:nogroup")));
+
+        let response: ManifestAndConfig = serde_json::from_str(
+            r#"{"manifest": {}, "digest": "sha256:abc", "config": {"architecture": "amd64", "os": "linux", "config": {"User": "65532", "Env": []}}}"#,
+        )
+        .expect("cannot parse response");
+        assert_eq!(
+            response.config.config.and_then(|config| config.user),
+            Some("65532".to_string())
+        );
+    }
+
+    #[test]
+    fn attestation_predicate_types() {
+        let manifest: ImageManifest = serde_json::from_str(
+            r#"{
+                "schemaVersion": 2,
+                "layers": [
+                    {"mediaType": "application/vnd.dsse.envelope.v1+json", "annotations": {"predicateType": "https://spdx.dev/Document"}},
+                    {"mediaType": "application/vnd.dsse.envelope.v1+json"}
+                ]
+            }"#,
+        )
+        .expect("cannot parse manifest");
+        assert_eq!(
+            predicate_types(manifest.layers),
+            vec!["https://spdx.dev/Document"]
+        );
+    }
+
+    #[test]
+    fn creation_time() {
+        assert_eq!(
+            parse_created("1970-01-02T00:00:00Z").expect("cannot parse creation time"),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(86400)
+        );
+        assert_eq!(
+            parse_created("1970-01-02T01:00:00+01:00").expect("cannot parse creation time"),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(86400)
+        );
+        assert!(parse_created("yesterday").is_err());
+    }
+}
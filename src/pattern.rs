@@ -0,0 +1,48 @@
+/// Matches `value` against a glob `pattern`, where `*` matches any
+/// sequence of characters (including an empty one) and every other
+/// character matches itself.
+pub(crate) fn glob_matches(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+
+    let (mut pattern_index, mut value_index) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while value_index < value.len() {
+        if pattern_index < pattern.len() && pattern[pattern_index] == '*' {
+            backtrack = Some((pattern_index, value_index));
+            pattern_index += 1;
+        } else if pattern_index < pattern.len() && pattern[pattern_index] == value[value_index] {
+            pattern_index += 1;
+            value_index += 1;
+        } else if let Some((star_index, star_value_index)) = backtrack {
+            pattern_index = star_index + 1;
+            value_index = star_value_index + 1;
+            backtrack = Some((star_index, value_index));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[pattern_index..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("example.com/image", "example.com/image"));
+        assert!(!glob_matches("example.com/image", "example.com/image:tag"));
+        assert!(glob_matches("example.com/*", "example.com/image:tag"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("*/image:*", "example.com/image:tag"));
+        assert!(!glob_matches("*/image:*", "example.com/other:tag"));
+        assert!(glob_matches(
+            "example.com/*/image",
+            "example.com/org/team/image"
+        ));
+        assert!(!glob_matches("example.com/*", "quay.io/example.com/image"));
+    }
+}
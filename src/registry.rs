@@ -0,0 +1,484 @@
+use std::net::IpAddr;
+
+use crate::image::{is_docker_hub_alias, DEFAULT_REGISTRY};
+use crate::rule::{Component, Covers, Specific, Specificity};
+
+/// Port of the registries addressed without an explicit one.
+pub(crate) const HTTPS_PORT: u16 = 443;
+
+/// Port part of a registry pattern.
+#[derive(Debug, PartialEq)]
+enum PortPattern {
+    /// No port given: only matches registries without an explicit port.
+    None,
+    /// `*`: matches any port, and registries without an explicit port.
+    Any,
+    /// Inclusive range of ports; a single port is a range of one.
+    Range(u16, u16),
+}
+
+/// Splits a registry into its host and port parts. IPv6 hosts must be
+/// enclosed in brackets to carry a port.
+pub(crate) fn split_host_port(registry: &str) -> (&str, Option<&str>) {
+    if registry.starts_with('[') {
+        if let Some((host, rest)) = registry.split_once(']') {
+            return (&registry[..host.len() + 1], rest.strip_prefix(':'));
+        }
+        return (registry, None);
+    }
+    match registry.split_once(':') {
+        Some((host, port)) if !port.contains(':') => (host, Some(port)),
+        _ => (registry, None),
+    }
+}
+
+fn parse_port(port: &str) -> Result<u16, String> {
+    port.parse::<u16>()
+        .map_err(|_| format!("invalid port {}", port))
+}
+
+fn parse_port_pattern(port: Option<&str>) -> Result<PortPattern, String> {
+    match port {
+        None => Ok(PortPattern::None),
+        Some("*") => Ok(PortPattern::Any),
+        Some(port) => match port.split_once('-') {
+            Some((from, to)) => {
+                let (from, to) = (parse_port(from)?, parse_port(to)?);
+                if from > to {
+                    return Err(format!("invalid port range {}", port));
+                }
+                Ok(PortPattern::Range(from, to))
+            }
+            None => {
+                let port = parse_port(port)?;
+                Ok(PortPattern::Range(port, port))
+            }
+        },
+    }
+}
+
+/// Parses a CIDR block, like `10.0.0.0/8` or `fd00::/8`, optionally
+/// enclosed in brackets.
+fn parse_cidr(cidr: &str) -> Result<(IpAddr, u8), String> {
+    let cidr = cidr.trim_start_matches('[').trim_end_matches(']');
+    let (address, prefix_length) = cidr
+        .split_once('/')
+        .ok_or_else(|| format!("invalid CIDR {}", cidr))?;
+    let address = address
+        .parse::<IpAddr>()
+        .map_err(|_| format!("invalid CIDR address {}", address))?;
+    let max_prefix_length = if address.is_ipv4() { 32 } else { 128 };
+    let prefix_length = prefix_length
+        .parse::<u8>()
+        .ok()
+        .filter(|prefix_length| *prefix_length <= max_prefix_length)
+        .ok_or_else(|| format!("invalid CIDR prefix length {}", prefix_length))?;
+    Ok((address, prefix_length))
+}
+
+fn cidr_contains((network, prefix_length): (IpAddr, u8), address: IpAddr) -> bool {
+    match (network, address) {
+        (IpAddr::V4(network), IpAddr::V4(address)) => {
+            let mask = u32::MAX
+                .checked_shl(32 - u32::from(prefix_length))
+                .unwrap_or(0);
+            u32::from(network) & mask == u32::from(address) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(address)) => {
+            let mask = u128::MAX
+                .checked_shl(128 - u32::from(prefix_length))
+                .unwrap_or(0);
+            u128::from(network) & mask == u128::from(address) & mask
+        }
+        _ => false,
+    }
+}
+
+fn is_cidr(host: &str) -> bool {
+    host.contains('/')
+}
+
+/// Parses the host of a registry as an IP address. IPv6 addresses are
+/// enclosed in brackets in image references, but the brackets are
+/// optional in patterns without a port.
+fn parse_address(host: &str) -> Option<IpAddr> {
+    match host.strip_prefix('[') {
+        Some(address) => address
+            .strip_suffix(']')?
+            .parse()
+            .ok()
+            .filter(IpAddr::is_ipv6),
+        None => host.parse().ok(),
+    }
+}
+
+/// Returns true when the host of the registry is an IP address instead
+/// of a domain name. Besides the literals accepted by `parse_address`,
+/// the hosts whose last label is numeric, like `127.1` or `0x7f000001`,
+/// are IPv4 addresses for the resolvers accepting the shorthand forms,
+/// and cannot be domain names, whose top level label is never numeric.
+pub(crate) fn is_ip_registry(registry: &str) -> bool {
+    let (host, _) = split_host_port(registry);
+    if parse_address(host).is_some() {
+        return true;
+    }
+    let last_label = host.rsplit('.').next().unwrap_or(host);
+    match last_label
+        .strip_prefix("0x")
+        .or_else(|| last_label.strip_prefix("0X"))
+    {
+        Some(hex) => hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => !last_label.is_empty() && last_label.chars().all(|c| c.is_ascii_digit()),
+    }
+}
+
+/// Returns true when the host of the registry is the loopback interface
+/// of the node, like `localhost:5000`, `127.0.0.1:5000` or `[::1]`.
+pub(crate) fn is_loopback_registry(registry: &str) -> bool {
+    let (host, _) = split_host_port(registry);
+    host.eq_ignore_ascii_case("localhost")
+        || parse_address(host)
+            .map(|address| address.is_loopback())
+            .unwrap_or(false)
+}
+
+/// Host part of a registry pattern.
+#[derive(Debug, PartialEq)]
+enum HostPattern {
+    Name(String),
+    /// IP address, compared regardless of how it is written, so that
+    /// `[2001:db8::1]` matches `[2001:0db8:0:0::1]`.
+    Address(IpAddr),
+    Cidr((IpAddr, u8)),
+}
+
+impl HostPattern {
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            HostPattern::Name(name) => name.eq_ignore_ascii_case(host),
+            HostPattern::Address(address) => parse_address(host) == Some(*address),
+            HostPattern::Cidr(cidr) => parse_address(host)
+                .map(|address| cidr_contains(*cidr, address))
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// Registry pattern. Registry patterns are a host, optionally followed
+/// by a port (`registry.internal:5000`), a port range
+/// (`registry.internal:30000-32767`) or a `*` wildcard matching any
+/// port (`registry.internal:*`). The host can also be a CIDR block
+/// (`10.0.0.0/8`, `192.168.1.0/24:5000` or `[fd00::/8]:5000`) matching
+/// registries addressed by IP. IPv6 hosts followed by a port must be
+/// enclosed in brackets, like `[2001:db8::1]:5000`.
+#[derive(Debug, PartialEq)]
+pub struct RegistryPattern {
+    host: HostPattern,
+    port: PortPattern,
+}
+
+impl RegistryPattern {
+    pub fn parse(pattern: &str) -> Result<RegistryPattern, String> {
+        let (host, port) = split_host_port(pattern);
+        if host.is_empty() {
+            return Err(format!("registry {} has no host", pattern));
+        }
+        let host = if is_cidr(host) {
+            HostPattern::Cidr(parse_cidr(host).map_err(|e| format!("registry {}: {}", pattern, e))?)
+        } else if let Some(address) = parse_address(host) {
+            HostPattern::Address(address)
+        } else if host.starts_with('[') {
+            return Err(format!(
+                "registry {}: invalid IPv6 address {}",
+                pattern, host
+            ));
+        } else {
+            HostPattern::Name(host.to_string())
+        };
+        let port = parse_port_pattern(port).map_err(|e| format!("registry {}: {}", pattern, e))?;
+        Ok(RegistryPattern { host, port })
+    }
+
+    /// Parses the pattern like `parse`, treating the aliases of the
+    /// Docker Hub as `docker.io`.
+    pub fn parse_normalized(pattern: &str) -> Result<RegistryPattern, String> {
+        let mut registry_pattern = RegistryPattern::parse(pattern)?;
+        if let HostPattern::Name(name) = &registry_pattern.host {
+            if is_docker_hub_alias(name) {
+                registry_pattern.host = HostPattern::Name(DEFAULT_REGISTRY.to_string());
+            }
+        }
+        Ok(registry_pattern)
+    }
+
+    /// Returns true when `registry` is matched by the pattern. Hosts
+    /// and ports are compared separately.
+    pub fn matches(&self, registry: &str) -> bool {
+        let (host, port) = split_host_port(registry);
+        if !self.host.matches(host) {
+            return false;
+        }
+        match (&self.port, port) {
+            (PortPattern::None, None) => true,
+            (PortPattern::Any, _) => true,
+            (PortPattern::Range(from, to), Some(port)) => port
+                .parse::<u16>()
+                .map(|port| *from <= port && port <= *to)
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+}
+
+/// Host names and addresses are more specific than CIDR blocks, the
+/// longer prefixes being the more specific ones, and single ports than
+/// port ranges, themselves more specific than any port.
+impl Specific for RegistryPattern {
+    fn specificity(&self) -> Specificity {
+        let host = match self.host {
+            HostPattern::Cidr((_, prefix_length)) => usize::from(prefix_length),
+            HostPattern::Name(_) | HostPattern::Address(_) => 256,
+        };
+        let port = match self.port {
+            PortPattern::Any => 0,
+            PortPattern::Range(from, to) if from < to => 1,
+            PortPattern::Range(_, _) | PortPattern::None => 2,
+        };
+        Specificity::new(Component::Registry, host * 4 + port)
+    }
+}
+
+/// CIDR blocks cover the addresses and the narrower blocks they
+/// contain, port ranges the ranges they contain, and `*` every port.
+impl Covers for RegistryPattern {
+    fn covers(&self, other: &RegistryPattern) -> bool {
+        let host_covered = match (&self.host, &other.host) {
+            (HostPattern::Name(name), HostPattern::Name(other)) => name.eq_ignore_ascii_case(other),
+            (HostPattern::Address(address), HostPattern::Address(other)) => address == other,
+            (HostPattern::Cidr(cidr), HostPattern::Address(address)) => {
+                cidr_contains(*cidr, *address)
+            }
+            (HostPattern::Cidr(cidr), HostPattern::Cidr((network, prefix_length))) => {
+                cidr.1 <= *prefix_length && cidr_contains(*cidr, *network)
+            }
+            _ => false,
+        };
+        let port_covered = match (&self.port, &other.port) {
+            (PortPattern::Any, _) => true,
+            (PortPattern::None, PortPattern::None) => true,
+            (PortPattern::Range(from, to), PortPattern::Range(other_from, other_to)) => {
+                from <= other_from && other_to <= to
+            }
+            _ => false,
+        };
+        host_covered && port_covered
+    }
+}
+
+/// Validates a registry pattern, see [`RegistryPattern`].
+pub(crate) fn validate_registry_pattern(pattern: &str) -> Result<(), String> {
+    RegistryPattern::parse(pattern).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry_matches(pattern: &str, registry: &str) -> bool {
+        RegistryPattern::parse(pattern)
+            .map(|pattern| pattern.matches(registry))
+            .unwrap_or(false)
+    }
+
+    #[test]
+    fn test_split_host_port() {
+        assert_eq!(split_host_port("example.com"), ("example.com", None));
+        assert_eq!(
+            split_host_port("example.com:5000"),
+            ("example.com", Some("5000"))
+        );
+        assert_eq!(split_host_port("[::1]:5000"), ("[::1]", Some("5000")));
+        assert_eq!(split_host_port("[::1]"), ("[::1]", None));
+        assert_eq!(
+            split_host_port("[2001:db8::1]:5000"),
+            ("[2001:db8::1]", Some("5000"))
+        );
+        assert_eq!(split_host_port("2001:db8::1"), ("2001:db8::1", None));
+    }
+
+    #[test]
+    fn test_is_ip_registry() {
+        for registry in [
+            "10.0.0.100",
+            "10.0.0.100:5000",
+            "[2001:db8::1]:5000",
+            "[::1]",
+            "127.1",
+            "2130706433:5000",
+            "0x7f000001",
+            "0177.0.0.1",
+        ] {
+            assert!(is_ip_registry(registry), "{}", registry);
+        }
+        for registry in [
+            "docker.io",
+            "registry.internal:5000",
+            "localhost:5000",
+            "10.0.0.100.nip.io",
+            "123.example.com",
+            "ab.0xcafe.io",
+        ] {
+            assert!(!is_ip_registry(registry), "{}", registry);
+        }
+    }
+
+    #[test]
+    fn test_is_loopback_registry() {
+        for registry in [
+            "localhost",
+            "localhost:5000",
+            "127.0.0.1:5000",
+            "127.0.1.1",
+            "[::1]:5000",
+        ] {
+            assert!(is_loopback_registry(registry), "{}", registry);
+        }
+        for registry in [
+            "docker.io",
+            "10.0.0.100:5000",
+            "localhost.example.com",
+            "[2001:db8::1]",
+        ] {
+            assert!(!is_loopback_registry(registry), "{}", registry);
+        }
+    }
+
+    #[test]
+    fn test_validate_registry_pattern() {
+        assert!(validate_registry_pattern("registry.internal").is_ok());
+        assert!(validate_registry_pattern("registry.internal:*").is_ok());
+        assert!(validate_registry_pattern("registry.internal:5000").is_ok());
+        assert!(validate_registry_pattern("registry.internal:30000-32767").is_ok());
+        assert_eq!(
+            validate_registry_pattern("registry.internal:70000"),
+            Err("registry registry.internal:70000: invalid port 70000".to_string())
+        );
+        assert_eq!(
+            validate_registry_pattern("registry.internal:5001-5000"),
+            Err("registry registry.internal:5001-5000: invalid port range 5001-5000".to_string())
+        );
+        assert!(validate_registry_pattern(":5000").is_err());
+        assert!(validate_registry_pattern("10.0.0.0/8").is_ok());
+        assert!(validate_registry_pattern("192.168.1.0/24:5000").is_ok());
+        assert!(validate_registry_pattern("fd00::/8").is_ok());
+        assert!(validate_registry_pattern("[fd00::/8]:5000").is_ok());
+        assert!(validate_registry_pattern("[2001:db8::1]:5000").is_ok());
+        assert_eq!(
+            validate_registry_pattern("[2001:db8::1:5000"),
+            Err("registry [2001:db8::1:5000: invalid IPv6 address [2001:db8::1:5000".to_string())
+        );
+        assert_eq!(
+            validate_registry_pattern("[10.0.0.1]:5000"),
+            Err("registry [10.0.0.1]:5000: invalid IPv6 address [10.0.0.1]".to_string())
+        );
+        assert_eq!(
+            validate_registry_pattern("10.0.0.0/33"),
+            Err("registry 10.0.0.0/33: invalid CIDR prefix length 33".to_string())
+        );
+        assert_eq!(
+            validate_registry_pattern("10.0.0/8"),
+            Err("registry 10.0.0/8: invalid CIDR address 10.0.0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_registry_matches_cidr() {
+        assert!(registry_matches("10.0.0.0/8", "10.1.2.3"));
+        assert!(!registry_matches("10.0.0.0/8", "10.1.2.3:5000"));
+        assert!(!registry_matches("10.0.0.0/8", "11.1.2.3"));
+        assert!(!registry_matches("10.0.0.0/8", "registry.internal"));
+        assert!(registry_matches("192.168.1.0/24:5000", "192.168.1.10:5000"));
+        assert!(!registry_matches(
+            "192.168.1.0/24:5000",
+            "192.168.2.10:5000"
+        ));
+        assert!(registry_matches("0.0.0.0/0:*", "172.16.0.1:443"));
+        assert!(registry_matches("192.168.1.10/32", "192.168.1.10"));
+        assert!(registry_matches("fd00::/8", "fd12::1"));
+        assert!(!registry_matches("fd00::/8", "fe80::1"));
+        assert!(registry_matches("[fd00::/8]:5000", "[fd12::1]:5000"));
+        assert!(!registry_matches("fd00::/8", "10.1.2.3"));
+    }
+
+    #[test]
+    fn test_registry_matches_ipv6() {
+        assert!(registry_matches("[2001:db8::1]:5000", "[2001:db8::1]:5000"));
+        assert!(registry_matches(
+            "[2001:db8::1]:5000",
+            "[2001:0db8:0:0::1]:5000"
+        ));
+        assert!(!registry_matches("[2001:db8::1]:5000", "[2001:db8::1]"));
+        assert!(!registry_matches(
+            "[2001:db8::1]:5000",
+            "[2001:db8::2]:5000"
+        ));
+        assert!(registry_matches("[2001:db8::1]:*", "[2001:db8::1]:443"));
+        assert!(registry_matches("2001:db8::1", "[2001:db8::1]"));
+        assert!(registry_matches("[2001:db8::1]", "[2001:db8::1]"));
+        assert!(registry_matches("10.0.0.1:5000", "10.0.0.1:5000"));
+    }
+
+    #[test]
+    fn test_parse_normalized() {
+        let pattern = RegistryPattern::parse_normalized("index.docker.io:*").unwrap();
+        assert!(pattern.matches("docker.io"));
+        assert!(!pattern.matches("index.docker.io"));
+        assert!(RegistryPattern::parse_normalized("quay.io")
+            .unwrap()
+            .matches("quay.io"));
+    }
+
+    #[test]
+    fn test_registry_matches() {
+        assert!(registry_matches("registry.internal", "registry.internal"));
+        assert!(registry_matches("Registry.Internal", "registry.internal"));
+        assert!(registry_matches("registry.internal", "REGISTRY.internal"));
+        assert!(!registry_matches(
+            "registry.internal",
+            "registry.internal:5000"
+        ));
+        assert!(registry_matches("registry.internal:*", "registry.internal"));
+        assert!(registry_matches(
+            "registry.internal:*",
+            "registry.internal:31234"
+        ));
+        assert!(!registry_matches(
+            "registry.internal:*",
+            "other.internal:31234"
+        ));
+        assert!(registry_matches(
+            "registry.internal:5000",
+            "registry.internal:5000"
+        ));
+        assert!(!registry_matches(
+            "registry.internal:5000",
+            "registry.internal:5001"
+        ));
+        assert!(!registry_matches(
+            "registry.internal:5000",
+            "registry.internal"
+        ));
+        assert!(registry_matches(
+            "registry.internal:30000-32767",
+            "registry.internal:30000"
+        ));
+        assert!(registry_matches(
+            "registry.internal:30000-32767",
+            "registry.internal:32767"
+        ));
+        assert!(!registry_matches(
+            "registry.internal:30000-32767",
+            "registry.internal:5000"
+        ));
+    }
+}
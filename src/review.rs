@@ -0,0 +1,112 @@
+use kubewarden::request::KubernetesAdmissionRequest;
+#[cfg(feature = "policy")]
+use kubewarden::request::ValidationRequest;
+use serde_json::{Map, Value};
+
+/// Versions of the AdmissionReview API whose requests are accepted.
+const ADMISSION_REVIEW_API_VERSIONS: &[&str] = &["admission.k8s.io/v1", "admission.k8s.io/v1beta1"];
+
+/// Parses an admission request, given either as a whole AdmissionReview
+/// of the `admission.k8s.io/v1` or `v1beta1` API, or as its `request`
+/// field. Older API servers and test harnesses send v1beta1 requests
+/// lacking some fields, like `dryRun` or `options`, or setting them to
+/// null, which are read as their default value.
+pub fn parse_admission_request(request: Value) -> Result<KubernetesAdmissionRequest, String> {
+    let mut request = match request {
+        Value::Object(review)
+            if review.get("kind").and_then(Value::as_str) == Some("AdmissionReview") =>
+        {
+            let api_version = review
+                .get("apiVersion")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            if !api_version.is_empty() && !ADMISSION_REVIEW_API_VERSIONS.contains(&api_version) {
+                return Err(format!(
+                    "unsupported AdmissionReview apiVersion {}",
+                    api_version
+                ));
+            }
+            match review.get("request") {
+                Some(Value::Object(request)) => request.clone(),
+                _ => return Err("AdmissionReview without a request".to_string()),
+            }
+        }
+        Value::Object(request) => request,
+        _ => return Err("admission request must be an object".to_string()),
+    };
+    remove_null_fields(&mut request);
+    if let Some(Value::Object(user_info)) = request.get_mut("userInfo") {
+        remove_null_fields(user_info);
+    }
+    serde_json::from_value(Value::Object(request))
+        .map_err(|e| format!("invalid admission request: {}", e))
+}
+
+/// Parses the payload of a `validate` call, holding the settings and
+/// the admission request, see `parse_admission_request`.
+#[cfg(feature = "policy")]
+pub(crate) fn parse_validation_request(payload: &[u8]) -> anyhow::Result<ValidationRequest<Value>> {
+    let mut payload: Map<String, Value> = serde_json::from_slice(payload)
+        .map_err(|e| anyhow::anyhow!("cannot decode the validation payload: {}", e))?;
+    let request = payload.remove("request").unwrap_or_default();
+    Ok(ValidationRequest {
+        settings: payload.remove("settings").unwrap_or_default(),
+        request: parse_admission_request(request).map_err(|e| anyhow::anyhow!(e))?,
+    })
+}
+
+/// The fields of the requests all have a default value, but null is
+/// only accepted by the object fields.
+fn remove_null_fields(fields: &mut Map<String, Value>) {
+    fields.retain(|_, value| !value.is_null());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admission_review_versions() {
+        let request = serde_json::json!({
+            "uid": "1",
+            "kind": {"group": "", "version": "v1", "kind": "Pod"},
+            "namespace": "default",
+            "operation": "CREATE",
+            "object": {"spec": {"containers": [{"name": "app", "image": "nginx:1.25"}]}},
+            "dryRun": true
+        });
+        let mut v1beta1_request = request.clone();
+        v1beta1_request["dryRun"] = Value::Null;
+        v1beta1_request["options"] = Value::Null;
+        v1beta1_request["userInfo"] = serde_json::json!({"username": "admin", "groups": null});
+        let admission_review = |api_version: &str, request: &Value| {
+            serde_json::json!({
+                "apiVersion": api_version,
+                "kind": "AdmissionReview",
+                "request": request
+            })
+        };
+
+        for (review, dry_run) in [
+            (request.clone(), true),
+            (admission_review("admission.k8s.io/v1", &request), true),
+            (v1beta1_request.clone(), false),
+            (
+                admission_review("admission.k8s.io/v1beta1", &v1beta1_request),
+                false,
+            ),
+        ] {
+            let request = parse_admission_request(review.clone())
+                .unwrap_or_else(|e| panic!("{}: {}", review, e));
+            assert_eq!(request.kind.kind, "Pod");
+            assert_eq!(request.namespace, "default");
+            assert_eq!(request.dry_run, dry_run, "{}", review);
+        }
+
+        assert_eq!(
+            parse_admission_request(admission_review("admission.k8s.io/v2", &request)).unwrap_err(),
+            "unsupported AdmissionReview apiVersion admission.k8s.io/v2"
+        );
+        assert!(parse_admission_request(Value::Null).is_err());
+    }
+}
@@ -0,0 +1,962 @@
+use k8s_openapi::chrono::DateTime;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+use crate::matcher::{Glob, PatternEngine, Prefix, Regex};
+use crate::pattern::glob_matches;
+
+/// How the pattern of a rule is matched.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum RuleKind {
+    /// The pattern must be equal to the matched value.
+    Exact,
+    /// The pattern is a path prefix of the matched value, like
+    /// `quay.io/org` for `quay.io/org/app`, but not for
+    /// `quay.io/organization`.
+    Prefix,
+    /// The pattern is a glob, where `*` matches any sequence of
+    /// characters.
+    Glob,
+    /// The pattern is a regular expression, that must match the whole
+    /// value.
+    Regex,
+    /// The pattern is the organization of the images, the first
+    /// component of their repository path, like `bitnami` for
+    /// `docker.io/bitnami/redis`. Only supported by the images lists.
+    Organization,
+}
+
+/// Whether a reject rule rejects the values it matches, or only warns
+/// about them.
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Enforcement {
+    /// The values are accepted, with an admission warning telling that
+    /// the rule would reject them, so that new rules can be tried out.
+    Warn,
+    #[default]
+    Deny,
+}
+
+/// Decides between the allow and reject lists of a filter when both
+/// are provided and both match a value.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum RulePrecedence {
+    /// Values matched by a reject rule are rejected, even when they are
+    /// matched by an allow rule.
+    #[default]
+    RejectWins,
+    /// Values matched by an allow rule are allowed, even when they are
+    /// matched by a reject rule.
+    AllowWins,
+    /// The most specific of the matching rules decides, see
+    /// `Specificity`. The reject rules win the ties.
+    MostSpecific,
+}
+
+/// Component of the values that a rule pins down, from the least to the
+/// most specific.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Component {
+    /// Glob and regex rules, which can match anything.
+    Pattern,
+    Registry,
+    /// Organization of the images, on any registry.
+    Organization,
+    /// Prefix of the repository path, like `quay.io/org/*`.
+    RepositoryPrefix,
+    Repository,
+    Tag,
+    Digest,
+    /// Exact rules, matching the whole value.
+    Value,
+}
+
+/// How specific a rule is, for the `mostSpecific` precedence. Rules
+/// pinning down a more specific component of the values win, and
+/// between rules pinning down the same component, the rules with the
+/// highest `detail`, like the rules with more literal characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Specificity {
+    pub(crate) component: Component,
+    pub(crate) detail: usize,
+}
+
+impl Specificity {
+    pub(crate) fn new(component: Component, detail: usize) -> Specificity {
+        Specificity { component, detail }
+    }
+}
+
+/// Matchers of the plain string entries of the lists, which can tell
+/// how specific they are.
+pub(crate) trait Specific: Sized {
+    fn specificity(&self) -> Specificity;
+
+    /// Matcher of the values of the given organization, for the lists
+    /// supporting organization rules.
+    fn organization(_organization: &str) -> Option<Self> {
+        None
+    }
+}
+
+/// Matchers of the plain string entries of the lists, which can tell
+/// whether they match every value another entry matches. They can
+/// answer false when unsure, but never true when another entry matches
+/// a value they do not.
+pub(crate) trait Covers {
+    fn covers(&self, other: &Self) -> bool;
+}
+
+/// Repository globs of the registry rules, like `prometheus/*`.
+impl Specific for String {
+    fn specificity(&self) -> Specificity {
+        if self.contains('*') {
+            Specificity::new(Component::RepositoryPrefix, literal_characters(self))
+        } else {
+            Specificity::new(Component::Repository, self.len())
+        }
+    }
+
+    fn organization(organization: &str) -> Option<String> {
+        Some(format!("{}/*", organization))
+    }
+}
+
+/// A glob matching the pattern of another glob matches all the values
+/// of the other glob, whose wildcards can only be matched by its own.
+impl Covers for String {
+    fn covers(&self, other: &String) -> bool {
+        glob_matches(self, other)
+    }
+}
+
+/// Number of characters of a glob pattern that are not wildcards.
+pub(crate) fn literal_characters(pattern: &str) -> usize {
+    pattern.chars().filter(|c| *c != '*').count()
+}
+
+/// Decision for the values matched by no rule of a list.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum DefaultAction {
+    Allow,
+    Deny,
+}
+
+/// Decisions a list takes when its rules alone do not decide.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct ListDefaults {
+    pub(crate) precedence: RulePrecedence,
+    /// When not provided, values matched by no rule are denied by allow
+    /// lists and allowed by reject lists.
+    pub(crate) default_action: Option<DefaultAction>,
+}
+
+/// Rule given as an object, like
+/// `{"pattern": "quay.io/*", "kind": "glob", "message": "..."}`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RuleSpec {
+    pub(crate) pattern: String,
+    /// When not provided, the pattern is matched the same way as the
+    /// plain string entries of the list it belongs to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) kind: Option<RuleKind>,
+    /// Message reported when a reject rule matches an image.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) message: Option<String>,
+    /// Time after which the rule is ignored, in RFC 3339 format, like
+    /// `2025-06-30T00:00:00Z`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) expires_at: Option<String>,
+    /// Only supported by the reject rules. Defaults to `deny`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) enforcement: Option<Enforcement>,
+}
+
+/// Plain entry matching every value, like in `reject: ["*"]`.
+pub(crate) const WILDCARD: &str = "*";
+
+/// Entry of an allow or reject list: either a plain string, matched
+/// the way the list matches its entries, or a structured rule.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum Rule {
+    Pattern(String),
+    Spec(RuleSpec),
+}
+
+impl From<&str> for Rule {
+    fn from(pattern: &str) -> Self {
+        Rule::Pattern(pattern.to_string())
+    }
+}
+
+impl Rule {
+    pub(crate) fn pattern(&self) -> &str {
+        match self {
+            Rule::Pattern(pattern) => pattern,
+            Rule::Spec(spec) => &spec.pattern,
+        }
+    }
+
+    pub(crate) fn kind(&self) -> Option<RuleKind> {
+        match self {
+            Rule::Pattern(_) => None,
+            Rule::Spec(spec) => spec.kind,
+        }
+    }
+
+    pub(crate) fn message(&self) -> Option<&str> {
+        match self {
+            Rule::Pattern(_) => None,
+            Rule::Spec(spec) => spec.message.as_deref(),
+        }
+    }
+
+    pub(crate) fn expires_at(&self) -> Option<&str> {
+        match self {
+            Rule::Pattern(_) => None,
+            Rule::Spec(spec) => spec.expires_at.as_deref(),
+        }
+    }
+
+    pub(crate) fn enforcement(&self) -> Enforcement {
+        match self {
+            Rule::Pattern(_) => Enforcement::default(),
+            Rule::Spec(spec) => spec.enforcement.unwrap_or_default(),
+        }
+    }
+
+    /// Returns true when the rule has an expiry time, and it is past
+    /// `now`.
+    pub(crate) fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at()
+            .and_then(|expires_at| parse_expiry(expires_at).ok())
+            .map(|expires_at| now >= expires_at)
+            .unwrap_or(false)
+    }
+
+    /// Returns the pattern when it is matched the way the list it
+    /// belongs to matches its entries, so that the list can validate
+    /// it.
+    pub(crate) fn default_pattern(&self) -> Option<&str> {
+        match self.kind() {
+            None if !self.is_wildcard() => Some(self.pattern()),
+            _ => None,
+        }
+    }
+
+    /// Returns true for the plain `*` entries, which match every value
+    /// of any list.
+    pub(crate) fn is_wildcard(&self) -> bool {
+        self.kind().is_none() && self.pattern() == WILDCARD
+    }
+
+    /// Returns the rule with the kind of the `matchStrategy` of its list
+    /// when it does not provide one. The plain `*` entries keep matching
+    /// every value.
+    pub(crate) fn with_strategy(&self, strategy: Option<RuleKind>) -> Rule {
+        match (self, strategy) {
+            (Rule::Pattern(pattern), Some(kind)) if !self.is_wildcard() => Rule::Spec(RuleSpec {
+                pattern: pattern.clone(),
+                kind: Some(kind),
+                message: None,
+                expires_at: None,
+                enforcement: None,
+            }),
+            (Rule::Spec(spec), Some(kind)) if spec.kind.is_none() => Rule::Spec(RuleSpec {
+                kind: Some(kind),
+                ..spec.clone()
+            }),
+            _ => self.clone(),
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.pattern().is_empty() {
+            return Err("rules must provide a pattern".to_string());
+        }
+        if self.kind() == Some(RuleKind::Regex) {
+            Regex::compile(self.pattern())
+                .map_err(|e| format!("invalid regex {}: {}", self.pattern(), e))?;
+        }
+        if self.kind() == Some(RuleKind::Organization) && self.pattern().contains(['/', '*']) {
+            return Err(format!(
+                "invalid organization {}: expected a single repository path component",
+                self.pattern()
+            ));
+        }
+        if let Some(expires_at) = self.expires_at() {
+            parse_expiry(expires_at)
+                .map_err(|e| format!("invalid expiresAt of rule {}: {}", self.pattern(), e))?;
+        }
+        Ok(())
+    }
+
+    /// Compiles the rule. Rules without an explicit kind are compiled
+    /// with `compile_default`, which receives the pattern.
+    pub(crate) fn compile<M, F>(&self, compile_default: F) -> Result<CompiledRule<M>, String>
+    where
+        M: Specific,
+        F: Fn(&str) -> Result<M, String>,
+    {
+        let (matcher, specificity) = match self.kind() {
+            None if self.is_wildcard() => {
+                (RuleMatcher::Any, Specificity::new(Component::Pattern, 0))
+            }
+            None => {
+                let matcher = compile_default(self.pattern())?;
+                let specificity = matcher.specificity();
+                (RuleMatcher::Default(matcher), specificity)
+            }
+            Some(RuleKind::Exact) => (
+                RuleMatcher::Exact(self.pattern().to_string()),
+                Specificity::new(Component::Value, self.pattern().len()),
+            ),
+            Some(RuleKind::Prefix) => (
+                RuleMatcher::Prefix(Prefix::compile(self.pattern())?),
+                Specificity::new(Component::Pattern, self.pattern().len()),
+            ),
+            Some(RuleKind::Glob) => (
+                RuleMatcher::Glob(Glob::compile(self.pattern())?),
+                Specificity::new(Component::Pattern, literal_characters(self.pattern())),
+            ),
+            Some(RuleKind::Regex) => (
+                RuleMatcher::Regex(
+                    Regex::compile(self.pattern())
+                        .map_err(|e| format!("invalid regex {}: {}", self.pattern(), e))?,
+                ),
+                Specificity::new(Component::Pattern, 0),
+            ),
+            Some(RuleKind::Organization) => {
+                let matcher = M::organization(self.pattern()).ok_or_else(|| {
+                    format!(
+                        "organization rule {} is only supported by the images lists",
+                        self.pattern()
+                    )
+                })?;
+                let specificity = matcher.specificity();
+                (RuleMatcher::Default(matcher), specificity)
+            }
+        };
+        Ok(CompiledRule {
+            matcher,
+            pattern: self.pattern().to_string(),
+            message: self.message().map(String::from),
+            specificity,
+            expires_at: self.expires_at().map(parse_expiry).transpose()?,
+            enforcement: self.enforcement(),
+        })
+    }
+}
+
+#[derive(Debug)]
+enum RuleMatcher<M> {
+    /// The `*` entries.
+    Any,
+    Default(M),
+    Exact(String),
+    Prefix(Prefix),
+    Glob(Glob),
+    Regex(Regex),
+}
+
+/// Rule ready to be matched, where `M` is the matcher of the plain
+/// string entries of the list the rule belongs to.
+#[derive(Debug)]
+pub(crate) struct CompiledRule<M> {
+    matcher: RuleMatcher<M>,
+    /// Pattern of the rule, as configured.
+    pub(crate) pattern: String,
+    pub(crate) message: Option<String>,
+    pub(crate) specificity: Specificity,
+    expires_at: Option<SystemTime>,
+    pub(crate) enforcement: Enforcement,
+}
+
+impl<M> CompiledRule<M> {
+    /// Returns true once the expiry time of the rule, if any, is past.
+    /// Compiled rules are cached across requests, so the current time
+    /// is read on every match.
+    fn is_expired(&self) -> bool {
+        self.expires_at
+            .map(|expires_at| SystemTime::now() >= expires_at)
+            .unwrap_or(false)
+    }
+
+    /// Returns true when `value` is matched by the rule. Rules without
+    /// an explicit kind are matched with `default_matches`. Expired
+    /// rules match nothing.
+    pub(crate) fn matches<F>(&self, value: &str, default_matches: F) -> bool
+    where
+        F: Fn(&M) -> bool,
+    {
+        if self.is_expired() {
+            return false;
+        }
+        match &self.matcher {
+            RuleMatcher::Any => true,
+            RuleMatcher::Default(matcher) => default_matches(matcher),
+            RuleMatcher::Exact(pattern) => pattern == value,
+            RuleMatcher::Prefix(prefix) => prefix.is_match(value),
+            RuleMatcher::Glob(glob) => glob.is_match(value),
+            RuleMatcher::Regex(regex) => regex.is_match(value),
+        }
+    }
+
+    /// Returns true when the rule matches every value the other rule
+    /// matches, see `Covers`. Rules that expire only do so for a while.
+    pub(crate) fn covers(&self, other: &CompiledRule<M>) -> bool
+    where
+        M: Covers,
+    {
+        if self.expires_at.is_some() {
+            return false;
+        }
+        match (&self.matcher, &other.matcher) {
+            (RuleMatcher::Any, _) => true,
+            (RuleMatcher::Default(matcher), RuleMatcher::Default(other)) => matcher.covers(other),
+            (RuleMatcher::Exact(pattern), RuleMatcher::Exact(other)) => pattern == other,
+            (RuleMatcher::Prefix(prefix), RuleMatcher::Exact(other)) => prefix.is_match(other),
+            (RuleMatcher::Prefix(prefix), RuleMatcher::Prefix(other)) => {
+                prefix.is_match(other.as_str())
+            }
+            (RuleMatcher::Glob(glob), RuleMatcher::Exact(other)) => glob.is_match(other),
+            (RuleMatcher::Glob(glob), RuleMatcher::Glob(other)) => glob.is_match(other.as_str()),
+            (RuleMatcher::Regex(regex), RuleMatcher::Exact(other)) => regex.is_match(other),
+            (RuleMatcher::Regex(regex), RuleMatcher::Regex(other)) => {
+                regex.as_str() == other.as_str()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Parses the RFC 3339 expiry time of a rule.
+fn parse_expiry(expires_at: &str) -> Result<SystemTime, String> {
+    DateTime::parse_from_rfc3339(expires_at)
+        .map(SystemTime::from)
+        .map_err(|e| {
+            format!(
+                "expected an RFC 3339 time, like 2025-06-30T00:00:00Z: {}",
+                e
+            )
+        })
+}
+
+/// Returns the rules of a list, with the kind of its `matchStrategy`
+/// for the rules that do not provide one.
+pub(crate) fn apply_match_strategy(
+    rules: &Option<Vec<Rule>>,
+    strategy: Option<RuleKind>,
+) -> Option<Vec<Rule>> {
+    rules.as_ref().map(|rules| {
+        rules
+            .iter()
+            .map(|rule| rule.with_strategy(strategy))
+            .collect()
+    })
+}
+
+/// Compiles the rules of an allow or reject list.
+pub(crate) fn compile_rules<M, F>(
+    rules: &Option<Vec<Rule>>,
+    compile_default: F,
+) -> Result<Option<Vec<CompiledRule<M>>>, String>
+where
+    M: Specific,
+    F: Fn(&str) -> Result<M, String>,
+{
+    rules
+        .as_ref()
+        .map(|rules| {
+            rules
+                .iter()
+                .map(|rule| rule.compile(&compile_default))
+                .collect()
+        })
+        .transpose()
+}
+
+/// Compiled allow and reject lists of a filter. Both of them are only
+/// provided when a rule precedence is configured.
+#[derive(Debug)]
+pub(crate) struct CompiledList<M> {
+    pub(crate) allow: Option<Vec<CompiledRule<M>>>,
+    pub(crate) reject: Option<Vec<CompiledRule<M>>>,
+    pub(crate) defaults: ListDefaults,
+}
+
+impl<M> CompiledList<M> {
+    /// Returns true when the value is matched by an allow rule and by
+    /// no reject rule. When both lists match the value, the precedence
+    /// decides, and when none does, the default action. `matches` tells
+    /// whether a rule matches the value.
+    pub(crate) fn is_allowed<F>(&self, matches: F) -> bool
+    where
+        F: Fn(&CompiledRule<M>) -> bool,
+    {
+        self.is_allowed_enforcing(Enforcement::Deny, matches)
+    }
+
+    /// Returns true when the value is allowed, taking into account the
+    /// reject rules enforced at least at the given level: `deny` leaves
+    /// out the `warn` rules.
+    fn is_allowed_enforcing<F>(&self, enforcement: Enforcement, matches: F) -> bool
+    where
+        F: Fn(&CompiledRule<M>) -> bool,
+    {
+        let enforced_matches =
+            |rule: &CompiledRule<M>| rule.enforcement >= enforcement && matches(rule);
+        let allowed = self.allow.iter().flatten().any(&matches);
+        let rejected = self.reject.iter().flatten().any(enforced_matches);
+        match (allowed, rejected) {
+            (true, true) => match self.defaults.precedence {
+                RulePrecedence::RejectWins => false,
+                RulePrecedence::AllowWins => true,
+                RulePrecedence::MostSpecific => {
+                    let most_specific = |rules: &Option<Vec<CompiledRule<M>>>| {
+                        rules
+                            .iter()
+                            .flatten()
+                            .filter(|rule| enforced_matches(rule))
+                            .map(|rule| rule.specificity)
+                            .max()
+                    };
+                    most_specific(&self.allow) > most_specific(&self.reject)
+                }
+            },
+            (true, false) => true,
+            (false, true) => false,
+            (false, false) => match self.defaults.default_action {
+                Some(default_action) => default_action == DefaultAction::Allow,
+                None => self.allow.is_none(),
+            },
+        }
+    }
+
+    /// Returns the reject rule of the given enforcement level violated
+    /// by the value: with `deny`, the rule that rejected it, and with
+    /// `warn`, the rule that would have rejected the allowed value if
+    /// it was enforced.
+    pub(crate) fn violated_by<F>(
+        &self,
+        enforcement: Enforcement,
+        matches: F,
+    ) -> Option<&CompiledRule<M>>
+    where
+        F: Fn(&CompiledRule<M>) -> bool,
+    {
+        let violated = match enforcement {
+            Enforcement::Deny => !self.is_allowed(&matches),
+            Enforcement::Warn => {
+                self.is_allowed(&matches) && !self.is_allowed_enforcing(Enforcement::Warn, &matches)
+            }
+        };
+        if !violated {
+            return None;
+        }
+        self.reject
+            .iter()
+            .flatten()
+            .find(|rule| rule.enforcement == enforcement && matches(rule))
+    }
+
+    /// Returns the position of the first allow rule matching the value.
+    pub(crate) fn allow_index<F>(&self, matches: F) -> Option<usize>
+    where
+        F: Fn(&CompiledRule<M>) -> bool,
+    {
+        self.allow.as_ref()?.iter().position(matches)
+    }
+
+    /// Describes the entries that never decide: the entries repeated,
+    /// or shadowed by a broader entry of the same list, and the entries
+    /// always overridden by an entry of the other list. The `*` entries,
+    /// and the entries both allowed and rejected as they are, are left
+    /// to the settings warnings. `name` names the filter, like
+    /// `registryRules[0].tags`.
+    pub(crate) fn diagnostics(&self, name: &str) -> Vec<String>
+    where
+        M: Covers,
+    {
+        let mut diagnostics = Vec::new();
+        let precedence = self.defaults.precedence;
+        let (allow, reject) = (
+            non_wildcard_rules(&self.allow),
+            non_wildcard_rules(&self.reject),
+        );
+        // With `mostSpecific`, the narrower entries can outrank the
+        // entries of the other list
+        let shadowing = self.allow.is_none()
+            || self.reject.is_none()
+            || precedence != RulePrecedence::MostSpecific;
+        for (list, rules) in [("allow", &allow), ("reject", &reject)] {
+            for (index, rule) in rules {
+                // The first matching reject entry provides the message,
+                // so a broader entry listed after one with a message
+                // does not shadow it
+                let broader = rules.iter().find(|(other_index, other)| {
+                    other_index != index
+                        && other.enforcement == rule.enforcement
+                        && other.covers(rule)
+                        && (other_index < index || !rule.covers(other))
+                        && (other_index < index || list == "allow" || rule.message.is_none())
+                });
+                match broader {
+                    Some((_, other)) if other.pattern == rule.pattern && rule.covers(other) => {
+                        diagnostics.push(format!(
+                            "{} {} entry {} is listed more than once",
+                            name, list, rule.pattern
+                        ))
+                    }
+                    Some((_, other)) if shadowing => diagnostics.push(format!(
+                        "{} {} entry {} has no effect: {} matches all its values",
+                        name, list, rule.pattern, other.pattern
+                    )),
+                    _ => {}
+                }
+            }
+        }
+
+        let wins = |winner: &CompiledRule<M>, loser: &CompiledRule<M>, winner_list: &str| {
+            winner.covers(loser)
+                && match precedence {
+                    RulePrecedence::RejectWins => winner_list == "reject",
+                    RulePrecedence::AllowWins => winner_list == "allow",
+                    // The reject entries win the ties
+                    RulePrecedence::MostSpecific if winner_list == "reject" => {
+                        winner.specificity >= loser.specificity
+                    }
+                    RulePrecedence::MostSpecific => winner.specificity > loser.specificity,
+                }
+        };
+        for (_, allow_rule) in &allow {
+            for (_, reject_rule) in &reject {
+                if allow_rule.pattern == reject_rule.pattern {
+                    continue;
+                }
+                if allow_rule.covers(reject_rule) && reject_rule.covers(allow_rule) {
+                    diagnostics.push(format!(
+                        "{} allow entry {} and reject entry {} match the same values",
+                        name, allow_rule.pattern, reject_rule.pattern
+                    ));
+                } else if reject_rule.enforcement == Enforcement::Deny
+                    && wins(reject_rule, allow_rule, "reject")
+                {
+                    diagnostics.push(format!(
+                        "{} allow entry {} has no effect: reject entry {} matches all its values and wins",
+                        name, allow_rule.pattern, reject_rule.pattern
+                    ));
+                } else if wins(allow_rule, reject_rule, "allow") {
+                    diagnostics.push(format!(
+                        "{} reject entry {} has no effect: allow entry {} matches all its values and wins",
+                        name, reject_rule.pattern, allow_rule.pattern
+                    ));
+                }
+            }
+        }
+        diagnostics
+    }
+}
+
+/// Returns the rules of a list besides the `*` entries, along with
+/// their position.
+fn non_wildcard_rules<M>(rules: &Option<Vec<CompiledRule<M>>>) -> Vec<(usize, &CompiledRule<M>)> {
+    rules
+        .iter()
+        .flatten()
+        .enumerate()
+        .filter(|(_, rule)| !matches!(rule.matcher, RuleMatcher::Any))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_rules() {
+        let rules: Vec<Rule> = serde_json::from_str(
+            r#"["quay.io", {"pattern": "ghcr.io/*", "kind": "glob", "message": "no ghcr.io"}]"#,
+        )
+        .expect("cannot parse rules");
+        assert_eq!(
+            rules,
+            vec![
+                Rule::from("quay.io"),
+                Rule::Spec(RuleSpec {
+                    pattern: "ghcr.io/*".to_string(),
+                    kind: Some(RuleKind::Glob),
+                    message: Some("no ghcr.io".to_string()),
+                    expires_at: None,
+                    enforcement: None,
+                }),
+            ]
+        );
+        assert!(serde_json::from_str::<Rule>(r#"{"pattern": "a", "kind": "fuzzy"}"#).is_err());
+    }
+
+    #[test]
+    fn validate_rules() {
+        assert!(Rule::from("quay.io").validate().is_ok());
+        assert_eq!(
+            Rule::from("").validate(),
+            Err("rules must provide a pattern".to_string())
+        );
+        let rule: Rule = serde_json::from_str(r#"{"pattern": "(", "kind": "regex"}"#)
+            .expect("cannot parse rule");
+        assert!(rule.validate().unwrap_err().starts_with("invalid regex ("));
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn regex_limits() {
+        let validate = |pattern: &str| {
+            serde_json::from_value::<Rule>(serde_json::json!({"pattern": pattern, "kind": "regex"}))
+                .expect("cannot parse rule")
+                .validate()
+        };
+        assert_eq!(validate("(latest|dev-.*)"), Ok(()));
+
+        let long = "a".repeat(1025);
+        assert_eq!(
+            validate(&long),
+            Err(format!(
+                "invalid regex {}: longer than 1024 characters",
+                long
+            ))
+        );
+        assert_eq!(
+            validate("((a{100}){100}){100}"),
+            Err(
+                "invalid regex ((a{100}){100}){100}: compiled regex exceeds the limit of 1048576 bytes"
+                    .to_string()
+            )
+        );
+        let nested = format!("{}a{}", "(".repeat(40), ")".repeat(40));
+        assert!(validate(&nested)
+            .unwrap_err()
+            .starts_with(&format!("invalid regex {}:", nested)));
+        assert_eq!(
+            validate(&format!("{}a{}", "(".repeat(20), ")".repeat(20))),
+            Ok(())
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "regex"))]
+    fn glob_only_build() {
+        let rule: Rule = serde_json::from_str(r#"{"pattern": "dev-.*", "kind": "regex"}"#)
+            .expect("cannot parse rule");
+        assert_eq!(
+            rule.validate(),
+            Err(
+                "invalid regex dev-.*: regex rules are not supported by this build of the policy"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn rule_matches() {
+        let compile_default = |pattern: &str| Ok(pattern.to_string());
+        fn default_matches(value: &str) -> impl Fn(&String) -> bool + '_ {
+            move |pattern| value.starts_with(pattern.as_str())
+        }
+        let rule = Rule::from("quay")
+            .compile(compile_default)
+            .expect("cannot compile rule");
+        assert!(rule.matches("quay.io", default_matches("quay.io")));
+
+        let rule = |kind: &str, pattern: &str| -> CompiledRule<String> {
+            serde_json::from_value::<Rule>(serde_json::json!({"pattern": pattern, "kind": kind}))
+                .expect("cannot parse rule")
+                .compile(compile_default)
+                .expect("cannot compile rule")
+        };
+        assert!(!rule("exact", "quay").matches("quay.io", default_matches("quay.io")));
+        assert!(rule("exact", "quay.io").matches("quay.io", default_matches("quay.io")));
+        assert!(rule("glob", "*.io").matches("quay.io", default_matches("quay.io")));
+        assert!(!rule("glob", "*.com").matches("quay.io", default_matches("quay.io")));
+        #[cfg(feature = "regex")]
+        assert!(rule("regex", r"[a-z]+\.io").matches("quay.io", default_matches("quay.io")));
+        #[cfg(feature = "regex")]
+        assert!(!rule("regex", "quay").matches("quay.io", default_matches("quay.io")));
+    }
+
+    #[test]
+    fn expiring_rules() {
+        let rule = |expires_at: &str| -> Rule {
+            serde_json::from_value(
+                serde_json::json!({"pattern": "quay.io", "expiresAt": expires_at}),
+            )
+            .expect("cannot parse rule")
+        };
+        let matches = |rule: &Rule| {
+            rule.compile(|pattern| Ok(pattern.to_string()))
+                .expect("cannot compile rule")
+                .matches("quay.io", |pattern: &String| pattern == "quay.io")
+        };
+        let now = SystemTime::now();
+
+        let expired = rule("2000-01-01T00:00:00Z");
+        assert_eq!(expired.validate(), Ok(()));
+        assert!(expired.is_expired(now));
+        assert!(!matches(&expired));
+
+        let active = rule("2999-01-01T00:00:00+02:00");
+        assert!(!active.is_expired(now));
+        assert!(matches(&active));
+
+        assert!(rule("next week")
+            .validate()
+            .unwrap_err()
+            .starts_with("invalid expiresAt of rule quay.io: expected an RFC 3339 time"));
+    }
+
+    #[test]
+    fn compiled_list() {
+        let compile_default = |pattern: &str| Ok(pattern.to_string());
+        let matches = |value: &'static str| {
+            move |rule: &CompiledRule<String>| rule.matches(value, |pattern| pattern == value)
+        };
+        let list = CompiledList {
+            allow: compile_rules(
+                &Some(vec!["quay.io".into(), "ghcr.io".into()]),
+                compile_default,
+            )
+            .expect("cannot compile rules"),
+            reject: None,
+            defaults: ListDefaults::default(),
+        };
+        assert!(list.is_allowed(matches("ghcr.io")));
+        assert!(!list.is_allowed(matches("docker.io")));
+        assert_eq!(list.allow_index(matches("ghcr.io")), Some(1));
+
+        let list = CompiledList {
+            allow: None,
+            reject: compile_rules(&Some(vec!["docker.io".into()]), compile_default)
+                .expect("cannot compile rules"),
+            defaults: ListDefaults::default(),
+        };
+        assert!(list.is_allowed(matches("quay.io")));
+        assert!(!list.is_allowed(matches("docker.io")));
+        assert_eq!(
+            list.violated_by(Enforcement::Deny, matches("docker.io"))
+                .map(|rule| rule.pattern.as_str()),
+            Some("docker.io")
+        );
+    }
+
+    #[test]
+    fn rule_precedence() {
+        let compile_default = |pattern: &str| Ok(pattern.to_string());
+        let matches = |value: &'static str| {
+            move |rule: &CompiledRule<String>| rule.matches(value, |pattern| pattern == value)
+        };
+        let list = |precedence| CompiledList {
+            allow: compile_rules(
+                &Some(vec!["quay.io".into(), "docker.io".into()]),
+                compile_default,
+            )
+            .expect("cannot compile rules"),
+            reject: compile_rules(&Some(vec!["docker.io".into()]), compile_default)
+                .expect("cannot compile rules"),
+            defaults: ListDefaults {
+                precedence,
+                default_action: None,
+            },
+        };
+        let list_reject_wins = list(RulePrecedence::RejectWins);
+        assert!(list_reject_wins.is_allowed(matches("quay.io")));
+        assert!(!list_reject_wins.is_allowed(matches("docker.io")));
+        assert!(!list_reject_wins.is_allowed(matches("ghcr.io")));
+        assert!(list_reject_wins
+            .violated_by(Enforcement::Deny, matches("docker.io"))
+            .is_some());
+        assert!(list_reject_wins
+            .violated_by(Enforcement::Deny, matches("ghcr.io"))
+            .is_none());
+
+        let list_allow_wins = list(RulePrecedence::AllowWins);
+        assert!(list_allow_wins.is_allowed(matches("quay.io")));
+        assert!(list_allow_wins.is_allowed(matches("docker.io")));
+        assert!(!list_allow_wins.is_allowed(matches("ghcr.io")));
+        assert!(list_allow_wins
+            .violated_by(Enforcement::Deny, matches("docker.io"))
+            .is_none());
+    }
+
+    #[test]
+    fn enforcement() {
+        let compile_default = |pattern: &str| Ok(pattern.to_string());
+        let matches = |value: &'static str| {
+            move |rule: &CompiledRule<String>| rule.matches(value, |pattern| pattern == value)
+        };
+        let rules: Vec<Rule> =
+            serde_json::from_str(r#"[{"pattern": "docker.io", "enforcement": "warn"}, "ghcr.io"]"#)
+                .expect("cannot parse rules");
+        let list = CompiledList {
+            allow: None,
+            reject: compile_rules(&Some(rules), compile_default).expect("cannot compile rules"),
+            defaults: ListDefaults::default(),
+        };
+        assert!(list.is_allowed(matches("docker.io")));
+        assert!(!list.is_allowed(matches("ghcr.io")));
+        assert!(list
+            .violated_by(Enforcement::Deny, matches("docker.io"))
+            .is_none());
+        assert_eq!(
+            list.violated_by(Enforcement::Warn, matches("docker.io"))
+                .map(|rule| rule.pattern.as_str()),
+            Some("docker.io")
+        );
+        assert!(list
+            .violated_by(Enforcement::Warn, matches("ghcr.io"))
+            .is_none());
+        assert!(list
+            .violated_by(Enforcement::Warn, matches("quay.io"))
+            .is_none());
+    }
+
+    #[test]
+    fn default_action() {
+        let compile_default = |pattern: &str| Ok(pattern.to_string());
+        let matches = |value: &'static str| {
+            move |rule: &CompiledRule<String>| rule.matches(value, |pattern| pattern == value)
+        };
+        let allow_list = |default_action| CompiledList {
+            allow: compile_rules(&Some(vec!["quay.io".into()]), compile_default)
+                .expect("cannot compile rules"),
+            reject: None,
+            defaults: ListDefaults {
+                default_action,
+                ..Default::default()
+            },
+        };
+        assert!(!allow_list(None).is_allowed(matches("docker.io")));
+        assert!(allow_list(Some(DefaultAction::Allow)).is_allowed(matches("docker.io")));
+        assert!(allow_list(Some(DefaultAction::Allow)).is_allowed(matches("quay.io")));
+
+        let reject_list = |default_action| CompiledList {
+            allow: None,
+            reject: compile_rules(&Some(vec!["docker.io".into()]), compile_default)
+                .expect("cannot compile rules"),
+            defaults: ListDefaults {
+                default_action,
+                ..Default::default()
+            },
+        };
+        assert!(reject_list(None).is_allowed(matches("quay.io")));
+        assert!(!reject_list(Some(DefaultAction::Deny)).is_allowed(matches("quay.io")));
+        assert!(!reject_list(Some(DefaultAction::Deny)).is_allowed(matches("docker.io")));
+    }
+}
@@ -0,0 +1,236 @@
+use anyhow::{anyhow, Result};
+use kubewarden::host_capabilities::kubernetes::ListAllResourcesRequest;
+use kubewarden_policy_sdk::wapc_guest;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+
+const DEFAULT_API_VERSION: &str = "aquasecurity.github.io/v1alpha1";
+const DEFAULT_KIND: &str = "VulnerabilityReport";
+
+/// Severity of a vulnerability.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Severity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+/// Rejects the images with vulnerabilities at or above `severity`,
+/// according to the vulnerability reports stored in the cluster by a
+/// scanner, like the ones of the Trivy operator.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct VulnerabilityScan {
+    #[serde(default = "default_api_version")]
+    pub(crate) api_version: String,
+    #[serde(default = "default_kind")]
+    pub(crate) kind: String,
+    pub(crate) severity: Severity,
+    /// What to do when no report is found for an image.
+    #[serde(default)]
+    pub(crate) on_missing: OnMissingReport,
+}
+
+/// What to do with the images that have no vulnerability report.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum OnMissingReport {
+    /// Reject the images, as they might not have been scanned yet.
+    #[default]
+    Reject,
+    /// Accept the images.
+    Ignore,
+}
+
+fn default_api_version() -> String {
+    DEFAULT_API_VERSION.to_string()
+}
+
+fn default_kind() -> String {
+    DEFAULT_KIND.to_string()
+}
+
+/// Vulnerability reports stored in the cluster.
+#[derive(Deserialize)]
+pub(crate) struct VulnerabilityReports {
+    #[serde(default)]
+    items: Vec<VulnerabilityReport>,
+}
+
+#[derive(Deserialize)]
+struct VulnerabilityReport {
+    report: Report,
+}
+
+#[derive(Deserialize)]
+struct Report {
+    artifact: Artifact,
+    #[serde(default)]
+    summary: Summary,
+}
+
+#[derive(Deserialize)]
+struct Artifact {
+    #[serde(default)]
+    digest: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct Summary {
+    #[serde(default)]
+    critical_count: u64,
+    #[serde(default)]
+    high_count: u64,
+    #[serde(default)]
+    medium_count: u64,
+    #[serde(default)]
+    low_count: u64,
+}
+
+impl Summary {
+    /// Describes the vulnerabilities at or above `severity`, like
+    /// `2 critical, 1 high`, or returns None when there are none.
+    fn findings(&self, severity: Severity) -> Option<String> {
+        let findings: Vec<String> = [
+            (Severity::Critical, self.critical_count),
+            (Severity::High, self.high_count),
+            (Severity::Medium, self.medium_count),
+            (Severity::Low, self.low_count),
+        ]
+        .iter()
+        .filter(|(level, count)| *level >= severity && *count > 0)
+        .map(|(level, count)| format!("{} {}", count, level.as_str()))
+        .collect();
+        if findings.is_empty() {
+            None
+        } else {
+            Some(findings.join(", "))
+        }
+    }
+}
+
+impl VulnerabilityScan {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.api_version.is_empty() || self.kind.is_empty() {
+            return Err("vulnerabilityScan must provide an apiVersion and a kind".to_string());
+        }
+        Ok(())
+    }
+
+    /// Returns why the image with the given digest is rejected, if it
+    /// is. `reports` is only called when the digest is known, so that
+    /// the caller can list the reports once for all the images of a
+    /// request, see `fetch_reports`.
+    pub(crate) fn rejection(
+        &self,
+        digest: Option<&str>,
+        reports: impl FnOnce() -> Result<Rc<VulnerabilityReports>, String>,
+    ) -> Option<String> {
+        let findings = match digest {
+            Some(digest) => reports().and_then(|reports| self.findings(&reports, digest)),
+            None => Err("unknown digest".to_string()),
+        };
+        match findings {
+            Ok(findings) => findings,
+            Err(_) if self.on_missing == OnMissingReport::Ignore => None,
+            Err(_) => Some("no vulnerability report".to_string()),
+        }
+    }
+
+    /// Lists the reports of the cluster. Scanners like the Trivy
+    /// operator do not label the reports with the digest of the image,
+    /// so they cannot be narrowed down by a selector.
+    pub(crate) fn fetch_reports(&self) -> Result<VulnerabilityReports> {
+        let msg = serde_json::to_vec(&ListAllResourcesRequest {
+            api_version: self.api_version.clone(),
+            kind: self.kind.clone(),
+            label_selector: None,
+            field_selector: None,
+        })?;
+        let response =
+            wapc_guest::host_call("kubewarden", "kubernetes", "list_all_resources", &msg)
+                .map_err(|e| anyhow!("{}", e))?;
+        Ok(serde_json::from_slice(&response)?)
+    }
+
+    /// Describes the vulnerabilities of the image with the given digest.
+    fn findings(
+        &self,
+        reports: &VulnerabilityReports,
+        digest: &str,
+    ) -> Result<Option<String>, String> {
+        let report = reports
+            .items
+            .iter()
+            .find(|report| report.report.artifact.digest.as_deref() == Some(digest))
+            .ok_or_else(|| format!("no vulnerability report for {}", digest))?;
+        Ok(report.report.summary.findings(self.severity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vulnerability_findings() {
+        let scan: VulnerabilityScan =
+            serde_json::from_str(r#"{"severity": "high"}"#).expect("cannot parse scan");
+        assert_eq!(scan.kind, "VulnerabilityReport");
+        assert_eq!(scan.on_missing, OnMissingReport::Reject);
+        let reports: Rc<VulnerabilityReports> = Rc::new(
+            serde_json::from_str(
+                r#"{"items": [
+                    {"report": {"artifact": {"repository": "library/nginx", "digest": "sha256:abc"}, "summary": {"criticalCount": 2, "highCount": 1, "mediumCount": 7}}},
+                    {"report": {"artifact": {"repository": "library/redis", "digest": "sha256:def"}, "summary": {"mediumCount": 3}}}
+                ]}"#,
+            )
+            .expect("cannot parse reports"),
+        );
+        assert_eq!(
+            scan.findings(&reports, "sha256:abc").unwrap(),
+            Some("2 critical, 1 high".to_string())
+        );
+        assert_eq!(scan.findings(&reports, "sha256:def").unwrap(), None);
+        assert!(scan.findings(&reports, "sha256:123").is_err());
+        assert_eq!(
+            scan.rejection(Some("sha256:abc"), || Ok(reports.clone())),
+            Some("2 critical, 1 high".to_string())
+        );
+        assert_eq!(
+            scan.rejection(None, || panic!("no digest to look up")),
+            Some("no vulnerability report".to_string())
+        );
+        assert_eq!(
+            scan.rejection(Some("sha256:abc"), || Err("forbidden".to_string())),
+            Some("no vulnerability report".to_string())
+        );
+
+        let scan: VulnerabilityScan =
+            serde_json::from_str(r#"{"severity": "medium", "onMissing": "ignore"}"#)
+                .expect("cannot parse scan");
+        assert_eq!(
+            scan.findings(&reports, "sha256:def").unwrap(),
+            Some("3 medium".to_string())
+        );
+        assert_eq!(
+            scan.rejection(Some("sha256:123"), || Ok(reports.clone())),
+            None
+        );
+    }
+}
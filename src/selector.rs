@@ -0,0 +1,159 @@
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use std::collections::BTreeMap;
+
+/// Operators accepted by the `matchExpressions` of label selectors.
+const OPERATORS: &[&str] = &["In", "NotIn", "Exists", "DoesNotExist"];
+
+/// Validates the requirements of a label selector, like the API server
+/// does for the selectors of workloads.
+pub(crate) fn validate_selector(selector: &LabelSelector) -> Result<(), String> {
+    for requirement in selector.match_expressions.iter().flatten() {
+        let has_values = requirement
+            .values
+            .as_ref()
+            .map(|values| !values.is_empty())
+            .unwrap_or(false);
+        match requirement.operator.as_str() {
+            "In" | "NotIn" if !has_values => {
+                return Err(format!(
+                    "selector requirement for {} must provide values with operator {}",
+                    requirement.key, requirement.operator
+                ))
+            }
+            "Exists" | "DoesNotExist" if has_values => {
+                return Err(format!(
+                    "selector requirement for {} cannot provide values with operator {}",
+                    requirement.key, requirement.operator
+                ))
+            }
+            operator if !OPERATORS.contains(&operator) => {
+                return Err(format!(
+                    "unknown selector operator {}, expected one of {}",
+                    operator,
+                    OPERATORS.join(", ")
+                ))
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Returns true when the labels satisfy all the requirements of the
+/// selector. An empty selector matches every object.
+pub(crate) fn selector_matches(
+    selector: &LabelSelector,
+    labels: &BTreeMap<String, String>,
+) -> bool {
+    let labels_match = selector
+        .match_labels
+        .iter()
+        .flatten()
+        .all(|(key, value)| labels.get(key) == Some(value));
+    labels_match
+        && selector
+            .match_expressions
+            .iter()
+            .flatten()
+            .all(|requirement| {
+                let value = labels.get(&requirement.key);
+                let listed = |value: &String| {
+                    requirement
+                        .values
+                        .iter()
+                        .flatten()
+                        .any(|listed| listed == value)
+                };
+                match requirement.operator.as_str() {
+                    "In" => value.map(listed).unwrap_or(false),
+                    "NotIn" => !value.map(listed).unwrap_or(false),
+                    "Exists" => value.is_some(),
+                    "DoesNotExist" => value.is_none(),
+                    _ => false,
+                }
+            })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selector(selector: serde_json::Value) -> LabelSelector {
+        serde_json::from_value(selector).expect("cannot parse selector")
+    }
+
+    #[test]
+    fn validate_selectors() {
+        assert_eq!(
+            validate_selector(&selector(serde_json::json!({
+                "matchLabels": {"tier": "critical"},
+                "matchExpressions": [{"key": "team", "operator": "Exists"}]
+            }))),
+            Ok(())
+        );
+        assert_eq!(
+            validate_selector(&selector(serde_json::json!({
+                "matchExpressions": [{"key": "tier", "operator": "In"}]
+            }))),
+            Err("selector requirement for tier must provide values with operator In".to_string())
+        );
+        assert!(validate_selector(&selector(serde_json::json!({
+            "matchExpressions": [{"key": "tier", "operator": "Exists", "values": ["critical"]}]
+        })))
+        .is_err());
+        assert!(validate_selector(&selector(serde_json::json!({
+            "matchExpressions": [{"key": "tier", "operator": "Gt", "values": ["1"]}]
+        })))
+        .unwrap_err()
+        .starts_with("unknown selector operator Gt"));
+    }
+
+    #[test]
+    fn match_selectors() {
+        let labels: BTreeMap<String, String> = vec![
+            ("tier".to_string(), "critical".to_string()),
+            ("team".to_string(), "payments".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        for (matching, selector_value) in &[
+            (true, serde_json::json!({})),
+            (
+                true,
+                serde_json::json!({"matchLabels": {"tier": "critical"}}),
+            ),
+            (
+                false,
+                serde_json::json!({"matchLabels": {"tier": "critical", "env": "prod"}}),
+            ),
+            (
+                true,
+                serde_json::json!({"matchExpressions": [
+                    {"key": "tier", "operator": "In", "values": ["critical", "high"]},
+                    {"key": "env", "operator": "NotIn", "values": ["dev"]},
+                    {"key": "team", "operator": "Exists"},
+                    {"key": "legacy", "operator": "DoesNotExist"}
+                ]}),
+            ),
+            (
+                false,
+                serde_json::json!({"matchExpressions": [
+                    {"key": "team", "operator": "NotIn", "values": ["payments"]}
+                ]}),
+            ),
+            (
+                false,
+                serde_json::json!({"matchExpressions": [
+                    {"key": "env", "operator": "In", "values": ["prod"]}
+                ]}),
+            ),
+        ] {
+            assert_eq!(
+                selector_matches(&selector(selector_value.clone()), &labels),
+                *matching,
+                "{}",
+                selector_value
+            );
+        }
+    }
+}
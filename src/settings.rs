@@ -1,383 +1,8045 @@
 use k8s_openapi::api::core::v1 as apicore;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use kubewarden::host_capabilities::verification::KeylessInfo;
+use kubewarden::request::GroupVersionKind;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use slog::{debug, info, Logger};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
 
 use kubewarden::settings::Validatable;
 
+use crate::context::{RulesFrom, TrustedRepositoriesReference};
+use crate::duration::{format_duration, parse_duration};
+use crate::exception::Exceptions;
+use crate::extract::{CustomResourceRule, CustomWorkload};
+use crate::image::validate_digest;
+use crate::image::{
+    has_uppercase_repository, is_docker_hub_alias, split_transport, ImageParseError, ImagePattern,
+    DEFAULT_REGISTRY,
+};
+use crate::logging::{logger, LogLevel};
+use crate::migration::{migrate, CURRENT_API_VERSION};
+use crate::oci::{
+    attestation_predicate_types, attestation_reference, image_created, image_exists,
+    image_platforms, image_user, is_root_user, resolve_digest, Platform,
+};
+use crate::pattern::glob_matches;
+use crate::registry::{
+    is_ip_registry, is_loopback_registry, split_host_port, validate_registry_pattern,
+    RegistryPattern, HTTPS_PORT,
+};
+use crate::rule::{
+    apply_match_strategy, compile_rules, CompiledList, CompiledRule, DefaultAction, ListDefaults,
+    Rule, RulePrecedence, Specific,
+};
+use crate::rule::{Enforcement, RuleKind, RuleSpec};
+use crate::scan::{VulnerabilityReports, VulnerabilityScan};
+use crate::selector::{selector_matches, validate_selector};
+use crate::tag::{is_pre_release_tag, is_version_range, parse_version_range, TagPattern};
+use crate::template::MessageTemplate;
+use crate::verification::{verify_attestation_signatures, verify_image_signatures};
 use crate::Image;
 
-#[derive(Serialize, Deserialize, Default, Debug)]
+const DEFAULT_DECISION_ANNOTATION_KEY: &str = "trusted-repos.policy/decision";
+const DEFAULT_VIOLATIONS_ANNOTATION_KEY: &str = "trusted-repos.policy/violations";
+const DEFAULT_QUARANTINE_LABEL_KEY: &str = "trusted-repos.policy/quarantined";
+
+/// Fields of the settings that cannot be overridden per namespace, since
+/// they are resolved before the namespace of the request is looked at.
+const NON_OVERRIDABLE_FIELDS: [&str; 4] = [
+    "apiVersion",
+    "namespaceOverrides",
+    "trustedRepositories",
+    "rulesFrom",
+];
+
+/// Fields of the settings that `Settings::merge` takes from the sources
+/// of rules stored in the cluster.
+const MERGED_SETTINGS: [&str; 16] = [
+    "registries",
+    "tags",
+    "images",
+    "registryPresets",
+    "registryRules",
+    "namespaceRules",
+    "insecureRegistries",
+    "privateRegistries",
+    "allowedRegistryPorts",
+    "allowedPullSecrets",
+    "imageTags",
+    "allowedDigests",
+    "blockedDigests",
+    "allowedDigestsPerImage",
+    "signatures",
+    "attestations",
+];
+
+/// Most entries accepted in a list of the settings, like an allow list or
+/// the tags of an `imageTags` entry. Every request is checked against the
+/// lists, so their size bounds the work done per request.
+const MAX_LIST_ENTRIES: usize = 1000;
+
+/// Most rules accepted in the scoped rules of the settings, like
+/// `namespaceRules` or `signatures`.
+const MAX_SCOPED_RULES: usize = 100;
+
+/// Longest pattern accepted in the lists of the settings.
+const MAX_PATTERN_LENGTH: usize = 1024;
+
+/// Deepest nesting of objects and arrays accepted in the raw settings.
+const MAX_SETTINGS_DEPTH: usize = 16;
+
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug)]
+#[serde(rename_all = "camelCase")]
 pub(crate) struct Settings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api_version: Option<String>,
     registries: Option<Registries>,
+    /// Registries of cloud providers allowed along with the entries of
+    /// `registries.allow`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    registry_presets: Option<Vec<RegistryPreset>>,
     tags: Option<Tags>,
     images: Option<Images>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    registry_rules: Option<Vec<RegistryRule>>,
+    /// Lists replacing the global ones in some namespaces, or for the
+    /// objects with some labels. The first entry matching the request
+    /// applies.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    namespace_rules: Option<Vec<NamespaceRule>>,
+    /// Partial settings replacing the global ones in the namespaces
+    /// matching their glob pattern, like `prod-*`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    namespace_overrides: Option<BTreeMap<String, serde_json::Value>>,
+    /// Registries whose images are always rejected, regardless of the
+    /// other filters.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    insecure_registries: Option<Vec<String>>,
+    /// Reject the images whose registry is addressed by an IP address
+    /// instead of a domain name.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    reject_ip_registries: bool,
+    /// Only allow the official images of the Docker Hub, in the
+    /// `library/` namespace, like `docker.io/library/nginx`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    official_images_only: bool,
+    /// Exempt the registries on the loopback interface of the nodes,
+    /// like the `localhost:5000` pull-through caches of kind or k3s,
+    /// from the registry rules, whatever their port.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    allow_node_local_registries: bool,
+    /// Reject the tags following a pre-release convention, like
+    /// `1.0-rc1` or `2.0-SNAPSHOT`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    reject_pre_release_tags: bool,
+    /// Evaluate the images referenced by neither a tag nor a digest,
+    /// like `nginx`, as if they were tagged `latest`, which is the tag
+    /// pulled, instead of leaving them out of the tag rules.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    untagged_as_latest: bool,
+    /// Handling of the image references that cannot be parsed.
+    #[serde(default, skip_serializing_if = "is_default_on_parse_error")]
+    on_parse_error: OnParseError,
+    /// Ports, besides 443, that the registries of the images can be
+    /// addressed with. Any port is accepted when not provided.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_registry_ports: Option<Vec<u16>>,
+    /// Registries requiring credentials: the pods pulling from them
+    /// must reference an image pull secret.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    private_registries: Option<Vec<String>>,
+    /// Names of the image pull secrets accepted for the private
+    /// registries, any secret when not provided.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_pull_secrets: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_tags: Option<BTreeMap<String, Vec<String>>>,
+    /// Decides between the allow and reject lists of the filters
+    /// providing both. Each filter accepts a single list when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rule_precedence: Option<RulePrecedence>,
+    /// Decision for the values matched by neither the allow nor the
+    /// reject list of a filter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    default_action: Option<DefaultAction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_digests: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blocked_digests: Option<Vec<String>>,
+    /// Digests allowed for the images of some repositories, like
+    /// `registry.internal/payments/api`. The other digests of these
+    /// repositories are rejected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_digests_per_image: Option<BTreeMap<String, Vec<String>>>,
+    /// Only admit the images whose digest is listed in `allowedDigests`
+    /// or `allowedDigestsPerImage`, ignoring the `registries`, `tags`
+    /// and `images` filters.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    strict_digest_mode: bool,
+    /// Resolve the digest of the images referenced by tag, so that
+    /// they are also checked against the digest lists.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    resolve_digests: bool,
+    /// Match `index.docker.io` and `registry-1.docker.io` as written,
+    /// instead of treating them as `docker.io`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    disable_registry_aliases: bool,
+    /// Require every image to be referenced by both a tag and a digest,
+    /// like `quay.io/app:1.2.3@sha256:...`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    require_tag_and_digest: bool,
+    /// Reject the images whose reference does not name a registry, like
+    /// `nginx:1.25`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    require_fully_qualified: bool,
+    /// Reject the images referenced by digest without a tag, like
+    /// `quay.io/app@sha256:...`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    reject_digest_only: bool,
+    /// Reject the images whose manifest cannot be found in their
+    /// registry.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    verify_image_exists: bool,
+    /// Platforms, like `linux/arm64`, the manifest lists of the images
+    /// must include.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    required_platforms: Option<Vec<String>>,
+    /// Reject the containers whose image runs as root, unless the pod
+    /// makes them run as another user.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    reject_root_images: bool,
+    /// Maximum time elapsed since the creation of the images, like
+    /// `90d`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_image_age: Option<String>,
+    /// Minimum time elapsed since the creation of the images.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_image_age: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vulnerability_scan: Option<VulnerabilityScan>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signatures: Option<Vec<SignatureRule>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attestations: Option<Vec<AttestationRule>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_pull_policy: Option<ImagePullPolicyRule>,
+    /// Registry prepended to the image references that do not name
+    /// one, like `registry.internal` turning `nginx:1.25` into
+    /// `registry.internal/nginx:1.25`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    default_registry: Option<String>,
+    /// Registries searched, in order, for the images whose reference
+    /// does not name one, like the `unqualified-search-registries` of
+    /// the `registries.conf` of CRI-O and Podman. The images are
+    /// evaluated as pulled from each of them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    short_name_search_registries: Option<Vec<String>>,
+    /// Reject the short names that the other rules allow from more
+    /// than one search registry, since the registry they are pulled
+    /// from depends on which ones host them.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    reject_ambiguous_short_names: bool,
+    /// Rewrite the image references of the containers to their fully
+    /// qualified form, like `docker.io/library/nginx:latest` for
+    /// `nginx`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    canonicalize_images: bool,
+    /// Replacements of the image references with a known-bad floating
+    /// tag, like `nginx:1.25`, by an approved one, like
+    /// `nginx:1.25@sha256:...` or `nginx:1.25.3`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag_replacements: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) decision_annotation: Option<DecisionAnnotation>,
+    /// Accept the requests violating the rules, annotating their object
+    /// with the violations, instead of rejecting them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) quarantine: Option<Quarantine>,
+    /// Break-glass exceptions, granted by a token signed with a shared
+    /// secret in an annotation of the object.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exceptions: Option<Exceptions>,
+    /// Template of the rejection message, rendered for every violation
+    /// found in the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_template: Option<String>,
+    /// Admission operations evaluated by the policy, all of them when
+    /// not provided.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    operations: Option<Vec<Operation>>,
+    /// Kinds of the objects evaluated, as `group/kind`, or `kind` for
+    /// the core group, like `apps/Deployment` or `Pod`. Every supported
+    /// kind is evaluated when not provided.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resources: Option<Vec<String>>,
+    /// Skip the pods whose controller, like a `ReplicaSet`, is of a
+    /// kind evaluated by the policy, since its images were already
+    /// evaluated.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    skip_owned_pods: bool,
+    /// Names of the containers whose images are evaluated, all of them
+    /// when not provided.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    container_selector: Option<ContainerSelector>,
+    /// Containers added by injectors, like service meshes, that are
+    /// not evaluated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skip_injected_containers: Option<InjectedContainers>,
+    /// Handling of the mirror pods, created by the kubelets for their
+    /// static pods, which run whatever the policy decides.
+    #[serde(default, skip_serializing_if = "is_default_mirror_pods")]
+    pub(crate) mirror_pods: MirrorPods,
+    /// Resources, other than pods, whose images are evaluated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    custom_resource_rules: Option<Vec<CustomResourceRule>>,
+    /// Workloads, like Knative services, whose images are evaluated
+    /// with the built-in rules of the policy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    supported_custom_workloads: Option<Vec<CustomWorkload>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) trusted_repositories: Option<TrustedRepositoriesReference>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) rules_from: Option<RulesFrom>,
+    #[serde(default, skip_serializing_if = "is_default_log_level")]
+    log_level: LogLevel,
+    /// Log, for every image, the rules evaluated and the one that took
+    /// the decision.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    debug_rules: bool,
+    /// Log the decision taken on every request, skipped ones included,
+    /// along with the rules that decided it, so that the decisions can
+    /// be counted from the logs.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) log_decisions: bool,
+    /// Hash of the serialized settings, computed on the first call of
+    /// `hash` as the settings do not change once merged.
+    #[serde(skip)]
+    hash: RefCell<Option<Vec<u8>>>,
+}
+
+/// Formats a kind as listed in `resources`, like `apps/Deployment`, or
+/// `Pod` for the core group.
+fn group_kind(group: &str, kind: &str) -> String {
+    if group.is_empty() {
+        kind.to_string()
+    } else {
+        format!("{}/{}", group, kind)
+    }
+}
+
+fn is_default_log_level(log_level: &LogLevel) -> bool {
+    *log_level == LogLevel::default()
+}
+
+fn is_default_mirror_pods(mirror_pods: &MirrorPods) -> bool {
+    *mirror_pods == MirrorPods::default()
+}
+
+fn is_default_on_parse_error(on_parse_error: &OnParseError) -> bool {
+    *on_parse_error == OnParseError::default()
 }
 
-#[derive(Serialize, Deserialize, Default, Debug)]
+/// Organizations are only matched by the images lists.
+fn reject_organization_rule(rule: &Rule) -> Result<(), String> {
+    if rule.kind() == Some(RuleKind::Organization) {
+        return Err(format!(
+            "organization rule {} is only supported by the images lists",
+            rule.pattern()
+        ));
+    }
+    Ok(())
+}
+
+/// Allow entries cannot reject anything, so they are always enforced.
+fn reject_warn_allow_rule(rule: &Rule) -> Result<(), String> {
+    if rule.enforcement() == Enforcement::Warn {
+        return Err(format!(
+            "allow entry {}: only reject entries can have a warn enforcement",
+            rule.pattern()
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug)]
 pub(crate) struct Registries {
-    allow: Option<Vec<String>>,
-    reject: Option<Vec<String>>,
+    allow: Option<Vec<Rule>>,
+    reject: Option<Vec<Rule>>,
+    /// How the entries without a `kind` are matched, instead of the
+    /// default matching of the list.
+    #[serde(rename = "matchStrategy", skip_serializing_if = "Option::is_none")]
+    match_strategy: Option<RuleKind>,
+}
+
+impl Registries {
+    /// Validates the lists, see `Tags::validate`.
+    fn validate(&self, both_lists: bool) -> Result<(), String> {
+        if self.allow.is_none() && self.reject.is_none()
+            || self.allow.is_some() && self.reject.is_some() && !both_lists
+        {
+            return Err(
+                "only one of registries allow or reject can be provided, and one must be provided"
+                    .to_string(),
+            );
+        }
+        let (allow, reject) = self.rules();
+        for registry in allow.iter().flatten() {
+            reject_warn_allow_rule(registry)?;
+        }
+        for registry in allow.iter().chain(reject.iter()).flatten() {
+            registry.validate()?;
+            reject_organization_rule(registry)?;
+            if let Some(registry) = registry.default_pattern() {
+                validate_registry_pattern(registry)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiles the lists, the host patterns of the presets being
+    /// appended to the allow list.
+    fn compile(
+        &self,
+        presets: &[RegistryPreset],
+        parse: RegistryPatternParser,
+        defaults: ListDefaults,
+    ) -> Result<CompiledList<RegistryPattern>, String> {
+        let (mut allow, reject) = self.rules();
+        if !presets.is_empty() {
+            allow
+                .get_or_insert_with(Vec::new)
+                .extend(presets.iter().flat_map(|preset| preset.rules()));
+        }
+        Ok(CompiledList {
+            allow: compile_rules(&allow, parse)?,
+            reject: compile_rules(&reject, parse)?,
+            defaults,
+        })
+    }
+
+    /// Returns the allow and reject lists, see `apply_match_strategy`.
+    fn rules(&self) -> (Option<Vec<Rule>>, Option<Vec<Rule>>) {
+        (
+            apply_match_strategy(&self.allow, self.match_strategy),
+            apply_match_strategy(&self.reject, self.match_strategy),
+        )
+    }
+}
+
+/// Registries of a cloud provider, allowed by `registryPresets` without
+/// having to list their regional hosts.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum RegistryPreset {
+    /// Private registries of Amazon ECR, in every region and partition.
+    AwsEcr,
+    /// Google Container Registry, and its multi-regional hosts.
+    Gcr,
+    /// GitHub Container Registry.
+    Ghcr,
+    /// Quay.io.
+    Quay,
+    /// Azure Container Registry, in every cloud.
+    Acr,
+}
+
+impl RegistryPreset {
+    /// Glob patterns of the hosts of the registries.
+    fn patterns(self) -> &'static [&'static str] {
+        match self {
+            RegistryPreset::AwsEcr => &[
+                "*.dkr.ecr.*.amazonaws.com",
+                "*.dkr.ecr-fips.*.amazonaws.com",
+                "*.dkr.ecr.*.amazonaws.com.cn",
+            ],
+            RegistryPreset::Gcr => &["gcr.io", "*.gcr.io"],
+            RegistryPreset::Ghcr => &["ghcr.io"],
+            RegistryPreset::Quay => &["quay.io"],
+            RegistryPreset::Acr => &["*.azurecr.io", "*.azurecr.cn", "*.azurecr.us"],
+        }
+    }
+
+    /// Allow rules matching the hosts of the registries.
+    fn rules(self) -> impl Iterator<Item = Rule> {
+        self.patterns().iter().map(|pattern| {
+            let kind = if pattern.contains('*') {
+                RuleKind::Glob
+            } else {
+                RuleKind::Exact
+            };
+            Rule::Spec(RuleSpec {
+                pattern: pattern.to_string(),
+                kind: Some(kind),
+                message: None,
+                expires_at: None,
+                enforcement: None,
+            })
+        })
+    }
 }
 
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug)]
 pub(crate) struct Tags {
-    reject: Option<Vec<String>>,
+    allow: Option<Vec<Rule>>,
+    reject: Option<Vec<Rule>>,
+    /// How the entries without a `kind` are matched, instead of the
+    /// default matching of the list.
+    #[serde(rename = "matchStrategy", skip_serializing_if = "Option::is_none")]
+    match_strategy: Option<RuleKind>,
+}
+
+impl Tags {
+    /// Validates the lists. Both of them can only be provided when
+    /// `both_lists` is true, that is when a rule precedence is set.
+    fn validate(&self, both_lists: bool) -> Result<(), String> {
+        if self.allow.is_some() && self.reject.is_some() && !both_lists {
+            return Err("only one of tags allow or reject can be provided".to_string());
+        }
+        let (allow, reject) = self.rules();
+        for rule in allow.iter().flatten() {
+            reject_warn_allow_rule(rule)?;
+        }
+        for rule in allow.iter().chain(reject.iter()).flatten() {
+            rule.validate()?;
+            reject_organization_rule(rule)?;
+        }
+        if let Some(allowed_tags) = &allow {
+            for allowed_tag in allowed_tags.iter().filter_map(Rule::default_pattern) {
+                if is_version_range(allowed_tag) {
+                    parse_version_range(allowed_tag)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn compile(&self, defaults: ListDefaults) -> Result<CompiledList<TagPattern>, String> {
+        let (allow, reject) = self.rules();
+        Ok(CompiledList {
+            allow: compile_rules(&allow, TagPattern::parse)?,
+            // Reject entries always match the tag exactly
+            reject: compile_rules(&reject, |rule| Ok(TagPattern::Exact(rule.to_string())))?,
+            defaults,
+        })
+    }
+
+    /// Returns the allow and reject lists, see `apply_match_strategy`.
+    fn rules(&self) -> (Option<Vec<Rule>>, Option<Vec<Rule>>) {
+        (
+            apply_match_strategy(&self.allow, self.match_strategy),
+            apply_match_strategy(&self.reject, self.match_strategy),
+        )
+    }
 }
 
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug)]
 pub(crate) struct Images {
-    allow: Option<Vec<String>>,
-    reject: Option<Vec<String>>,
+    allow: Option<Vec<Rule>>,
+    reject: Option<Vec<Rule>>,
+    /// How the entries without a `kind` are matched, instead of the
+    /// default matching of the list.
+    #[serde(rename = "matchStrategy", skip_serializing_if = "Option::is_none")]
+    match_strategy: Option<RuleKind>,
+}
+
+impl Images {
+    /// Validates the lists, see `Tags::validate`.
+    fn validate(&self, both_lists: bool) -> Result<(), String> {
+        if self.allow.is_none() && self.reject.is_none()
+            || self.allow.is_some() && self.reject.is_some() && !both_lists
+        {
+            return Err(
+                "only one of images allow or reject can be provided, and one must be provided"
+                    .to_string(),
+            );
+        }
+        let (allow, reject) = self.rules();
+        for rule in allow.iter().flatten() {
+            reject_warn_allow_rule(rule)?;
+        }
+        for rule in allow.iter().chain(reject.iter()).flatten() {
+            rule.validate()?;
+        }
+        Ok(())
+    }
+
+    fn compile<M, F>(
+        &self,
+        defaults: ListDefaults,
+        compile_default: F,
+    ) -> Result<CompiledList<M>, String>
+    where
+        M: Specific,
+        F: Fn(&str) -> Result<M, String>,
+    {
+        let (allow, reject) = self.rules();
+        Ok(CompiledList {
+            allow: compile_rules(&allow, &compile_default)?,
+            reject: compile_rules(&reject, &compile_default)?,
+            defaults,
+        })
+    }
+
+    /// Returns the allow and reject lists, see `apply_match_strategy`.
+    fn rules(&self) -> (Option<Vec<Rule>>, Option<Vec<Rule>>) {
+        (
+            apply_match_strategy(&self.allow, self.match_strategy),
+            apply_match_strategy(&self.reject, self.match_strategy),
+        )
+    }
+}
+
+/// Rules scoped to a single registry. The `images` lists contain glob
+/// patterns matched against the repository path inside the registry,
+/// e.g. `prometheus/*`.
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug)]
+pub(crate) struct RegistryRule {
+    registry: String,
+    images: Option<Images>,
+    tags: Option<Tags>,
+}
+
+impl RegistryRule {
+    fn validate(&self, both_lists: bool) -> Result<(), String> {
+        if self.registry.is_empty() {
+            return Err("registry rules must provide a registry".to_string());
+        }
+        validate_registry_pattern(&self.registry)?;
+        if let Some(tags) = &self.tags {
+            tags.validate(both_lists)
+                .map_err(|e| format!("registry rule for {}: {}", self.registry, e))?;
+        }
+        if let Some(images) = &self.images {
+            images
+                .validate(both_lists)
+                .map_err(|e| format!("registry rule for {}: {}", self.registry, e))?;
+        }
+        Ok(())
+    }
+
+    fn compile(
+        &self,
+        parse: RegistryPatternParser,
+        defaults: ListDefaults,
+    ) -> Result<CompiledRegistryRule, String> {
+        Ok(CompiledRegistryRule {
+            registry: parse(&self.registry)?,
+            images: self
+                .images
+                .as_ref()
+                .map(|images| images.compile(defaults, |pattern| Ok(pattern.to_string())))
+                .transpose()?,
+            tags: self
+                .tags
+                .as_ref()
+                .map(|tags| tags.compile(defaults))
+                .transpose()?,
+        })
+    }
+}
+
+/// Lists applying to the requests of some namespaces, or to the objects
+/// selected by their labels, instead of the global ones. Lists that are
+/// not provided are taken from the global settings.
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NamespaceRule {
+    /// Names of the namespaces, or glob patterns like `prod-*`. All the
+    /// namespaces when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    namespaces: Vec<String>,
+    /// Labels the object must have, all the objects when not provided.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    object_selector: Option<LabelSelector>,
+    registries: Option<Registries>,
+    tags: Option<Tags>,
+    images: Option<Images>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signatures: Option<Vec<SignatureRule>>,
+}
+
+impl NamespaceRule {
+    fn validate(&self, both_lists: bool) -> Result<(), String> {
+        if self.namespaces.is_empty() && self.object_selector.is_none()
+            || self.namespaces.iter().any(String::is_empty)
+        {
+            return Err(
+                "namespace rules must provide non empty namespaces or an objectSelector"
+                    .to_string(),
+            );
+        }
+        let context = |e: String| {
+            if self.namespaces.is_empty() {
+                format!("namespace rule with an objectSelector: {}", e)
+            } else {
+                format!("namespace rule for {}: {}", self.namespaces.join(","), e)
+            }
+        };
+        if let Some(object_selector) = &self.object_selector {
+            validate_selector(object_selector).map_err(context)?;
+        }
+        for signature in self.signatures.iter().flatten() {
+            signature.validate().map_err(context)?;
+        }
+        if let Some(registries) = &self.registries {
+            registries.validate(both_lists).map_err(context)?;
+        }
+        if let Some(tags) = &self.tags {
+            tags.validate(both_lists).map_err(context)?;
+        }
+        if let Some(images) = &self.images {
+            images.validate(both_lists).map_err(context)?;
+        }
+        Ok(())
+    }
+
+    fn applies_to(&self, scope: &RequestScope) -> bool {
+        (self.namespaces.is_empty()
+            || self
+                .namespaces
+                .iter()
+                .any(|pattern| glob_matches(pattern, scope.namespace)))
+            && self
+                .object_selector
+                .as_ref()
+                .map(|object_selector| selector_matches(object_selector, scope.labels))
+                .unwrap_or(true)
+    }
+}
+
+/// Namespace and labels of the object of a request, selecting the
+/// namespace rule applying to it.
+pub(crate) struct RequestScope<'a> {
+    pub(crate) namespace: &'a str,
+    pub(crate) labels: &'a BTreeMap<String, String>,
 }
 
+/// Name, allow list and reject list of a filter.
+type RuleLists<'a> = (String, &'a Option<Vec<Rule>>, &'a Option<Vec<Rule>>);
+
+thread_local! {
+    static COMPILED_FILTERS: RefCell<CompiledFiltersCache> = RefCell::new(Default::default());
+}
+
+/// Filters compiled by this instance of the policy, which receives the
+/// same settings on every request. The cache holds a single generation
+/// of settings: the filters, kept by index of the namespace rule
+/// providing them or None for the global ones, are dropped as soon as
+/// a request brings settings with another hash.
 #[derive(Default)]
+struct CompiledFiltersCache {
+    settings_hash: Vec<u8>,
+    filters: HashMap<Option<usize>, Rc<CompiledFilters>>,
+}
+
+/// Lists of the filters, whose patterns, like regular expressions, are
+/// expensive to compile.
+struct CompiledFilters {
+    registries: Option<CompiledList<RegistryPattern>>,
+    tags: Option<CompiledList<TagPattern>>,
+    images: Option<CompiledList<ImagePattern>>,
+    registry_rules: Option<Vec<CompiledRegistryRule>>,
+}
+
+/// Parses registry patterns, see `Settings::registry_pattern_parser`.
+type RegistryPatternParser = fn(&str) -> Result<RegistryPattern, String>;
+
+type ImagePatternParser = fn(&str) -> ImagePattern;
+
+/// Registry rule ready to be matched.
+struct CompiledRegistryRule {
+    registry: RegistryPattern,
+    images: Option<CompiledList<String>>,
+    tags: Option<CompiledList<TagPattern>>,
+}
+
+impl CompiledRegistryRule {
+    fn is_allowed_repository(&self, repository: &str) -> bool {
+        self.images
+            .as_ref()
+            .map(|images| images.is_allowed(repository_rule_matches(repository)))
+            .unwrap_or(true)
+    }
+
+    fn is_allowed_tag(&self, tag: &str) -> bool {
+        self.tags
+            .as_ref()
+            .map(|tags| tags.is_allowed(tag_rule_matches(tag)))
+            .unwrap_or(true)
+    }
+}
+
+/// Requires images matching the `image` glob pattern to be signed
+/// with all the given cosign public keys, and by all the given keyless
+/// identities.
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SignatureRule {
+    pub(crate) image: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) pub_keys: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<KeylessIdentity>")]
+    pub(crate) keyless: Vec<KeylessInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) annotations: Option<BTreeMap<String, String>>,
+}
+
+/// Keyless identity, as expected by `KeylessInfo`, which does not
+/// provide a schema.
+#[derive(JsonSchema)]
+#[allow(dead_code)]
+struct KeylessIdentity {
+    /// OIDC issuer of the signing certificate.
+    issuer: String,
+    /// Identity of the signer, like an email address.
+    subject: String,
+}
+
+impl SignatureRule {
+    fn validate(&self) -> Result<(), String> {
+        if self.image.is_empty() {
+            return Err("signature rules must provide an image pattern".to_string());
+        }
+        if self.pub_keys.is_empty() && self.keyless.is_empty() {
+            return Err(format!(
+                "signature rule for {} must provide at least one public key or keyless identity",
+                self.image
+            ));
+        }
+        validate_signers("signature", &self.image, &self.pub_keys, &self.keyless)
+    }
+}
+
+/// Validates the public keys and keyless identities of a signature or
+/// attestation rule.
+fn validate_signers(
+    rule: &str,
+    image: &str,
+    pub_keys: &[String],
+    keyless: &[KeylessInfo],
+) -> Result<(), String> {
+    if keyless
+        .iter()
+        .any(|keyless| keyless.issuer.is_empty() || keyless.subject.is_empty())
+    {
+        return Err(format!(
+            "{} rule for {} contains a keyless identity without issuer or subject",
+            rule, image
+        ));
+    }
+    if pub_keys.iter().any(|pub_key| {
+        !pub_key
+            .trim_start()
+            .starts_with("-----BEGIN PUBLIC KEY-----")
+    }) {
+        return Err(format!(
+            "{} rule for {} contains a public key that is not PEM encoded",
+            rule, image
+        ));
+    }
+    Ok(())
+}
+
+/// Requires images matching the `image` glob pattern to carry an
+/// attestation with one of the given predicate types, and attestations
+/// with every required predicate type. Without public keys nor keyless
+/// identities, only the presence of the attestations is checked.
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AttestationRule {
+    pub(crate) image: String,
+    /// Predicate types, either as URIs or as the shorthands of
+    /// `PREDICATE_TYPE_SHORTHANDS`, one of which is required.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) predicate_types: Vec<String>,
+    /// Predicate types that are all required, like the SLSA provenance.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) required_predicate_types: Vec<String>,
+    /// Public keys the attestations must be signed with. When neither
+    /// these nor `keyless` are given, the attestations are trusted
+    /// without verification.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) pub_keys: Vec<String>,
+    /// Keyless identities the attestations must be signed by.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<KeylessIdentity>")]
+    pub(crate) keyless: Vec<KeylessInfo>,
+}
+
+/// Shorthands of the predicate types, named after the attestation
+/// types of `cosign attest`.
+const PREDICATE_TYPE_SHORTHANDS: &[(&str, &str)] = &[
+    ("spdx", "https://spdx.dev/Document"),
+    ("cyclonedx", "https://cyclonedx.org/bom"),
+    ("slsaprovenance", "https://slsa.dev/provenance/v0.2"),
+    ("slsaprovenance1", "https://slsa.dev/provenance/v1"),
+];
+
+/// Returns the URI of a predicate type given as a URI or a shorthand.
+fn predicate_type_uri(predicate_type: &str) -> &str {
+    PREDICATE_TYPE_SHORTHANDS
+        .iter()
+        .find(|(shorthand, _)| *shorthand == predicate_type)
+        .map(|(_, uri)| *uri)
+        .unwrap_or(predicate_type)
+}
+
+impl AttestationRule {
+    /// Returns true when the attestations must be signed.
+    pub(crate) fn has_signers(&self) -> bool {
+        !self.pub_keys.is_empty() || !self.keyless.is_empty()
+    }
+
+    /// Returns the predicate types of the rule missing from the given
+    /// ones, as written in the rule: the alternatives of
+    /// `predicateTypes`, separated by `or`, when none of them is
+    /// present, followed by each missing required predicate type.
+    fn missing_predicate_types(&self, predicate_types: &[String]) -> Vec<String> {
+        let is_present = |required: &String| {
+            predicate_types
+                .iter()
+                .any(|predicate_type| predicate_type == predicate_type_uri(required))
+        };
+        let mut missing = Vec::new();
+        if !self.predicate_types.is_empty() && !self.predicate_types.iter().any(is_present) {
+            missing.push(self.predicate_types.join(" or "));
+        }
+        missing.extend(
+            self.required_predicate_types
+                .iter()
+                .filter(|required| !is_present(required))
+                .cloned(),
+        );
+        missing
+    }
+}
+
+/// Requires containers to use the `Always` image pull policy, so that
+/// the tag they reference is resolved again every time they start.
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ImagePullPolicyRule {
+    #[serde(default)]
+    mode: ImagePullPolicyMode,
+    /// Also accept `IfNotPresent` for the containers referencing their
+    /// image by digest, which cannot change.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    allow_if_not_present_for_digests: bool,
+}
+
+/// How containers violating the image pull policy rule are handled.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum ImagePullPolicyMode {
+    /// Reject the pod.
+    #[default]
+    Reject,
+    /// Set the `Always` pull policy on the containers referencing their
+    /// image by tag, and leave the ones referencing it by digest
+    /// untouched.
+    Mutate,
+}
+
+impl ImagePullPolicyRule {
+    /// Returns true when the pull policy of the container is allowed.
+    /// Containers without a pull policy get the default of Kubernetes:
+    /// `Always` for the `latest` tag or no tag, `IfNotPresent` otherwise.
+    fn is_allowed(&self, container: &apicore::Container) -> bool {
+        let image = container
+            .image
+            .as_deref()
+            .and_then(|image| Image::new(image).ok())
+            .unwrap_or_default();
+        let pull_policy = match container.image_pull_policy.as_deref() {
+            Some(pull_policy) => pull_policy,
+            None if image.digest.is_none()
+                && image.tag.as_deref().unwrap_or("latest") == "latest" =>
+            {
+                "Always"
+            }
+            None => "IfNotPresent",
+        };
+        pull_policy == "Always"
+            || (pull_policy == "IfNotPresent"
+                && self.allow_if_not_present_for_digests
+                && image.digest.is_some())
+    }
+}
+
+/// Admission operation that can be enforced.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub(crate) enum Operation {
+    Create,
+    Update,
+}
+
+impl Operation {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Create => "CREATE",
+            Operation::Update => "UPDATE",
+        }
+    }
+}
+
+/// When provided, admitted pods are mutated to carry an annotation
+/// describing the decision taken by the policy.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq)]
+pub(crate) struct DecisionAnnotation {
+    #[serde(default = "default_decision_annotation_key")]
+    pub(crate) key: String,
+}
+
+impl Default for DecisionAnnotation {
+    fn default() -> Self {
+        DecisionAnnotation {
+            key: default_decision_annotation_key(),
+        }
+    }
+}
+
+fn default_decision_annotation_key() -> String {
+    DEFAULT_DECISION_ANNOTATION_KEY.to_string()
+}
+
+/// Annotation, and optional label, added to the objects accepted in
+/// spite of their violations.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Quarantine {
+    /// Key of the annotation holding the violations, as a JSON list.
+    #[serde(default = "default_violations_annotation_key")]
+    pub(crate) annotation: String,
+    /// Label allowing other components, like network policies, to
+    /// select the quarantined objects.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) label: Option<QuarantineLabel>,
+}
+
+impl Default for Quarantine {
+    fn default() -> Self {
+        Quarantine {
+            annotation: default_violations_annotation_key(),
+            label: None,
+        }
+    }
+}
+
+fn default_violations_annotation_key() -> String {
+    DEFAULT_VIOLATIONS_ANNOTATION_KEY.to_string()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq)]
+pub(crate) struct QuarantineLabel {
+    #[serde(default = "default_quarantine_label_key")]
+    pub(crate) key: String,
+    #[serde(default = "default_quarantine_label_value")]
+    pub(crate) value: String,
+}
+
+fn default_quarantine_label_key() -> String {
+    DEFAULT_QUARANTINE_LABEL_KEY.to_string()
+}
+
+fn default_quarantine_label_value() -> String {
+    "true".to_string()
+}
+
+impl Quarantine {
+    fn validate(&self) -> Result<(), String> {
+        if !is_valid_annotation_key(&self.annotation) {
+            return Err(format!(
+                "quarantine annotation {} is not a valid annotation key",
+                self.annotation
+            ));
+        }
+        if let Some(label) = &self.label {
+            // Label keys follow the same syntax as annotation keys
+            if !is_valid_annotation_key(&label.key) {
+                return Err(format!(
+                    "quarantine label {} is not a valid label key",
+                    label.key
+                ));
+            }
+            if !is_valid_label_value(&label.value) {
+                return Err(format!(
+                    "quarantine label value {} is not a valid label value",
+                    label.value
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Globs of the names of the containers evaluated by the policy, like
+/// `istio-proxy` or `app-*`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default)]
+pub(crate) struct ContainerSelector {
+    /// Containers evaluated, all of them when not provided.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    include: Option<Vec<String>>,
+    /// Containers left out, even when included.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exclude: Option<Vec<String>>,
+}
+
+impl ContainerSelector {
+    fn validate(&self) -> Result<(), String> {
+        if self.include.is_none() && self.exclude.is_none() {
+            return Err("containerSelector must provide include or exclude".to_string());
+        }
+        if self.include.as_ref().map(Vec::is_empty).unwrap_or(false) {
+            return Err("containerSelector include must list at least one name".to_string());
+        }
+        if self
+            .include
+            .iter()
+            .chain(self.exclude.iter())
+            .flatten()
+            .any(String::is_empty)
+        {
+            return Err("containerSelector names cannot be empty".to_string());
+        }
+        Ok(())
+    }
+
+    /// Returns true when the container with the given name is
+    /// evaluated.
+    fn selects(&self, name: &str) -> bool {
+        let matches =
+            |patterns: &Vec<String>| patterns.iter().any(|pattern| glob_matches(pattern, name));
+        self.include.as_ref().map(matches).unwrap_or(true)
+            && !self.exclude.as_ref().map(matches).unwrap_or(false)
+    }
+}
+
+/// Containers added to the pods by injectors, like `linkerd-proxy` or
+/// `vault-agent-init`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default)]
+pub(crate) struct InjectedContainers {
+    /// Globs of the names of the injected containers.
+    names: Vec<String>,
+    /// Globs of the keys of the annotations set by the injectors. When
+    /// provided, the containers are only skipped in the pods carrying
+    /// one of them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    annotations: Option<Vec<String>>,
+}
+
+/// How the mirror pods, carrying the `kubernetes.io/config.mirror`
+/// annotation and requested by a kubelet, are handled.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum MirrorPods {
+    /// Accept them without evaluating their images.
+    #[default]
+    Accept,
+    /// Evaluate them, and accept the violating ones with a warning.
+    Warn,
+    /// Evaluate them like any other pod.
+    Evaluate,
+}
+
+/// How the image references that cannot be parsed, like
+/// `quay.io/app:1.0:alpine`, are handled.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum OnParseError {
+    /// Reject them, as their origin cannot be trusted.
+    #[default]
+    Reject,
+    /// Accept them without evaluating them, with a warning.
+    AllowWithWarning,
+}
+
+impl InjectedContainers {
+    fn validate(&self) -> Result<(), String> {
+        if self.names.is_empty() {
+            return Err("skipInjectedContainers names must list at least one name".to_string());
+        }
+        if self.names.iter().any(String::is_empty) {
+            return Err("skipInjectedContainers names cannot be empty".to_string());
+        }
+        if let Some(annotations) = &self.annotations {
+            if annotations.is_empty() {
+                return Err(
+                    "skipInjectedContainers annotations must list at least one key".to_string(),
+                );
+            }
+            if annotations.iter().any(String::is_empty) {
+                return Err("skipInjectedContainers annotations cannot be empty".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true when the container with the given name was added
+    /// to the pod by an injector.
+    fn is_injected(&self, pod: &apicore::Pod, name: &str) -> bool {
+        let annotated = match &self.annotations {
+            Some(annotations) => pod
+                .metadata
+                .annotations
+                .iter()
+                .flat_map(|pod_annotations| pod_annotations.keys())
+                .any(|key| {
+                    annotations
+                        .iter()
+                        .any(|annotation| glob_matches(annotation, key))
+                }),
+            None => true,
+        };
+        annotated && self.names.iter().any(|pattern| glob_matches(pattern, name))
+    }
+}
+
+#[derive(Clone, Default)]
 pub(crate) struct PodRejectionReasons {
     pub(crate) registries_not_allowed: Vec<String>,
     pub(crate) tags_not_allowed: Vec<String>,
+    /// Tags following a pre-release convention.
+    pub(crate) pre_release_tags: Vec<String>,
+    /// Images whose tag does not follow the tag grammar.
+    pub(crate) invalid_tags: Vec<String>,
+    /// Other image references that cannot be parsed, followed by the
+    /// reason.
+    pub(crate) unparseable_images: Vec<String>,
+    /// Images missing a tag or a digest, followed by the container
+    /// referencing them and the missing components.
+    pub(crate) incomplete_references: Vec<String>,
+    /// Images referenced by digest without a tag, followed by the
+    /// container referencing them.
+    pub(crate) digest_only_references: Vec<String>,
+    /// Images whose reference does not name a registry, followed by
+    /// the container referencing them.
+    pub(crate) unqualified_references: Vec<String>,
+    /// Short names allowed from more than one search registry,
+    /// followed by these registries.
+    pub(crate) ambiguous_short_names: Vec<String>,
     pub(crate) images_not_allowed: Vec<String>,
+    pub(crate) signatures_not_verified: Vec<String>,
+    /// Images missing a required attestation, followed by the accepted
+    /// predicate types.
+    pub(crate) attestations_missing: Vec<String>,
+    /// Images whose attestations are not signed by the signers of the
+    /// rule, followed by the reason.
+    pub(crate) attestations_not_verified: Vec<String>,
+    /// Images whose attestations cannot be looked up in their registry,
+    /// followed by the error.
+    pub(crate) attestation_lookups_failed: Vec<String>,
+    pub(crate) digests_blocked: Vec<String>,
+    /// Images, with their digest, whose digest is not listed for their
+    /// repository in `allowedDigestsPerImage`.
+    pub(crate) digests_not_allowed: Vec<String>,
+    /// Images whose digest is not listed at all, with `strictDigestMode`.
+    pub(crate) digests_not_listed: Vec<String>,
+    pub(crate) insecure_registries: Vec<String>,
+    /// Registries addressed by an IP address.
+    pub(crate) ip_registries: Vec<String>,
+    /// Docker Hub images outside of the `library/` namespace, with
+    /// `officialImagesOnly`.
+    pub(crate) unofficial_images: Vec<String>,
+    /// Registries addressed with a port that is not allowed.
+    pub(crate) registry_ports_not_allowed: Vec<String>,
+    pub(crate) images_not_found: Vec<String>,
+    /// Images that cannot be looked up in their registry, followed by
+    /// the error.
+    pub(crate) image_lookups_failed: Vec<String>,
+    /// Images missing some of the required platforms, followed by the
+    /// missing platforms.
+    pub(crate) platforms_missing: Vec<String>,
+    /// Images running as root, in containers not forced to run as
+    /// another user.
+    pub(crate) root_images: Vec<String>,
+    /// Images whose age is not allowed, followed by their age.
+    pub(crate) image_ages_not_allowed: Vec<String>,
+    /// Images with vulnerabilities not allowed, followed by a summary
+    /// of the vulnerabilities.
+    pub(crate) vulnerable_images: Vec<String>,
+    /// Names of the containers whose image pull policy is not allowed.
+    pub(crate) pull_policies_not_allowed: Vec<String>,
+    /// Images from private registries, pulled by pods without an
+    /// accepted image pull secret, followed by their container.
+    pub(crate) pull_secrets_missing: Vec<String>,
+    /// Reject rules that matched the images of the pod, along with
+    /// their messages.
+    pub(crate) messages: Vec<String>,
+    /// Every rule violated by the containers, used to render the
+    /// message template.
+    pub(crate) violations: Vec<Violation>,
+}
+
+/// Rule violated by the image of a container.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct Violation {
+    /// Name of the container, empty for the images of custom resources.
+    pub(crate) container: String,
+    /// Path of the field holding the image in the object, like
+    /// `spec.containers[2].image`.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub(crate) path: String,
+    pub(crate) image: String,
+    pub(crate) rule: String,
+    /// Stable code of the violation, like `REGISTRY_NOT_ALLOWED`, see
+    /// `reason_code`.
+    pub(crate) code: &'static str,
+    pub(crate) registry: String,
+    /// Message of the reject entry that rejected the image, if any.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub(crate) message: String,
+}
+
+impl Violation {
+    fn new(container: &str, path: &str, image: &Image, rule: &str) -> Violation {
+        Violation {
+            container: container.to_string(),
+            path: path.to_string(),
+            image: image.image.clone(),
+            rule: rule.to_string(),
+            code: reason_code(rule),
+            registry: image
+                .registry
+                .as_deref()
+                .unwrap_or(DEFAULT_REGISTRY)
+                .to_string(),
+            message: String::new(),
+        }
+    }
+
+    /// Returns the value of a placeholder of the message template.
+    pub(crate) fn placeholder(&self, name: &str) -> &str {
+        match name {
+            "container" => &self.container,
+            "path" => &self.path,
+            "image" => &self.image,
+            "rule" => &self.rule,
+            "code" => self.code,
+            "registry" => &self.registry,
+            "message" => &self.message,
+            _ => "",
+        }
+    }
+}
+
+/// Returns the stable code of the violations of a rule, which tools
+/// processing them can rely on instead of the messages. The rules of an
+/// entry, like `imageTags[nginx]`, share the code of their setting.
+fn reason_code(rule: &str) -> &'static str {
+    match rule.split('[').next().unwrap_or_default() {
+        "registries" | "registryRules" => "REGISTRY_NOT_ALLOWED",
+        "requireFullyQualified" => "REGISTRY_REQUIRED",
+        "rejectAmbiguousShortNames" => "SHORT_NAME_AMBIGUOUS",
+        "insecureRegistries" => "REGISTRY_INSECURE",
+        "rejectIpRegistries" => "REGISTRY_IP_ADDRESS",
+        "officialImagesOnly" => "IMAGE_NOT_OFFICIAL",
+        "allowedRegistryPorts" => "REGISTRY_PORT_NOT_ALLOWED",
+        "privateRegistries" => "PULL_SECRET_MISSING",
+        "tags" | "registryRules.tags" | "imageTags" => "TAG_REJECTED",
+        "tagSyntax" => "TAG_INVALID",
+        "imageSyntax" => "IMAGE_REFERENCE_INVALID",
+        "rejectPreReleaseTags" => "TAG_PRE_RELEASE",
+        "rejectDigestOnly" => "TAG_REQUIRED",
+        "requireTagAndDigest" => "DIGEST_REQUIRED",
+        "blockedDigests" => "DIGEST_BLOCKED",
+        "allowedDigestsPerImage" | "strictDigestMode" => "DIGEST_NOT_ALLOWED",
+        "images" | "registryRules.images" => "IMAGE_NOT_ALLOWED",
+        "verifyImageExists" => "IMAGE_NOT_FOUND",
+        "verifyImageExists.lookup" | "requiredPlatforms.lookup" | "rejectRootImages.lookup" => {
+            "IMAGE_LOOKUP_FAILED"
+        }
+        "requiredPlatforms" => "PLATFORM_MISSING",
+        "imageAge" => "IMAGE_AGE_NOT_ALLOWED",
+        "vulnerabilityScan" => "IMAGE_VULNERABLE",
+        "rejectRootImages" => "IMAGE_RUNS_AS_ROOT",
+        "signatures" => "SIGNATURE_MISSING",
+        "attestations" => "ATTESTATION_MISSING",
+        "attestations.verification" => "ATTESTATION_NOT_VERIFIED",
+        "attestations.lookup" => "ATTESTATION_LOOKUP_FAILED",
+        "imagePullPolicy" => "PULL_POLICY_NOT_ALLOWED",
+        _ => "POLICY_VIOLATION",
+    }
 }
 
 impl PodRejectionReasons {
+    /// Appends the reasons of another image.
+    fn extend(&mut self, other: PodRejectionReasons) {
+        let PodRejectionReasons {
+            registries_not_allowed,
+            tags_not_allowed,
+            pre_release_tags,
+            invalid_tags,
+            unparseable_images,
+            incomplete_references,
+            digest_only_references,
+            unqualified_references,
+            ambiguous_short_names,
+            images_not_allowed,
+            signatures_not_verified,
+            attestations_missing,
+            attestations_not_verified,
+            attestation_lookups_failed,
+            digests_blocked,
+            digests_not_allowed,
+            digests_not_listed,
+            insecure_registries,
+            ip_registries,
+            unofficial_images,
+            registry_ports_not_allowed,
+            images_not_found,
+            image_lookups_failed,
+            platforms_missing,
+            root_images,
+            image_ages_not_allowed,
+            vulnerable_images,
+            pull_policies_not_allowed,
+            pull_secrets_missing,
+            messages,
+            violations,
+        } = other;
+        self.registries_not_allowed.extend(registries_not_allowed);
+        self.tags_not_allowed.extend(tags_not_allowed);
+        self.pre_release_tags.extend(pre_release_tags);
+        self.invalid_tags.extend(invalid_tags);
+        self.unparseable_images.extend(unparseable_images);
+        self.incomplete_references.extend(incomplete_references);
+        self.digest_only_references.extend(digest_only_references);
+        self.unqualified_references.extend(unqualified_references);
+        self.ambiguous_short_names.extend(ambiguous_short_names);
+        self.images_not_allowed.extend(images_not_allowed);
+        self.signatures_not_verified.extend(signatures_not_verified);
+        self.attestations_missing.extend(attestations_missing);
+        self.attestations_not_verified
+            .extend(attestations_not_verified);
+        self.attestation_lookups_failed
+            .extend(attestation_lookups_failed);
+        self.digests_blocked.extend(digests_blocked);
+        self.digests_not_allowed.extend(digests_not_allowed);
+        self.digests_not_listed.extend(digests_not_listed);
+        self.insecure_registries.extend(insecure_registries);
+        self.ip_registries.extend(ip_registries);
+        self.unofficial_images.extend(unofficial_images);
+        self.registry_ports_not_allowed
+            .extend(registry_ports_not_allowed);
+        self.images_not_found.extend(images_not_found);
+        self.image_lookups_failed.extend(image_lookups_failed);
+        self.platforms_missing.extend(platforms_missing);
+        self.root_images.extend(root_images);
+        self.image_ages_not_allowed.extend(image_ages_not_allowed);
+        self.vulnerable_images.extend(vulnerable_images);
+        self.pull_policies_not_allowed
+            .extend(pull_policies_not_allowed);
+        self.pull_secrets_missing.extend(pull_secrets_missing);
+        self.messages.extend(messages);
+        self.violations.extend(violations);
+    }
+
     fn is_empty(&self) -> bool {
         self.registries_not_allowed.is_empty()
             && self.tags_not_allowed.is_empty()
+            && self.pre_release_tags.is_empty()
+            && self.invalid_tags.is_empty()
+            && self.unparseable_images.is_empty()
+            && self.incomplete_references.is_empty()
+            && self.digest_only_references.is_empty()
+            && self.unqualified_references.is_empty()
+            && self.ambiguous_short_names.is_empty()
             && self.images_not_allowed.is_empty()
+            && self.signatures_not_verified.is_empty()
+            && self.attestations_missing.is_empty()
+            && self.attestations_not_verified.is_empty()
+            && self.attestation_lookups_failed.is_empty()
+            && self.digests_blocked.is_empty()
+            && self.digests_not_allowed.is_empty()
+            && self.digests_not_listed.is_empty()
+            && self.insecure_registries.is_empty()
+            && self.ip_registries.is_empty()
+            && self.unofficial_images.is_empty()
+            && self.registry_ports_not_allowed.is_empty()
+            && self.images_not_found.is_empty()
+            && self.image_lookups_failed.is_empty()
+            && self.platforms_missing.is_empty()
+            && self.root_images.is_empty()
+            && self.image_ages_not_allowed.is_empty()
+            && self.vulnerable_images.is_empty()
+            && self.pull_policies_not_allowed.is_empty()
+            && self.pull_secrets_missing.is_empty()
+            && self.messages.is_empty()
     }
 }
 
 pub(crate) enum PodEvaluationResult {
-    Allowed,
-    NotAllowed(PodRejectionReasons),
+    /// The pod is allowed; contains the identifiers of the allow
+    /// rules that matched its images.
+    Allowed(Vec<String>),
+    NotAllowed(Box<PodRejectionReasons>),
+}
+
+impl PodEvaluationResult {
+    fn new(rejection_reasons: PodRejectionReasons, mut matched_rules: Vec<String>) -> Self {
+        if rejection_reasons.is_empty() {
+            matched_rules.sort();
+            matched_rules.dedup();
+            PodEvaluationResult::Allowed(matched_rules)
+        } else {
+            PodEvaluationResult::NotAllowed(Box::new(rejection_reasons))
+        }
+    }
 }
 
 impl Validatable for Settings {
     fn validate(&self) -> Result<(), String> {
-        if let Some(registries) = &self.registries {
-            if registries.allow.is_some() == registries.reject.is_some() {
-                return Err("only one of registries allow or reject can be provided, and one must be provided".to_string());
+        if let Some(api_version) = &self.api_version {
+            if api_version != CURRENT_API_VERSION {
+                return Err(format!("unsupported settings apiVersion {}", api_version));
             }
         }
+        self.validate_limits()?;
+        let both_lists = self.rule_precedence.is_some();
+        if let Some(registries) = &self.registries {
+            registries.validate(both_lists)?;
+        }
+        if let Some(tags) = &self.tags {
+            tags.validate(both_lists)?;
+        }
         if let Some(images) = &self.images {
-            if images.allow.is_some() == images.reject.is_some() {
+            images.validate(both_lists)?;
+        }
+        if let Some(registry_presets) = &self.registry_presets {
+            if registry_presets.is_empty() {
+                return Err("registryPresets must list at least one preset".to_string());
+            }
+            if self.registry_rules.is_some() {
+                return Err(
+                    "only one of registryPresets or registryRules can be provided".to_string(),
+                );
+            }
+            if !both_lists
+                && self
+                    .registries
+                    .as_ref()
+                    .map(|registries| registries.reject.is_some())
+                    .unwrap_or(false)
+            {
                 return Err(
-                    "only one of images allow or reject can be provided, and one must be provided"
+                    "registryPresets cannot be combined with registries reject, unless rulePrecedence is set"
+                        .to_string(),
+                );
+            }
+        }
+        if let Some(registry_rules) = &self.registry_rules {
+            if self.registries.is_some() {
+                return Err("only one of registries or registryRules can be provided".to_string());
+            }
+            for registry_rule in registry_rules {
+                registry_rule.validate(both_lists)?;
+            }
+        }
+        for namespace_rule in self.namespace_rules.iter().flatten() {
+            namespace_rule.validate(both_lists)?;
+        }
+        let max_image_age = self
+            .max_image_age
+            .as_deref()
+            .map(parse_duration)
+            .transpose()
+            .map_err(|e| format!("invalid maxImageAge: {}", e))?;
+        let min_image_age = self
+            .min_image_age
+            .as_deref()
+            .map(parse_duration)
+            .transpose()
+            .map_err(|e| format!("invalid minImageAge: {}", e))?;
+        if let (Some(max_image_age), Some(min_image_age)) = (max_image_age, min_image_age) {
+            if min_image_age >= max_image_age {
+                return Err("minImageAge must be lower than maxImageAge".to_string());
+            }
+        }
+        if let Some(vulnerability_scan) = &self.vulnerability_scan {
+            vulnerability_scan.validate()?;
+        }
+        for required_platform in self.required_platforms.iter().flatten() {
+            Platform::parse(required_platform)?;
+        }
+        for insecure_registry in self.insecure_registries.iter().flatten() {
+            validate_registry_pattern(insecure_registry)
+                .map_err(|e| format!("invalid insecure registry: {}", e))?;
+        }
+        for private_registry in self.private_registries.iter().flatten() {
+            validate_registry_pattern(private_registry)
+                .map_err(|e| format!("invalid private registry: {}", e))?;
+        }
+        if self
+            .allowed_registry_ports
+            .iter()
+            .flatten()
+            .any(|port| *port == 0)
+        {
+            return Err("invalid allowed registry port 0".to_string());
+        }
+        if self.allowed_pull_secrets.is_some() && self.private_registries.is_none() {
+            return Err("allowedPullSecrets requires privateRegistries".to_string());
+        }
+        if let Some(image_tags) = &self.image_tags {
+            for (image, allowed_tags) in image_tags {
+                if allowed_tags.is_empty() {
+                    return Err(format!("imageTags entry for {} has no tags", image));
+                }
+                for allowed_tag in allowed_tags.iter().filter(|tag| is_version_range(tag)) {
+                    parse_version_range(allowed_tag)
+                        .map_err(|e| format!("imageTags entry for {}: {}", image, e))?;
+                }
+            }
+        }
+        if let Some(allowed_digests) = &self.allowed_digests {
+            for allowed_digest in allowed_digests {
+                validate_digest(allowed_digest)
+                    .map_err(|e| format!("invalid allowed digest {}: {}", allowed_digest, e))?;
+            }
+        }
+        if let Some(blocked_digests) = &self.blocked_digests {
+            for blocked_digest in blocked_digests {
+                validate_digest(blocked_digest)
+                    .map_err(|e| format!("invalid blocked digest {}: {}", blocked_digest, e))?;
+                if self
+                    .allowed_digests
+                    .iter()
+                    .flatten()
+                    .any(|d| d == blocked_digest)
+                {
+                    return Err(format!(
+                        "digest {} cannot be both allowed and blocked",
+                        blocked_digest
+                    ));
+                }
+            }
+        }
+        if self.strict_digest_mode
+            && self.allowed_digests.iter().flatten().next().is_none()
+            && self
+                .allowed_digests_per_image
+                .iter()
+                .flatten()
+                .next()
+                .is_none()
+        {
+            return Err(
+                "strictDigestMode requires allowedDigests or allowedDigestsPerImage".to_string(),
+            );
+        }
+        for (repository, digests) in self.allowed_digests_per_image.iter().flatten() {
+            let image = Image::new(repository.as_str())
+                .map_err(|e| format!("invalid allowedDigestsPerImage entry: {}", e))?;
+            if image.tag.is_some() || image.digest.is_some() || image.transport.is_some() {
+                return Err(format!(
+                    "invalid allowedDigestsPerImage entry {}: expected a repository, like registry.internal/payments/api",
+                    repository
+                ));
+            }
+            if digests.is_empty() {
+                return Err(format!(
+                    "allowedDigestsPerImage entry for {} has no digests",
+                    repository
+                ));
+            }
+            for digest in digests {
+                validate_digest(digest).map_err(|e| {
+                    format!(
+                        "invalid digest {} of allowedDigestsPerImage entry {}: {}",
+                        digest, repository, e
+                    )
+                })?;
+            }
+        }
+        for signature in self.signatures.iter().flatten() {
+            signature.validate()?;
+        }
+        for attestation in self.attestations.iter().flatten() {
+            if attestation.image.is_empty() {
+                return Err("attestation rules must provide an image pattern".to_string());
+            }
+            if attestation.predicate_types.is_empty()
+                && attestation.required_predicate_types.is_empty()
+            {
+                return Err(format!(
+                    "attestation rule for {} must provide at least one predicate type",
+                    attestation.image
+                ));
+            }
+            validate_signers(
+                "attestation",
+                &attestation.image,
+                &attestation.pub_keys,
+                &attestation.keyless,
+            )?;
+        }
+        if let Some(trusted_repositories) = &self.trusted_repositories {
+            trusted_repositories.validate()?;
+        }
+        if let Some(rules_from) = &self.rules_from {
+            rules_from.validate()?;
+        }
+        if let Some(operations) = &self.operations {
+            if operations.is_empty() {
+                return Err("operations must list at least one operation".to_string());
+            }
+        }
+        if let Some(resources) = &self.resources {
+            if resources.is_empty() {
+                return Err("resources must list at least one kind".to_string());
+            }
+            if let Some(resource) = resources.iter().find(|resource| {
+                resource.split('/').count() > 2 || resource.split('/').any(str::is_empty)
+            }) {
+                return Err(format!(
+                    "invalid resources entry {}: expected group/kind, or kind for the core group",
+                    resource
+                ));
+            }
+        }
+        for custom_resource_rule in self.custom_resource_rules.iter().flatten() {
+            custom_resource_rule.validate()?;
+        }
+        if let Some(container_selector) = &self.container_selector {
+            container_selector.validate()?;
+        }
+        if let Some(skip_injected_containers) = &self.skip_injected_containers {
+            skip_injected_containers.validate()?;
+        }
+        if let Some(message_template) = &self.message_template {
+            MessageTemplate::parse(message_template)
+                .map_err(|e| format!("invalid messageTemplate: {}", e))?;
+        }
+        if let Some(quarantine) = &self.quarantine {
+            quarantine.validate()?;
+        }
+        if let Some(exceptions) = &self.exceptions {
+            exceptions.validate()?;
+        }
+        if let Some(default_registry) = &self.default_registry {
+            if !is_registry_domain(default_registry) {
+                return Err(format!(
+                    "invalid default registry {}: expected a domain, like registry.internal",
+                    default_registry
+                ));
+            }
+        }
+        if let Some(search_registries) = &self.short_name_search_registries {
+            if search_registries.is_empty() {
+                return Err("shortNameSearchRegistries must list at least one registry".to_string());
+            }
+            if self.default_registry.is_some() || self.require_fully_qualified {
+                return Err("shortNameSearchRegistries cannot be combined with defaultRegistry or requireFullyQualified, which handle the short names differently".to_string());
+            }
+            if let Some(search_registry) = search_registries
+                .iter()
+                .find(|search_registry| !is_registry_domain(search_registry))
+            {
+                return Err(format!(
+                    "invalid shortNameSearchRegistries entry {}: expected a domain, like registry.internal",
+                    search_registry
+                ));
+            }
+        } else if self.reject_ambiguous_short_names {
+            return Err("rejectAmbiguousShortNames requires shortNameSearchRegistries".to_string());
+        }
+        for (reference, replacement) in self.tag_replacements.iter().flatten() {
+            let image = Image::new(reference.as_str())
+                .map_err(|e| format!("invalid tagReplacements entry: {}", e))?;
+            if image.tag.is_none() || image.digest.is_some() || image.transport.is_some() {
+                return Err(format!(
+                    "invalid tagReplacements entry {}: expected a repository and a tag, like nginx:latest",
+                    reference
+                ));
+            }
+            let replacement_image = Image::new(replacement.as_str()).map_err(|e| {
+                format!(
+                    "invalid replacement of tagReplacements entry {}: {}",
+                    reference, e
+                )
+            })?;
+            if (replacement_image.tag.is_none() && replacement_image.digest.is_none())
+                || replacement_image.transport.is_some()
+            {
+                return Err(format!(
+                    "invalid replacement {} of tagReplacements entry {}: expected a tag or a digest",
+                    replacement, reference
+                ));
+            }
+        }
+        if let Some(decision_annotation) = &self.decision_annotation {
+            if !is_valid_annotation_key(&decision_annotation.key) {
+                return Err(format!(
+                    "decision annotation key {} is not a valid annotation key",
+                    decision_annotation.key
+                ));
+            }
+        }
+        for (pattern, overrides) in self.namespace_overrides.iter().flatten() {
+            let fields = overrides.as_object().ok_or_else(|| {
+                format!("namespaceOverrides entry for {} is not an object", pattern)
+            })?;
+            if let Some(field) = NON_OVERRIDABLE_FIELDS
+                .iter()
+                .find(|field| fields.contains_key(**field))
+            {
+                return Err(format!(
+                    "namespaceOverrides entry for {} cannot override {}",
+                    pattern, field
+                ));
+            }
+            self.with_overrides([overrides])
+                .and_then(|settings| settings.validate())
+                .map_err(|e| format!("namespaceOverrides entry for {}: {}", pattern, e))?;
+        }
+        Ok(())
+    }
+}
+
+fn merge_lists<T>(list: &mut Option<Vec<T>>, other: Option<Vec<T>>) {
+    if let Some(other) = other {
+        list.get_or_insert_with(Vec::new).extend(other);
+    }
+}
+
+fn is_valid_label_value(value: &str) -> bool {
+    value.is_empty() || is_valid_qualified_name(value)
+}
+
+/// Returns true for the names of annotation and label keys: at most 63
+/// alphanumeric characters, dashes, underscores and dots, starting and
+/// ending with an alphanumeric character.
+fn is_valid_qualified_name(name: &str) -> bool {
+    name.len() <= 63
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.ends_with(|c: char| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+pub(crate) fn is_valid_annotation_key(key: &str) -> bool {
+    let (prefix, name) = match key.split_once('/') {
+        Some((prefix, name)) => (Some(prefix), name),
+        None => (None, key),
+    };
+    // The prefix is a DNS subdomain, made of lowercase labels
+    let valid_prefix = prefix
+        .map(|prefix| {
+            prefix.len() <= 253
+                && prefix.split('.').all(|label| {
+                    !label.is_empty()
+                        && !label.starts_with('-')
+                        && !label.ends_with('-')
+                        && label
+                            .chars()
+                            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+                })
+        })
+        .unwrap_or(true);
+    valid_prefix && is_valid_qualified_name(name)
+}
+
+/// Merges `overrides` into `value`: the fields of objects are merged
+/// recursively, while any other value, `null` included, replaces the one
+/// it overrides.
+fn deep_merge(value: &mut serde_json::Value, overrides: &serde_json::Value) {
+    match (value, overrides) {
+        (serde_json::Value::Object(fields), serde_json::Value::Object(overriding_fields)) => {
+            for (key, overriding) in overriding_fields {
+                deep_merge(
+                    fields.entry(key.clone()).or_insert(serde_json::Value::Null),
+                    overriding,
+                );
+            }
+        }
+        (value, overrides) => *value = overrides.clone(),
+    }
+}
+
+/// Rejects a list of the settings with more than `limit` entries.
+fn check_count(name: &str, count: usize, limit: usize) -> Result<(), String> {
+    if count > limit {
+        return Err(format!(
+            "{} has {} entries, more than the limit of {}",
+            name, count, limit
+        ));
+    }
+    Ok(())
+}
+
+/// Rejects a list of the settings with too many entries, or with an entry
+/// longer than `MAX_PATTERN_LENGTH`.
+fn check_entries<'a>(name: &str, entries: impl Iterator<Item = &'a str>) -> Result<(), String> {
+    let mut count = 0;
+    for (index, entry) in entries.enumerate() {
+        if entry.len() > MAX_PATTERN_LENGTH {
+            return Err(format!(
+                "{}[{}] is longer than {} characters",
+                name, index, MAX_PATTERN_LENGTH
+            ));
+        }
+        count += 1;
+    }
+    check_count(name, count, MAX_LIST_ENTRIES)
+}
+
+/// Rejects raw settings whose objects and arrays are nested deeper than
+/// `MAX_SETTINGS_DEPTH`, naming the first value beyond the limit.
+fn check_depth(value: &serde_json::Value, path: &str, depth: usize) -> Result<(), String> {
+    if depth > MAX_SETTINGS_DEPTH {
+        return Err(format!(
+            "{} is nested more than {} levels deep",
+            path, MAX_SETTINGS_DEPTH
+        ));
+    }
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (key, field) in fields {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                check_depth(field, &path, depth + 1)?;
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for (index, item) in items.iter().enumerate() {
+                check_depth(item, &format!("{}[{}]", path, index), depth + 1)?;
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+impl Settings {
+    /// Builds the settings from their raw representation, migrating
+    /// them from older versions of the settings format when needed.
+    pub(crate) fn from_value(settings: serde_json::Value) -> Result<Settings, String> {
+        check_depth(&settings, "", 0)?;
+        serde_json::from_value(migrate(settings)?).map_err(|e| e.to_string())
+    }
+
+    /// Builds the settings from a raw payload, given either as JSON or,
+    /// when it is not valid JSON, as YAML.
+    pub(crate) fn from_slice(payload: &[u8]) -> Result<Settings, String> {
+        let settings = serde_json::from_slice(payload).or_else(|json_error| {
+            serde_yaml::from_slice(payload).map_err(|yaml_error| {
+                format!(
+                    "cannot parse settings as JSON ({}) nor as YAML ({})",
+                    json_error, yaml_error
+                )
+            })
+        })?;
+        Settings::from_value(settings)
+    }
+
+    pub(crate) fn logger(&self) -> Logger {
+        logger(self.log_level)
+    }
+
+    /// Returns the problems of valid settings that are worth reporting,
+    /// like entries that can never match any image.
+    pub(crate) fn warnings(&self) -> Vec<String> {
+        let never_matches = |rule: &Rule, is_uppercase: fn(&str) -> bool| {
+            rule.kind() != Some(RuleKind::Regex) && is_uppercase(rule.pattern())
+        };
+        let mut warnings = Vec::new();
+        let images = self.images.iter().flat_map(|images| {
+            let (allow, reject) = images.rules();
+            allow.into_iter().chain(reject).flatten()
+        });
+        for rule in images.filter(|rule| never_matches(rule, has_uppercase_repository)) {
+            warnings.push(format!(
+                "images entry {} never matches: repositories are lowercase",
+                rule.pattern()
+            ));
+        }
+        for registry_rule in self.registry_rules.iter().flatten() {
+            let images = registry_rule.images.iter().flat_map(|images| {
+                let (allow, reject) = images.rules();
+                allow.into_iter().chain(reject).flatten()
+            });
+            for rule in images.filter(|rule| {
+                never_matches(rule, |pattern| {
+                    pattern.chars().any(|c| c.is_ascii_uppercase())
+                })
+            }) {
+                warnings.push(format!(
+                    "registry rule for {}: images entry {} never matches: repositories are lowercase",
+                    registry_rule.registry,
+                    rule.pattern()
+                ));
+            }
+        }
+        let now = SystemTime::now();
+        for (name, allow, reject) in self.rule_lists() {
+            for (list, rules) in [("allow", allow), ("reject", reject)] {
+                for rule in rules.iter().flatten().filter(|rule| rule.is_expired(now)) {
+                    warnings.push(format!(
+                        "{} {} entry {} expired at {} and is ignored",
+                        name,
+                        list,
+                        rule.pattern(),
+                        rule.expires_at().unwrap_or_default()
+                    ));
+                }
+            }
+        }
+        for attestation in self
+            .attestations
+            .iter()
+            .flatten()
+            .filter(|attestation| !attestation.has_signers())
+        {
+            warnings.push(format!(
+                "attestation rule for {} lists no signer: the attestations are not verified",
+                attestation.image
+            ));
+        }
+        for pattern in self.image_tags.iter().flat_map(BTreeMap::keys) {
+            if has_uppercase_repository(pattern) {
+                warnings.push(format!(
+                    "imageTags entry {} never matches: repositories are lowercase",
+                    pattern
+                ));
+            }
+        }
+        if let Some(precedence) = self.rule_precedence {
+            let both_lists: Vec<_> = self
+                .rule_lists()
+                .into_iter()
+                .filter_map(|(name, allow, reject)| Some((name, allow.as_ref()?, reject.as_ref()?)))
+                .collect();
+            if both_lists.is_empty() {
+                warnings.push(
+                    "rulePrecedence has no effect: no filter provides both allow and reject lists"
                         .to_string(),
                 );
             }
+            for (name, allow, reject) in both_lists {
+                for rule in allow.iter().filter(|rule| reject.contains(rule)) {
+                    warnings.push(format!(
+                        "{} entry {} is both allowed and rejected, {} applies",
+                        name,
+                        rule.pattern(),
+                        match precedence {
+                            RulePrecedence::RejectWins => "rejectWins",
+                            RulePrecedence::AllowWins => "allowWins",
+                            // Identical entries are equally specific
+                            RulePrecedence::MostSpecific => "the reject entry",
+                        }
+                    ));
+                }
+            }
+        }
+        if let Some(resources) = &self.resources {
+            let supported: Vec<String> = vec!["Pod".to_string(), "PodTemplate".to_string()]
+                .into_iter()
+                .chain(self.all_custom_resource_rules().map(|rule| {
+                    let group = rule
+                        .api_version
+                        .rsplit_once('/')
+                        .map(|(group, _)| group)
+                        .unwrap_or_default();
+                    group_kind(group, &rule.kind)
+                }))
+                .collect();
+            for resource in resources {
+                if !supported
+                    .iter()
+                    .any(|group_kind| glob_matches(resource, group_kind))
+                {
+                    warnings.push(format!(
+                        "resources entry {} matches no supported kind, add a customResourceRules entry to evaluate it",
+                        resource
+                    ));
+                }
+            }
+        }
+        if self.skip_owned_pods
+            && !self
+                .all_custom_resource_rules()
+                .any(|rule| self.is_resource_evaluated(&rule.group_version_kind()))
+        {
+            warnings.push(
+                "skipOwnedPods has no effect: no controller kind is evaluated, add a customResourceRules entry for it"
+                    .to_string(),
+            );
+        }
+        for (name, allow, reject) in self.rule_lists() {
+            let has_wildcard =
+                |list: &Option<Vec<Rule>>| list.iter().flatten().any(Rule::is_wildcard);
+            let has_other_entries =
+                |list: &Option<Vec<Rule>>| list.iter().flatten().any(|rule| !rule.is_wildcard());
+            let precedence = self.rule_precedence.unwrap_or_default();
+            if has_wildcard(allow) {
+                if has_other_entries(allow) {
+                    warnings.push(format!("{} allow entries besides * have no effect", name));
+                }
+                if reject.is_none() {
+                    warnings.push(format!(
+                        "{} allow entry * allows every value, the filter has no effect",
+                        name
+                    ));
+                } else if precedence == RulePrecedence::AllowWins {
+                    warnings.push(format!(
+                        "{} reject entries have no effect: allow entry * wins",
+                        name
+                    ));
+                }
+            }
+            if has_wildcard(reject) {
+                if has_other_entries(reject) {
+                    warnings.push(format!("{} reject entries besides * have no effect", name));
+                }
+                if allow.is_none() || precedence == RulePrecedence::RejectWins {
+                    warnings.push(format!("{} reject entry * rejects every value", name));
+                }
+            }
+        }
+        // The settings are validated before their warnings are computed
+        warnings.extend(self.rule_diagnostics().unwrap_or_default());
+        warnings
+    }
+
+    /// Checks the settings against the size limits, before the lists are
+    /// validated and compiled.
+    fn validate_limits(&self) -> Result<(), String> {
+        let scoped_rules = [
+            (
+                "namespaceRules",
+                self.namespace_rules.as_ref().map(Vec::len),
+            ),
+            ("registryRules", self.registry_rules.as_ref().map(Vec::len)),
+            (
+                "registryPresets",
+                self.registry_presets.as_ref().map(Vec::len),
+            ),
+            (
+                "namespaceOverrides",
+                self.namespace_overrides.as_ref().map(BTreeMap::len),
+            ),
+            ("signatures", self.signatures.as_ref().map(Vec::len)),
+            ("attestations", self.attestations.as_ref().map(Vec::len)),
+        ];
+        for (name, count) in scoped_rules {
+            check_count(name, count.unwrap_or_default(), MAX_SCOPED_RULES)?;
+        }
+        let string_lists = [
+            ("insecureRegistries", &self.insecure_registries),
+            ("privateRegistries", &self.private_registries),
+            ("allowedDigests", &self.allowed_digests),
+            ("blockedDigests", &self.blocked_digests),
+        ];
+        for (name, entries) in string_lists {
+            check_entries(name, entries.iter().flatten().map(String::as_str))?;
+        }
+        if let Some(image_tags) = &self.image_tags {
+            check_entries("imageTags", image_tags.keys().map(String::as_str))?;
+            for (image, tags) in image_tags {
+                check_entries(
+                    &format!("imageTags[{}]", image),
+                    tags.iter().map(String::as_str),
+                )?;
+            }
+        }
+        if let Some(allowed_digests_per_image) = &self.allowed_digests_per_image {
+            check_entries(
+                "allowedDigestsPerImage",
+                allowed_digests_per_image.keys().map(String::as_str),
+            )?;
+            for (repository, digests) in allowed_digests_per_image {
+                check_entries(
+                    &format!("allowedDigestsPerImage[{}]", repository),
+                    digests.iter().map(String::as_str),
+                )?;
+            }
+        }
+        if let Some(tag_replacements) = &self.tag_replacements {
+            check_entries(
+                "tagReplacements",
+                tag_replacements.keys().map(String::as_str),
+            )?;
+        }
+        for (name, allow, reject) in self.rule_lists() {
+            for (list, rules) in [("allow", allow), ("reject", reject)] {
+                check_entries(
+                    &format!("{}.{}", name, list),
+                    rules.iter().flatten().map(Rule::pattern),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the name, the allow list and the reject list of every
+    /// filter.
+    fn rule_lists(&self) -> Vec<RuleLists<'_>> {
+        let mut lists = Vec::new();
+        if let Some(registries) = &self.registries {
+            lists.push((
+                "registries".to_string(),
+                &registries.allow,
+                &registries.reject,
+            ));
+        }
+        if let Some(tags) = &self.tags {
+            lists.push(("tags".to_string(), &tags.allow, &tags.reject));
+        }
+        if let Some(images) = &self.images {
+            lists.push(("images".to_string(), &images.allow, &images.reject));
+        }
+        for (index, namespace_rule) in self.namespace_rules.iter().flatten().enumerate() {
+            let path = format!("namespaceRules[{}]", index);
+            if let Some(registries) = &namespace_rule.registries {
+                lists.push((
+                    format!("{}.registries", path),
+                    &registries.allow,
+                    &registries.reject,
+                ));
+            }
+            if let Some(tags) = &namespace_rule.tags {
+                lists.push((format!("{}.tags", path), &tags.allow, &tags.reject));
+            }
+            if let Some(images) = &namespace_rule.images {
+                lists.push((format!("{}.images", path), &images.allow, &images.reject));
+            }
+        }
+        for (index, registry_rule) in self.registry_rules.iter().flatten().enumerate() {
+            if let Some(images) = &registry_rule.images {
+                lists.push((
+                    format!("registryRules[{}].images", index),
+                    &images.allow,
+                    &images.reject,
+                ));
+            }
+            if let Some(tags) = &registry_rule.tags {
+                lists.push((
+                    format!("registryRules[{}].tags", index),
+                    &tags.allow,
+                    &tags.reject,
+                ));
+            }
+        }
+        lists
+    }
+
+    /// Describes the entries of the lists that never decide, see
+    /// `CompiledList::diagnostics`.
+    fn rule_diagnostics(&self) -> Result<Vec<String>, String> {
+        let parse = self.registry_pattern_parser();
+        let parse_image = self.image_pattern_parser();
+        let defaults = self.list_defaults();
+        let mut diagnostics = Vec::new();
+        let lists = std::iter::once((
+            String::new(),
+            self.registries.as_ref(),
+            self.tags.as_ref(),
+            self.images.as_ref(),
+        ))
+        .chain(self.namespace_rules.iter().flatten().enumerate().map(
+            |(index, namespace_rule)| {
+                (
+                    format!("namespaceRules[{}].", index),
+                    namespace_rule.registries.as_ref(),
+                    namespace_rule.tags.as_ref(),
+                    namespace_rule.images.as_ref(),
+                )
+            },
+        ));
+        for (path, registries, tags, images) in lists {
+            if let Some(registries) = registries {
+                diagnostics.extend(
+                    registries
+                        .compile(&[], parse, defaults)?
+                        .diagnostics(&format!("{}registries", path)),
+                );
+            }
+            if let Some(tags) = tags {
+                diagnostics.extend(
+                    tags.compile(defaults)?
+                        .diagnostics(&format!("{}tags", path)),
+                );
+            }
+            if let Some(images) = images {
+                diagnostics.extend(
+                    images
+                        .compile(defaults, |entry| Ok(parse_image(entry)))?
+                        .diagnostics(&format!("{}images", path)),
+                );
+            }
+        }
+        for (index, registry_rule) in self.registry_rules.iter().flatten().enumerate() {
+            let registry_rule = registry_rule.compile(parse, defaults)?;
+            if let Some(images) = &registry_rule.images {
+                diagnostics.extend(images.diagnostics(&format!("registryRules[{}].images", index)));
+            }
+            if let Some(tags) = &registry_rule.tags {
+                diagnostics.extend(tags.diagnostics(&format!("registryRules[{}].tags", index)));
+            }
+        }
+        Ok(diagnostics)
+    }
+
+    /// Returns the settings applying to the namespace, with the overrides
+    /// whose pattern matches it merged into the global ones, in the
+    /// order of their patterns. None when no override applies.
+    pub(crate) fn for_namespace(&self, namespace: &str) -> Result<Option<Settings>, String> {
+        let overrides: Vec<&serde_json::Value> = self
+            .namespace_overrides
+            .iter()
+            .flatten()
+            .filter(|(pattern, _)| glob_matches(pattern, namespace))
+            .map(|(_, overrides)| overrides)
+            .collect();
+        if overrides.is_empty() {
+            return Ok(None);
+        }
+        self.with_overrides(overrides).map(Some)
+    }
+
+    /// Returns these settings, without their namespace overrides, with
+    /// the given overrides deeply merged into them.
+    fn with_overrides<'a>(
+        &self,
+        overrides: impl IntoIterator<Item = &'a serde_json::Value>,
+    ) -> Result<Settings, String> {
+        let mut settings = serde_json::to_value(self).map_err(|e| e.to_string())?;
+        if let Some(fields) = settings.as_object_mut() {
+            fields.remove("namespaceOverrides");
+        }
+        for overrides in overrides {
+            deep_merge(&mut settings, overrides);
+        }
+        Settings::from_value(settings)
+    }
+
+    /// Merges the filters of `other` into these settings, the ones of
+    /// `MERGED_SETTINGS`. Lists are concatenated, and `imageTags` and
+    /// `allowedDigestsPerImage` entries of `other` replace the ones with
+    /// the same key. The other fields of `other` are ignored, see
+    /// `unmerged_settings`.
+    pub(crate) fn merge(&mut self, other: Settings) {
+        // The match strategies are applied to the entries before the
+        // lists are concatenated, as they can differ between the lists
+        if let Some(registries) = other.registries {
+            let merged = self.registries.get_or_insert_with(Default::default);
+            let (allow, reject) = merged.rules();
+            let (other_allow, other_reject) = registries.rules();
+            merged.allow = allow;
+            merged.reject = reject;
+            merged.match_strategy = None;
+            merge_lists(&mut merged.allow, other_allow);
+            merge_lists(&mut merged.reject, other_reject);
+        }
+        if let Some(tags) = other.tags {
+            let merged = self.tags.get_or_insert_with(Default::default);
+            let (allow, reject) = merged.rules();
+            let (other_allow, other_reject) = tags.rules();
+            merged.allow = allow;
+            merged.reject = reject;
+            merged.match_strategy = None;
+            merge_lists(&mut merged.allow, other_allow);
+            merge_lists(&mut merged.reject, other_reject);
+        }
+        if let Some(images) = other.images {
+            let merged = self.images.get_or_insert_with(Default::default);
+            let (allow, reject) = merged.rules();
+            let (other_allow, other_reject) = images.rules();
+            merged.allow = allow;
+            merged.reject = reject;
+            merged.match_strategy = None;
+            merge_lists(&mut merged.allow, other_allow);
+            merge_lists(&mut merged.reject, other_reject);
+        }
+        merge_lists(&mut self.registry_presets, other.registry_presets);
+        merge_lists(&mut self.registry_rules, other.registry_rules);
+        merge_lists(&mut self.namespace_rules, other.namespace_rules);
+        merge_lists(&mut self.insecure_registries, other.insecure_registries);
+        merge_lists(&mut self.private_registries, other.private_registries);
+        merge_lists(
+            &mut self.allowed_registry_ports,
+            other.allowed_registry_ports,
+        );
+        merge_lists(&mut self.allowed_pull_secrets, other.allowed_pull_secrets);
+        if let Some(image_tags) = other.image_tags {
+            self.image_tags
+                .get_or_insert_with(Default::default)
+                .extend(image_tags);
+        }
+        merge_lists(&mut self.allowed_digests, other.allowed_digests);
+        merge_lists(&mut self.blocked_digests, other.blocked_digests);
+        if let Some(allowed_digests_per_image) = other.allowed_digests_per_image {
+            self.allowed_digests_per_image
+                .get_or_insert_with(Default::default)
+                .extend(allowed_digests_per_image);
+        }
+        merge_lists(&mut self.signatures, other.signatures);
+        merge_lists(&mut self.attestations, other.attestations);
+        self.hash.get_mut().take();
+    }
+
+    /// Returns the names of the fields set in these settings that
+    /// `merge` ignores, which the sources of rules stored in the cluster
+    /// cannot set.
+    pub(crate) fn unmerged_settings(&self) -> Vec<String> {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(fields)) => fields
+                .into_iter()
+                .filter(|(name, value)| {
+                    !value.is_null()
+                        && name != "apiVersion"
+                        && !MERGED_SETTINGS.contains(&name.as_str())
+                })
+                .map(|(name, _)| name)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the rule describing where the images of resources of the
+    /// given kind are stored, if any. The custom resource rules take
+    /// precedence over the built-in rules of the supported workloads.
+    pub(crate) fn custom_resource_rule_for(
+        &self,
+        gvk: &GroupVersionKind,
+    ) -> Option<CustomResourceRule> {
+        self.all_custom_resource_rules()
+            .find(|rule| rule.applies_to(gvk))
+    }
+
+    /// Returns the custom resource rules, followed by the ones of the
+    /// supported custom workloads.
+    fn all_custom_resource_rules(&self) -> impl Iterator<Item = CustomResourceRule> + '_ {
+        self.custom_resource_rules.iter().flatten().cloned().chain(
+            self.supported_custom_workloads
+                .iter()
+                .flatten()
+                .flat_map(CustomWorkload::rules),
+        )
+    }
+
+    /// Returns true when the objects of the given kind are evaluated,
+    /// according to `resources`.
+    pub(crate) fn is_resource_evaluated(&self, gvk: &GroupVersionKind) -> bool {
+        let group_kind = group_kind(&gvk.group, &gvk.kind);
+        self.resources
+            .as_ref()
+            .map(|resources| {
+                resources
+                    .iter()
+                    .any(|resource| glob_matches(resource, &group_kind))
+            })
+            .unwrap_or(true)
+    }
+
+    /// Returns the kind of the controller of the pod, as listed in
+    /// `resources`, when `skipOwnedPods` is set and the objects of that
+    /// kind are evaluated.
+    pub(crate) fn evaluated_controller(&self, pod: &apicore::Pod) -> Option<String> {
+        if !self.skip_owned_pods {
+            return None;
+        }
+        pod.metadata
+            .owner_references
+            .iter()
+            .flatten()
+            .filter(|owner| owner.controller == Some(true))
+            .find_map(|owner| {
+                let (group, version) = owner
+                    .api_version
+                    .rsplit_once('/')
+                    .unwrap_or(("", &owner.api_version));
+                let gvk = GroupVersionKind {
+                    group: group.to_string(),
+                    version: version.to_string(),
+                    kind: owner.kind.clone(),
+                };
+                (self.is_resource_evaluated(&gvk) && self.custom_resource_rule_for(&gvk).is_some())
+                    .then(|| group_kind(group, &owner.kind))
+            })
+    }
+
+    /// Sets the `Always` pull policy on the containers of the pod that
+    /// reference their image by tag, when the image pull policy rule is
+    /// in mutate mode. Returns true when the pod was changed.
+    pub(crate) fn mutate_pull_policies(&self, pod: &mut apicore::Pod) -> bool {
+        match &self.image_pull_policy {
+            Some(image_pull_policy) if image_pull_policy.mode == ImagePullPolicyMode::Mutate => {}
+            _ => return false,
+        }
+        let mut mutated = false;
+        let pod_spec = match pod.spec.as_mut() {
+            Some(pod_spec) => pod_spec,
+            None => return false,
+        };
+        for container in pod_spec
+            .init_containers
+            .iter_mut()
+            .flatten()
+            .chain(pod_spec.containers.iter_mut())
+        {
+            let referenced_by_tag = container
+                .image
+                .as_deref()
+                .and_then(|image| Image::new(image).ok())
+                .map(|image| image.digest.is_none())
+                .unwrap_or(false);
+            if referenced_by_tag && container.image_pull_policy.as_deref() != Some("Always") {
+                container.image_pull_policy = Some("Always".to_string());
+                mutated = true;
+            }
+        }
+        mutated
+    }
+
+    /// Prepends the default registry, when set, to the image references
+    /// of the containers of the pod that do not name a registry. The
+    /// containers whose image is unchanged since the old pod, or cannot
+    /// be parsed, are left untouched. Returns true when the pod was
+    /// changed.
+    pub(crate) fn apply_default_registry(
+        &self,
+        pod: &mut apicore::Pod,
+        old_pod: Option<&apicore::Pod>,
+    ) -> bool {
+        let default_registry = match &self.default_registry {
+            Some(default_registry) => default_registry,
+            None => return false,
+        };
+        let unchanged_images = old_pod.map(container_images).unwrap_or_default();
+        let mut mutated = false;
+        let pod_spec = match pod.spec.as_mut() {
+            Some(pod_spec) => pod_spec,
+            None => return false,
+        };
+        for container in pod_spec
+            .init_containers
+            .iter_mut()
+            .flatten()
+            .chain(pod_spec.containers.iter_mut())
+        {
+            let image = match container.image.as_deref() {
+                Some(image) if !unchanged_images.contains(&(container.name.as_str(), image)) => {
+                    image
+                }
+                _ => continue,
+            };
+            if Image::new(image)
+                .map(|parsed_image| parsed_image.has_explicit_registry())
+                .unwrap_or(true)
+            {
+                continue;
+            }
+            container.image = Some(with_registry(image, default_registry));
+            mutated = true;
+        }
+        mutated
+    }
+
+    /// Replaces the image references of the containers of the pod that
+    /// match an entry of `tagReplacements`. The containers whose image
+    /// is unchanged since the old pod, or cannot be parsed, are left
+    /// untouched. Returns true when the pod was changed.
+    pub(crate) fn replace_tags(
+        &self,
+        pod: &mut apicore::Pod,
+        old_pod: Option<&apicore::Pod>,
+    ) -> bool {
+        if self.tag_replacements.is_none() {
+            return false;
+        }
+        let unchanged_images = old_pod.map(container_images).unwrap_or_default();
+        let mut mutated = false;
+        let pod_spec = match pod.spec.as_mut() {
+            Some(pod_spec) => pod_spec,
+            None => return false,
+        };
+        for container in pod_spec
+            .init_containers
+            .iter_mut()
+            .flatten()
+            .chain(pod_spec.containers.iter_mut())
+        {
+            let image = match container.image.as_deref() {
+                Some(image) if !unchanged_images.contains(&(container.name.as_str(), image)) => {
+                    image
+                }
+                _ => continue,
+            };
+            let replacement = match self.tag_replacement(image) {
+                Some(replacement) => replacement,
+                None => continue,
+            };
+            // The transport prefix, if any, stays in front of the replacement
+            let (_, reference) = split_transport(image);
+            let transport = &image[..image.len() - reference.len()];
+            container.image = Some(format!("{}{}", transport, replacement));
+            mutated = true;
+        }
+        mutated
+    }
+
+    /// Returns the replacement of an image reference by tag whose
+    /// repository and tag, in their normal form, are the ones of an
+    /// entry of `tagReplacements`.
+    fn tag_replacement(&self, image: &str) -> Option<&String> {
+        let normal_form = |reference: &str| {
+            let mut image = Image::new(reference).ok()?;
+            if image.digest.is_some() {
+                return None;
+            }
+            if !self.disable_registry_aliases {
+                image.normalize_registry_alias();
+            }
+            Some((image.full_repository(), image.tag?))
+        };
+        let image = normal_form(image)?;
+        self.tag_replacements
+            .iter()
+            .flatten()
+            .find(|(reference, _)| normal_form(reference.as_str()).as_ref() == Some(&image))
+            .map(|(_, replacement)| replacement)
+    }
+
+    /// Rewrites the image references of the containers of the pod to
+    /// their fully qualified form, when enabled. The containers whose
+    /// image is unchanged since the old pod, or cannot be parsed, are
+    /// left untouched. Returns true when the pod was changed.
+    pub(crate) fn canonicalize_images(
+        &self,
+        pod: &mut apicore::Pod,
+        old_pod: Option<&apicore::Pod>,
+    ) -> bool {
+        if !self.canonicalize_images {
+            return false;
+        }
+        let unchanged_images = old_pod.map(container_images).unwrap_or_default();
+        let mut mutated = false;
+        let pod_spec = match pod.spec.as_mut() {
+            Some(pod_spec) => pod_spec,
+            None => return false,
+        };
+        for container in pod_spec
+            .init_containers
+            .iter_mut()
+            .flatten()
+            .chain(pod_spec.containers.iter_mut())
+        {
+            let image = match container.image.as_deref() {
+                Some(image) if !unchanged_images.contains(&(container.name.as_str(), image)) => {
+                    image
+                }
+                _ => continue,
+            };
+            let fully_qualified = match Image::new(image) {
+                Ok(parsed_image) => parsed_image.fully_qualified(),
+                Err(_) => continue,
+            };
+            if fully_qualified != image {
+                container.image = Some(fully_qualified);
+                mutated = true;
+            }
+        }
+        mutated
+    }
+
+    /// Returns true when the images of the container with the given
+    /// name are evaluated.
+    pub(crate) fn is_container_selected(&self, name: &str) -> bool {
+        self.container_selector
+            .as_ref()
+            .map(|container_selector| container_selector.selects(name))
+            .unwrap_or(true)
+    }
+
+    /// Returns true when the container with the given name was added to
+    /// the pod by an injector, and is thus skipped.
+    pub(crate) fn is_injected_container(&self, pod: &apicore::Pod, name: &str) -> bool {
+        self.skip_injected_containers
+            .as_ref()
+            .map(|skip_injected_containers| skip_injected_containers.is_injected(pod, name))
+            .unwrap_or(false)
+    }
+
+    /// Returns true when requests of the given admission operation, like
+    /// `CREATE`, must be evaluated.
+    pub(crate) fn is_operation_enforced(&self, operation: &str) -> bool {
+        self.operations
+            .as_ref()
+            .map(|operations| {
+                operations
+                    .iter()
+                    .any(|enforced_operation| enforced_operation.as_str() == operation)
+            })
+            .unwrap_or(true)
+    }
+
+    /// JSON Schema of the settings, so that user interfaces can validate
+    /// and complete them.
+    #[cfg(any(feature = "policy", feature = "lib", test))]
+    pub(crate) fn json_schema() -> serde_json::Value {
+        serde_json::to_value(schemars::schema_for!(Settings)).unwrap_or_default()
+    }
+
+    /// Short fingerprint of the settings, used to identify which
+    /// configuration took a decision.
+    pub(crate) fn fingerprint(&self) -> String {
+        self.hash()
+            .iter()
+            .take(6)
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Hash of the serialized settings, which identifies them. The
+    /// settings are serialized once, however many namespaces are
+    /// compiled or decisions annotated with their fingerprint.
+    fn hash(&self) -> Vec<u8> {
+        self.hash
+            .borrow_mut()
+            .get_or_insert_with(|| {
+                let serialized_settings = serde_json::to_vec(self).unwrap_or_default();
+                Sha256::digest(serialized_settings).to_vec()
+            })
+            .clone()
+    }
+
+    fn list_defaults(&self) -> ListDefaults {
+        ListDefaults {
+            precedence: self.rule_precedence.unwrap_or_default(),
+            default_action: self.default_action,
+        }
+    }
+
+    /// Returns the parser of the registry patterns, which treats the
+    /// aliases of the Docker Hub as `docker.io` unless they are
+    /// disabled.
+    fn registry_pattern_parser(&self) -> RegistryPatternParser {
+        if self.disable_registry_aliases {
+            RegistryPattern::parse
+        } else {
+            RegistryPattern::parse_normalized
+        }
+    }
+
+    /// Returns the parser of the image entries, which treats the
+    /// aliases of the Docker Hub as `docker.io` unless they are
+    /// disabled.
+    fn image_pattern_parser(&self) -> ImagePatternParser {
+        if self.disable_registry_aliases {
+            ImagePattern::new
+        } else {
+            ImagePattern::new_normalized
+        }
+    }
+
+    /// Returns the template of the rejection message, if any. The
+    /// settings must have been validated.
+    pub(crate) fn message_template(&self) -> Option<MessageTemplate> {
+        self.message_template
+            .as_deref()
+            .and_then(|template| MessageTemplate::parse(template).ok())
+    }
+
+    /// Value of the decision annotation for an allowed pod.
+    pub(crate) fn decision_annotation_value(&self, matched_rules: &[String]) -> String {
+        format!(
+            "allowed; rules={}; fingerprint={}",
+            if matched_rules.is_empty() {
+                "none".to_string()
+            } else {
+                matched_rules.join(",")
+            },
+            self.fingerprint()
+        )
+    }
+
+    /// Returns the index of the first namespace rule applying to the
+    /// given namespace, along with the rule.
+    fn namespace_rule_for(&self, scope: &RequestScope) -> Option<(usize, &NamespaceRule)> {
+        self.namespace_rules
+            .iter()
+            .flatten()
+            .enumerate()
+            .find(|(_, namespace_rule)| namespace_rule.applies_to(scope))
+    }
+
+    /// Compiles all the patterns of the settings, so that they are
+    /// parsed once instead of on every comparison. The global lists are
+    /// replaced by the ones of the namespace rule applying to the given
+    /// scope, if any; the registries of a namespace rule also replace
+    /// the global registry rules. The filters are reused across
+    /// requests as long as the settings do not change.
+    pub(crate) fn compile(
+        &self,
+        scope: Option<&RequestScope>,
+    ) -> Result<CompiledSettings<'_>, String> {
+        let namespace_rule = scope.and_then(|scope| self.namespace_rule_for(scope));
+        let lists = namespace_rule.map(|(_, namespace_rule)| namespace_rule);
+        Ok(CompiledSettings {
+            settings: self,
+            log: self.logger(),
+            dry_run: false,
+            namespace: scope
+                .map(|scope| scope.namespace.to_string())
+                .unwrap_or_default(),
+            signatures: lists
+                .and_then(|lists| lists.signatures.as_ref())
+                .or(self.signatures.as_ref()),
+            filters_path: namespace_rule
+                .map(|(index, _)| format!("namespaceRules[{}].", index))
+                .unwrap_or_default(),
+            filters: self.cached_filters(namespace_rule)?,
+            insecure_registries: self
+                .insecure_registries
+                .iter()
+                .flatten()
+                .map(|insecure_registry| (self.registry_pattern_parser())(insecure_registry))
+                .collect::<Result<Vec<_>, String>>()?,
+            private_registries: self
+                .private_registries
+                .iter()
+                .flatten()
+                .map(|private_registry| (self.registry_pattern_parser())(private_registry))
+                .collect::<Result<Vec<_>, String>>()?,
+            max_image_age: self
+                .max_image_age
+                .as_deref()
+                .map(parse_duration)
+                .transpose()?,
+            min_image_age: self
+                .min_image_age
+                .as_deref()
+                .map(parse_duration)
+                .transpose()?,
+            required_platforms: self
+                .required_platforms
+                .iter()
+                .flatten()
+                .map(|required_platform| {
+                    Platform::parse(required_platform).map(|platform| (required_platform, platform))
+                })
+                .collect::<Result<Vec<_>, String>>()?,
+            image_tags: self
+                .image_tags
+                .iter()
+                .flatten()
+                .map(|(pattern, allowed_tags)| {
+                    allowed_tags
+                        .iter()
+                        .map(|allowed_tag| TagPattern::parse(allowed_tag))
+                        .collect::<Result<Vec<_>, String>>()
+                        .map(|allowed_tags| (pattern, allowed_tags))
+                })
+                .collect::<Result<Vec<_>, String>>()?,
+            allowed_digests_per_image: self
+                .allowed_digests_per_image
+                .iter()
+                .flatten()
+                .map(|(repository, digests)| {
+                    let mut image = Image::new(repository.as_str()).map_err(|e| e.to_string())?;
+                    if !self.disable_registry_aliases {
+                        image.normalize_registry_alias();
+                    }
+                    Ok((repository, image.full_repository(), digests))
+                })
+                .collect::<Result<Vec<_>, String>>()?,
+            image_evaluations: Default::default(),
+            vulnerability_reports: Default::default(),
+        })
+    }
+
+    /// Returns the filters of the global lists, or of the given
+    /// namespace rule, compiling them unless a previous request already
+    /// did for the same settings.
+    fn cached_filters(
+        &self,
+        namespace_rule: Option<(usize, &NamespaceRule)>,
+    ) -> Result<Rc<CompiledFilters>, String> {
+        let settings_hash = self.hash();
+        let index = namespace_rule.map(|(index, _)| index);
+        let cached = COMPILED_FILTERS.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            if cache.settings_hash != settings_hash {
+                cache.settings_hash = settings_hash;
+                cache.filters.clear();
+            }
+            cache.filters.get(&index).cloned()
+        });
+        if let Some(filters) = cached {
+            return Ok(filters);
+        }
+
+        let lists = namespace_rule.map(|(_, namespace_rule)| namespace_rule);
+        // The presets only extend the global registries, and apply even
+        // when these do not provide any list
+        let no_registries = Registries::default();
+        let (registries, registry_presets) = match lists.and_then(|lists| lists.registries.as_ref())
+        {
+            Some(registries) => (Some(registries), &[][..]),
+            None => match self.registry_presets.as_deref() {
+                Some(registry_presets) => (
+                    Some(self.registries.as_ref().unwrap_or(&no_registries)),
+                    registry_presets,
+                ),
+                None => (self.registries.as_ref(), &[][..]),
+            },
+        };
+        let tags = lists
+            .and_then(|lists| lists.tags.as_ref())
+            .or(self.tags.as_ref());
+        let images = lists
+            .and_then(|lists| lists.images.as_ref())
+            .or(self.images.as_ref());
+        let registry_rules = match lists {
+            Some(lists) if lists.registries.is_some() => None,
+            _ => self.registry_rules.as_ref(),
+        };
+        let filters = Rc::new(CompiledFilters {
+            registries: registries
+                .map(|registries| {
+                    registries.compile(
+                        registry_presets,
+                        self.registry_pattern_parser(),
+                        self.list_defaults(),
+                    )
+                })
+                .transpose()?,
+            tags: tags
+                .map(|tags| tags.compile(self.list_defaults()))
+                .transpose()?,
+            images: images
+                .map(|images| {
+                    let parse = self.image_pattern_parser();
+                    images.compile(self.list_defaults(), |entry| Ok(parse(entry)))
+                })
+                .transpose()?,
+            registry_rules: registry_rules
+                .map(|registry_rules| {
+                    registry_rules
+                        .iter()
+                        .map(|registry_rule| {
+                            registry_rule
+                                .compile(self.registry_pattern_parser(), self.list_defaults())
+                        })
+                        .collect::<Result<Vec<_>, String>>()
+                })
+                .transpose()?,
+        });
+        COMPILED_FILTERS.with(|cache| {
+            cache
+                .borrow_mut()
+                .filters
+                .insert(index, Rc::clone(&filters))
+        });
+        Ok(filters)
+    }
+}
+
+fn registry_rule_matches(registry: &str) -> impl Fn(&CompiledRule<RegistryPattern>) -> bool + '_ {
+    move |rule| rule.matches(registry, |pattern| pattern.matches(registry))
+}
+
+fn tag_rule_matches(tag: &str) -> impl Fn(&CompiledRule<TagPattern>) -> bool + '_ {
+    move |rule| rule.matches(tag, |pattern| pattern.matches(tag))
+}
+
+/// Rules with an explicit kind are matched against the image
+/// reference.
+fn image_rule_matches(image: &Image) -> impl Fn(&CompiledRule<ImagePattern>) -> bool + '_ {
+    move |rule| rule.matches(&image.image, |pattern| pattern.matches(image))
+}
+
+fn repository_rule_matches(repository: &str) -> impl Fn(&CompiledRule<String>) -> bool + '_ {
+    move |rule| rule.matches(repository, |pattern| glob_matches(pattern, repository))
+}
+
+/// Returns the init containers and containers of the pod.
+fn containers(pod: &apicore::Pod) -> impl Iterator<Item = &apicore::Container> {
+    pod.spec.iter().flat_map(|pod_spec| {
+        pod_spec
+            .init_containers
+            .iter()
+            .flatten()
+            .chain(pod_spec.containers.iter())
+    })
+}
+
+/// Returns the init containers and containers of the pod, along with
+/// the path of their image in the pod, like `spec.containers[2].image`.
+fn located_containers(pod: &apicore::Pod) -> impl Iterator<Item = (String, &apicore::Container)> {
+    pod.spec.iter().flat_map(|pod_spec| {
+        let init_containers = pod_spec
+            .init_containers
+            .iter()
+            .flatten()
+            .enumerate()
+            .map(|(index, container)| (format!("spec.initContainers[{}].image", index), container));
+        let containers = pod_spec
+            .containers
+            .iter()
+            .enumerate()
+            .map(|(index, container)| (format!("spec.containers[{}].image", index), container));
+        init_containers.chain(containers)
+    })
+}
+
+/// Returns true when the registry is a domain, optionally with a port,
+/// that can be prepended to an image reference.
+fn is_registry_domain(registry: &str) -> bool {
+    Image::new(format!("{}/image", registry).as_str())
+        .map(|image| image.has_explicit_registry())
+        .unwrap_or(false)
+}
+
+/// Prepends the registry to an image reference that does not name one.
+/// The transport prefix, if any, stays in front of the registry.
+fn with_registry(image: &str, registry: &str) -> String {
+    let (_, reference) = split_transport(image);
+    let transport = &image[..image.len() - reference.len()];
+    format!("{}{}/{}", transport, registry, reference)
+}
+
+/// Describes a finding about the reference of an image, naming the
+/// container holding it, if any, like `quay.io/app (container app,
+/// missing tag)`.
+fn describe_reference(image: &str, container: &str, finding: &str) -> String {
+    if container.is_empty() {
+        format!("{} ({})", image, finding)
+    } else {
+        format!("{} (container {}, {})", image, container, finding)
+    }
+}
+
+/// Returns the name and image of the init containers and containers
+/// of the pod.
+pub(crate) fn container_images(pod: &apicore::Pod) -> Vec<(&str, &str)> {
+    containers(pod)
+        .filter_map(|container| {
+            container
+                .image
+                .as_deref()
+                .map(|image| (container.name.as_str(), image))
+        })
+        .collect()
+}
+
+/// Outcome of checking the attestations of an image against the
+/// attestation rules applying to it.
+#[derive(Debug, Default, PartialEq)]
+struct AttestationCheck {
+    /// Predicate types missing, see
+    /// `AttestationRule::missing_predicate_types`.
+    missing: Vec<String>,
+    /// Why the attestations are not trusted by some of the rules.
+    not_verified: Vec<String>,
+}
+
+impl AttestationCheck {
+    /// Checks the predicate types of the attestations against the rules.
+    /// The attestations must pass `verify` for the rules listing
+    /// signers, otherwise their predicate types are not trusted.
+    fn new(
+        rules: &[&AttestationRule],
+        predicate_types: &[String],
+        verify: impl Fn(&AttestationRule) -> anyhow::Result<()>,
+    ) -> AttestationCheck {
+        let mut check = AttestationCheck::default();
+        for rule in rules {
+            if rule.has_signers() && !predicate_types.is_empty() {
+                if let Err(e) = verify(rule) {
+                    check.not_verified.push(e.to_string());
+                    continue;
+                }
+            }
+            for predicate_type in rule.missing_predicate_types(predicate_types) {
+                if !check.missing.contains(&predicate_type) {
+                    check.missing.push(predicate_type);
+                }
+            }
+        }
+        check
+    }
+}
+
+/// Records the outcome of checking the attestations of the image, see
+/// `record_image_lookup`. Returns true when the attestations satisfy
+/// every rule.
+fn record_attestation_check(
+    image: &str,
+    check: anyhow::Result<AttestationCheck>,
+    trace: &mut RuleTrace,
+    rejection_reasons: &mut PodRejectionReasons,
+) -> bool {
+    let check = match check {
+        Ok(check) => check,
+        Err(e) => {
+            rejection_reasons
+                .attestation_lookups_failed
+                .push(format!("{} ({})", image, e));
+            return trace.record("attestations.lookup", false);
+        }
+    };
+    for reason in &check.not_verified {
+        rejection_reasons
+            .attestations_not_verified
+            .push(format!("{} ({})", image, reason));
+    }
+    for missing in &check.missing {
+        rejection_reasons
+            .attestations_missing
+            .push(format!("{} ({})", image, missing));
+    }
+    if !check.not_verified.is_empty() {
+        trace.record("attestations.verification", false);
+    }
+    trace.record("attestations", check.missing.is_empty()) && check.not_verified.is_empty()
+}
+
+/// Records the outcome of looking the image up in its registry with
+/// `verifyImageExists`, telling the images that do not exist apart from
+/// the registries that cannot be queried. Returns true when the image
+/// was found.
+fn record_image_lookup(
+    image: &str,
+    lookup: anyhow::Result<bool>,
+    trace: &mut RuleTrace,
+    rejection_reasons: &mut PodRejectionReasons,
+) -> bool {
+    match lookup {
+        Ok(true) => trace.record("verifyImageExists", true),
+        Ok(false) => {
+            rejection_reasons.images_not_found.push(image.to_string());
+            trace.record("verifyImageExists", false)
+        }
+        Err(e) => {
+            rejection_reasons
+                .image_lookups_failed
+                .push(format!("{} ({})", image, e));
+            trace.record("verifyImageExists.lookup", false)
+        }
+    }
+}
+
+fn image_age_rejection(
+    age: Duration,
+    max_image_age: Option<Duration>,
+    min_image_age: Option<Duration>,
+) -> Option<String> {
+    if let Some(max_image_age) = max_image_age {
+        if age > max_image_age {
+            return Some(format!(
+                "created {} ago, older than {}",
+                format_duration(age),
+                format_duration(max_image_age)
+            ));
+        }
+    }
+    if let Some(min_image_age) = min_image_age {
+        if age < min_image_age {
+            return Some(format!(
+                "created {} ago, newer than {}",
+                format_duration(age),
+                format_duration(min_image_age)
+            ));
+        }
+    }
+    None
+}
+
+/// Returns true when the security context of the container, or the one
+/// of the pod, forces the container to run as a user other than root.
+fn runs_as_non_root(pod: &apicore::Pod, container: &apicore::Container) -> bool {
+    let container_context = container.security_context.as_ref();
+    let pod_context = pod
+        .spec
+        .as_ref()
+        .and_then(|pod_spec| pod_spec.security_context.as_ref());
+    let run_as_non_root = container_context
+        .and_then(|context| context.run_as_non_root)
+        .or_else(|| pod_context.and_then(|context| context.run_as_non_root));
+    let run_as_user = container_context
+        .and_then(|context| context.run_as_user)
+        .or_else(|| pod_context.and_then(|context| context.run_as_user));
+    run_as_non_root == Some(true) || run_as_user.map(|uid| uid != 0).unwrap_or(false)
+}
+
+/// Rules evaluated for an image, in order, and whether the image passed
+/// them, along with the messages of the reject entries that rejected
+/// it.
+#[derive(Clone, Default)]
+struct RuleTrace(Vec<(String, bool)>, BTreeMap<String, String>);
+
+impl RuleTrace {
+    /// Records the outcome of a rule, and returns it.
+    fn record(&mut self, rule: &str, passed: bool) -> bool {
+        self.0.push((rule.to_string(), passed));
+        passed
+    }
+
+    /// Records the message of the reject entry of the rule that
+    /// rejected the image, keeping the first one.
+    fn record_message(&mut self, rule: &str, message: &str) {
+        self.1
+            .entry(rule.to_string())
+            .or_insert_with(|| message.to_string());
+    }
+
+    /// Appends the outcomes of another trace, keeping the messages
+    /// already recorded.
+    fn extend(&mut self, other: RuleTrace) {
+        self.0.extend(other.0);
+        for (rule, message) in other.1 {
+            self.1.entry(rule).or_insert(message);
+        }
+    }
+
+    /// Returns the message of the reject entry of the rule that rejected
+    /// the image, if it has one.
+    fn message(&self, rule: &str) -> Option<&str> {
+        self.1.get(rule).map(String::as_str)
+    }
+
+    /// Returns the rule that decided the outcome: the first one the
+    /// image failed, otherwise the last one it passed.
+    fn decided_by(&self) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(_, passed)| !passed)
+            .or_else(|| self.0.last())
+            .map(|(rule, _)| rule.as_str())
+    }
+
+    /// Returns the rules the image failed.
+    fn failed(&self) -> impl Iterator<Item = &str> {
+        self.0
+            .iter()
+            .filter(|(_, passed)| !passed)
+            .map(|(rule, _)| rule.as_str())
+    }
+
+    fn summary(&self) -> String {
+        self.0
+            .iter()
+            .map(|(rule, passed)| format!("{}={}", rule, if *passed { "passed" } else { "failed" }))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Reject entry that rejected an image.
+struct RejectingRule<'a> {
+    /// Rule the entry belongs to, as recorded in the rule trace, like
+    /// `registryRules.tags`.
+    rule: &'static str,
+    /// Path of the list holding the entry, like `registryRules[0].tags`.
+    path: String,
+    pattern: &'a str,
+    message: Option<&'a str>,
+    enforcement: Enforcement,
+}
+
+impl RejectingRule<'_> {
+    /// Describes the entry, preceded by its message when it has one,
+    /// like ``use a release tag (rejected by tags.reject entry
+    /// `latest`)``.
+    fn describe(&self) -> String {
+        let verb = match self.enforcement {
+            Enforcement::Deny => "rejected",
+            Enforcement::Warn => "would be rejected",
+        };
+        let provenance = format!("{} by {}.reject entry `{}`", verb, self.path, self.pattern);
+        match self.message {
+            Some(message) => format!("{} ({})", message, provenance),
+            None => provenance,
+        }
+    }
+}
+
+/// Settings with all their patterns compiled, used to evaluate pods.
+pub(crate) struct CompiledSettings<'a> {
+    settings: &'a Settings,
+    pub(crate) log: Logger,
+    /// Skip the checks querying the registries, for requests that are
+    /// not persisted.
+    pub(crate) dry_run: bool,
+    /// Namespace of the request, empty when unknown.
+    namespace: String,
+    /// Prefix of the paths of the `registries`, `tags` and `images`
+    /// lists, like `namespaceRules[0].`, empty for the global lists.
+    filters_path: String,
+    signatures: Option<&'a Vec<SignatureRule>>,
+    filters: Rc<CompiledFilters>,
+    insecure_registries: Vec<RegistryPattern>,
+    private_registries: Vec<RegistryPattern>,
+    required_platforms: Vec<(&'a String, Platform)>,
+    max_image_age: Option<Duration>,
+    min_image_age: Option<Duration>,
+    image_tags: Vec<(&'a String, Vec<TagPattern>)>,
+    /// Entries of `allowedDigestsPerImage`, along with their repository
+    /// in its normal form.
+    allowed_digests_per_image: Vec<(&'a String, String, &'a Vec<String>)>,
+    /// Outcome of the evaluation of the images, by reference. The
+    /// settings are compiled for every request, so an image referenced
+    /// by several containers is only evaluated once per request.
+    image_evaluations: RefCell<HashMap<String, ImageEvaluation>>,
+    /// Vulnerability reports of the cluster, listed on the first image
+    /// of the request that needs them.
+    vulnerability_reports: RefCell<Option<Result<Rc<VulnerabilityReports>, String>>>,
+}
+
+/// Outcome of the evaluation of an image, see `evaluate_image`.
+#[derive(Clone, Default)]
+struct ImageEvaluation {
+    allowed: bool,
+    rejection_reasons: PodRejectionReasons,
+    matched_rules: Vec<String>,
+    trace: RuleTrace,
+}
+
+impl CompiledSettings<'_> {
+    /// Returns the enabled checks that query the registries, and are
+    /// thus skipped on dry runs.
+    pub(crate) fn registry_checks(&self) -> Vec<&'static str> {
+        let mut checks = Vec::new();
+        if self.settings.resolve_digests && !self.digest_lists_are_empty() {
+            checks.push("resolveDigests");
+        }
+        if self.settings.verify_image_exists {
+            checks.push("verifyImageExists");
+        }
+        if !self.required_platforms.is_empty() {
+            checks.push("requiredPlatforms");
+        }
+        if self.settings.reject_root_images {
+            checks.push("rejectRootImages");
+        }
+        if self.max_image_age.is_some() || self.min_image_age.is_some() {
+            checks.push("imageAge");
+        }
+        if self.settings.vulnerability_scan.is_some() {
+            checks.push("vulnerabilityScan");
+        }
+        if self.signatures.is_some() {
+            checks.push("signatures");
+        }
+        if self.settings.attestations.is_some() {
+            checks.push("attestations");
+        }
+        checks
+    }
+
+    /// Evaluates the images of the pod. On updates, `old_pod` is the
+    /// pod before the update, and only the containers whose image
+    /// changed are evaluated, so that images admitted before the rules
+    /// were tightened do not block the update.
+    pub(crate) fn is_pod_accepted(
+        &self,
+        pod: &apicore::Pod,
+        old_pod: Option<&apicore::Pod>,
+    ) -> PodEvaluationResult {
+        let mut rejection_reasons = PodRejectionReasons::default();
+        let mut matched_rules = Vec::new();
+
+        let unchanged_images = old_pod.map(container_images).unwrap_or_default();
+        for (path, container) in located_containers(pod) {
+            if !self.settings.is_container_selected(&container.name)
+                || self.settings.is_injected_container(pod, &container.name)
+            {
+                continue;
+            }
+            let container_image = match container.image.as_deref() {
+                Some(image) if !unchanged_images.contains(&(container.name.as_str(), image)) => {
+                    image
+                }
+                _ => continue,
+            };
+            if let Some(expiry) = self.exception_for(pod, container_image) {
+                info!(self.log, "exception applied";
+                    "container" => &container.name,
+                    "image" => container_image,
+                    "expiry" => expiry);
+                matched_rules.push("exceptions".to_string());
+                continue;
+            }
+            let violation = |rule: &str| {
+                Image::new(container_image)
+                    .ok()
+                    .map(|image| Violation::new(&container.name, &path, &image, rule))
+            };
+            if let Some(image_pull_policy) = &self.settings.image_pull_policy {
+                if image_pull_policy.mode == ImagePullPolicyMode::Reject
+                    && !image_pull_policy.is_allowed(container)
+                {
+                    rejection_reasons
+                        .pull_policies_not_allowed
+                        .push(container.name.clone());
+                    rejection_reasons
+                        .violations
+                        .extend(violation("imagePullPolicy"));
+                }
+            }
+            if self.settings.reject_root_images
+                && !self.dry_run
+                && !runs_as_non_root(pod, container)
+            {
+                match image_user(split_transport(container_image).1) {
+                    Ok(user) if !is_root_user(user.as_deref()) => {}
+                    Ok(_) => {
+                        rejection_reasons
+                            .root_images
+                            .push(container_image.to_string());
+                        rejection_reasons
+                            .violations
+                            .extend(violation("rejectRootImages"));
+                    }
+                    Err(e) => {
+                        rejection_reasons
+                            .image_lookups_failed
+                            .push(format!("{} ({})", container_image, e));
+                        rejection_reasons
+                            .violations
+                            .extend(violation("rejectRootImages.lookup"));
+                    }
+                }
+            }
+            if self.is_private_image(container_image) && !self.has_pull_secret(pod) {
+                rejection_reasons
+                    .pull_secrets_missing
+                    .push(describe_reference(
+                        container_image,
+                        &container.name,
+                        "no accepted imagePullSecret",
+                    ));
+                rejection_reasons
+                    .violations
+                    .extend(violation("privateRegistries"));
+            }
+            self.evaluate_and_log(
+                &container.name,
+                &path,
+                container_image,
+                &mut rejection_reasons,
+                &mut matched_rules,
+            );
+        }
+
+        PodEvaluationResult::new(rejection_reasons, matched_rules)
+    }
+
+    /// Returns the expiry of the exception granted to the image by the
+    /// annotation of the pod, if any.
+    fn exception_for<'p>(&self, pod: &'p apicore::Pod, image: &str) -> Option<&'p str> {
+        let exceptions = self.settings.exceptions.as_ref()?;
+        let tokens = pod
+            .metadata
+            .annotations
+            .as_ref()?
+            .get(&exceptions.annotation)?;
+        exceptions.granted_until(tokens, &self.namespace, image, SystemTime::now())
+    }
+
+    /// Returns true when the image is pulled from a private registry.
+    fn is_private_image(&self, image: &str) -> bool {
+        if self.private_registries.is_empty() {
+            return false;
+        }
+        let mut image = match Image::new(image) {
+            Ok(image) => image,
+            Err(_) => return false,
+        };
+        if !self.settings.disable_registry_aliases {
+            image.normalize_registry_alias();
+        }
+        let registry = image.registry.as_deref().unwrap_or(DEFAULT_REGISTRY);
+        self.private_registries
+            .iter()
+            .any(|private_registry| private_registry.matches(registry))
+    }
+
+    /// Returns true when the pod references an image pull secret
+    /// accepted for the private registries.
+    fn has_pull_secret(&self, pod: &apicore::Pod) -> bool {
+        pod.spec
+            .iter()
+            .flat_map(|pod_spec| pod_spec.image_pull_secrets.iter().flatten())
+            .filter_map(|secret| secret.name.as_deref())
+            .any(|name| match &self.settings.allowed_pull_secrets {
+                Some(allowed_pull_secrets) => {
+                    allowed_pull_secrets.iter().any(|allowed| allowed == name)
+                }
+                None => !name.is_empty(),
+            })
+    }
+
+    /// Evaluates the given image references, each along with the path
+    /// of the field holding it.
+    pub(crate) fn are_images_accepted<'i>(
+        &self,
+        images: impl IntoIterator<Item = (&'i str, &'i str)>,
+    ) -> PodEvaluationResult {
+        let mut rejection_reasons = PodRejectionReasons::default();
+        let mut matched_rules = Vec::new();
+
+        for (path, image) in images {
+            self.evaluate_and_log("", path, image, &mut rejection_reasons, &mut matched_rules);
+        }
+
+        PodEvaluationResult::new(rejection_reasons, matched_rules)
+    }
+
+    /// Returns a warning for every `warn` reject entry that would reject
+    /// one of the given images if it was enforced, and for every image
+    /// accepted although it cannot be parsed.
+    pub(crate) fn image_warnings(&self, images: &[&str]) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for image in images {
+            let mut parsed_image = match Image::new(*image) {
+                Ok(parsed_image) => parsed_image,
+                Err(e) => {
+                    if self.settings.on_parse_error == OnParseError::AllowWithWarning {
+                        warnings.push(format!(
+                            "image {} cannot be parsed ({}), accepted without evaluation",
+                            image,
+                            e.reason()
+                        ));
+                    }
+                    continue;
+                }
+            };
+            if !self.settings.disable_registry_aliases {
+                parsed_image.normalize_registry_alias();
+            }
+            warnings.extend(
+                self.rejecting_rules(&parsed_image, Enforcement::Warn)
+                    .iter()
+                    .map(|rule| format!("image {}: {}", image, rule.describe())),
+            );
+        }
+        warnings
+    }
+
+    /// Returns the search registries of the image when its reference
+    /// is a short name, which does not name a registry.
+    fn short_name_search_registries(&self, image: &str) -> Option<&Vec<String>> {
+        let search_registries = self.settings.short_name_search_registries.as_ref()?;
+        Image::new(image)
+            .ok()
+            .filter(|parsed_image| !parsed_image.has_explicit_registry())
+            .map(|_| search_registries)
+    }
+
+    /// Evaluates a short name as pulled from each search registry: the
+    /// container runtime pulls it from the first one hosting it, which
+    /// can be any of them.
+    fn evaluate_short_name(
+        &self,
+        container: &str,
+        path: &str,
+        image: &str,
+        search_registries: &[String],
+        rejection_reasons: &mut PodRejectionReasons,
+        matched_rules: &mut Vec<String>,
+    ) {
+        let violations = rejection_reasons.violations.len();
+        for search_registry in search_registries {
+            let candidate = with_registry(image, search_registry);
+            self.evaluate_and_log(
+                container,
+                path,
+                &candidate,
+                rejection_reasons,
+                matched_rules,
+            );
+        }
+        // Every candidate is allowed when none of them added a violation
+        if self.settings.reject_ambiguous_short_names
+            && search_registries.len() > 1
+            && rejection_reasons.violations.len() == violations
+        {
+            rejection_reasons.ambiguous_short_names.push(format!(
+                "{} ({})",
+                image,
+                search_registries.join(", ")
+            ));
+            rejection_reasons.violations.extend(
+                Image::new(image).ok().map(|image| {
+                    Violation::new(container, path, &image, "rejectAmbiguousShortNames")
+                }),
+            );
+        }
+    }
+
+    /// Evaluates the image reference of a container, when it can be
+    /// parsed, and logs the decision taken for it.
+    fn evaluate_and_log(
+        &self,
+        container: &str,
+        path: &str,
+        image: &str,
+        rejection_reasons: &mut PodRejectionReasons,
+        matched_rules: &mut Vec<String>,
+    ) {
+        if let Some(search_registries) = self.short_name_search_registries(image) {
+            self.evaluate_short_name(
+                container,
+                path,
+                image,
+                search_registries,
+                rejection_reasons,
+                matched_rules,
+            );
+            return;
+        }
+        let mut parsed_image = match Image::new(image) {
+            Ok(parsed_image) => parsed_image,
+            Err(e) if self.settings.on_parse_error == OnParseError::AllowWithWarning => {
+                debug!(self.log, "image not evaluated"; "image" => image, "error" => e.to_string());
+                return;
+            }
+            Err(e) => {
+                debug!(self.log, "image evaluated";
+                    "image" => image,
+                    "error" => e.to_string(),
+                    "decision" => "rejected");
+                let rule = match e {
+                    ImageParseError::InvalidTag(_) => {
+                        rejection_reasons.invalid_tags.push(image.to_string());
+                        "tagSyntax"
+                    }
+                    e => {
+                        rejection_reasons.unparseable_images.push(format!(
+                            "{} ({})",
+                            image,
+                            e.reason()
+                        ));
+                        "imageSyntax"
+                    }
+                };
+                rejection_reasons.violations.push(Violation {
+                    container: container.to_string(),
+                    path: path.to_string(),
+                    image: image.to_string(),
+                    rule: rule.to_string(),
+                    code: reason_code(rule),
+                    registry: String::new(),
+                    message: String::new(),
+                });
+                return;
+            }
+        };
+        if !self.settings.disable_registry_aliases {
+            parsed_image.normalize_registry_alias();
+        }
+        let first_matched_rule = matched_rules.len();
+        let violation = Violation::new(container, path, &parsed_image, "");
+        let mut trace = RuleTrace::default();
+        let mut complete = true;
+        if self.settings.require_tag_and_digest {
+            let missing: Vec<&str> = [
+                ("tag", parsed_image.tag.is_none()),
+                ("digest", parsed_image.digest.is_none()),
+            ]
+            .iter()
+            .filter(|(_, missing)| *missing)
+            .map(|(component, _)| *component)
+            .collect();
+            complete = trace.record("requireTagAndDigest", missing.is_empty());
+            if !complete {
+                rejection_reasons
+                    .incomplete_references
+                    .push(describe_reference(
+                        image,
+                        container,
+                        &format!("missing {}", missing.join(" and ")),
+                    ));
+            }
+        }
+        if self.settings.reject_digest_only
+            && !trace.record(
+                "rejectDigestOnly",
+                parsed_image.digest.is_none() || parsed_image.tag.is_some(),
+            )
+        {
+            complete = false;
+            rejection_reasons
+                .digest_only_references
+                .push(describe_reference(image, container, "missing tag"));
+        }
+        if self.settings.require_fully_qualified
+            && !trace.record(
+                "requireFullyQualified",
+                parsed_image.has_explicit_registry(),
+            )
+        {
+            complete = false;
+            rejection_reasons
+                .unqualified_references
+                .push(describe_reference(image, container, "missing registry"));
+        }
+        let allowed =
+            self.evaluate_image_once(parsed_image, rejection_reasons, matched_rules, &mut trace)
+                && complete;
+        rejection_reasons
+            .violations
+            .extend(trace.failed().map(|rule| Violation {
+                rule: rule.to_string(),
+                code: reason_code(rule),
+                message: trace.message(rule).unwrap_or_default().to_string(),
+                ..violation.clone()
+            }));
+        if allowed {
+            debug!(self.log, "image evaluated";
+                "image" => image,
+                "rule" => matched_rules[first_matched_rule..].join(","),
+                "decision" => "allowed");
+        } else {
+            debug!(self.log, "image evaluated"; "image" => image, "decision" => "rejected");
+        }
+        if self.settings.debug_rules {
+            info!(self.log, "rules evaluated";
+                "image" => image,
+                "rules" => trace.summary(),
+                "decided_by" => trace.decided_by().unwrap_or("default"),
+                "decision" => if allowed { "allowed" } else { "rejected" });
+        }
+    }
+
+    /// Evaluates the image with `evaluate_image`, reusing the outcome
+    /// of a previous evaluation of the same image in the request, as
+    /// verifying its signatures and querying its registry are costly.
+    fn evaluate_image_once(
+        &self,
+        image: Image,
+        rejection_reasons: &mut PodRejectionReasons,
+        matched_rules: &mut Vec<String>,
+        trace: &mut RuleTrace,
+    ) -> bool {
+        let cached = self.image_evaluations.borrow().get(&image.image).cloned();
+        let evaluation = match cached {
+            Some(evaluation) => evaluation,
+            None => {
+                let key = image.image.clone();
+                let mut evaluation = ImageEvaluation::default();
+                evaluation.allowed = self.evaluate_image(
+                    image,
+                    &mut evaluation.rejection_reasons,
+                    &mut evaluation.matched_rules,
+                    &mut evaluation.trace,
+                );
+                self.image_evaluations
+                    .borrow_mut()
+                    .insert(key, evaluation.clone());
+                evaluation
+            }
+        };
+        rejection_reasons.extend(evaluation.rejection_reasons);
+        matched_rules.extend(evaluation.matched_rules);
+        trace.extend(evaluation.trace);
+        evaluation.allowed
+    }
+
+    /// Evaluates a single image, recording why it is not allowed, or
+    /// the allow rules that matched it. Returns true when the image is
+    /// allowed.
+    fn evaluate_image(
+        &self,
+        image: Image,
+        rejection_reasons: &mut PodRejectionReasons,
+        matched_rules: &mut Vec<String>,
+        trace: &mut RuleTrace,
+    ) -> bool {
+        // Every rule is evaluated, even once the image is known to be
+        // rejected, so that all the violations are reported at once
+        let mut image_allowed = true;
+        if let Some(registry) = &image.registry {
+            if !self.insecure_registries.is_empty()
+                && !trace.record(
+                    "insecureRegistries",
+                    !self
+                        .insecure_registries
+                        .iter()
+                        .any(|insecure_registry| insecure_registry.matches(registry)),
+                )
+            {
+                image_allowed = false;
+                rejection_reasons.insecure_registries.push(registry.clone());
+            }
+            if self.settings.reject_ip_registries
+                && !self.is_node_local_registry(registry)
+                && !trace.record("rejectIpRegistries", !is_ip_registry(registry))
+            {
+                image_allowed = false;
+                rejection_reasons.ip_registries.push(registry.clone());
+            }
+            if self.settings.official_images_only
+                && (registry == DEFAULT_REGISTRY || is_docker_hub_alias(registry))
+                && !trace.record("officialImagesOnly", image.is_official_image())
+            {
+                image_allowed = false;
+                rejection_reasons
+                    .unofficial_images
+                    .push(image.image.clone());
+            }
+            if let Some(allowed_registry_ports) = self
+                .settings
+                .allowed_registry_ports
+                .as_ref()
+                .filter(|_| !self.is_node_local_registry(registry))
+            {
+                let port_allowed = match split_host_port(registry).1 {
+                    Some(port) => port
+                        .parse::<u16>()
+                        .map(|port| port == HTTPS_PORT || allowed_registry_ports.contains(&port))
+                        .unwrap_or(false),
+                    None => true,
+                };
+                if !trace.record("allowedRegistryPorts", port_allowed) {
+                    image_allowed = false;
+                    rejection_reasons
+                        .registry_ports_not_allowed
+                        .push(registry.clone());
+                }
+            }
+        }
+        let digest = self.image_digest(&image);
+        if let Some(digest) = &digest {
+            if self.settings.blocked_digests.is_some()
+                && !trace.record(
+                    "blockedDigests",
+                    !self
+                        .settings
+                        .blocked_digests
+                        .iter()
+                        .flatten()
+                        .any(|d| d == digest),
+                )
+            {
+                image_allowed = false;
+                rejection_reasons.digests_blocked.push(digest.clone());
+            }
+            // Checked before allowedDigests, which cannot bypass it
+            if let Some((_, _, digests)) = self
+                .allowed_digests_per_image
+                .iter()
+                .find(|(_, full_repository, _)| image.has_full_repository(full_repository))
+            {
+                if !trace.record("allowedDigestsPerImage", digests.contains(digest)) {
+                    image_allowed = false;
+                    rejection_reasons.digests_not_allowed.push(format!(
+                        "{}@{}",
+                        image.full_repository(),
+                        digest
+                    ));
+                }
+            }
+        }
+        if let Some(index) = self.allowed_digest_index(digest.as_deref()) {
+            trace.record(&format!("allowedDigests[{}]", index), true);
+            if !trace.record("signatures", self.are_signatures_verified(&image)) {
+                rejection_reasons.signatures_not_verified.push(image.image);
+                return false;
+            }
+            if image_allowed {
+                matched_rules.push(format!("allowedDigests[{}]", index));
+            }
+            return image_allowed;
+        }
+        if self.settings.strict_digest_mode {
+            return self.evaluate_strict_digest(
+                image,
+                digest,
+                image_allowed,
+                rejection_reasons,
+                matched_rules,
+                trace,
+            );
+        }
+
+        // The message of a reject entry replaces the generic text of the
+        // rule it belongs to
+        let rejecting_rules = self.rejecting_rules(&image, Enforcement::Deny);
+        let has_message = |rule: &str| {
+            rejecting_rules.iter().any(|rejecting_rule| {
+                rejecting_rule.rule == rule && rejecting_rule.message.is_some()
+            })
+        };
+        for rejecting_rule in &rejecting_rules {
+            if let Some(message) = rejecting_rule.message {
+                trace.record_message(rejecting_rule.rule, message);
+            }
+        }
+        rejection_reasons
+            .messages
+            .extend(rejecting_rules.iter().map(RejectingRule::describe));
+        if let Some(registry) = &image.registry {
+            let rule = if self.filters.registry_rules.is_some() {
+                "registryRules"
+            } else {
+                "registries"
+            };
+            if self.is_node_local_registry(registry) {
+                trace.record("allowNodeLocalRegistries", true);
+            } else if (self.filters.registries.is_some() || self.filters.registry_rules.is_some())
+                && !trace.record(rule, self.is_allowed_registry(registry))
+            {
+                image_allowed = false;
+                if !has_message(rule) {
+                    rejection_reasons
+                        .registries_not_allowed
+                        .push(registry.clone())
+                }
+            }
+        }
+        let registry_rule = self.registry_rule_for(&image);
+        let implicit_tag =
+            (self.settings.untagged_as_latest && image.tag.is_none() && image.digest.is_none())
+                .then(|| "latest".to_string());
+        if let Some(tag) = image.tag.as_ref().or(implicit_tag.as_ref()) {
+            let image_tags = self.image_tags_for(&image);
+            let mut tag_explained = false;
+            let tag_allowed = match image_tags {
+                Some((pattern, allowed_tags)) => trace.record(
+                    &format!("imageTags[{}]", pattern),
+                    allowed_tags
+                        .iter()
+                        .any(|allowed_tag| allowed_tag.matches(tag)),
+                ),
+                None => {
+                    let tags_allowed = self.filters.tags.is_none()
+                        || trace.record("tags", self.is_allowed_tag(tag));
+                    let registry_rule_tags_allowed = !tags_allowed
+                        || registry_rule
+                            .filter(|registry_rule| registry_rule.tags.is_some())
+                            .map(|registry_rule| {
+                                trace
+                                    .record("registryRules.tags", registry_rule.is_allowed_tag(tag))
+                            })
+                            .unwrap_or(true);
+                    tag_explained = !tags_allowed && has_message("tags")
+                        || !registry_rule_tags_allowed && has_message("registryRules.tags");
+                    tags_allowed && registry_rule_tags_allowed
+                }
+            };
+            if !tag_allowed {
+                image_allowed = false;
+                if !tag_explained {
+                    rejection_reasons.tags_not_allowed.push(tag.clone());
+                }
+            }
+            // `imageTags` entries take precedence over the tag rules
+            if self.settings.reject_pre_release_tags
+                && image_tags.is_none()
+                && !trace.record("rejectPreReleaseTags", !is_pre_release_tag(tag))
+            {
+                image_allowed = false;
+                rejection_reasons.pre_release_tags.push(tag.clone());
+            }
+        }
+        let repository_allowed = registry_rule
+            .filter(|registry_rule| registry_rule.images.is_some())
+            .map(|registry_rule| {
+                trace.record(
+                    "registryRules.images",
+                    registry_rule.is_allowed_repository(&image.repository),
+                )
+            })
+            .unwrap_or(true);
+        let image_listed =
+            self.filters.images.is_none() || trace.record("images", self.is_allowed_image(&image));
+        if !repository_allowed || !image_listed {
+            image_allowed = false;
+            if !(repository_allowed || has_message("registryRules.images"))
+                || !(image_listed || has_message("images"))
+            {
+                rejection_reasons
+                    .images_not_allowed
+                    .push(image.image.clone());
+            }
+        }
+        if self.settings.verify_image_exists
+            && !self.dry_run
+            && !record_image_lookup(
+                &image.image,
+                image_exists(&image.image),
+                trace,
+                rejection_reasons,
+            )
+        {
+            // The rules looking at the registry would only report the
+            // image again
+            return false;
+        }
+        if !self.required_platforms.is_empty() && !self.dry_run {
+            match self.missing_platforms(&image) {
+                Ok(None) => {
+                    trace.record("requiredPlatforms", true);
+                }
+                Ok(Some(missing_platforms)) => {
+                    trace.record("requiredPlatforms", false);
+                    rejection_reasons.platforms_missing.push(format!(
+                        "{} ({})",
+                        image.image,
+                        missing_platforms.join(", ")
+                    ));
+                    image_allowed = false;
+                }
+                Err(e) => {
+                    trace.record("requiredPlatforms.lookup", false);
+                    rejection_reasons
+                        .image_lookups_failed
+                        .push(format!("{} ({})", image.image, e));
+                    image_allowed = false;
+                }
+            }
+        }
+        let image_age_rejection = self.image_age_rejection(&image);
+        if (self.max_image_age.is_some() || self.min_image_age.is_some()) && !self.dry_run {
+            trace.record("imageAge", image_age_rejection.is_none());
+        }
+        if let Some(reason) = image_age_rejection {
+            rejection_reasons
+                .image_ages_not_allowed
+                .push(format!("{} ({})", image.image, reason));
+            image_allowed = false;
+        }
+        if let Some(vulnerability_scan) = self
+            .settings
+            .vulnerability_scan
+            .as_ref()
+            .filter(|_| !self.dry_run)
+        {
+            let digest = digest.clone().or_else(|| resolve_digest(&image.image).ok());
+            let rejection = vulnerability_scan.rejection(digest.as_deref(), || {
+                self.vulnerability_reports
+                    .borrow_mut()
+                    .get_or_insert_with(|| {
+                        vulnerability_scan
+                            .fetch_reports()
+                            .map(Rc::new)
+                            .map_err(|e| e.to_string())
+                    })
+                    .clone()
+            });
+            trace.record("vulnerabilityScan", rejection.is_none());
+            if let Some(reason) = rejection {
+                rejection_reasons
+                    .vulnerable_images
+                    .push(format!("{} ({})", image.image, reason));
+                image_allowed = false;
+            }
+        }
+        if self.signatures.is_some()
+            && !self.dry_run
+            && !trace.record("signatures", self.are_signatures_verified(&image))
+        {
+            rejection_reasons
+                .signatures_not_verified
+                .push(image.image.clone());
+            image_allowed = false;
+        }
+        if self.settings.attestations.is_some()
+            && !self.dry_run
+            && !record_attestation_check(
+                &image.image,
+                self.check_attestations(&image, digest.as_deref()),
+                trace,
+                rejection_reasons,
+            )
+        {
+            image_allowed = false;
+        }
+        if image_allowed {
+            matched_rules.extend(self.matched_allow_rules(&image));
+        }
+        image_allowed
+    }
+
+    /// Checks the attestations attached to the image against the
+    /// attestation rules applying to it. Returns an error when the
+    /// attestations cannot be looked up.
+    fn check_attestations(
+        &self,
+        image: &Image,
+        digest: Option<&str>,
+    ) -> anyhow::Result<AttestationCheck> {
+        let rules: Vec<&AttestationRule> = self
+            .settings
+            .attestations
+            .iter()
+            .flatten()
+            .filter(|attestation| glob_matches(&attestation.image, &image.image))
+            .collect();
+        if rules.is_empty() || self.dry_run {
+            return Ok(AttestationCheck::default());
+        }
+        let digest = match digest {
+            Some(digest) => digest.to_string(),
+            None => resolve_digest(&image.image)?,
+        };
+        let reference = attestation_reference(&image.full_repository(), &digest);
+        let predicate_types = attestation_predicate_types(&reference)?;
+        Ok(AttestationCheck::new(&rules, &predicate_types, |rule| {
+            verify_attestation_signatures(&reference, rule)
+        }))
+    }
+
+    /// Returns why the age of the image is not allowed, if it is not.
+    /// Images whose creation time cannot be fetched are not allowed.
+    fn image_age_rejection(&self, image: &Image) -> Option<String> {
+        if self.max_image_age.is_none() && self.min_image_age.is_none() || self.dry_run {
+            return None;
+        }
+        let created = match image_created(&image.image) {
+            Ok(created) => created,
+            Err(e) => return Some(format!("unknown creation time: {}", e)),
+        };
+        image_age_rejection(
+            SystemTime::now()
+                .duration_since(created)
+                .unwrap_or_default(),
+            self.max_image_age,
+            self.min_image_age,
+        )
+    }
+
+    /// Returns the required platforms the manifest list of the image
+    /// does not include, or None when it includes all of them. Returns
+    /// an error when the manifest list cannot be fetched.
+    fn missing_platforms(&self, image: &Image) -> anyhow::Result<Option<Vec<&str>>> {
+        let platforms = image_platforms(&image.image)?;
+        let missing_platforms: Vec<&str> = self
+            .required_platforms
+            .iter()
+            .filter(|(_, required_platform)| {
+                !platforms
+                    .iter()
+                    .any(|platform| required_platform.matches(platform))
+            })
+            .map(|(required_platform, _)| required_platform.as_str())
+            .collect();
+        if missing_platforms.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(missing_platforms))
+        }
+    }
+
+    /// Returns the digest of the image. Images referenced by tag only
+    /// have their digest resolved when `resolveDigests` is enabled.
+    fn image_digest(&self, image: &Image) -> Option<String> {
+        if image.digest.is_some()
+            || !self.settings.resolve_digests
+            || self.digest_lists_are_empty()
+            || self.dry_run
+        {
+            return image.digest.clone();
+        }
+        resolve_digest(&image.image).ok()
+    }
+
+    /// Evaluates an image whose digest is not in `allowedDigests` with
+    /// `strictDigestMode`: it is only allowed when its digest is listed
+    /// for its repository in `allowedDigestsPerImage`, whatever the
+    /// other filters. `image_allowed` is the outcome of the rules
+    /// evaluated before.
+    fn evaluate_strict_digest(
+        &self,
+        image: Image,
+        digest: Option<String>,
+        mut image_allowed: bool,
+        rejection_reasons: &mut PodRejectionReasons,
+        matched_rules: &mut Vec<String>,
+        trace: &mut RuleTrace,
+    ) -> bool {
+        let allowed_digests = self
+            .allowed_digests_per_image
+            .iter()
+            .find(|(_, full_repository, _)| image.has_full_repository(full_repository));
+        let matched_rule = match (&digest, allowed_digests) {
+            (Some(digest), Some((repository, _, digests))) => {
+                // The digests not listed for the repository are already
+                // reported by allowedDigestsPerImage
+                image_allowed &= digests.contains(digest);
+                format!("allowedDigestsPerImage[{}]", repository)
+            }
+            _ => {
+                trace.record("strictDigestMode", false);
+                rejection_reasons
+                    .digests_not_listed
+                    .push(image.image.clone());
+                return false;
+            }
+        };
+        if !trace.record("signatures", self.are_signatures_verified(&image)) {
+            rejection_reasons.signatures_not_verified.push(image.image);
+            return false;
+        }
+        if image_allowed {
+            matched_rules.push(matched_rule);
+        }
+        image_allowed
+    }
+
+    fn digest_lists_are_empty(&self) -> bool {
+        self.allowed_digests_per_image.is_empty()
+            && self
+                .settings
+                .allowed_digests
+                .iter()
+                .flatten()
+                .next()
+                .is_none()
+            && self
+                .settings
+                .blocked_digests
+                .iter()
+                .flatten()
+                .next()
+                .is_none()
+    }
+
+    /// Returns the position of the digest inside of the
+    /// `allowedDigests` list.
+    fn allowed_digest_index(&self, digest: Option<&str>) -> Option<usize> {
+        let digest = digest?;
+        self.settings
+            .allowed_digests
+            .iter()
+            .flatten()
+            .position(|allowed_digest| allowed_digest == digest)
+    }
+
+    /// Verifies the signatures of the image against the signature rules
+    /// that apply to it, if any.
+    fn are_signatures_verified(&self, image: &Image) -> bool {
+        let signature_rules = self.signature_rules_for(image);
+        signature_rules.is_empty()
+            || self.dry_run
+            || verify_image_signatures(&image.image, &signature_rules).is_ok()
+    }
+
+    /// Returns the signature rules whose image pattern matches the
+    /// given image.
+    fn signature_rules_for(&self, image: &Image) -> Vec<&SignatureRule> {
+        self.signatures
+            .iter()
+            .copied()
+            .flatten()
+            .filter(|signature| glob_matches(&signature.image, &image.image))
+            .collect()
+    }
+
+    /// Returns true when the registry is exempted from the registry
+    /// rules by `allowNodeLocalRegistries`.
+    fn is_node_local_registry(&self, registry: &str) -> bool {
+        self.settings.allow_node_local_registries && is_loopback_registry(registry)
+    }
+
+    /// Returns the identifiers of the allow rules that matched the
+    /// given image, e.g. `registries.allow[0]`.
+    fn matched_allow_rules(&self, image: &Image) -> Vec<String> {
+        let mut matched_rules = Vec::new();
+        if let Some(registry) = &image.registry {
+            if self.is_node_local_registry(registry) {
+                matched_rules.push("allowNodeLocalRegistries".to_string());
+            }
+            if let Some(index) = self
+                .filters
+                .registry_rules
+                .iter()
+                .flatten()
+                .position(|registry_rule| registry_rule.registry.matches(registry))
+            {
+                matched_rules.push(format!("registryRules[{}]", index));
+            }
+            if let Some(index) = self
+                .filters
+                .registries
+                .as_ref()
+                .and_then(|registries| registries.allow_index(registry_rule_matches(registry)))
+            {
+                matched_rules.push(format!("{}registries.allow[{}]", self.filters_path, index));
+            }
+        }
+        if let Some(index) = self
+            .filters
+            .images
+            .as_ref()
+            .and_then(|images| images.allow_index(image_rule_matches(image)))
+        {
+            matched_rules.push(format!("{}images.allow[{}]", self.filters_path, index));
+        }
+        matched_rules
+    }
+
+    /// Returns the registry rule that applies to the registry of the
+    /// given image.
+    fn registry_rule_for(&self, image: &Image) -> Option<&CompiledRegistryRule> {
+        image.registry.as_ref().and_then(|registry| {
+            self.filters
+                .registry_rules
+                .iter()
+                .flatten()
+                .find(|registry_rule| registry_rule.registry.matches(registry))
+        })
+    }
+
+    /// Returns the most specific `imageTags` entry that applies to the
+    /// given image. Entries are glob patterns matched against the
+    /// registry and repository of the image, like
+    /// `docker.io/library/nginx`. An exact entry always wins, otherwise
+    /// the entry with the most non wildcard characters is picked.
+    fn image_tags_for(&self, image: &Image) -> Option<(&String, &Vec<TagPattern>)> {
+        let repository = image.full_repository();
+        self.image_tags
+            .iter()
+            .filter(|(pattern, _)| glob_matches(pattern, &repository))
+            .max_by_key(|(pattern, _)| {
+                (
+                    !pattern.contains('*'),
+                    pattern.chars().filter(|c| *c != '*').count(),
+                )
+            })
+            .map(|(pattern, allowed_tags)| (*pattern, allowed_tags))
+    }
+
+    fn is_allowed_registry(&self, registry: &str) -> bool {
+        if let Some(registry_rules) = &self.filters.registry_rules {
+            return registry_rules
+                .iter()
+                .any(|registry_rule| registry_rule.registry.matches(registry));
+        }
+        self.filters
+            .registries
+            .as_ref()
+            .map(|registries| registries.is_allowed(registry_rule_matches(registry)))
+            .unwrap_or(true)
+    }
+
+    fn is_allowed_tag(&self, tag: &str) -> bool {
+        self.filters
+            .tags
+            .as_ref()
+            .map(|tags| tags.is_allowed(tag_rule_matches(tag)))
+            .unwrap_or(true)
+    }
+
+    fn is_allowed_image(&self, image: &Image) -> bool {
+        self.filters
+            .images
+            .as_ref()
+            .map(|images| images.is_allowed(image_rule_matches(image)))
+            .unwrap_or(true)
+    }
+
+    /// Describes the reject rules of the given enforcement level
+    /// violated by the image, like ``rejected by registries.reject
+    /// entry `docker.io` ``, preceded by their message when they have
+    /// one.
+    fn rejecting_rules(&self, image: &Image, enforcement: Enforcement) -> Vec<RejectingRule<'_>> {
+        let mut rejecting_rules = Vec::new();
+        // The registry rules replace the registries lists
+        if let (Some(registries), Some(registry), None) = (
+            &self.filters.registries,
+            image
+                .registry
+                .as_ref()
+                .filter(|registry| !self.is_node_local_registry(registry)),
+            &self.filters.registry_rules,
+        ) {
+            rejecting_rules.push((
+                "registries",
+                format!("{}registries", self.filters_path),
+                registries
+                    .violated_by(enforcement, registry_rule_matches(registry))
+                    .map(|rule| (&rule.pattern, &rule.message)),
+            ));
+        }
+        if let Some(images) = &self.filters.images {
+            rejecting_rules.push((
+                "images",
+                format!("{}images", self.filters_path),
+                images
+                    .violated_by(enforcement, image_rule_matches(image))
+                    .map(|rule| (&rule.pattern, &rule.message)),
+            ));
+        }
+        let registry_rule = self.registry_rule_for(image);
+        let registry_rule_path = image.registry.as_ref().and_then(|registry| {
+            self.filters
+                .registry_rules
+                .iter()
+                .flatten()
+                .position(|registry_rule| registry_rule.registry.matches(registry))
+                .map(|index| format!("registryRules[{}]", index))
+        });
+        if let (Some(images), Some(path)) = (
+            registry_rule.and_then(|registry_rule| registry_rule.images.as_ref()),
+            &registry_rule_path,
+        ) {
+            rejecting_rules.push((
+                "registryRules.images",
+                format!("{}.images", path),
+                images
+                    .violated_by(enforcement, repository_rule_matches(&image.repository))
+                    .map(|rule| (&rule.pattern, &rule.message)),
+            ));
+        }
+        // `imageTags` entries take precedence over the tag rules
+        if let (Some(tag), None) = (&image.tag, self.image_tags_for(image)) {
+            if let Some(tags) = &self.filters.tags {
+                rejecting_rules.push((
+                    "tags",
+                    format!("{}tags", self.filters_path),
+                    tags.violated_by(enforcement, tag_rule_matches(tag))
+                        .map(|rule| (&rule.pattern, &rule.message)),
+                ));
+            }
+            if let (Some(tags), Some(path)) = (
+                registry_rule.and_then(|registry_rule| registry_rule.tags.as_ref()),
+                &registry_rule_path,
+            ) {
+                rejecting_rules.push((
+                    "registryRules.tags",
+                    format!("{}.tags", path),
+                    tags.violated_by(enforcement, tag_rule_matches(tag))
+                        .map(|rule| (&rule.pattern, &rule.message)),
+                ));
+            }
+        }
+        rejecting_rules
+            .into_iter()
+            .filter_map(|(rule, path, compiled_rule)| {
+                compiled_rule.map(|(pattern, message)| RejectingRule {
+                    rule,
+                    path,
+                    pattern,
+                    message: message.as_deref(),
+                    enforcement,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a pod with a single container running `image`.
+    fn pod_with_image(image: &str) -> apicore::Pod {
+        apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                containers: vec![apicore::Container {
+                    image: Some(image.to_string()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_is_allowed_registry() {
+        let settings: Settings = Default::default();
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(settings.is_allowed_registry(&String::from("docker.io")));
+
+        let settings = Settings {
+            registries: Some(Registries {
+                allow: Some(vec![Rule::from("allowed-registry.com")]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(settings.is_allowed_registry(&String::from("allowed-registry.com")));
+        assert!(!settings.is_allowed_registry(&String::from("allowed-registry.com:5001")));
+        assert!(!settings.is_allowed_registry(&String::from("docker.io")));
+
+        let settings = Settings {
+            registries: Some(Registries {
+                reject: Some(vec![Rule::from("forbidden-registry.com")]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(settings.is_allowed_registry(&String::from("docker.io")));
+        assert!(settings.is_allowed_registry(&String::from("non-forbidden-registry.com:5001")));
+        assert!(!settings.is_allowed_registry(&String::from("forbidden-registry.com")));
+
+        let settings = Settings {
+            registries: Some(Registries {
+                allow: Some(vec![
+                    Rule::from("registry.internal:*"),
+                    Rule::from("other-registry.internal:30000-32767"),
+                ]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(settings.is_allowed_registry(&String::from("registry.internal")));
+        assert!(settings.is_allowed_registry(&String::from("registry.internal:31000")));
+        assert!(settings.is_allowed_registry(&String::from("other-registry.internal:31000")));
+        assert!(!settings.is_allowed_registry(&String::from("other-registry.internal:5000")));
+        assert!(!settings.is_allowed_registry(&String::from("other-registry.internal")));
+
+        let settings = Settings {
+            registries: Some(Registries {
+                reject: Some(vec![Rule::from("10.0.0.0/8:*")]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(!settings.is_allowed_registry(&String::from("10.0.0.100:5000")));
+        assert!(settings.is_allowed_registry(&String::from("192.168.1.10:5000")));
+    }
+
+    #[test]
+    fn test_is_allowed_tag() {
+        let settings: Settings = Default::default();
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(settings.is_allowed_tag(&String::from("latest")));
+
+        let settings = Settings {
+            tags: Some(Tags {
+                reject: Some(vec![Rule::from("latest")]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(!settings.is_allowed_tag(&String::from("latest")));
+
+        let settings = Settings {
+            tags: Some(Tags {
+                allow: Some(vec![Rule::from(">=1.24, <2.0"), Rule::from("stable")]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(settings.is_allowed_tag(&String::from("1.24")));
+        assert!(settings.is_allowed_tag(&String::from("v1.27.3")));
+        assert!(settings.is_allowed_tag(&String::from("stable")));
+        assert!(!settings.is_allowed_tag(&String::from("2.0.0")));
+        assert!(!settings.is_allowed_tag(&String::from("latest")));
+    }
+
+    #[test]
+    fn is_allowed_image() {
+        assert!(Image::new("example.com/image:tag@sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb").is_ok());
+        assert!(Image::new("image:tag").is_ok());
+        assert!(Image::new("registry.com/image:tag").is_ok());
+        assert!(Image::new("registry.com/image@sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb").is_ok());
+        assert!(Image::new("quay.io/etcd/etcd:1.1.1@sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb").is_ok());
+        assert!(Image::new("quay.io/etcd/etcd@sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb").is_ok());
+        assert!(Image::new("redis:v1.2").is_ok());
+    }
+
+    #[test]
+    fn test_is_allowed_image() -> anyhow::Result<()> {
+        let settings = Settings {
+            images: Some(Images {
+                allow: Some(vec![
+                    Rule::from("gcr.io/my-project/team/service"),
+                    Rule::from("quay.io/coreos/etcd:v3.4.12"),
+                    Rule::from("ghcr.io/my-org/"),
+                ]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(settings.is_allowed_image(&Image::new("gcr.io/my-project/team/service:1.2")?));
+        assert!(settings.is_allowed_image(&Image::new("gcr.io/my-project/team/service")?));
+        assert!(!settings.is_allowed_image(&Image::new("gcr.io/my-project/team/other:1.2")?));
+        assert!(settings.is_allowed_image(&Image::new("quay.io/coreos/etcd:v3.4.12")?));
+        assert!(!settings.is_allowed_image(&Image::new("quay.io/coreos/etcd:v3.4.13")?));
+        assert!(settings.is_allowed_image(&Image::new("ghcr.io/my-org/app:1.0")?));
+        assert!(settings.is_allowed_image(&Image::new("ghcr.io/my-org/team/app:1.0")?));
+        assert!(!settings.is_allowed_image(&Image::new("ghcr.io/my-org-2/app:1.0")?));
+
+        let settings = Settings {
+            images: Some(Images {
+                reject: Some(vec![Rule::from("nginx")]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(!settings.is_allowed_image(&Image::new("docker.io/library/nginx:1.25")?));
+        assert!(settings.is_allowed_image(&Image::new("docker.io/bitnami/nginx:1.25")?));
+        Ok(())
+    }
+
+    #[test]
+    fn image_entries_in_normal_form() -> anyhow::Result<()> {
+        let images = || {
+            Some(Images {
+                allow: Some(vec![
+                    Rule::from("index.docker.io/nginx:1.25"),
+                    Rule::from("registry-1.docker.io/bitnami/*"),
+                ]),
+                ..Default::default()
+            })
+        };
+        let settings = Settings {
+            images: images(),
+            ..Default::default()
+        };
+        let compiled = settings.compile(None).expect("cannot compile settings");
+        for reference in [
+            "nginx:1.25",
+            "docker.io/library/nginx:1.25",
+            "index.docker.io/library/nginx:1.25",
+        ] {
+            let mut image = Image::new(reference)?;
+            image.normalize_registry_alias();
+            assert!(compiled.is_allowed_image(&image), "{}", reference);
+        }
+        let mut image = Image::new("bitnami/redis:7.2")?;
+        image.normalize_registry_alias();
+        assert!(compiled.is_allowed_image(&image));
+
+        let settings = Settings {
+            images: images(),
+            disable_registry_aliases: true,
+            ..Default::default()
+        };
+        let compiled = settings.compile(None).expect("cannot compile settings");
+        assert!(!compiled.is_allowed_image(&Image::new("nginx:1.25")?));
+        assert!(compiled.is_allowed_image(&Image::new("index.docker.io/nginx:1.25")?));
+
+        // Entries naming the same repository through an alias are
+        // reported by the validation
+        let settings: Settings = serde_json::from_value(serde_json::json!({
+            "images": {"allow": ["nginx", "index.docker.io/library/nginx"]},
+        }))
+        .expect("cannot parse settings");
+        assert_eq!(
+            settings.warnings(),
+            vec!["images allow entry index.docker.io/library/nginx has no effect: nginx matches all its values"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn organization_rules() {
+        let is_accepted = |settings: serde_json::Value, image: &str| {
+            let settings: Settings =
+                serde_json::from_value(settings).expect("cannot parse settings");
+            assert_eq!(settings.validate(), Ok(()));
+            matches!(
+                settings
+                    .compile(None)
+                    .expect("cannot compile settings")
+                    .are_images_accepted([("", image)]),
+                PodEvaluationResult::Allowed(_)
+            )
+        };
+        let organization = serde_json::json!({"pattern": "bitnami", "kind": "organization"});
+
+        let settings = serde_json::json!({"images": {"allow": [organization]}});
+        assert!(is_accepted(settings.clone(), "bitnami/redis:7.2"));
+        assert!(is_accepted(settings.clone(), "ghcr.io/bitnami/redis:7.2"));
+        assert!(!is_accepted(
+            settings.clone(),
+            "quay.io/bitnami-fork/redis:7.2"
+        ));
+        assert!(!is_accepted(settings.clone(), "quay.io/bitnami:7.2"));
+        assert!(!is_accepted(settings, "nginx:1.25"));
+
+        // Scoped to the registry of a registry rule
+        let settings = serde_json::json!({"registryRules": [
+            {"registry": "quay.io", "images": {"allow": [organization]}},
+            {"registry": "docker.io"},
+        ]});
+        assert!(is_accepted(settings.clone(), "quay.io/bitnami/redis:7.2"));
+        assert!(!is_accepted(settings.clone(), "quay.io/coreos/etcd:v3.5.9"));
+        assert!(is_accepted(settings, "docker.io/coreos/etcd:v3.5.9"));
+
+        let settings: Settings = serde_json::from_value(serde_json::json!({
+            "images": {"allow": [organization, "docker.io/bitnami/redis"]},
+        }))
+        .expect("cannot parse settings");
+        assert_eq!(
+            settings.warnings(),
+            vec!["images allow entry docker.io/bitnami/redis has no effect: bitnami matches all its values"]
+        );
+
+        for settings in [
+            serde_json::json!({"registries": {"allow": [organization]}}),
+            serde_json::json!({"tags": {"reject": [organization]}}),
+        ] {
+            let settings: Settings =
+                serde_json::from_value(settings).expect("cannot parse settings");
+            assert_eq!(
+                settings.validate(),
+                Err("organization rule bitnami is only supported by the images lists".to_string())
+            );
+        }
+        let settings: Settings = serde_json::from_value(serde_json::json!({
+            "images": {"allow": [{"pattern": "bitnami/*", "kind": "organization"}]},
+        }))
+        .expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err(
+                "invalid organization bitnami/*: expected a single repository path component"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn size_limits() {
+        let validate = |settings: serde_json::Value| {
+            Settings::from_value(settings).and_then(|settings| settings.validate())
+        };
+        let entries = |count: usize| {
+            (0..count)
+                .map(|index| format!("registry-{}.example.com", index))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            validate(serde_json::json!({"registries": {"allow": entries(1000)}})),
+            Ok(())
+        );
+        assert_eq!(
+            validate(serde_json::json!({"registries": {"allow": entries(1001)}})),
+            Err("registries.allow has 1001 entries, more than the limit of 1000".to_string())
+        );
+        assert_eq!(
+            validate(serde_json::json!({
+                "registryRules": [{"registry": "quay.io", "images": {"reject": ["a".repeat(1025)]}}]
+            })),
+            Err("registryRules[0].images.reject[0] is longer than 1024 characters".to_string())
+        );
+        assert_eq!(
+            validate(serde_json::json!({"imageTags": {"nginx": entries(1001)}})),
+            Err("imageTags[nginx] has 1001 entries, more than the limit of 1000".to_string())
+        );
+        let namespace_rules = (0..101)
+            .map(|index| serde_json::json!({"namespaces": [format!("team-{}", index)]}))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            validate(serde_json::json!({"namespaceRules": namespace_rules})),
+            Err("namespaceRules has 101 entries, more than the limit of 100".to_string())
+        );
+
+        let mut nested = serde_json::json!("latest");
+        for _ in 0..16 {
+            nested = serde_json::json!([nested]);
+        }
+        assert_eq!(
+            validate(serde_json::json!({"extra": nested})),
+            Err("extra[0][0][0][0][0][0][0][0][0][0][0][0][0][0][0][0] is nested more than 16 levels deep".to_string())
+        );
+    }
+
+    #[test]
+    fn settings_from_json_or_yaml() {
+        let expected = Settings::from_value(serde_json::json!({
+            "registries": {"allow": ["registry.my-corp.com"]},
+            "tags": {"reject": ["latest"]}
+        }))
+        .expect("cannot parse settings");
+        for payload in [
+            r#"{"registries": {"allow": ["registry.my-corp.com"]}, "tags": {"reject": ["latest"]}}"#,
+            "registries:\n  allow:\n  - registry.my-corp.com\ntags:\n  reject: [latest]\n",
+        ] {
+            let settings = Settings::from_slice(payload.as_bytes()).expect("cannot parse settings");
+            assert_eq!(
+                serde_json::to_value(&settings).expect("cannot serialize settings"),
+                serde_json::to_value(&expected).expect("cannot serialize settings"),
+                "{}",
+                payload
+            );
+        }
+        assert_eq!(
+            Settings::from_slice(b"").map(|settings| settings.registries.is_none()),
+            Ok(true)
+        );
+        assert!(Settings::from_slice(b"registries: [")
+            .unwrap_err()
+            .starts_with("cannot parse settings as JSON"));
+        assert_eq!(
+            Settings::from_slice(b"- registries").unwrap_err(),
+            "settings must be an object"
+        );
+    }
+
+    #[test]
+    fn valid_allowed_registries() {
+        assert_eq!(
+            Settings {
+                registries: Some(Registries {
+                    allow: Some(vec!("allowed-registry.com".into())),
+                    reject: None,
+                    match_strategy: None,
+                },),
+                tags: None,
+                images: None,
+                ..Default::default()
+            }
+            .validate(),
+            Ok(()),
+        );
+    }
+
+    #[test]
+    fn invalid_registry_ports() {
+        assert_eq!(
+            Settings {
+                registries: Some(Registries {
+                    allow: Some(vec!("registry.internal:port".into())),
+                    reject: None,
+                    match_strategy: None,
+                },),
+                ..Default::default()
+            }
+            .validate(),
+            Err("registry registry.internal:port: invalid port port".to_string()),
+        );
+    }
+
+    #[test]
+    fn valid_rejected_registries() {
+        assert_eq!(
+            Settings {
+                registries: Some(Registries {
+                    allow: None,
+                    reject: Some(vec!("rejected-registry.com".into())),
+                    match_strategy: None,
+                },),
+                tags: None,
+                images: None,
+                ..Default::default()
+            }
+            .validate(),
+            Ok(()),
+        );
+    }
+
+    #[test]
+    fn invalid_allowed_and_rejected_registries() {
+        assert_eq!(
+            Settings {
+                registries: Some(Registries {
+                    allow: Some(vec!("allowed-registry.com".into())),
+                    reject: Some(vec!("rejected-registry.com".into())),
+                    match_strategy: None,
+                },),
+                tags: None,
+                images: None,
+                ..Default::default()
+            }
+            .validate(),
+            Err(
+                "only one of registries allow or reject can be provided, and one must be provided"
+                    .to_string()
+            ),
+        );
+    }
+
+    #[test]
+    fn invalid_none_allowed_nor_rejected_registries() {
+        assert_eq!(
+            Settings {
+                registries: Some(Registries {
+                    allow: None,
+                    reject: None,
+                    match_strategy: None,
+                },),
+                tags: None,
+                images: None,
+                ..Default::default()
+            }
+            .validate(),
+            Err(
+                "only one of registries allow or reject can be provided, and one must be provided"
+                    .to_string()
+            ),
+        );
+    }
+
+    #[test]
+    fn valid_allowed_tags() {
+        assert_eq!(
+            Settings {
+                tags: Some(Tags {
+                    allow: Some(vec![">=1.24, <2.0".into(), "stable".into()]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }
+            .validate(),
+            Ok(()),
+        );
+    }
+
+    #[test]
+    fn invalid_allowed_tags() {
+        assert_eq!(
+            Settings {
+                tags: Some(Tags {
+                    allow: Some(vec!["stable".into()]),
+                    reject: Some(vec!["latest".into()]),
+                    match_strategy: None,
+                }),
+                ..Default::default()
+            }
+            .validate(),
+            Err("only one of tags allow or reject can be provided".to_string()),
+        );
+        assert!(Settings {
+            tags: Some(Tags {
+                allow: Some(vec![">=1.24, <<2.0".into()]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+        .validate()
+        .unwrap_err()
+        .starts_with("invalid version range >=1.24, <<2.0"));
+    }
+
+    #[test]
+    fn valid_allowed_images() {
+        assert_eq!(
+            Settings {
+                registries: None,
+                tags: None,
+                images: Some(Images {
+                    allow: Some(vec!("some-registry.com/some/allowed/image:tag".into())),
+                    reject: None,
+                    match_strategy: None,
+                },),
+                ..Default::default()
+            }
+            .validate(),
+            Ok(()),
+        );
+    }
+
+    #[test]
+    fn valid_rejected_images() {
+        assert_eq!(
+            Settings {
+                registries: None,
+                tags: None,
+                images: Some(Images {
+                    allow: None,
+                    reject: Some(vec!("some-registry.com/some/rejected/image:tag".into())),
+                    match_strategy: None,
+                },),
+                ..Default::default()
+            }
+            .validate(),
+            Ok(()),
+        );
+    }
+
+    #[test]
+    fn invalid_allowed_and_rejected_images() {
+        assert_eq!(
+            Settings {
+                registries: None,
+                tags: None,
+                images: Some(Images {
+                    allow: Some(vec!("some-registry.com/some/allowed/image:tag".into())),
+                    reject: Some(vec!("some-registry.com/some/rejected/image:tag".into())),
+                    match_strategy: None,
+                },),
+                ..Default::default()
+            }
+            .validate(),
+            Err(
+                "only one of images allow or reject can be provided, and one must be provided"
+                    .to_string()
+            ),
+        );
+    }
+
+    #[test]
+    fn invalid_none_allowed_nor_rejected_images() {
+        assert_eq!(
+            Settings {
+                registries: None,
+                tags: None,
+                images: Some(Images {
+                    allow: None,
+                    reject: None,
+                    match_strategy: None,
+                },),
+                ..Default::default()
+            }
+            .validate(),
+            Err(
+                "only one of images allow or reject can be provided, and one must be provided"
+                    .to_string()
+            ),
+        );
+    }
+
+    #[test]
+    fn valid_decision_annotation() {
+        assert_eq!(
+            Settings {
+                decision_annotation: Some(DecisionAnnotation::default()),
+                ..Default::default()
+            }
+            .validate(),
+            Ok(()),
+        );
+        assert_eq!(
+            Settings {
+                decision_annotation: Some(DecisionAnnotation {
+                    key: "decision".to_string(),
+                }),
+                ..Default::default()
+            }
+            .validate(),
+            Ok(()),
+        );
+    }
+
+    #[test]
+    fn invalid_decision_annotation_key() {
+        assert_eq!(
+            Settings {
+                decision_annotation: Some(DecisionAnnotation {
+                    key: "Example.com/decision".to_string(),
+                }),
+                ..Default::default()
+            }
+            .validate(),
+            Err(
+                "decision annotation key Example.com/decision is not a valid annotation key"
+                    .to_string()
+            ),
+        );
+        assert!(Settings {
+            decision_annotation: Some(DecisionAnnotation {
+                key: "example.com/-decision".to_string(),
+            }),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn invalid_message_template() {
+        assert_eq!(
+            Settings {
+                message_template: Some("{{image}} is not allowed by {{policy}}".to_string()),
+                ..Default::default()
+            }
+            .validate(),
+            Err("invalid messageTemplate: unknown placeholder {{policy}}, expected one of container, path, image, rule, code, registry, message".to_string()),
+        );
+    }
+
+    #[test]
+    fn decision_annotation_default_key() {
+        let settings: Settings =
+            serde_json::from_str(r#"{"decisionAnnotation": {}}"#).expect("cannot parse settings");
+        assert_eq!(
+            settings.decision_annotation,
+            Some(DecisionAnnotation {
+                key: "trusted-repos.policy/decision".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn quarantine_settings() {
+        let settings: Settings =
+            serde_json::from_str(r#"{"quarantine": {"label": {"key": "example.com/isolated"}}}"#)
+                .expect("cannot parse settings");
+        assert_eq!(
+            settings.quarantine,
+            Some(Quarantine {
+                annotation: "trusted-repos.policy/violations".to_string(),
+                label: Some(QuarantineLabel {
+                    key: "example.com/isolated".to_string(),
+                    value: "true".to_string(),
+                }),
+            })
+        );
+        assert!(settings.validate().is_ok());
+
+        let settings: Settings =
+            serde_json::from_str(r#"{"quarantine": {"label": {"value": "not a label value"}}}"#)
+                .expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err("quarantine label value not a label value is not a valid label value".to_string())
+        );
+        let settings = Settings {
+            quarantine: Some(Quarantine {
+                annotation: "/violations".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            settings.validate(),
+            Err("quarantine annotation /violations is not a valid annotation key".to_string())
+        );
+    }
+
+    #[test]
+    fn namespace_rules() -> anyhow::Result<()> {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "registryRules": [{"registry": "registry.internal"}],
+                "tags": {"reject": ["latest"]},
+                "namespaceRules": [
+                    {"namespaces": ["prod-*"], "images": {"reject": ["docker.io/*"]}},
+                    {"namespaces": ["dev", "prod-sandbox"], "registries": {"allow": ["docker.io"]}}
+                ]
+            }"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let no_labels = BTreeMap::new();
+        let accepted = |namespace: Option<&str>, image: &str| {
+            let scope = namespace.map(|namespace| RequestScope {
+                namespace,
+                labels: &no_labels,
+            });
+            matches!(
+                settings
+                    .compile(scope.as_ref())
+                    .expect("cannot compile settings")
+                    .is_pod_accepted(&pod_with_image(image), None),
+                PodEvaluationResult::Allowed(_)
+            )
+        };
+        assert!(!accepted(None, "docker.io/nginx:1.25"));
+        assert!(accepted(Some("dev"), "docker.io/nginx:1.25"));
+        assert!(!accepted(Some("dev"), "docker.io/nginx:latest"));
+        assert!(!accepted(Some("dev"), "registry.internal/app:1.0"));
+        // The first matching rule applies, with the global registry rules
+        assert!(!accepted(Some("prod-sandbox"), "docker.io/nginx:1.25"));
+        assert!(accepted(Some("prod-eu"), "registry.internal/app:1.0"));
+        assert!(!accepted(Some("staging"), "docker.io/nginx:1.25"));
+
+        let compiled_settings = settings
+            .compile(Some(&RequestScope {
+                namespace: "dev",
+                labels: &no_labels,
+            }))
+            .expect("cannot compile settings");
+        assert_eq!(
+            compiled_settings.matched_allow_rules(&Image::new("docker.io/nginx:1.25")?),
+            vec!["namespaceRules[1].registries.allow[0]"]
+        );
+
+        let settings: Settings =
+            serde_json::from_str(r#"{"namespaceRules": [{"namespaces": ["dev"], "images": {}}]}"#)
+                .expect("cannot parse settings");
+        assert!(settings
+            .validate()
+            .unwrap_err()
+            .starts_with("namespace rule for dev: "));
+        let settings: Settings =
+            serde_json::from_str(r#"{"namespaceRules": [{"namespaces": []}]}"#)
+                .expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err(
+                "namespace rules must provide non empty namespaces or an objectSelector"
+                    .to_string()
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn namespace_overrides() {
+        let settings: Settings = serde_json::from_value(serde_json::json!({
+            "rulePrecedence": "rejectWins",
+            "registries": {"allow": ["quay.io"], "reject": ["untrusted.io"]},
+            "tags": {"reject": ["latest"]},
+            "namespaceOverrides": {
+                "dev-*": {"registries": {"allow": ["quay.io", "docker.io"]}, "tags": null},
+                "dev-sandbox": {"logDecisions": true}
+            }
+        }))
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        assert!(settings
+            .for_namespace("prod")
+            .expect("cannot override settings")
+            .is_none());
+
+        let dev = settings
+            .for_namespace("dev-sandbox")
+            .expect("cannot override settings")
+            .expect("no override applies");
+        let registries = dev.registries.as_ref().expect("no registries");
+        let patterns = |rules: &Option<Vec<Rule>>| -> Vec<String> {
+            rules
+                .iter()
+                .flatten()
+                .map(|rule| rule.pattern().to_string())
+                .collect()
+        };
+        assert_eq!(patterns(&registries.allow), vec!["quay.io", "docker.io"]);
+        assert_eq!(patterns(&registries.reject), vec!["untrusted.io"]);
+        assert!(dev.tags.is_none());
+        assert!(dev.log_decisions);
+        assert!(dev.namespace_overrides.is_none());
+
+        for (overrides, error) in [
+            (
+                serde_json::json!({"dev-*": ["quay.io"]}),
+                "namespaceOverrides entry for dev-* is not an object",
+            ),
+            (
+                serde_json::json!({"dev-*": {"rulesFrom": {"configMap": "rules"}}}),
+                "namespaceOverrides entry for dev-* cannot override rulesFrom",
+            ),
+            (
+                serde_json::json!({"dev-*": {"registries": {}}}),
+                "namespaceOverrides entry for dev-*: only one of registries allow or reject can be provided, and one must be provided",
+            ),
+        ] {
+            let settings: Settings =
+                serde_json::from_value(serde_json::json!({"namespaceOverrides": overrides}))
+                    .expect("cannot parse settings");
+            assert_eq!(settings.validate(), Err(error.to_string()));
+        }
+    }
+
+    #[test]
+    fn namespace_rules_object_selector() -> anyhow::Result<()> {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "registries": {"allow": ["registry.internal"]},
+                "namespaceRules": [{
+                    "namespaces": ["prod-*"],
+                    "objectSelector": {"matchLabels": {"tier": "critical"}},
+                    "images": {"allow": ["registry.internal/*@*"]}
+                }]
+            }"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let image = Image::new("registry.internal/app:1.0")?;
+        let critical: BTreeMap<String, String> = vec![("tier".to_string(), "critical".to_string())]
+            .into_iter()
+            .collect();
+        let allowed = |namespace: &str, labels: &BTreeMap<String, String>| {
+            settings
+                .compile(Some(&RequestScope { namespace, labels }))
+                .expect("cannot compile settings")
+                .is_allowed_image(&image)
+        };
+        assert!(!allowed("prod-eu", &critical));
+        assert!(allowed("prod-eu", &BTreeMap::new()));
+        assert!(allowed("dev", &critical));
+
+        let settings: Settings = serde_json::from_str(
+            r#"{"namespaceRules": [{"objectSelector": {"matchExpressions": [{"key": "tier", "operator": "In"}]}}]}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err("namespace rule with an objectSelector: selector requirement for tier must provide values with operator In".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn matched_allow_rules() -> anyhow::Result<()> {
+        let settings = Settings {
+            registries: Some(Registries {
+                allow: Some(vec![
+                    Rule::from("docker.io"),
+                    Rule::from("allowed-registry.com"),
+                ]),
+                ..Default::default()
+            }),
+            images: Some(Images {
+                allow: Some(vec![Rule::from("allowed-registry.com/image:tag")]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert_eq!(
+            settings.matched_allow_rules(&Image::new("allowed-registry.com/image:tag")?),
+            vec!["registries.allow[1]", "images.allow[0]"],
+        );
+        assert!(Settings::default()
+            .compile(None)
+            .expect("cannot compile settings")
+            .matched_allow_rules(&Image::new("image:tag")?)
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn decision_annotation_value() {
+        let settings = Settings::default();
+        let fingerprint = settings.fingerprint();
+        assert_eq!(fingerprint.len(), 12);
+        assert_eq!(
+            settings.decision_annotation_value(&[]),
+            format!("allowed; rules=none; fingerprint={}", fingerprint)
+        );
+        assert_eq!(
+            settings.decision_annotation_value(&[
+                "registries.allow[0]".to_string(),
+                "images.allow[1]".to_string()
+            ]),
+            format!(
+                "allowed; rules=registries.allow[0],images.allow[1]; fingerprint={}",
+                fingerprint
+            )
+        );
+    }
+
+    #[test]
+    fn valid_signatures() {
+        assert_eq!(
+            Settings {
+                signatures: Some(vec![SignatureRule {
+                    image: "registry.my-corp.com/*".to_string(),
+                    pub_keys: vec![
+                        "-----BEGIN PUBLIC KEY-----\nMFkw\n-----END PUBLIC KEY-----".to_string()
+                    ],
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }
+            .validate(),
+            Ok(()),
+        );
+    }
+
+    #[test]
+    fn valid_keyless_signatures() {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "signatures": [{
+                    "image": "ghcr.io/my-org/*",
+                    "keyless": [{
+                        "issuer": "https://token.actions.githubusercontent.com",
+                        "subject": "https://github.com/my-org/app/.github/workflows/release.yml@refs/heads/main"
+                    }]
+                }]
+            }"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+    }
+
+    #[test]
+    fn invalid_keyless_signatures() {
+        assert_eq!(
+            Settings {
+                signatures: Some(vec![SignatureRule {
+                    image: "ghcr.io/my-org/*".to_string(),
+                    keyless: vec![KeylessInfo {
+                        issuer: "https://token.actions.githubusercontent.com".to_string(),
+                        subject: String::new(),
+                    }],
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }
+            .validate(),
+            Err(
+                "signature rule for ghcr.io/my-org/* contains a keyless identity without issuer or subject"
+                    .to_string()
+            ),
+        );
+    }
+
+    #[test]
+    fn invalid_signatures() {
+        assert_eq!(
+            Settings {
+                signatures: Some(vec![SignatureRule {
+                    image: "registry.my-corp.com/*".to_string(),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }
+            .validate(),
+            Err(
+                "signature rule for registry.my-corp.com/* must provide at least one public key or keyless identity"
+                    .to_string()
+            ),
+        );
+        assert_eq!(
+            Settings {
+                signatures: Some(vec![SignatureRule {
+                    image: "registry.my-corp.com/*".to_string(),
+                    pub_keys: vec!["not a key".to_string()],
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }
+            .validate(),
+            Err(
+                "signature rule for registry.my-corp.com/* contains a public key that is not PEM encoded"
+                    .to_string()
+            ),
+        );
+    }
+
+    #[test]
+    fn test_signature_rules_for() -> anyhow::Result<()> {
+        let settings = Settings {
+            signatures: Some(vec![
+                SignatureRule {
+                    image: "registry.my-corp.com/*".to_string(),
+                    ..Default::default()
+                },
+                SignatureRule {
+                    image: "registry.my-corp.com/team/*".to_string(),
+                    ..Default::default()
+                },
+            ]),
+            ..Default::default()
+        };
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert_eq!(
+            settings
+                .signature_rules_for(&Image::new("registry.my-corp.com/team/image:tag")?)
+                .len(),
+            2
+        );
+        assert_eq!(
+            settings
+                .signature_rules_for(&Image::new("registry.my-corp.com/image:tag")?)
+                .len(),
+            1
+        );
+        assert!(settings
+            .signature_rules_for(&Image::new("docker.io/image:tag")?)
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn valid_registry_rules() {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "registryRules": [
+                    {"registry": "quay.io", "images": {"allow": ["prometheus/*"]}},
+                    {"registry": "docker.io", "images": {"allow": ["library/*"]}, "tags": {"reject": ["latest"]}}
+                ]
+            }"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+    }
+
+    #[test]
+    fn invalid_registry_rules() {
+        assert_eq!(
+            Settings {
+                registries: Some(Registries {
+                    allow: Some(vec!["quay.io".into()]),
+                    ..Default::default()
+                }),
+                registry_rules: Some(vec![RegistryRule {
+                    registry: "quay.io".to_string(),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }
+            .validate(),
+            Err("only one of registries or registryRules can be provided".to_string()),
+        );
+        assert_eq!(
+            Settings {
+                registry_rules: Some(vec![RegistryRule {
+                    registry: "quay.io".to_string(),
+                    images: Some(Images::default()),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }
+            .validate(),
+            Err("registry rule for quay.io: only one of images allow or reject can be provided, and one must be provided".to_string()),
+        );
+    }
+
+    #[test]
+    fn test_registry_rules() -> anyhow::Result<()> {
+        let settings = Settings {
+            registry_rules: Some(vec![
+                RegistryRule {
+                    registry: "quay.io".to_string(),
+                    images: Some(Images {
+                        allow: Some(vec!["prometheus/*".into()]),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                RegistryRule {
+                    registry: "docker.io".to_string(),
+                    images: Some(Images {
+                        allow: Some(vec!["library/*".into()]),
+                        ..Default::default()
+                    }),
+                    tags: Some(Tags {
+                        reject: Some(vec!["latest".into()]),
+                        ..Default::default()
+                    }),
+                },
+            ]),
+            ..Default::default()
+        };
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(settings.is_allowed_registry("quay.io"));
+        assert!(settings.is_allowed_registry("docker.io"));
+        assert!(!settings.is_allowed_registry("ghcr.io"));
+
+        let quay = settings
+            .registry_rule_for(&Image::new("quay.io/prometheus/prometheus:v2.45.0")?)
+            .expect("missing quay.io rule");
+        assert!(quay.is_allowed_repository("prometheus/prometheus"));
+        assert!(!quay.is_allowed_repository("coreos/etcd"));
+        assert!(quay.is_allowed_tag("latest"));
+
+        let docker = settings
+            .registry_rule_for(&Image::new("nginx:1.25")?)
+            .expect("missing docker.io rule");
+        assert!(docker.is_allowed_repository(&Image::new("nginx:1.25")?.repository));
+        assert!(!docker.is_allowed_repository(&Image::new("docker.io/bitnami/redis")?.repository));
+        assert!(!docker.is_allowed_tag("latest"));
+        assert!(docker.is_allowed_tag("1.25"));
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_image_tags() {
+        let settings: Settings =
+            serde_json::from_str(r#"{"imageTags": {"docker.io/library/nginx": []}}"#)
+                .expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err("imageTags entry for docker.io/library/nginx has no tags".to_string())
+        );
+        let settings: Settings = serde_json::from_str(
+            r#"{"imageTags": {"docker.io/library/nginx": [">=1.24, <<2.0"]}}"#,
+        )
+        .expect("cannot parse settings");
+        assert!(settings
+            .validate()
+            .unwrap_err()
+            .starts_with("imageTags entry for docker.io/library/nginx: invalid version range"));
+    }
+
+    #[test]
+    fn test_image_tags_for() -> anyhow::Result<()> {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "imageTags": {
+                    "docker.io/library/nginx": ["1.25", "1.26"],
+                    "docker.io/library/*": ["stable"],
+                    "docker.io/*": ["latest"]
+                }
+            }"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+
+        let (pattern, _) = settings
+            .image_tags_for(&Image::new("nginx:1.25")?)
+            .expect("missing imageTags entry");
+        assert_eq!(pattern, "docker.io/library/nginx");
+
+        let (pattern, _) = settings
+            .image_tags_for(&Image::new("redis:7")?)
+            .expect("missing imageTags entry");
+        assert_eq!(pattern, "docker.io/library/*");
+
+        let (pattern, _) = settings
+            .image_tags_for(&Image::new("docker.io/bitnami/redis:7")?)
+            .expect("missing imageTags entry");
+        assert_eq!(pattern, "docker.io/*");
+
+        assert!(settings
+            .image_tags_for(&Image::new("quay.io/coreos/etcd:v3.4.12")?)
+            .is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn image_tags_take_precedence_over_tags() {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "tags": {"reject": ["latest"]},
+                "imageTags": {"docker.io/library/nginx": ["1.25", "latest"]}
+            }"#,
+        )
+        .expect("cannot parse settings");
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(matches!(
+            settings.is_pod_accepted(&pod_with_image("nginx:latest"), None),
+            PodEvaluationResult::Allowed(_)
+        ));
+        assert!(matches!(
+            settings.is_pod_accepted(&pod_with_image("nginx:1.24"), None),
+            PodEvaluationResult::NotAllowed(_)
+        ));
+        assert!(matches!(
+            settings.is_pod_accepted(&pod_with_image("redis:latest"), None),
+            PodEvaluationResult::NotAllowed(_)
+        ));
+    }
+
+    #[test]
+    fn invalid_allowed_digests() {
+        let settings: Settings = serde_json::from_str(r#"{"allowedDigests": ["sha256:abc"]}"#)
+            .expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err(
+                "invalid allowed digest sha256:abc: sha256 digests must be 64 hex characters"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn allowed_digests_bypass_registry_rules() {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "registries": {"allow": ["registry.my-corp.com"]},
+                "tags": {"reject": ["latest"]},
+                "allowedDigests": ["sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb"]
+            }"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        match settings.is_pod_accepted(&pod_with_image("quay.io/etcd/etcd:latest@sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb"), None) {
+            PodEvaluationResult::Allowed(matched_rules) => {
+                assert_eq!(matched_rules, vec!["allowedDigests[0]"])
+            }
+            PodEvaluationResult::NotAllowed(_) => panic!("pod should be allowed"),
+        }
+        assert!(matches!(
+            settings.is_pod_accepted(&pod_with_image("quay.io/etcd/etcd@sha256:73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049"), None),
+            PodEvaluationResult::NotAllowed(_)
+        ));
+        assert!(matches!(
+            settings.is_pod_accepted(&pod_with_image("registry.my-corp.com/etcd:v3.4.12"), None),
+            PodEvaluationResult::Allowed(_)
+        ));
+    }
+
+    #[test]
+    fn invalid_blocked_digests() {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "allowedDigests": ["sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb"],
+                "blockedDigests": ["sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb"]
+            }"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err("digest sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb cannot be both allowed and blocked".to_string())
+        );
+        let settings: Settings =
+            serde_json::from_str(r#"{"blockedDigests": ["md5"]}"#).expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err("invalid blocked digest md5: expected algorithm:encoded".to_string())
+        );
+    }
+
+    #[test]
+    fn blocked_digests_are_rejected() {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "registries": {"allow": ["quay.io"]},
+                "blockedDigests": ["sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb"]
+            }"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        let pod = apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                containers: vec![apicore::Container {
+                    image: Some("quay.io/etcd/etcd:v3.4.12@sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb".to_string()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        match settings.is_pod_accepted(&pod, None) {
+            PodEvaluationResult::NotAllowed(rejection_reasons) => assert_eq!(
+                rejection_reasons.digests_blocked,
+                vec!["sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb"]
+            ),
+            PodEvaluationResult::Allowed(_) => panic!("pod should be rejected"),
+        }
+    }
+
+    #[test]
+    fn allowed_digests_per_image() {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "registries": {"allow": ["registry.internal", "docker.io"]},
+                "allowedDigestsPerImage": {
+                    "registry.internal/payments/api": ["sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb"],
+                    "nginx": ["sha256:73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049"]
+                }
+            }"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        for image in [
+            "registry.internal/payments/api:1.2@sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb",
+            "registry.internal/payments/api:1.2",
+            "registry.internal/payments/worker@sha256:73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049",
+            "docker.io/library/nginx@sha256:73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049",
+        ] {
+            assert!(
+                matches!(
+                    settings.is_pod_accepted(&pod_with_image(image), None),
+                    PodEvaluationResult::Allowed(_)
+                ),
+                "{}",
+                image
+            );
+        }
+        match settings.is_pod_accepted(&pod_with_image("registry.internal/payments/api@sha256:73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049"), None) {
+            PodEvaluationResult::NotAllowed(rejection_reasons) => assert_eq!(
+                rejection_reasons.digests_not_allowed,
+                vec!["registry.internal/payments/api@sha256:73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049"]
+            ),
+            PodEvaluationResult::Allowed(_) => panic!("pod should be rejected"),
+        }
+        assert!(matches!(
+            settings.is_pod_accepted(&pod_with_image("index.docker.io/nginx@sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb"), None),
+            PodEvaluationResult::NotAllowed(_)
+        ));
+
+        let settings: Settings = serde_json::from_str(
+            r#"{"allowedDigestsPerImage": {"registry.internal/payments/api:1.2": ["sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb"]}}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err("invalid allowedDigestsPerImage entry registry.internal/payments/api:1.2: expected a repository, like registry.internal/payments/api".to_string())
+        );
+        let settings: Settings =
+            serde_json::from_str(r#"{"allowedDigestsPerImage": {"nginx": ["md5"]}}"#)
+                .expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err("invalid digest md5 of allowedDigestsPerImage entry nginx: expected algorithm:encoded".to_string())
+        );
+    }
+
+    #[test]
+    fn strict_digest_mode() {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "strictDigestMode": true,
+                "registries": {"allow": ["docker.io"]},
+                "tags": {"reject": ["latest"]},
+                "allowedDigests": ["sha256:73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049"],
+                "allowedDigestsPerImage": {
+                    "registry.internal/payments/api": ["sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb"]
+                }
+            }"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        for (image, digests_not_listed) in [
+            (
+                "registry.internal/payments/api:latest@sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb",
+                None,
+            ),
+            (
+                "quay.io/app@sha256:73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049",
+                None,
+            ),
+            ("nginx:1.25", Some(vec!["nginx:1.25"])),
+            ("registry.internal/payments/api:1.2", Some(vec!["registry.internal/payments/api:1.2"])),
+            (
+                "registry.internal/payments/worker@sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb",
+                Some(vec!["registry.internal/payments/worker@sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb"]),
+            ),
+            (
+                "registry.internal/payments/api@sha256:73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049",
+                Some(vec![]),
+            ),
+        ] {
+            match (settings.is_pod_accepted(&pod_with_image(image), None), digests_not_listed) {
+                (PodEvaluationResult::Allowed(_), None) => (),
+                (PodEvaluationResult::NotAllowed(rejection_reasons), Some(digests_not_listed)) => {
+                    assert_eq!(rejection_reasons.digests_not_listed, digests_not_listed, "{}", image);
+                    assert!(rejection_reasons.registries_not_allowed.is_empty(), "{}", image);
+                }
+                _ => panic!("unexpected decision for {}", image),
+            }
+        }
+
+        let settings: Settings =
+            serde_json::from_str(r#"{"strictDigestMode": true}"#).expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err("strictDigestMode requires allowedDigests or allowedDigestsPerImage".to_string())
+        );
+    }
+
+    #[test]
+    fn settings_from_value() {
+        let settings = Settings::from_value(serde_json::json!({
+            "registries": {"allow": ["quay.io"]}
+        }))
+        .expect("cannot build settings");
+        assert_eq!(settings.api_version, Some("v1".to_string()));
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(settings.is_allowed_registry("quay.io"));
+
+        assert_eq!(
+            Settings::from_value(serde_json::json!({"apiVersion": "v9"})).unwrap_err(),
+            "unsupported settings apiVersion v9"
+        );
+    }
+
+    #[test]
+    fn invalid_api_version() {
+        assert_eq!(
+            Settings {
+                api_version: Some("v9".to_string()),
+                ..Default::default()
+            }
+            .validate(),
+            Err("unsupported settings apiVersion v9".to_string()),
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn structured_rules() {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "registries": {"reject": [
+                    "docker.io",
+                    {"pattern": "*.untrusted.com", "kind": "glob", "message": "untrusted registries are not allowed"}
+                ]},
+                "tags": {"reject": [{"pattern": "latest|dev-.*", "kind": "regex", "message": "use a release tag"}]}
+            }"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(!settings.is_allowed_registry("docker.io"));
+        assert!(!settings.is_allowed_registry("registry.untrusted.com"));
+        assert!(settings.is_allowed_registry("quay.io"));
+        assert!(!settings.is_allowed_tag("dev-1234"));
+        assert!(settings.is_allowed_tag("v1.0"));
+
+        let pod = apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                containers: vec![apicore::Container {
+                    image: Some("registry.untrusted.com/app:dev-1234".to_string()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        match settings.is_pod_accepted(&pod, None) {
+            PodEvaluationResult::NotAllowed(rejection_reasons) => {
+                assert_eq!(
+                    rejection_reasons.messages,
+                    vec![
+                        "untrusted registries are not allowed (rejected by registries.reject entry `*.untrusted.com`)",
+                        "use a release tag (rejected by tags.reject entry `latest|dev-.*`)"
+                    ]
+                );
+                // The messages replace the generic text
+                assert!(rejection_reasons.registries_not_allowed.is_empty());
+                assert!(rejection_reasons.tags_not_allowed.is_empty());
+                assert_eq!(
+                    rejection_reasons
+                        .violations
+                        .iter()
+                        .map(|violation| (violation.rule.as_str(), violation.message.as_str()))
+                        .collect::<Vec<_>>(),
+                    vec![
+                        ("registries", "untrusted registries are not allowed"),
+                        ("tags", "use a release tag")
+                    ]
+                );
+            }
+            PodEvaluationResult::Allowed(_) => panic!("pod should be rejected"),
+        }
+
+        let pod = apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                containers: vec![apicore::Container {
+                    image: Some("docker.io/library/busybox:1.36".to_string()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        match settings.is_pod_accepted(&pod, None) {
+            PodEvaluationResult::NotAllowed(rejection_reasons) => {
+                assert_eq!(rejection_reasons.registries_not_allowed, vec!["docker.io"]);
+                assert_eq!(
+                    rejection_reasons.messages,
+                    vec!["rejected by registries.reject entry `docker.io`"]
+                );
+                assert_eq!(rejection_reasons.violations[0].message, "");
+            }
+            PodEvaluationResult::Allowed(_) => panic!("pod should be rejected"),
+        }
+    }
+
+    #[test]
+    fn invalid_structured_rules() {
+        let settings: Settings = serde_json::from_str(
+            r#"{"registries": {"allow": [{"pattern": "registry.internal:port"}]}}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err("registry registry.internal:port: invalid port port".to_string())
+        );
+        let settings: Settings =
+            serde_json::from_str(r#"{"images": {"reject": [{"pattern": "[", "kind": "regex"}]}}"#)
+                .expect("cannot parse settings");
+        assert!(settings
+            .validate()
+            .unwrap_err()
+            .starts_with("invalid regex ["));
+    }
+
+    #[test]
+    fn merge_settings() {
+        let mut settings: Settings = serde_json::from_str(
+            r#"{
+                "registries": {"allow": ["registry.my-corp.com"]},
+                "tags": {"reject": ["latest"]},
+                "trustedRepositories": {"name": "cluster"}
+            }"#,
+        )
+        .expect("cannot parse settings");
+        let cluster_settings: Settings = serde_json::from_str(
+            r#"{
+                "registries": {"allow": ["quay.io"]},
+                "images": {"reject": ["quay.io/etcd/etcd"]},
+                "decisionAnnotation": {}
+            }"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(
+            cluster_settings.unmerged_settings(),
+            vec!["decisionAnnotation"]
+        );
+        let fingerprint = settings.fingerprint();
+        settings.merge(cluster_settings);
+        assert_ne!(settings.fingerprint(), fingerprint);
+        assert_eq!(settings.validate(), Ok(()));
+        assert_eq!(settings.decision_annotation, None);
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(settings.is_allowed_registry("registry.my-corp.com"));
+        assert!(settings.is_allowed_registry("quay.io"));
+        assert!(!settings.is_allowed_registry("docker.io"));
+        assert!(!settings.is_allowed_tag("latest"));
+        assert!(!settings
+            .is_allowed_image(&Image::new("quay.io/etcd/etcd:v3.4.12").expect("invalid image")));
+    }
+
+    #[test]
+    fn match_strategy() {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "registries": {"allow": ["example.com", {"pattern": "*.corp.io", "kind": "glob"}], "matchStrategy": "exact"},
+                "images": {"reject": ["quay.io/untrusted"], "matchStrategy": "prefix"}
+            }"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        assert!(settings.warnings().is_empty());
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(settings.is_allowed_registry("example.com"));
+        assert!(!settings.is_allowed_registry("example.com.evil.io"));
+        assert!(settings.is_allowed_registry("registry.corp.io"));
+        for (image, allowed) in [
+            ("quay.io/untrusted", false),
+            ("quay.io/untrusted:1.0", false),
+            ("quay.io/untrusted/app@sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb", false),
+            ("quay.io/untrusted-fork/app:1.0", true),
+        ] {
+            assert_eq!(
+                settings.is_allowed_image(&Image::new(image).expect("invalid image")),
+                allowed,
+                "{}",
+                image
+            );
+        }
+
+        let settings: Settings = serde_json::from_str(
+            r#"{"registries": {"allow": ["quay.io"], "matchStrategy": "organization"}}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err("organization rule quay.io is only supported by the images lists".to_string())
+        );
+
+        // The strategies of merged lists keep applying to their entries
+        let mut settings: Settings = serde_json::from_str(
+            r#"{"registries": {"allow": ["example.com"], "matchStrategy": "exact"}}"#,
+        )
+        .expect("cannot parse settings");
+        let cluster_settings: Settings = serde_json::from_str(
+            r#"{"registries": {"allow": ["*.corp.io"], "matchStrategy": "glob"}}"#,
+        )
+        .expect("cannot parse settings");
+        settings.merge(cluster_settings);
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(settings.is_allowed_registry("example.com"));
+        assert!(settings.is_allowed_registry("registry.corp.io"));
+        assert!(!settings.is_allowed_registry("example.com.evil.io"));
+    }
+
+    #[test]
+    fn registry_presets() {
+        let settings: Settings = serde_json::from_str(
+            r#"{"registryPresets": ["aws-ecr", "gcr", "ghcr", "quay", "acr"], "registries": {"allow": ["registry.my-corp.com"]}}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        for (registry, allowed) in [
+            ("registry.my-corp.com", true),
+            ("123456789012.dkr.ecr.eu-west-1.amazonaws.com", true),
+            ("123456789012.dkr.ecr-fips.us-east-1.amazonaws.com", true),
+            ("123456789012.dkr.ecr.cn-north-1.amazonaws.com.cn", true),
+            ("gcr.io", true),
+            ("eu.gcr.io", true),
+            ("ghcr.io", true),
+            ("quay.io", true),
+            ("myregistry.azurecr.io", true),
+            ("dkr.ecr.eu-west-1.amazonaws.com.evil.io", false),
+            ("ghcr.io.evil.io", false),
+            ("docker.io", false),
+        ] {
+            assert_eq!(
+                settings.is_allowed_registry(registry),
+                allowed,
+                "{}",
+                registry
+            );
+        }
+
+        // Presets alone make an allow list
+        let settings: Settings = serde_json::from_str(r#"{"registryPresets": ["ghcr"]}"#)
+            .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(settings.is_allowed_registry("ghcr.io"));
+        assert!(!settings.is_allowed_registry("quay.io"));
+
+        for (settings, error) in [
+            (
+                r#"{"registryPresets": []}"#,
+                "registryPresets must list at least one preset",
+            ),
+            (
+                r#"{"registryPresets": ["ghcr"], "registries": {"reject": ["ghcr.io"]}}"#,
+                "registryPresets cannot be combined with registries reject, unless rulePrecedence is set",
+            ),
+            (
+                r#"{"registryPresets": ["ghcr"], "registryRules": [{"registry": "quay.io", "tags": {"reject": ["latest"]}}]}"#,
+                "only one of registryPresets or registryRules can be provided",
+            ),
+        ] {
+            let settings: Settings = serde_json::from_str(settings).expect("cannot parse settings");
+            assert_eq!(settings.validate(), Err(error.to_string()));
+        }
+        assert!(
+            serde_json::from_str::<Settings>(r#"{"registryPresets": ["docker-hub"]}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn image_evaluated_once() {
+        let settings: Settings = serde_json::from_str(r#"{"registries": {"allow": ["quay.io"]}}"#)
+            .expect("cannot parse settings");
+        let settings = settings.compile(None).expect("cannot compile settings");
+        let container = |name: &str, image: &str| apicore::Container {
+            name: name.to_string(),
+            image: Some(image.to_string()),
+            ..Default::default()
+        };
+        let pod = apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                init_containers: Some(vec![container("init", "nginx:1.25")]),
+                containers: vec![
+                    container("app", "nginx:1.25"),
+                    container("sidecar", "quay.io/etcd:v3.4.12"),
+                ],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        match settings.is_pod_accepted(&pod, None) {
+            PodEvaluationResult::NotAllowed(rejection_reasons) => {
+                assert_eq!(
+                    rejection_reasons
+                        .violations
+                        .iter()
+                        .map(|violation| (violation.container.as_str(), violation.rule.as_str()))
+                        .collect::<Vec<_>>(),
+                    vec![("init", "registries"), ("app", "registries")]
+                );
+                assert_eq!(
+                    rejection_reasons.registries_not_allowed,
+                    vec!["docker.io", "docker.io"]
+                );
+            }
+            PodEvaluationResult::Allowed(_) => panic!("pod should be rejected"),
+        }
+        assert_eq!(settings.image_evaluations.borrow().len(), 2);
+    }
+
+    #[test]
+    fn rule_enforcement() {
+        let settings: Settings = serde_json::from_str(
+            r#"{"registries": {"reject": [{"pattern": "docker.io", "enforcement": "warn"}]}}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(settings.is_allowed_registry("docker.io"));
+        assert_eq!(
+            settings.image_warnings(&["nginx:1.25", "quay.io/etcd:v3.4.12"]),
+            vec![
+                "image nginx:1.25: would be rejected by registries.reject entry `docker.io`"
+                    .to_string()
+            ]
+        );
+
+        let settings: Settings = serde_json::from_str(
+            r#"{"tags": {"allow": [{"pattern": "1.25", "enforcement": "warn"}]}}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err("allow entry 1.25: only reject entries can have a warn enforcement".to_string())
+        );
+    }
+
+    #[test]
+    fn trusted_repositories_reference() {
+        let settings: Settings = serde_json::from_str(
+            r#"{"trustedRepositories": {"name": "cluster", "namespace": "kubewarden"}}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        assert_eq!(
+            settings.trusted_repositories,
+            Some(TrustedRepositoriesReference {
+                api_version: "policies.kubewarden.io/v1alpha1".to_string(),
+                kind: "TrustedRepositories".to_string(),
+                name: "cluster".to_string(),
+                namespace: Some("kubewarden".to_string()),
+            })
+        );
+
+        let settings: Settings = serde_json::from_str(r#"{"trustedRepositories": {"name": ""}}"#)
+            .expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err("trustedRepositories must provide the name of the resource".to_string())
+        );
+    }
+
+    #[test]
+    fn evaluated_resources() {
+        let gvk = |group: &str, kind: &str| GroupVersionKind {
+            group: group.to_string(),
+            version: "v1".to_string(),
+            kind: kind.to_string(),
+        };
+        assert!(Settings::default().is_resource_evaluated(&gvk("", "Pod")));
+
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "registries": {"allow": ["quay.io"]},
+                "resources": ["Pod", "argoproj.io/*", "apps/Deployment"],
+                "supportedCustomWorkloads": ["argoRollout"]
+            }"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        assert!(settings.is_resource_evaluated(&gvk("", "Pod")));
+        assert!(!settings.is_resource_evaluated(&gvk("", "PodTemplate")));
+        assert!(settings.is_resource_evaluated(&gvk("argoproj.io", "Rollout")));
+        assert!(!settings.is_resource_evaluated(&gvk("apps", "StatefulSet")));
+        assert_eq!(
+            settings.warnings(),
+            vec![
+                "resources entry apps/Deployment matches no supported kind, \
+                 add a customResourceRules entry to evaluate it"
+            ]
+        );
+
+        for resources in [r#"[]"#, r#"["apps/"]"#, r#"["apps/v1/Deployment"]"#] {
+            let settings: Settings = serde_json::from_str(&format!(
+                r#"{{"registries": {{"allow": ["quay.io"]}}, "resources": {}}}"#,
+                resources
+            ))
+            .expect("cannot parse settings");
+            assert!(settings.validate().is_err(), "{}", resources);
+        }
+    }
+
+    #[test]
+    fn compiled_filters_are_cached() {
+        let parse = |settings: &str| -> Settings {
+            serde_json::from_str(settings).expect("cannot parse settings")
+        };
+        let settings = parse(
+            r#"{
+                "registries": {"allow": ["quay.io"]},
+                "namespaceRules": [{"namespaces": ["kube-*"], "registries": {"allow": ["*"]}}]
+            }"#,
+        );
+        let labels = BTreeMap::new();
+        let filters = |settings: &Settings, namespace: &str| {
+            let scope = RequestScope {
+                namespace,
+                labels: &labels,
+            };
+            settings
+                .compile(Some(&scope))
+                .expect("cannot compile settings")
+                .filters
+        };
+        let global = filters(&settings, "default");
+        assert!(Rc::ptr_eq(&global, &filters(&settings, "apps")));
+        assert!(!Rc::ptr_eq(&global, &filters(&settings, "kube-system")));
+        // Settings parsed again for the next request are identical
+        let same_settings = parse(&serde_json::to_string(&settings).unwrap());
+        assert!(Rc::ptr_eq(&global, &filters(&same_settings, "default")));
+
+        let other_settings = parse(r#"{"registries": {"allow": ["docker.io"]}}"#);
+        let other_global = filters(&other_settings, "default");
+        assert!(!Rc::ptr_eq(&global, &other_global));
+        assert!(other_settings
+            .compile(None)
+            .expect("cannot compile settings")
+            .is_allowed_registry("docker.io"));
+    }
+
+    #[test]
+    fn enforced_operations() {
+        let settings = Settings::default();
+        assert!(settings.is_operation_enforced("CREATE"));
+        assert!(settings.is_operation_enforced("UPDATE"));
+
+        let settings: Settings =
+            serde_json::from_str(r#"{"operations": ["CREATE"]}"#).expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        assert!(settings.is_operation_enforced("CREATE"));
+        assert!(!settings.is_operation_enforced("UPDATE"));
+
+        let settings: Settings =
+            serde_json::from_str(r#"{"operations": []}"#).expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err("operations must list at least one operation".to_string())
+        );
+        assert!(serde_json::from_str::<Settings>(r#"{"operations": ["DELETE"]}"#).is_err());
+    }
+
+    #[test]
+    fn only_changed_images_are_evaluated_on_update() {
+        let settings: Settings = serde_json::from_str(r#"{"registries": {"allow": ["quay.io"]}}"#)
+            .expect("cannot parse settings");
+        let settings = settings.compile(None).expect("cannot compile settings");
+        let pod = |app_image: &str, sidecar_image: &str| apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                containers: vec![
+                    apicore::Container {
+                        name: "app".to_string(),
+                        image: Some(app_image.to_string()),
+                        ..Default::default()
+                    },
+                    apicore::Container {
+                        name: "sidecar".to_string(),
+                        image: Some(sidecar_image.to_string()),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let old_pod = pod("quay.io/app:1.0", "docker.io/sidecar:1.0");
+        assert!(matches!(
+            settings.is_pod_accepted(&old_pod, None),
+            PodEvaluationResult::NotAllowed(_)
+        ));
+        assert!(matches!(
+            settings.is_pod_accepted(
+                &pod("quay.io/app:1.1", "docker.io/sidecar:1.0"),
+                Some(&old_pod)
+            ),
+            PodEvaluationResult::Allowed(_)
+        ));
+        match settings.is_pod_accepted(
+            &pod("quay.io/app:1.1", "docker.io/sidecar:1.1"),
+            Some(&old_pod),
+        ) {
+            PodEvaluationResult::NotAllowed(rejection_reasons) => {
+                assert_eq!(rejection_reasons.registries_not_allowed, vec!["docker.io"])
+            }
+            PodEvaluationResult::Allowed(_) => panic!("pod should be rejected"),
+        }
+    }
+
+    #[test]
+    fn custom_resource_rules() {
+        let settings: Settings = serde_json::from_str(
+            r#"{"registries": {"allow": ["quay.io"]}, "customResourceRules": [{"apiVersion": "apps.example.com/v1", "kind": "AppDeployment", "paths": ["spec.components[*].image"]}]}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let gvk = GroupVersionKind {
+            group: "apps.example.com".to_string(),
+            version: "v1".to_string(),
+            kind: "AppDeployment".to_string(),
+        };
+        let rule = settings
+            .custom_resource_rule_for(&gvk)
+            .expect("rule not found");
+        let object = serde_json::json!({
+            "spec": {"components": [{"image": "quay.io/app:1.0"}, {"image": "docker.io/worker:1.0"}]}
+        });
+        let images = rule.located_images(&object);
+
+        let settings = settings.compile(None).expect("cannot compile settings");
+        match settings
+            .are_images_accepted(images.iter().map(|(path, image)| (path.as_str(), *image)))
+        {
+            PodEvaluationResult::NotAllowed(rejection_reasons) => {
+                assert_eq!(rejection_reasons.registries_not_allowed, vec!["docker.io"]);
+                let paths: Vec<&str> = rejection_reasons
+                    .violations
+                    .iter()
+                    .map(|violation| violation.path.as_str())
+                    .collect();
+                assert_eq!(paths, vec!["spec.components[1].image"]);
+            }
+            PodEvaluationResult::Allowed(_) => panic!("resource should be rejected"),
+        }
+
+        let settings: Settings = serde_json::from_str(
+            r#"{"customResourceRules": [{"apiVersion": "apps.example.com/v1", "kind": "AppDeployment", "paths": []}]}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err(
+                "customResourceRules entry for AppDeployment must provide at least one path"
+                    .to_string()
+            )
+        );
+
+        let settings: Settings =
+            serde_json::from_str(r#"{"supportedCustomWorkloads": ["knativeService"]}"#)
+                .expect("cannot parse settings");
+        let gvk = |group: &str, version: &str, kind: &str| GroupVersionKind {
+            group: group.to_string(),
+            version: version.to_string(),
+            kind: kind.to_string(),
+        };
+        assert_eq!(
+            settings.custom_resource_rule_for(&gvk("serving.knative.dev", "v1", "Service")),
+            CustomWorkload::KnativeService.rules().into_iter().next()
+        );
+        assert_eq!(
+            settings.custom_resource_rule_for(&gvk("argoproj.io", "v1alpha1", "Rollout")),
+            None
+        );
+    }
+
+    #[test]
+    fn image_pull_policy() {
+        let container = |image: &str, image_pull_policy: Option<&str>| apicore::Container {
+            name: "app".to_string(),
+            image: Some(image.to_string()),
+            image_pull_policy: image_pull_policy.map(String::from),
+            ..Default::default()
+        };
+        let digest = "sha256:7ed2739c96eb16de3d7169e2a0aa4ccf3a1f44af24f2bb6cad826935a51bcb3d";
+        let pinned = format!("nginx@{}", digest);
+
+        let rule = ImagePullPolicyRule::default();
+        assert!(rule.is_allowed(&container("nginx:1.25", Some("Always"))));
+        assert!(!rule.is_allowed(&container("nginx:1.25", Some("IfNotPresent"))));
+        assert!(!rule.is_allowed(&container("nginx:1.25", None)));
+        assert!(rule.is_allowed(&container("nginx", None)));
+        assert!(rule.is_allowed(&container("nginx:latest", None)));
+        assert!(!rule.is_allowed(&container(&pinned, Some("IfNotPresent"))));
+
+        let rule = ImagePullPolicyRule {
+            allow_if_not_present_for_digests: true,
+            ..Default::default()
+        };
+        assert!(rule.is_allowed(&container(&pinned, Some("IfNotPresent"))));
+        assert!(rule.is_allowed(&container(&pinned, None)));
+        assert!(!rule.is_allowed(&container(&pinned, Some("Never"))));
+        assert!(!rule.is_allowed(&container("nginx:1.25", Some("IfNotPresent"))));
+
+        let settings: Settings =
+            serde_json::from_str(r#"{"imagePullPolicy": {}}"#).expect("cannot parse settings");
+        let settings = settings.compile(None).expect("cannot compile settings");
+        let pod = apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                containers: vec![container("nginx:1.25", Some("IfNotPresent"))],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        match settings.is_pod_accepted(&pod, None) {
+            PodEvaluationResult::NotAllowed(rejection_reasons) => {
+                assert_eq!(rejection_reasons.pull_policies_not_allowed, vec!["app"])
+            }
+            PodEvaluationResult::Allowed(_) => panic!("pod should be rejected"),
+        }
+    }
+
+    #[test]
+    fn mutate_pull_policies() {
+        let digest = "sha256:7ed2739c96eb16de3d7169e2a0aa4ccf3a1f44af24f2bb6cad826935a51bcb3d";
+        let mut pod = apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                containers: vec![
+                    apicore::Container {
+                        name: "app".to_string(),
+                        image: Some("nginx:1.25".to_string()),
+                        image_pull_policy: Some("IfNotPresent".to_string()),
+                        ..Default::default()
+                    },
+                    apicore::Container {
+                        name: "sidecar".to_string(),
+                        image: Some(format!("envoy@{}", digest)),
+                        image_pull_policy: Some("IfNotPresent".to_string()),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let settings: Settings =
+            serde_json::from_str(r#"{"imagePullPolicy": {}}"#).expect("cannot parse settings");
+        assert!(!settings.mutate_pull_policies(&mut pod));
+
+        let settings: Settings = serde_json::from_str(r#"{"imagePullPolicy": {"mode": "mutate"}}"#)
+            .expect("cannot parse settings");
+        assert!(matches!(
+            settings
+                .compile(None)
+                .expect("cannot compile settings")
+                .is_pod_accepted(&pod, None),
+            PodEvaluationResult::Allowed(_)
+        ));
+        assert!(settings.mutate_pull_policies(&mut pod));
+        let pull_policies: Vec<_> = pod
+            .spec
+            .as_ref()
+            .unwrap()
+            .containers
+            .iter()
+            .map(|container| container.image_pull_policy.as_deref())
+            .collect();
+        assert_eq!(pull_policies, vec![Some("Always"), Some("IfNotPresent")]);
+        assert!(!settings.mutate_pull_policies(&mut pod));
+    }
+
+    #[test]
+    fn canonicalize_images() {
+        let pod = |images: &[(&str, &str)]| apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                init_containers: Some(vec![apicore::Container {
+                    name: "init".to_string(),
+                    image: Some("busybox".to_string()),
+                    ..Default::default()
+                }]),
+                containers: images
+                    .iter()
+                    .map(|(name, image)| apicore::Container {
+                        name: name.to_string(),
+                        image: Some(image.to_string()),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let images = |pod: &apicore::Pod| -> Vec<String> {
+            container_images(pod)
+                .into_iter()
+                .map(|(_, image)| image.to_string())
+                .collect()
+        };
+
+        let mut new_pod = pod(&[("app", "nginx:1.25"), ("sidecar", "quay.io/envoy:v1")]);
+        assert!(!Settings::default().canonicalize_images(&mut new_pod, None));
+
+        let settings: Settings =
+            serde_json::from_str(r#"{"canonicalizeImages": true}"#).expect("cannot parse settings");
+        assert!(settings.canonicalize_images(&mut new_pod, None));
+        assert_eq!(
+            images(&new_pod),
+            vec![
+                "docker.io/library/busybox:latest",
+                "docker.io/library/nginx:1.25",
+                "quay.io/envoy:v1"
+            ]
+        );
+        assert!(!settings.canonicalize_images(&mut new_pod.clone(), None));
+
+        let old_pod = pod(&[("app", "nginx:1.25"), ("sidecar", "envoy:v1")]);
+        let mut updated_pod = pod(&[("app", "nginx:1.25"), ("sidecar", "envoy:v2")]);
+        assert!(settings.canonicalize_images(&mut updated_pod, Some(&old_pod)));
+        assert_eq!(
+            images(&updated_pod),
+            vec!["busybox", "nginx:1.25", "docker.io/library/envoy:v2"]
+        );
+        let mut invalid_pod = pod(&[("app", "nginx:1.25:alpine")]);
+        assert!(!settings.canonicalize_images(&mut invalid_pod, Some(&old_pod)));
+    }
+
+    #[test]
+    fn tag_replacements() {
+        let settings: Settings = serde_json::from_value(serde_json::json!({
+            "tagReplacements": {
+                "nginx:latest": "nginx:1.25.3",
+                "quay.io/my-org/app:v2": "quay.io/my-org/app:v2@sha256:9b2f3a1de8c4a0d9b7e5c61f3a2b4d8e0c7f1a6b3d5e9c2f4a8b0d1e6c3f7a29"
+            }
+        }))
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        for (image, replacement) in [
+            ("nginx:latest", Some("nginx:1.25.3")),
+            ("docker.io/library/nginx:latest", Some("nginx:1.25.3")),
+            ("index.docker.io/nginx:latest", Some("nginx:1.25.3")),
+            ("nginx", None),
+            ("nginx:1.25", None),
+            ("nginx:latest@sha256:9b2f3a1de8c4a0d9b7e5c61f3a2b4d8e0c7f1a6b3d5e9c2f4a8b0d1e6c3f7a29", None),
+            ("quay.io/my-org/app:v2", Some("quay.io/my-org/app:v2@sha256:9b2f3a1de8c4a0d9b7e5c61f3a2b4d8e0c7f1a6b3d5e9c2f4a8b0d1e6c3f7a29")),
+            ("quay.io/my-org/app:v3", None),
+        ] {
+            assert_eq!(
+                settings.tag_replacement(image).map(String::as_str),
+                replacement,
+                "{}",
+                image
+            );
+        }
+
+        for (reference, replacement, error) in [
+            ("nginx", "nginx:1.25.3", "invalid tagReplacements entry nginx: expected a repository and a tag, like nginx:latest"),
+            ("nginx:latest", "nginx", "invalid replacement nginx of tagReplacements entry nginx:latest: expected a tag or a digest"),
+        ] {
+            let settings: Settings = serde_json::from_value(serde_json::json!({
+                "tagReplacements": {reference: replacement}
+            }))
+            .expect("cannot parse settings");
+            assert_eq!(settings.validate(), Err(error.to_string()));
+        }
+    }
+
+    #[test]
+    fn container_selector() {
+        let pod = apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                init_containers: Some(vec![apicore::Container {
+                    name: "istio-init".to_string(),
+                    image: Some("docker.io/istio/proxyv2:1.20.0".to_string()),
+                    ..Default::default()
+                }]),
+                containers: vec![
+                    apicore::Container {
+                        name: "app".to_string(),
+                        image: Some("quay.io/app:1.0".to_string()),
+                        ..Default::default()
+                    },
+                    apicore::Container {
+                        name: "istio-proxy".to_string(),
+                        image: Some("docker.io/istio/proxyv2:1.20.0".to_string()),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        for (container_selector, allowed) in [
+            (r#"{"exclude": ["istio-*"]}"#, true),
+            (r#"{"exclude": ["istio-proxy"]}"#, false),
+            (r#"{"include": ["app"]}"#, true),
+            (r#"{"include": ["app", "istio-proxy"]}"#, false),
+            (r#"{"include": ["*"], "exclude": ["istio-*"]}"#, true),
+        ] {
+            let settings: Settings = serde_json::from_str(&format!(
+                r#"{{"registries": {{"allow": ["quay.io"]}}, "containerSelector": {}}}"#,
+                container_selector
+            ))
+            .expect("cannot parse settings");
+            assert_eq!(settings.validate(), Ok(()));
+            let settings = settings.compile(None).expect("cannot compile settings");
+            assert_eq!(
+                matches!(
+                    settings.is_pod_accepted(&pod, None),
+                    PodEvaluationResult::Allowed(_)
+                ),
+                allowed,
+                "{}",
+                container_selector
+            );
+        }
+
+        for (container_selector, error) in [
+            ("{}", "containerSelector must provide include or exclude"),
+            (
+                r#"{"include": []}"#,
+                "containerSelector include must list at least one name",
+            ),
+            (
+                r#"{"exclude": [""]}"#,
+                "containerSelector names cannot be empty",
+            ),
+        ] {
+            let settings: Settings = serde_json::from_str(&format!(
+                r#"{{"registries": {{"allow": ["quay.io"]}}, "containerSelector": {}}}"#,
+                container_selector
+            ))
+            .expect("cannot parse settings");
+            assert_eq!(settings.validate(), Err(error.to_string()));
+        }
+    }
+
+    #[test]
+    fn skip_injected_containers() {
+        let pod = |annotations: &[&str]| apicore::Pod {
+            metadata: k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+                annotations: Some(
+                    annotations
+                        .iter()
+                        .map(|key| (key.to_string(), "enabled".to_string()))
+                        .collect(),
+                ),
+                ..Default::default()
+            },
+            spec: Some(apicore::PodSpec {
+                init_containers: Some(vec![apicore::Container {
+                    name: "vault-agent-init".to_string(),
+                    image: Some("docker.io/hashicorp/vault:1.15".to_string()),
+                    ..Default::default()
+                }]),
+                containers: vec![
+                    apicore::Container {
+                        name: "app".to_string(),
+                        image: Some("quay.io/app:1.0".to_string()),
+                        ..Default::default()
+                    },
+                    apicore::Container {
+                        name: "linkerd-proxy".to_string(),
+                        image: Some("cr.l5d.io/linkerd/proxy:stable-2.14.1".to_string()),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let accepted = |skip_injected_containers: &str, pod: &apicore::Pod| {
+            let settings: Settings = serde_json::from_str(&format!(
+                r#"{{"registries": {{"allow": ["quay.io"]}}, "skipInjectedContainers": {}}}"#,
+                skip_injected_containers
+            ))
+            .expect("cannot parse settings");
+            assert_eq!(settings.validate(), Ok(()));
+            let settings = settings.compile(None).expect("cannot compile settings");
+            matches!(
+                settings.is_pod_accepted(pod, None),
+                PodEvaluationResult::Allowed(_)
+            )
+        };
+        let names = r#"{"names": ["linkerd-*", "vault-agent-init"]}"#;
+        assert!(accepted(names, &pod(&[])));
+        assert!(!accepted(r#"{"names": ["linkerd-*"]}"#, &pod(&[])));
+        let annotated = r#"{
+            "names": ["linkerd-*", "vault-agent-init"],
+            "annotations": ["linkerd.io/inject", "vault.hashicorp.com/agent-*"]
+        }"#;
+        assert!(!accepted(annotated, &pod(&[])));
+        assert!(!accepted(annotated, &pod(&["app.kubernetes.io/name"])));
+        assert!(accepted(
+            annotated,
+            &pod(&["vault.hashicorp.com/agent-inject"])
+        ));
+
+        for (skip_injected_containers, error) in [
+            (
+                r#"{"names": []}"#,
+                "skipInjectedContainers names must list at least one name",
+            ),
+            (
+                r#"{"names": ["linkerd-*"], "annotations": [""]}"#,
+                "skipInjectedContainers annotations cannot be empty",
+            ),
+        ] {
+            let settings: Settings = serde_json::from_str(&format!(
+                r#"{{"registries": {{"allow": ["quay.io"]}}, "skipInjectedContainers": {}}}"#,
+                skip_injected_containers
+            ))
+            .expect("cannot parse settings");
+            assert_eq!(settings.validate(), Err(error.to_string()));
+        }
+    }
+
+    #[test]
+    fn apply_default_registry() {
+        let pod = |images: &[(&str, &str)]| apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                containers: images
+                    .iter()
+                    .map(|(name, image)| apicore::Container {
+                        name: name.to_string(),
+                        image: Some(image.to_string()),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let images = |pod: &apicore::Pod| -> Vec<String> {
+            container_images(pod)
+                .into_iter()
+                .map(|(_, image)| image.to_string())
+                .collect()
+        };
+
+        let mut new_pod = pod(&[
+            ("app", "nginx:1.25"),
+            ("sidecar", "quay.io/envoy:v1"),
+            ("proxy", "docker://myorg/proxy"),
+        ]);
+        assert!(!Settings::default().apply_default_registry(&mut new_pod, None));
+
+        let settings: Settings =
+            serde_json::from_str(r#"{"defaultRegistry": "registry.internal"}"#)
+                .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        assert!(settings.apply_default_registry(&mut new_pod, None));
+        assert_eq!(
+            images(&new_pod),
+            vec![
+                "registry.internal/nginx:1.25",
+                "quay.io/envoy:v1",
+                "docker://registry.internal/myorg/proxy"
+            ]
+        );
+        assert!(!settings.apply_default_registry(&mut new_pod.clone(), None));
+
+        let old_pod = pod(&[("app", "nginx:1.25"), ("sidecar", "envoy:v1")]);
+        let mut updated_pod = pod(&[("app", "nginx:1.25"), ("sidecar", "envoy:v2")]);
+        assert!(settings.apply_default_registry(&mut updated_pod, Some(&old_pod)));
+        assert_eq!(
+            images(&updated_pod),
+            vec!["nginx:1.25", "registry.internal/envoy:v2"]
+        );
+
+        for default_registry in ["internal", "registry.internal/", "Registry Internal"] {
+            let settings = Settings {
+                default_registry: Some(default_registry.to_string()),
+                ..Default::default()
+            };
+            assert!(settings.validate().is_err(), "{}", default_registry);
+        }
+    }
+
+    #[test]
+    fn ipv6_registries() {
+        let settings: Settings = serde_json::from_str(
+            r#"{"registries": {"allow": ["[2001:db8::1]:5000", "[fd00::/8]:*"]}}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        for image in [
+            "[2001:db8::1]:5000/app:1.0",
+            "[2001:db8:0::1]:5000/team/app:1.0",
+            "[fd12::1]:443/app:1.0",
+        ] {
+            assert!(
+                matches!(
+                    settings.is_pod_accepted(&pod_with_image(image), None),
+                    PodEvaluationResult::Allowed(_)
+                ),
+                "{} should be allowed",
+                image
+            );
+        }
+        for image in [
+            "[2001:db8::1]/app:1.0",
+            "[2001:db8::1]:5001/app:1.0",
+            "[2001:db8::2]:5000/app:1.0",
+        ] {
+            assert!(
+                matches!(
+                    settings.is_pod_accepted(&pod_with_image(image), None),
+                    PodEvaluationResult::NotAllowed(_)
+                ),
+                "{} should be rejected",
+                image
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn uppercase_repository_warnings() {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "images": {"allow": ["Quay.io/myorg/app", "quay.io/MyOrg/*", {"pattern": "[A-Z].*", "kind": "regex"}]},
+                "registryRules": [{"registry": "ghcr.io", "images": {"allow": ["Team/*", "team/*"]}}],
+                "imageTags": {"docker.io/library/Nginx": ["1.25"]}
+            }"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        assert_eq!(
+            settings.warnings(),
+            vec![
+                "images entry quay.io/MyOrg/* never matches: repositories are lowercase",
+                "registry rule for ghcr.io: images entry Team/* never matches: repositories are lowercase",
+                "imageTags entry docker.io/library/Nginx never matches: repositories are lowercase",
+            ]
+        );
+    }
+
+    #[test]
+    fn expired_rules() {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "registries": {"allow": [
+                    "quay.io",
+                    {"pattern": "docker.io", "expiresAt": "2000-01-01T00:00:00Z"},
+                    {"pattern": "ghcr.io", "expiresAt": "2999-01-01T00:00:00Z"}
+                ]}
+            }"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        assert_eq!(
+            settings.warnings(),
+            vec!["registries allow entry docker.io expired at 2000-01-01T00:00:00Z and is ignored"]
+        );
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(settings.is_allowed_registry("quay.io"));
+        assert!(!settings.is_allowed_registry("docker.io"));
+        assert!(settings.is_allowed_registry("ghcr.io"));
+    }
+
+    #[test]
+    fn rule_precedence() {
+        let settings = |precedence: &str| -> Settings {
+            serde_json::from_value(serde_json::json!({
+                "registries": {"allow": ["quay.io", "docker.io"], "reject": ["docker.io"]},
+                "tags": {"reject": ["latest"]},
+                "rulePrecedence": precedence,
+            }))
+            .expect("cannot parse settings")
+        };
+        let is_accepted = |settings: &Settings, image: &str| {
+            matches!(
+                settings
+                    .compile(None)
+                    .expect("cannot compile settings")
+                    .are_images_accepted([("", image)]),
+                PodEvaluationResult::Allowed(_)
+            )
+        };
+
+        let reject_wins = settings("rejectWins");
+        assert_eq!(reject_wins.validate(), Ok(()));
+        assert!(is_accepted(&reject_wins, "quay.io/app:1.0"));
+        assert!(!is_accepted(&reject_wins, "docker.io/app:1.0"));
+        assert!(!is_accepted(&reject_wins, "ghcr.io/app:1.0"));
+        assert_eq!(
+            reject_wins.warnings(),
+            vec!["registries entry docker.io is both allowed and rejected, rejectWins applies"]
+        );
+
+        let allow_wins = settings("allowWins");
+        assert_eq!(allow_wins.validate(), Ok(()));
+        assert!(is_accepted(&allow_wins, "quay.io/app:1.0"));
+        assert!(is_accepted(&allow_wins, "docker.io/app:1.0"));
+        assert!(!is_accepted(&allow_wins, "ghcr.io/app:1.0"));
+
+        let settings: Settings = serde_json::from_str(
+            r#"{"registries": {"allow": ["quay.io"], "reject": ["docker.io"]}}"#,
+        )
+        .expect("cannot parse settings");
+        assert!(settings.validate().is_err());
+        let settings: Settings = serde_json::from_str(
+            r#"{"tags": {"reject": ["latest"]}, "rulePrecedence": "allowWins"}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        assert_eq!(
+            settings.warnings(),
+            vec!["rulePrecedence has no effect: no filter provides both allow and reject lists"]
+        );
+    }
+
+    #[test]
+    fn most_specific_rule_precedence() {
+        let digest = "sha256:7ed2739c96eb16de3d7169e2a0aa4ccf3a1f44af24f2bb6cad826935a51bcb3d";
+        let settings: Settings = serde_json::from_value(serde_json::json!({
+            "registries": {
+                "allow": ["registry.internal:5000"],
+                "reject": ["registry.internal:*"]
+            },
+            "rulePrecedence": "mostSpecific",
+        }))
+        .expect("cannot parse settings");
+        let compiled_settings = settings.compile(None).expect("cannot compile settings");
+        assert!(matches!(
+            compiled_settings.are_images_accepted([("", "registry.internal:5000/app:1.0")]),
+            PodEvaluationResult::Allowed(_)
+        ));
+        assert!(matches!(
+            compiled_settings.are_images_accepted([("", "registry.internal:6000/app:1.0")]),
+            PodEvaluationResult::NotAllowed(_)
+        ));
+
+        let settings: Settings = serde_json::from_value(serde_json::json!({
+            "images": {
+                "allow": [
+                    "quay.io/",
+                    "quay.io/org/app:1.*",
+                    format!("quay.io/org/app@{}", digest),
+                    "quay.io/org/lib"
+                ],
+                "reject": ["quay.io/org/*", "quay.io/org/app:1.3", "quay.io/org/lib"]
+            },
+            "rulePrecedence": "mostSpecific",
+        }))
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        assert_eq!(
+            settings.warnings(),
+            vec!["images entry quay.io/org/lib is both allowed and rejected, the reject entry applies"]
+        );
+        let compiled_settings = settings.compile(None).expect("cannot compile settings");
+        let is_accepted = |image: &str| {
+            matches!(
+                compiled_settings.are_images_accepted([("", image)]),
+                PodEvaluationResult::Allowed(_)
+            )
+        };
+        assert!(is_accepted("quay.io/other/app:1.0"));
+        assert!(!is_accepted("quay.io/org/other:1.0"));
+        assert!(is_accepted("quay.io/org/app:1.2"));
+        assert!(!is_accepted("quay.io/org/app:1.3"));
+        assert!(!is_accepted("quay.io/org/app:2.0"));
+        assert!(is_accepted(&format!("quay.io/org/app:2.0@{}", digest)));
+        assert!(!is_accepted("quay.io/org/lib:1.0"));
+    }
+
+    #[test]
+    fn wildcard_entries() {
+        let is_accepted = |settings: &Settings, image: &str| {
+            matches!(
+                settings
+                    .compile(None)
+                    .expect("cannot compile settings")
+                    .are_images_accepted([("", image)]),
+                PodEvaluationResult::Allowed(_)
+            )
+        };
+
+        let deny_all: Settings = serde_json::from_value(serde_json::json!({
+            "registries": {"allow": ["quay.io"], "reject": ["*"]},
+            "rulePrecedence": "mostSpecific",
+        }))
+        .expect("cannot parse settings");
+        assert_eq!(deny_all.validate(), Ok(()));
+        assert!(deny_all.warnings().is_empty());
+        assert!(is_accepted(&deny_all, "quay.io/app:1.0"));
+        assert!(!is_accepted(&deny_all, "docker.io/app:1.0"));
+        assert!(!is_accepted(&deny_all, "registry.internal:5000/app:1.0"));
+
+        let allow_all: Settings = serde_json::from_value(serde_json::json!({
+            "images": {"allow": ["*"], "reject": ["quay.io/org/*"]},
+            "tags": {"allow": ["*"]},
+            "rulePrecedence": "rejectWins",
+        }))
+        .expect("cannot parse settings");
+        assert_eq!(allow_all.validate(), Ok(()));
+        assert_eq!(
+            allow_all.warnings(),
+            vec!["tags allow entry * allows every value, the filter has no effect"]
+        );
+        assert!(is_accepted(&allow_all, "docker.io/app:latest"));
+        assert!(!is_accepted(&allow_all, "quay.io/org/app:1.0"));
+
+        let meaningless: Settings = serde_json::from_value(serde_json::json!({
+            "registries": {"reject": ["*", "docker.io"]},
+            "images": {"allow": ["*"], "reject": ["quay.io/org/*"]},
+            "rulePrecedence": "allowWins",
+        }))
+        .expect("cannot parse settings");
+        assert_eq!(meaningless.validate(), Ok(()));
+        assert_eq!(
+            meaningless.warnings(),
+            vec![
+                "registries reject entries besides * have no effect",
+                "registries reject entry * rejects every value",
+                "images reject entries have no effect: allow entry * wins",
+            ]
+        );
+        assert!(!is_accepted(&meaningless, "quay.io/app:1.0"));
+    }
+
+    #[test]
+    fn rule_diagnostics() {
+        let settings: Settings = serde_json::from_value(serde_json::json!({
+            "registries": {"allow": ["quay.io", "quay.io", "10.0.0.0/8:*", "10.1.0.0/16:5000"]},
+            "images": {
+                "allow": ["quay.io/org/*", "quay.io/org/app:1.*", "ghcr.io/app"],
+                "reject": ["quay.io/org/app:1.3", "ghcr.io/*"]
+            },
+            "tags": {"reject": [
+                {"pattern": "dev-*", "kind": "glob", "message": "use a release tag"},
+                {"pattern": "*-*", "kind": "glob"}
+            ]},
+            "rulePrecedence": "rejectWins",
+        }))
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        assert_eq!(
+            settings.warnings(),
+            vec![
+                "registries allow entry quay.io is listed more than once",
+                "registries allow entry 10.1.0.0/16:5000 has no effect: 10.0.0.0/8:* matches all its values",
+                "images allow entry quay.io/org/app:1.* has no effect: quay.io/org/* matches all its values",
+                "images allow entry ghcr.io/app has no effect: reject entry ghcr.io/* matches all its values and wins",
+            ]
+        );
+
+        // The narrower entries outrank the broader entries of the other
+        // list
+        let settings: Settings = serde_json::from_value(serde_json::json!({
+            "images": {
+                "allow": ["quay.io/org/app:1.*"],
+                "reject": ["quay.io/org/*", "quay.io/org/app:1.3"]
+            },
+            "rulePrecedence": "mostSpecific",
+        }))
+        .expect("cannot parse settings");
+        assert!(settings.warnings().is_empty());
+    }
+
+    #[test]
+    fn default_action() {
+        let settings = |default_action: &str| -> Settings {
+            serde_json::from_value(serde_json::json!({
+                "registries": {"allow": ["quay.io"]},
+                "tags": {"reject": ["latest"]},
+                "defaultAction": default_action,
+            }))
+            .expect("cannot parse settings")
+        };
+        let is_accepted = |settings: &Settings, image: &str| {
+            assert_eq!(settings.validate(), Ok(()));
+            matches!(
+                settings
+                    .compile(None)
+                    .expect("cannot compile settings")
+                    .are_images_accepted([("", image)]),
+                PodEvaluationResult::Allowed(_)
+            )
+        };
+        let allow = settings("allow");
+        assert!(is_accepted(&allow, "docker.io/app:1.0"));
+        assert!(!is_accepted(&allow, "docker.io/app:latest"));
+        // With a default deny, tags must be allowed explicitly
+        let deny = settings("deny");
+        assert!(!is_accepted(&deny, "quay.io/app:1.0"));
+        assert!(!is_accepted(&deny, "docker.io/app:1.0"));
+        assert!(serde_json::from_str::<Settings>(r#"{"defaultAction": "audit"}"#).is_err());
+    }
+
+    #[test]
+    fn registry_aliases() {
+        let is_accepted = |settings: &str, image: &str| {
+            let settings: Settings = serde_json::from_str(settings).expect("cannot parse settings");
+            assert_eq!(settings.validate(), Ok(()));
+            let settings = settings.compile(None).expect("cannot compile settings");
+            matches!(
+                settings.is_pod_accepted(&pod_with_image(image), None),
+                PodEvaluationResult::Allowed(_)
+            )
+        };
+        let settings = r#"{"registries": {"allow": ["docker.io"]}, "images": {"allow": ["docker.io/library/nginx"]}}"#;
+        assert!(is_accepted(settings, "nginx:1.25"));
+        assert!(is_accepted(settings, "index.docker.io/nginx:1.25"));
+        assert!(is_accepted(
+            settings,
+            "registry-1.docker.io/library/nginx:1.25"
+        ));
+        assert!(!is_accepted(settings, "quay.io/nginx:1.25"));
+        assert!(is_accepted(
+            r#"{"registries": {"reject": ["index.docker.io"]}}"#,
+            "quay.io/nginx:1.25"
+        ));
+        assert!(!is_accepted(
+            r#"{"registries": {"reject": ["index.docker.io"]}}"#,
+            "nginx:1.25"
+        ));
+
+        let settings =
+            r#"{"registries": {"allow": ["docker.io"]}, "disableRegistryAliases": true}"#;
+        assert!(is_accepted(settings, "nginx:1.25"));
+        assert!(!is_accepted(settings, "index.docker.io/nginx:1.25"));
+    }
+
+    #[test]
+    fn reject_ip_registries() {
+        let settings: Settings = serde_json::from_str(
+            r#"{"registries": {"reject": ["docker.io"]}, "rejectIpRegistries": true}"#,
+        )
+        .expect("cannot parse settings");
+        let settings = settings.compile(None).expect("cannot compile settings");
+        for image in ["registry.internal:5000/app:1.0", "localhost:5000/app:1.0"] {
+            assert!(
+                matches!(
+                    settings.is_pod_accepted(&pod_with_image(image), None),
+                    PodEvaluationResult::Allowed(_)
+                ),
+                "{}",
+                image
+            );
+        }
+        for (image, registry) in [
+            ("10.0.0.100:5000/app:1.0", "10.0.0.100:5000"),
+            ("[2001:db8::1]:5000/app:1.0", "[2001:db8::1]:5000"),
+            ("127.1/app:1.0", "127.1"),
+        ] {
+            match settings.is_pod_accepted(&pod_with_image(image), None) {
+                PodEvaluationResult::NotAllowed(rejection_reasons) => {
+                    assert_eq!(rejection_reasons.ip_registries, vec![registry]);
+                    assert_eq!(rejection_reasons.violations[0].rule, "rejectIpRegistries");
+                }
+                PodEvaluationResult::Allowed(_) => panic!("{} should be rejected", image),
+            }
+        }
+    }
+
+    #[test]
+    fn reject_pre_release_tags() {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "rejectPreReleaseTags": true,
+                "imageTags": {"quay.io/pinned/app": ["2.0-rc1"]}
+            }"#,
+        )
+        .expect("cannot parse settings");
+        let settings = settings.compile(None).expect("cannot compile settings");
+        // `imageTags` entries take precedence
+        for image in [
+            "quay.io/app:1.0",
+            "quay.io/app",
+            "quay.io/pinned/app:2.0-rc1",
+        ] {
+            assert!(
+                matches!(
+                    settings.is_pod_accepted(&pod_with_image(image), None),
+                    PodEvaluationResult::Allowed(_)
+                ),
+                "{}",
+                image
+            );
+        }
+        for (image, tag) in [
+            ("quay.io/app:2.0-rc1", "2.0-rc1"),
+            ("quay.io/app:2.0-SNAPSHOT", "2.0-SNAPSHOT"),
+        ] {
+            match settings.is_pod_accepted(&pod_with_image(image), None) {
+                PodEvaluationResult::NotAllowed(rejection_reasons) => {
+                    assert_eq!(rejection_reasons.pre_release_tags, vec![tag]);
+                    assert_eq!(rejection_reasons.violations[0].rule, "rejectPreReleaseTags");
+                }
+                PodEvaluationResult::Allowed(_) => panic!("{} should be rejected", image),
+            }
+        }
+    }
+
+    #[test]
+    fn image_lookups() {
+        let image = "quay.io/prometheus/prometheus:v2.45.0";
+        let mut trace = RuleTrace::default();
+        let mut rejection_reasons = PodRejectionReasons::default();
+        assert!(record_image_lookup(
+            image,
+            Ok(true),
+            &mut trace,
+            &mut rejection_reasons
+        ));
+        assert!(rejection_reasons.is_empty());
+
+        let mut trace = RuleTrace::default();
+        assert!(!record_image_lookup(
+            image,
+            Ok(false),
+            &mut trace,
+            &mut rejection_reasons
+        ));
+        assert_eq!(rejection_reasons.images_not_found, vec![image]);
+        assert!(rejection_reasons.image_lookups_failed.is_empty());
+        assert_eq!(trace.decided_by().map(reason_code), Some("IMAGE_NOT_FOUND"));
+
+        let mut trace = RuleTrace::default();
+        let mut rejection_reasons = PodRejectionReasons::default();
+        assert!(!record_image_lookup(
+            image,
+            Err(anyhow::anyhow!("503 Service Unavailable")),
+            &mut trace,
+            &mut rejection_reasons
+        ));
+        assert!(rejection_reasons.images_not_found.is_empty());
+        assert_eq!(
+            rejection_reasons.image_lookups_failed,
+            vec!["quay.io/prometheus/prometheus:v2.45.0 (503 Service Unavailable)"]
+        );
+        assert_eq!(
+            trace.decided_by().map(reason_code),
+            Some("IMAGE_LOOKUP_FAILED")
+        );
+    }
+
+    #[test]
+    fn official_images_only() {
+        for (settings, image, unofficial_images) in [
+            (r#"{"officialImagesOnly": true}"#, "nginx:1.25", None),
+            (
+                r#"{"officialImagesOnly": true}"#,
+                "docker.io/library/nginx:1.25",
+                None,
+            ),
+            (
+                r#"{"officialImagesOnly": true}"#,
+                "index.docker.io/nginx:1.25",
+                None,
+            ),
+            (
+                r#"{"officialImagesOnly": true}"#,
+                "quay.io/someone/nginx:1.25",
+                None,
+            ),
+            (
+                r#"{"officialImagesOnly": true}"#,
+                "someone/nginx:1.25",
+                Some(vec!["someone/nginx:1.25"]),
+            ),
+            (
+                r#"{"officialImagesOnly": true}"#,
+                "registry-1.docker.io/ngnix/nginx:1.25",
+                Some(vec!["registry-1.docker.io/ngnix/nginx:1.25"]),
+            ),
+            (
+                r#"{"officialImagesOnly": true, "disableRegistryAliases": true}"#,
+                "index.docker.io/someone/nginx:1.25",
+                Some(vec!["index.docker.io/someone/nginx:1.25"]),
+            ),
+            (
+                r#"{"officialImagesOnly": true, "disableRegistryAliases": true}"#,
+                "index.docker.io/nginx:1.25",
+                None,
+            ),
+            (
+                r#"{"officialImagesOnly": true, "registries": {"allow": ["docker.io"]}}"#,
+                "quay.io/someone/nginx:1.25",
+                Some(vec![]),
+            ),
+        ] {
+            let settings: Settings = serde_json::from_str(settings).expect("cannot parse settings");
+            assert_eq!(settings.validate(), Ok(()));
+            let settings = settings.compile(None).expect("cannot compile settings");
+            match (
+                settings.is_pod_accepted(&pod_with_image(image), None),
+                unofficial_images,
+            ) {
+                (PodEvaluationResult::Allowed(_), None) => (),
+                (PodEvaluationResult::NotAllowed(rejection_reasons), Some(unofficial_images)) => {
+                    assert_eq!(
+                        rejection_reasons.unofficial_images, unofficial_images,
+                        "{}",
+                        image
+                    )
+                }
+                _ => panic!("unexpected decision for {}", image),
+            }
+        }
+    }
+
+    #[test]
+    fn allow_node_local_registries() {
+        let settings: Settings = serde_json::from_value(serde_json::json!({
+            "registries": {"allow": ["quay.io"]},
+            "tags": {"reject": ["latest"]},
+            "rejectIpRegistries": true,
+            "allowedRegistryPorts": [5000],
+            "allowNodeLocalRegistries": true,
+        }))
+        .expect("cannot parse settings");
+        let settings = settings.compile(None).expect("cannot compile settings");
+        for (image, accepted) in [
+            ("localhost:5000/app:1.0", true),
+            ("127.0.0.1:30500/app:1.0", true),
+            ("[::1]:31337/app:1.0", true),
+            ("localhost:5000/app:latest", false),
+            ("10.0.0.100:5000/app:1.0", false),
+            ("docker.io/app:1.0", false),
+        ] {
+            assert_eq!(
+                matches!(
+                    settings.are_images_accepted([("", image)]),
+                    PodEvaluationResult::Allowed(_)
+                ),
+                accepted,
+                "{}",
+                image
+            );
         }
-        Ok(())
     }
-}
 
-impl Settings {
-    pub(crate) fn is_pod_accepted(&self, pod: &apicore::Pod) -> PodEvaluationResult {
-        let mut rejection_reasons = PodRejectionReasons::default();
+    #[test]
+    fn short_name_search_registries() {
+        let evaluate = |settings: serde_json::Value, image: &str| {
+            let settings: Settings =
+                serde_json::from_value(settings).expect("cannot parse settings");
+            assert_eq!(settings.validate(), Ok(()));
+            let settings = settings.compile(None).expect("cannot compile settings");
+            match settings.are_images_accepted([("", image)]) {
+                PodEvaluationResult::Allowed(_) => Vec::new(),
+                PodEvaluationResult::NotAllowed(rejection_reasons) => rejection_reasons
+                    .violations
+                    .into_iter()
+                    .map(|violation| format!("{} {}", violation.image, violation.rule))
+                    .collect(),
+            }
+        };
+        let search_registries = serde_json::json!(["registry.internal", "docker.io"]);
+        let only_internal = serde_json::json!({
+            "registries": {"allow": ["registry.internal"]},
+            "shortNameSearchRegistries": search_registries,
+        });
+        assert_eq!(
+            evaluate(only_internal.clone(), "nginx:1.25"),
+            vec!["docker.io/nginx:1.25 registries"]
+        );
+        assert!(evaluate(only_internal, "registry.internal/nginx:1.25").is_empty());
 
-        pod.spec
-            .as_ref()
-            .map(|pod_spec| {
-                let empty_containers = Vec::new();
-                let init_containers = pod_spec
-                    .init_containers
-                    .as_ref()
-                    .unwrap_or(&empty_containers);
-                let containers = &pod_spec.containers;
+        let both = serde_json::json!({
+            "registries": {"allow": ["registry.internal", "docker.io"]},
+            "shortNameSearchRegistries": search_registries,
+        });
+        assert!(evaluate(both.clone(), "nginx:1.25").is_empty());
+        let mut ambiguous = both;
+        ambiguous["rejectAmbiguousShortNames"] = serde_json::json!(true);
+        assert_eq!(
+            evaluate(ambiguous, "nginx:1.25"),
+            vec!["nginx:1.25 rejectAmbiguousShortNames"]
+        );
 
-                vec![init_containers, containers]
-                    .iter()
-                    .for_each(|containers| {
-                        containers.iter().for_each(|container| {
-                            if let Some(container_image) = &container.image {
-                                let image = Image::new(container_image);
-                                if let Ok(image) = image {
-                                    if let Some(registry) = &image.registry {
-                                        if !self.is_allowed_registry(registry) {
-                                            rejection_reasons
-                                                .registries_not_allowed
-                                                .push(registry.clone())
-                                        }
-                                    }
-                                    if let Some(tag) = &image.tag {
-                                        if !self.is_allowed_tag(tag) {
-                                            rejection_reasons.tags_not_allowed.push(tag.clone());
-                                        }
-                                    }
-                                    if !self.is_allowed_image(&image) {
-                                        rejection_reasons.images_not_allowed.push(image.image);
-                                    }
-                                }
-                            }
-                        });
-                    });
-
-                if rejection_reasons.is_empty() {
-                    PodEvaluationResult::Allowed
-                } else {
-                    PodEvaluationResult::NotAllowed(rejection_reasons)
-                }
-            })
-            .unwrap_or(PodEvaluationResult::Allowed)
+        for settings in [
+            r#"{"shortNameSearchRegistries": []}"#,
+            r#"{"shortNameSearchRegistries": ["registry.internal/"]}"#,
+            r#"{"shortNameSearchRegistries": ["quay.io"], "defaultRegistry": "quay.io"}"#,
+            r#"{"rejectAmbiguousShortNames": true}"#,
+        ] {
+            let settings: Settings = serde_json::from_str(settings).expect("cannot parse settings");
+            assert!(settings.validate().is_err(), "{:?}", settings);
+        }
     }
 
-    fn is_allowed_registry(&self, registry: &str) -> bool {
-        self.registries
-            .as_ref()
-            .map(|registries| {
-                if let Some(allowed_registries) = &registries.allow {
-                    allowed_registries
-                        .iter()
-                        .any(|allowed_registry| registry == allowed_registry)
-                } else if let Some(rejected_registries) = &registries.reject {
-                    !rejected_registries
+    #[test]
+    fn violation_codes() {
+        let settings: Settings = serde_json::from_value(serde_json::json!({
+            "registries": {"allow": ["quay.io"]},
+            "tags": {"reject": ["latest"]},
+            "imageTags": {"quay.io/pinned": ["1.0"]},
+        }))
+        .expect("cannot parse settings");
+        let settings = settings.compile(None).expect("cannot compile settings");
+        for (image, codes) in [
+            (
+                "docker.io/nginx:latest",
+                vec!["REGISTRY_NOT_ALLOWED", "TAG_REJECTED"],
+            ),
+            ("quay.io/pinned:2.0", vec!["TAG_REJECTED"]),
+            ("quay.io/app:1.0:alpine", vec!["TAG_INVALID"]),
+        ] {
+            match settings.are_images_accepted([("", image)]) {
+                PodEvaluationResult::NotAllowed(rejection_reasons) => assert_eq!(
+                    rejection_reasons
+                        .violations
                         .iter()
-                        .any(|rejected_registry| registry == rejected_registry)
-                } else {
-                    true
-                }
-            })
-            .or(Some(true))
-            .unwrap_or(false)
+                        .map(|violation| violation.code)
+                        .collect::<Vec<_>>(),
+                    codes,
+                    "{}",
+                    image
+                ),
+                PodEvaluationResult::Allowed(_) => panic!("{} should be rejected", image),
+            }
+        }
     }
 
-    fn is_allowed_tag(&self, tag: &str) -> bool {
-        self.tags
-            .as_ref()
-            .map(|tags| {
-                if let Some(rejected_tags) = &tags.reject {
-                    !rejected_tags.iter().any(|rejected_tag| tag == rejected_tag)
-                } else {
-                    true
+    #[test]
+    fn untagged_as_latest() {
+        for (untagged_as_latest, image, accepted) in [
+            (false, "quay.io/app", true),
+            (true, "quay.io/app", false),
+            (true, "quay.io/app:1.0", true),
+            (
+                true,
+                "quay.io/app@sha256:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+                true,
+            ),
+        ] {
+            let settings: Settings = serde_json::from_value(serde_json::json!({
+                "tags": {"reject": ["latest"]},
+                "untaggedAsLatest": untagged_as_latest,
+            }))
+            .expect("cannot parse settings");
+            let settings = settings.compile(None).expect("cannot compile settings");
+            match settings.is_pod_accepted(&pod_with_image(image), None) {
+                PodEvaluationResult::Allowed(_) => assert!(accepted, "{}", image),
+                PodEvaluationResult::NotAllowed(rejection_reasons) => {
+                    assert!(!accepted, "{}", image);
+                    assert_eq!(rejection_reasons.tags_not_allowed, vec!["latest"]);
                 }
-            })
-            .or(Some(true))
-            .unwrap_or(false)
+            }
+        }
     }
 
-    fn is_allowed_image(&self, image: &Image) -> bool {
-        self.images
-            .as_ref()
-            .map(|images| {
-                if let Some(allowed_images) = &images.allow {
-                    allowed_images
-                        .iter()
-                        .any(|allowed_image| allowed_image == image)
-                } else if let Some(rejected_images) = &images.reject {
-                    !rejected_images
-                        .iter()
-                        .any(|rejected_image| rejected_image == image)
-                } else {
-                    true
-                }
-            })
-            .or(Some(true))
-            .unwrap_or(false)
-    }
-}
+    #[test]
+    fn allowed_registry_ports() {
+        let settings: Settings = serde_json::from_str(
+            r#"{"registries": {"allow": ["allowed.example.com:*"]}, "allowedRegistryPorts": [5000]}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        for image in [
+            "allowed.example.com/app:1.0",
+            "allowed.example.com:443/app:1.0",
+            "allowed.example.com:5000/app:1.0",
+        ] {
+            assert!(
+                matches!(
+                    settings.is_pod_accepted(&pod_with_image(image), None),
+                    PodEvaluationResult::Allowed(_)
+                ),
+                "{}",
+                image
+            );
+        }
+        match settings.is_pod_accepted(&pod_with_image("allowed.example.com:31337/app:1.0"), None) {
+            PodEvaluationResult::NotAllowed(rejection_reasons) => {
+                assert_eq!(
+                    rejection_reasons.registry_ports_not_allowed,
+                    vec!["allowed.example.com:31337"]
+                );
+                assert!(rejection_reasons.registries_not_allowed.is_empty());
+            }
+            PodEvaluationResult::Allowed(_) => panic!("pod should be rejected"),
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let settings: Settings = serde_json::from_str(r#"{"allowedRegistryPorts": [0]}"#)
+            .expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err("invalid allowed registry port 0".to_string())
+        );
+    }
 
     #[test]
-    fn test_is_allowed_registry() {
-        let settings: Settings = Default::default();
-        assert!(settings.is_allowed_registry(&String::from("docker.io")));
+    fn insecure_registries() {
+        let settings: Settings = serde_json::from_str(
+            r#"{"registries": {"allow": ["registry.internal:*"]}, "insecureRegistries": ["registry.internal:5000"]}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(matches!(
+            settings.is_pod_accepted(&pod_with_image("registry.internal:6000/app:1.0"), None),
+            PodEvaluationResult::Allowed(_)
+        ));
+        match settings.is_pod_accepted(&pod_with_image("registry.internal:5000/app:1.0"), None) {
+            PodEvaluationResult::NotAllowed(rejection_reasons) => {
+                assert_eq!(
+                    rejection_reasons.insecure_registries,
+                    vec!["registry.internal:5000"]
+                );
+                assert!(rejection_reasons.registries_not_allowed.is_empty());
+            }
+            PodEvaluationResult::Allowed(_) => panic!("pod should be rejected"),
+        }
 
-        let settings = Settings {
-            registries: Some(Registries {
-                allow: Some(vec![String::from("allowed-registry.com")]),
+        let settings: Settings =
+            serde_json::from_str(r#"{"insecureRegistries": ["registry.internal:http"]}"#)
+                .expect("cannot parse settings");
+        assert!(settings
+            .validate()
+            .unwrap_err()
+            .starts_with("invalid insecure registry"));
+    }
+
+    #[test]
+    fn private_registries() {
+        let settings: Settings = serde_json::from_str(
+            r#"{"privateRegistries": ["registry.example.com", "docker.io"], "allowedPullSecrets": ["regcred"]}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        let pod = |image: &str, secret: Option<&str>| apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                containers: vec![apicore::Container {
+                    name: "app".to_string(),
+                    image: Some(image.to_string()),
+                    ..Default::default()
+                }],
+                image_pull_secrets: secret.map(|secret| {
+                    vec![apicore::LocalObjectReference {
+                        name: Some(secret.to_string()),
+                    }]
+                }),
                 ..Default::default()
             }),
             ..Default::default()
         };
-        assert!(settings.is_allowed_registry(&String::from("allowed-registry.com")));
-        assert!(!settings.is_allowed_registry(&String::from("allowed-registry.com:5001")));
-        assert!(!settings.is_allowed_registry(&String::from("docker.io")));
+        assert!(matches!(
+            settings.is_pod_accepted(&pod("quay.io/app:1.0", None), None),
+            PodEvaluationResult::Allowed(_)
+        ));
+        assert!(matches!(
+            settings.is_pod_accepted(&pod("registry.example.com/app:1.0", Some("regcred")), None),
+            PodEvaluationResult::Allowed(_)
+        ));
+        for (image, secret) in [
+            ("registry.example.com/app:1.0", None),
+            ("registry.example.com/app:1.0", Some("other")),
+            ("index.docker.io/team/app:1.0", None),
+        ] {
+            match settings.is_pod_accepted(&pod(image, secret), None) {
+                PodEvaluationResult::NotAllowed(rejection_reasons) => assert_eq!(
+                    rejection_reasons.pull_secrets_missing,
+                    vec![format!(
+                        "{} (container app, no accepted imagePullSecret)",
+                        image
+                    )]
+                ),
+                PodEvaluationResult::Allowed(_) => panic!("pod using {} should be rejected", image),
+            }
+        }
 
-        let settings = Settings {
-            registries: Some(Registries {
-                reject: Some(vec![String::from("forbidden-registry.com")]),
+        let settings: Settings = serde_json::from_str(r#"{"allowedPullSecrets": ["regcred"]}"#)
+            .expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err("allowedPullSecrets requires privateRegistries".to_string())
+        );
+    }
+
+    #[test]
+    fn require_tag_and_digest() {
+        let settings: Settings = serde_json::from_str(
+            r#"{"registries": {"allow": ["quay.io"]}, "requireTagAndDigest": true}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        let digest = "sha256:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+        let pod = apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                init_containers: Some(vec![apicore::Container {
+                    name: "init".to_string(),
+                    image: Some(format!("quay.io/init@{}", digest)),
+                    ..Default::default()
+                }]),
+                containers: vec![
+                    apicore::Container {
+                        name: "app".to_string(),
+                        image: Some(format!("quay.io/app:1.0@{}", digest)),
+                        ..Default::default()
+                    },
+                    apicore::Container {
+                        name: "sidecar".to_string(),
+                        image: Some("quay.io/sidecar".to_string()),
+                        ..Default::default()
+                    },
+                ],
                 ..Default::default()
             }),
             ..Default::default()
         };
-        assert!(settings.is_allowed_registry(&String::from("docker.io")));
-        assert!(settings.is_allowed_registry(&String::from("non-forbidden-registry.com:5001")));
-        assert!(!settings.is_allowed_registry(&String::from("forbidden-registry.com")));
+        match settings.is_pod_accepted(&pod, None) {
+            PodEvaluationResult::NotAllowed(rejection_reasons) => assert_eq!(
+                rejection_reasons.incomplete_references,
+                vec![
+                    format!("quay.io/init@{} (container init, missing tag)", digest),
+                    "quay.io/sidecar (container sidecar, missing tag and digest)".to_string(),
+                ]
+            ),
+            PodEvaluationResult::Allowed(_) => panic!("pod should be rejected"),
+        }
+        assert!(matches!(
+            settings.are_images_accepted([("", format!("quay.io/app:1.0@{}", digest).as_str())]),
+            PodEvaluationResult::Allowed(_)
+        ));
     }
 
     #[test]
-    fn test_is_allowed_tag() {
-        let settings: Settings = Default::default();
-        assert!(settings.is_allowed_tag(&String::from("latest")));
+    fn reject_digest_only() {
+        let settings: Settings = serde_json::from_str(
+            r#"{"registries": {"allow": ["quay.io"]}, "rejectDigestOnly": true}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        let digest = "sha256:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+        for image in ["quay.io/app:1.0", &format!("quay.io/app:1.0@{}", digest)] {
+            assert!(
+                matches!(
+                    settings.are_images_accepted([("", image)]),
+                    PodEvaluationResult::Allowed(_)
+                ),
+                "{} should be allowed",
+                image
+            );
+        }
+        match settings.are_images_accepted([("", format!("quay.io/app@{}", digest).as_str())]) {
+            PodEvaluationResult::NotAllowed(rejection_reasons) => assert_eq!(
+                rejection_reasons.digest_only_references,
+                vec![format!("quay.io/app@{} (missing tag)", digest)]
+            ),
+            PodEvaluationResult::Allowed(_) => panic!("image should be rejected"),
+        }
+    }
 
-        let settings = Settings {
-            tags: Some(Tags {
-                reject: Some(vec![String::from("latest")]),
+    #[test]
+    fn require_fully_qualified() {
+        let settings: Settings = serde_json::from_str(
+            r#"{"registries": {"allow": ["docker.io"]}, "requireFullyQualified": true}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        assert!(matches!(
+            settings.are_images_accepted([("", "docker.io/library/nginx:1.25")]),
+            PodEvaluationResult::Allowed(_)
+        ));
+        for image in ["nginx:1.25", "library/nginx:1.25"] {
+            match settings.are_images_accepted([("", image)]) {
+                PodEvaluationResult::NotAllowed(rejection_reasons) => {
+                    assert_eq!(
+                        rejection_reasons.unqualified_references,
+                        vec![format!("{} (missing registry)", image)]
+                    );
+                    assert_eq!(
+                        rejection_reasons.violations[0].rule,
+                        "requireFullyQualified"
+                    );
+                }
+                PodEvaluationResult::Allowed(_) => panic!("{} should be rejected", image),
+            }
+        }
+    }
+
+    #[test]
+    fn all_violations_are_reported() {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "insecureRegistries": ["registry.internal:5000"],
+                "registries": {"reject": ["registry.internal:5000"]},
+                "tags": {"reject": ["latest"]},
+                "images": {"reject": ["registry.internal:5000/app:latest"]}
+            }"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        let pod = apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                init_containers: Some(vec![apicore::Container {
+                    name: "init".to_string(),
+                    image: Some("registry.internal:5000/app:latest".to_string()),
+                    ..Default::default()
+                }]),
+                containers: vec![apicore::Container {
+                    name: "app".to_string(),
+                    image: Some("nginx:latest".to_string()),
+                    ..Default::default()
+                }],
+                ..Default::default()
             }),
             ..Default::default()
         };
-        assert!(!settings.is_allowed_tag(&String::from("latest")));
+        match settings.is_pod_accepted(&pod, None) {
+            PodEvaluationResult::NotAllowed(rejection_reasons) => {
+                assert_eq!(
+                    rejection_reasons.insecure_registries,
+                    vec!["registry.internal:5000"]
+                );
+                assert_eq!(
+                    rejection_reasons.registries_not_allowed,
+                    vec!["registry.internal:5000"]
+                );
+                assert_eq!(rejection_reasons.tags_not_allowed, vec!["latest", "latest"]);
+                assert_eq!(
+                    rejection_reasons.images_not_allowed,
+                    vec!["registry.internal:5000/app:latest"]
+                );
+            }
+            PodEvaluationResult::Allowed(_) => panic!("pod should be rejected"),
+        }
     }
 
     #[test]
-    fn is_allowed_image() {
-        let image = Image::new("example.com/image:tag@sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb");
-        println!("{:?}", image);
-
-        let image = Image::new("image:tag");
-        println!("{:?}", image);
-
-        let image = Image::new("registry.com/image:tag");
-        println!("{:?}", image);
-
-        let image = Image::new("registry.com/image@sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb");
-        println!("{:?}", image);
-
-        let image = Image::new("quay.io/etcd/etcd:1.1.1@sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb");
-        println!("{:?}", image);
-        let image = Image::new("quay.io/etcd/etcd@sha256:3fc9b689459d738f8c88a3a48aa9e33542016b7a4052e001aaa536fca74813cb");
-        println!("{:?}", image);
-        let image = Image::new("redis:v1.2");
-        println!("{:?}", image);
+    fn non_root_security_context() {
+        let pod = |pod_context: Option<apicore::PodSecurityContext>| apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                security_context: pod_context,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let container = |container_context: Option<apicore::SecurityContext>| apicore::Container {
+            security_context: container_context,
+            ..Default::default()
+        };
+        assert!(!runs_as_non_root(&pod(None), &container(None)));
+        assert!(runs_as_non_root(
+            &pod(Some(apicore::PodSecurityContext {
+                run_as_non_root: Some(true),
+                ..Default::default()
+            })),
+            &container(None)
+        ));
+        assert!(runs_as_non_root(
+            &pod(None),
+            &container(Some(apicore::SecurityContext {
+                run_as_user: Some(1000),
+                ..Default::default()
+            }))
+        ));
+        assert!(!runs_as_non_root(
+            &pod(Some(apicore::PodSecurityContext {
+                run_as_user: Some(1000),
+                ..Default::default()
+            })),
+            &container(Some(apicore::SecurityContext {
+                run_as_user: Some(0),
+                ..Default::default()
+            }))
+        ));
     }
 
     #[test]
-    fn valid_allowed_registries() {
+    fn attestation_rules() {
+        let settings: Settings = serde_json::from_str(
+            r#"{"attestations": [{"image": "registry.my-corp.com/*", "predicateTypes": ["spdx", "cyclonedx"]}]}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let rule = &settings.attestations.as_ref().unwrap()[0];
+        assert!(rule
+            .missing_predicate_types(&["https://cyclonedx.org/bom".to_string()])
+            .is_empty());
         assert_eq!(
-            Settings {
-                registries: Some(Registries {
-                    allow: Some(vec!("allowed-registry.com".to_string())),
-                    reject: None,
-                },),
-                tags: None,
-                images: None,
-            }
-            .validate(),
-            Ok(()),
+            rule.missing_predicate_types(&["https://slsa.dev/provenance/v0.2".to_string()]),
+            vec!["spdx or cyclonedx"]
         );
-    }
+        assert_eq!(rule.missing_predicate_types(&[]), vec!["spdx or cyclonedx"]);
 
-    #[test]
-    fn valid_rejected_registries() {
+        let settings: Settings = serde_json::from_str(
+            r#"{"attestations": [{
+                "image": "registry.my-corp.com/*",
+                "predicateTypes": ["spdx"],
+                "requiredPredicateTypes": ["slsaprovenance1", "https://example.com/test-results"]
+            }]}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let rule = &settings.attestations.as_ref().unwrap()[0];
+        for (predicate_types, missing) in [
+            (
+                vec![
+                    "https://spdx.dev/Document",
+                    "https://slsa.dev/provenance/v1",
+                    "https://example.com/test-results",
+                ],
+                vec![],
+            ),
+            (
+                vec![
+                    "https://spdx.dev/Document",
+                    "https://slsa.dev/provenance/v0.2",
+                ],
+                vec!["slsaprovenance1", "https://example.com/test-results"],
+            ),
+            (
+                vec!["https://slsa.dev/provenance/v1"],
+                vec!["spdx", "https://example.com/test-results"],
+            ),
+        ] {
+            let predicate_types: Vec<String> =
+                predicate_types.into_iter().map(String::from).collect();
+            assert_eq!(
+                rule.missing_predicate_types(&predicate_types),
+                missing,
+                "{:?}",
+                predicate_types
+            );
+        }
+
+        let settings: Settings = serde_json::from_str(
+            r#"{"attestations": [{"image": "registry.my-corp.com/*", "predicateTypes": []}]}"#,
+        )
+        .expect("cannot parse settings");
         assert_eq!(
-            Settings {
-                registries: Some(Registries {
-                    allow: None,
-                    reject: Some(vec!("rejected-registry.com".to_string())),
-                },),
-                tags: None,
-                images: None,
-            }
-            .validate(),
-            Ok(()),
+            settings.validate(),
+            Err("attestation rule for registry.my-corp.com/* must provide at least one predicate type".to_string())
+        );
+        let settings: Settings = serde_json::from_str(
+            r#"{"attestations": [{"image": "registry.my-corp.com/*", "predicateTypes": ["spdx"], "pubKeys": ["not a key"]}]}"#,
+        )
+        .expect("cannot parse settings");
+        assert_eq!(
+            settings.validate(),
+            Err("attestation rule for registry.my-corp.com/* contains a public key that is not PEM encoded".to_string())
         );
     }
 
     #[test]
-    fn invalid_allowed_and_rejected_registries() {
+    fn attestation_verification() {
+        let settings: Settings = serde_json::from_value(serde_json::json!({
+            "attestations": [
+                {
+                    "image": "registry.my-corp.com/*",
+                    "requiredPredicateTypes": ["slsaprovenance1"],
+                    "keyless": [{
+                        "issuer": "https://token.actions.githubusercontent.com",
+                        "subject": "https://github.com/my-org/app/.github/workflows/release.yml@refs/heads/main"
+                    }]
+                },
+                {"image": "registry.my-corp.com/*", "predicateTypes": ["spdx"]}
+            ]
+        }))
+        .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
         assert_eq!(
-            Settings {
-                registries: Some(Registries {
-                    allow: Some(vec!("allowed-registry.com".to_string())),
-                    reject: Some(vec!("rejected-registry.com".to_string())),
-                },),
-                tags: None,
-                images: None,
-            }
-            .validate(),
-            Err(
-                "only one of registries allow or reject can be provided, and one must be provided"
-                    .to_string()
-            ),
+            settings.warnings(),
+            vec!["attestation rule for registry.my-corp.com/* lists no signer: the attestations are not verified"]
         );
-    }
+        let rules: Vec<&AttestationRule> = settings.attestations.iter().flatten().collect();
+        let predicate_types = vec![
+            "https://slsa.dev/provenance/v1".to_string(),
+            "https://spdx.dev/Document".to_string(),
+        ];
+        let image = "registry.my-corp.com/app:1.0";
 
-    #[test]
-    fn invalid_none_allowed_nor_rejected_registries() {
+        let check = AttestationCheck::new(&rules, &predicate_types, |_| Ok(()));
+        assert_eq!(check, AttestationCheck::default());
+        let mut trace = RuleTrace::default();
+        let mut rejection_reasons = PodRejectionReasons::default();
+        assert!(record_attestation_check(
+            image,
+            Ok(check),
+            &mut trace,
+            &mut rejection_reasons
+        ));
+        assert!(rejection_reasons.is_empty());
+
+        // Unsigned attestations only satisfy the rules without signers
+        let check = AttestationCheck::new(&rules, &predicate_types, |rule| {
+            Err(anyhow::anyhow!(
+                "attestations are not signed by the keyless identities required by {}",
+                rule.image
+            ))
+        });
+        assert_eq!(check.missing, Vec::<String>::new());
+        let mut trace = RuleTrace::default();
+        assert!(!record_attestation_check(
+            image,
+            Ok(check),
+            &mut trace,
+            &mut rejection_reasons
+        ));
         assert_eq!(
-            Settings {
-                registries: Some(Registries {
-                    allow: None,
-                    reject: None,
-                },),
-                tags: None,
-                images: None,
-            }
-            .validate(),
-            Err(
-                "only one of registries allow or reject can be provided, and one must be provided"
-                    .to_string()
-            ),
+            rejection_reasons.attestations_not_verified,
+            vec!["registry.my-corp.com/app:1.0 (attestations are not signed by the keyless identities required by registry.my-corp.com/*)"]
+        );
+        assert_eq!(
+            trace.decided_by().map(reason_code),
+            Some("ATTESTATION_NOT_VERIFIED")
         );
-    }
 
-    #[test]
-    fn valid_allowed_images() {
+        // Without attestations, there is nothing to verify
+        let check = AttestationCheck::new(&rules, &[], |_| panic!("nothing to verify"));
+        assert_eq!(check.missing, vec!["slsaprovenance1", "spdx"]);
+        assert!(check.not_verified.is_empty());
+
+        let mut trace = RuleTrace::default();
+        let mut rejection_reasons = PodRejectionReasons::default();
+        assert!(!record_attestation_check(
+            image,
+            Err(anyhow::anyhow!("503 Service Unavailable")),
+            &mut trace,
+            &mut rejection_reasons
+        ));
         assert_eq!(
-            Settings {
-                registries: None,
-                tags: None,
-                images: Some(Images {
-                    allow: Some(vec!("some-registry.com/some/allowed/image:tag".to_string())),
-                    reject: None,
-                },),
-            }
-            .validate(),
-            Ok(()),
+            rejection_reasons.attestation_lookups_failed,
+            vec!["registry.my-corp.com/app:1.0 (503 Service Unavailable)"]
+        );
+        assert!(rejection_reasons.attestations_missing.is_empty());
+        assert_eq!(
+            trace.decided_by().map(reason_code),
+            Some("ATTESTATION_LOOKUP_FAILED")
         );
     }
 
     #[test]
-    fn valid_rejected_images() {
+    fn image_age() {
+        let settings: Settings =
+            serde_json::from_str(r#"{"maxImageAge": "90d", "minImageAge": "2d"}"#)
+                .expect("cannot parse settings");
+        assert_eq!(settings.validate(), Ok(()));
+        let settings = settings.compile(None).expect("cannot compile settings");
+        let day = Duration::from_secs(86400);
         assert_eq!(
-            Settings {
-                registries: None,
-                tags: None,
-                images: Some(Images {
-                    allow: None,
-                    reject: Some(vec!("some-registry.com/some/rejected/image:tag".to_string())),
-                },),
-            }
-            .validate(),
-            Ok(()),
+            image_age_rejection(day * 30, settings.max_image_age, settings.min_image_age),
+            None
+        );
+        assert_eq!(
+            image_age_rejection(day * 400, settings.max_image_age, settings.min_image_age),
+            Some("created 400d ago, older than 90d".to_string())
+        );
+        assert_eq!(
+            image_age_rejection(day / 2, settings.max_image_age, settings.min_image_age),
+            Some("created 12h ago, newer than 2d".to_string())
         );
-    }
 
-    #[test]
-    fn invalid_allowed_and_rejected_images() {
+        let settings: Settings =
+            serde_json::from_str(r#"{"maxImageAge": "1d", "minImageAge": "2d"}"#)
+                .expect("cannot parse settings");
         assert_eq!(
-            Settings {
-                registries: None,
-                tags: None,
-                images: Some(Images {
-                    allow: Some(vec!("some-registry.com/some/allowed/image:tag".to_string())),
-                    reject: Some(vec!("some-registry.com/some/rejected/image:tag".to_string())),
-                },),
-            }
-            .validate(),
-            Err(
-                "only one of images allow or reject can be provided, and one must be provided"
-                    .to_string()
-            ),
+            settings.validate(),
+            Err("minImageAge must be lower than maxImageAge".to_string())
         );
+        let settings: Settings =
+            serde_json::from_str(r#"{"maxImageAge": "3 months"}"#).expect("cannot parse settings");
+        assert!(settings
+            .validate()
+            .unwrap_err()
+            .starts_with("invalid maxImageAge"));
     }
 
     #[test]
-    fn invalid_none_allowed_nor_rejected_images() {
+    fn rule_trace() {
+        let settings: Settings = serde_json::from_str(
+            r#"{"registries": {"allow": ["quay.io"]}, "tags": {"reject": ["latest"]}, "debugRules": true}"#,
+        )
+        .expect("cannot parse settings");
+        let settings = settings.compile(None).expect("cannot compile settings");
+        let trace = |image: &str| {
+            let mut trace = RuleTrace::default();
+            settings.evaluate_image(
+                Image::new(image).expect("cannot parse image"),
+                &mut PodRejectionReasons::default(),
+                &mut Vec::new(),
+                &mut trace,
+            );
+            trace
+        };
+
+        let allowed = trace("quay.io/etcd:v3.4.12");
+        assert_eq!(allowed.summary(), "registries=passed,tags=passed");
+        assert_eq!(allowed.decided_by(), Some("tags"));
+
+        let rejected = trace("quay.io/etcd:latest");
+        assert_eq!(rejected.summary(), "registries=passed,tags=failed");
+        assert_eq!(rejected.decided_by(), Some("tags"));
+
+        assert_eq!(RuleTrace::default().decided_by(), None);
+    }
+
+    #[test]
+    fn settings_schema() {
+        let schema = Settings::json_schema();
+        let properties = &schema["properties"];
+        assert!(properties["registryRules"].is_object());
+        assert!(properties["rejectIpRegistries"].is_object());
         assert_eq!(
-            Settings {
-                registries: None,
-                tags: None,
-                images: Some(Images {
-                    allow: None,
-                    reject: None,
-                },),
-            }
-            .validate(),
-            Err(
-                "only one of images allow or reject can be provided, and one must be provided"
-                    .to_string()
-            ),
+            schema["definitions"]["RulePrecedence"]["oneOf"][0]["enum"],
+            serde_json::json!(["rejectWins"])
         );
     }
 }
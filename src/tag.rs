@@ -0,0 +1,159 @@
+use semver::{Version, VersionReq};
+
+use crate::pattern::glob_matches;
+use crate::rule::{Component, Covers, Specific, Specificity};
+
+const VERSION_RANGE_OPERATORS: &[char] = &['<', '>', '=', '~', '^'];
+
+/// Glob patterns of the common pre-release tag conventions, matched
+/// against the lowercased tag.
+const PRE_RELEASE_TAG_PATTERNS: &[&str] =
+    &["*-rc*", "*-alpha*", "*-beta*", "*-snapshot", "*-dirty"];
+
+/// Returns true when the tag rule is a semver range expression, like
+/// `>=1.24, <2.0`, instead of a plain tag.
+pub(crate) fn is_version_range(rule: &str) -> bool {
+    rule.trim_start().starts_with(VERSION_RANGE_OPERATORS)
+}
+
+/// Returns true when the tag follows a pre-release convention, like
+/// `1.25.0-rc.1`, `2.0-SNAPSHOT` or `v1.3-4-g1a2b3c4-dirty`.
+pub(crate) fn is_pre_release_tag(tag: &str) -> bool {
+    let tag = tag.to_lowercase();
+    PRE_RELEASE_TAG_PATTERNS
+        .iter()
+        .any(|pattern| glob_matches(pattern, &tag))
+}
+
+/// Parses a version range tag rule.
+pub(crate) fn parse_version_range(rule: &str) -> Result<VersionReq, String> {
+    VersionReq::parse(rule).map_err(|e| format!("invalid version range {}: {}", rule, e))
+}
+
+/// Parses tags that look like versions, like `1.24`, `v1.24.3` or
+/// `1.25.0-rc.1`. Missing minor and patch components are assumed to be
+/// zero.
+pub(crate) fn parse_tag_version(tag: &str) -> Option<Version> {
+    let tag = tag.strip_prefix('v').unwrap_or(tag);
+    let (core, suffix) = match tag.find(['-', '+']) {
+        Some(index) => tag.split_at(index),
+        None => (tag, ""),
+    };
+    let components: Vec<&str> = core.split('.').collect();
+    if components.is_empty()
+        || components.len() > 3
+        || components
+            .iter()
+            .any(|component| component.is_empty() || !component.chars().all(|c| c.is_ascii_digit()))
+    {
+        return None;
+    }
+    let padding = [".0"; 2][..3 - components.len()].concat();
+    Version::parse(&format!("{}{}{}", core, padding, suffix)).ok()
+}
+
+/// Tag rule. Version range rules only match tags that look like
+/// versions; other rules match the tag exactly.
+#[derive(Debug)]
+pub enum TagPattern {
+    Exact(String),
+    Range(VersionReq),
+}
+
+impl TagPattern {
+    pub fn parse(rule: &str) -> Result<TagPattern, String> {
+        if is_version_range(rule) {
+            parse_version_range(rule).map(TagPattern::Range)
+        } else {
+            Ok(TagPattern::Exact(rule.to_string()))
+        }
+    }
+
+    pub fn matches(&self, tag: &str) -> bool {
+        match self {
+            TagPattern::Exact(rule) => rule == tag,
+            TagPattern::Range(range) => parse_tag_version(tag)
+                .map(|version| range.matches(&version))
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// Exact tags are more specific than version ranges.
+impl Specific for TagPattern {
+    fn specificity(&self) -> Specificity {
+        match self {
+            TagPattern::Exact(_) => Specificity::new(Component::Tag, 1),
+            TagPattern::Range(_) => Specificity::new(Component::Tag, 0),
+        }
+    }
+}
+
+/// Version ranges cover the tags they match, and the same ranges.
+impl Covers for TagPattern {
+    fn covers(&self, other: &TagPattern) -> bool {
+        match (self, other) {
+            (_, TagPattern::Exact(tag)) => self.matches(tag),
+            (TagPattern::Range(range), TagPattern::Range(other)) => range == other,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag_matches(rule: &str, tag: &str) -> bool {
+        TagPattern::parse(rule)
+            .map(|pattern| pattern.matches(tag))
+            .unwrap_or(false)
+    }
+
+    #[test]
+    fn test_parse_tag_version() {
+        assert_eq!(parse_tag_version("1.24"), Some(Version::new(1, 24, 0)));
+        assert_eq!(parse_tag_version("v1.24.3"), Some(Version::new(1, 24, 3)));
+        assert_eq!(parse_tag_version("2"), Some(Version::new(2, 0, 0)));
+        assert_eq!(
+            parse_tag_version("1.25.0-rc.1"),
+            Version::parse("1.25.0-rc.1").ok()
+        );
+        assert_eq!(parse_tag_version("latest"), None);
+        assert_eq!(parse_tag_version("alpine3.18"), None);
+        assert_eq!(parse_tag_version("1.2.3.4"), None);
+    }
+
+    #[test]
+    fn test_tag_matches() {
+        assert!(tag_matches(">=1.24, <2.0", "1.24"));
+        assert!(tag_matches(">=1.24, <2.0", "v1.27.1"));
+        assert!(!tag_matches(">=1.24, <2.0", "2.0"));
+        assert!(!tag_matches(">=1.24, <2.0", "1.23.9"));
+        assert!(!tag_matches(">=1.24, <2.0", "latest"));
+        assert!(!tag_matches(">=1.24, <2.0", "1.25-alpine"));
+        assert!(tag_matches("latest", "latest"));
+        assert!(tag_matches("1.25", "1.25"));
+        assert!(!tag_matches("1.25", "1.25.1"));
+    }
+
+    #[test]
+    fn test_is_pre_release_tag() {
+        assert!(is_pre_release_tag("1.25.0-rc.1"));
+        assert!(is_pre_release_tag("v2.0-rc1"));
+        assert!(is_pre_release_tag("3.0.0-alpha"));
+        assert!(is_pre_release_tag("3.0.0-beta.2-alpine"));
+        assert!(is_pre_release_tag("2.0-SNAPSHOT"));
+        assert!(is_pre_release_tag("v1.3-4-g1a2b3c4-dirty"));
+        assert!(!is_pre_release_tag("1.25.0"));
+        assert!(!is_pre_release_tag("1.25-alpine"));
+        assert!(!is_pre_release_tag("latest"));
+        assert!(!is_pre_release_tag("snapshot-2024"));
+    }
+
+    #[test]
+    fn test_parse_version_range() {
+        assert!(parse_version_range(">=1.24, <2.0").is_ok());
+        assert!(parse_version_range(">=1.24, <<2.0").is_err());
+    }
+}
@@ -0,0 +1,110 @@
+/// Placeholders that can be used in message templates.
+pub(crate) const PLACEHOLDERS: &[&str] = &[
+    "container",
+    "path",
+    "image",
+    "rule",
+    "code",
+    "registry",
+    "message",
+];
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Placeholder(String),
+}
+
+/// Text where placeholders like `{{image}}` are replaced by values.
+/// Only the names listed in `PLACEHOLDERS` are accepted, and the
+/// values are inserted as they are, never interpreted as templates.
+#[derive(Debug, PartialEq)]
+pub(crate) struct MessageTemplate(Vec<Part>);
+
+impl MessageTemplate {
+    pub(crate) fn parse(template: &str) -> Result<MessageTemplate, String> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let (name, after) = rest[start + 2..]
+                .split_once("}}")
+                .ok_or_else(|| format!("unclosed placeholder in {}", template))?;
+            let name = name.trim();
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(format!(
+                    "unknown placeholder {{{{{}}}}}, expected one of {}",
+                    name,
+                    PLACEHOLDERS.join(", ")
+                ));
+            }
+            parts.push(Part::Placeholder(name.to_string()));
+            rest = after;
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(MessageTemplate(parts))
+    }
+
+    /// Renders the template, replacing every placeholder by the value
+    /// returned by `value` for its name.
+    pub(crate) fn render<'a, F>(&self, value: F) -> String
+    where
+        F: Fn(&str) -> &'a str,
+    {
+        self.0
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.as_str(),
+                Part::Placeholder(name) => value(name),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_template() {
+        assert_eq!(
+            MessageTemplate::parse(
+                "{{image}} rejected by {{ rule }}, see https://wiki.example.com/images"
+            ),
+            Ok(MessageTemplate(vec![
+                Part::Placeholder("image".to_string()),
+                Part::Text(" rejected by ".to_string()),
+                Part::Placeholder("rule".to_string()),
+                Part::Text(", see https://wiki.example.com/images".to_string()),
+            ]))
+        );
+        assert_eq!(
+            MessageTemplate::parse("{{tag}} is not allowed"),
+            Err(
+                "unknown placeholder {{tag}}, expected one of container, path, image, rule, code, registry, message"
+                    .to_string()
+            )
+        );
+        assert!(MessageTemplate::parse("{{image")
+            .unwrap_err()
+            .starts_with("unclosed placeholder"));
+    }
+
+    #[test]
+    fn render_template() {
+        let template = MessageTemplate::parse("{container} runs {{image}} from {{registry}}")
+            .expect("cannot parse template");
+        assert_eq!(
+            template.render(|name| match name {
+                "image" => "quay.io/app:{{rule}}",
+                "registry" => "quay.io",
+                _ => "",
+            }),
+            "{container} runs quay.io/app:{{rule}} from quay.io"
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::{anyhow, Result};
+use kubewarden::host_capabilities::verification::{
+    verify_keyless_exact_match, verify_pub_keys_image, KeylessInfo,
+};
+
+use crate::settings::{AttestationRule, SignatureRule};
+use std::collections::HashMap;
+
+/// Verifies the signatures of `image` against every signature rule
+/// that applies to it, using the sigstore capability of the host.
+pub(crate) fn verify_image_signatures(image: &str, rules: &[&SignatureRule]) -> Result<()> {
+    for rule in rules {
+        let annotations: Option<HashMap<String, String>> = rule
+            .annotations
+            .as_ref()
+            .map(|annotations| annotations.clone().into_iter().collect());
+        verify_signers(image, &rule.pub_keys, &rule.keyless, annotations).map_err(|signers| {
+            anyhow!(
+                "image {} is not signed by the {} required by {}",
+                image,
+                signers,
+                rule.image
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Verifies that the attestations at `reference`, see
+/// `attestation_reference`, are signed by the signers of the rule. The
+/// sigstore capability of the host verifies the cosign signature of the
+/// attestation manifest, the way it does for images.
+pub(crate) fn verify_attestation_signatures(reference: &str, rule: &AttestationRule) -> Result<()> {
+    verify_signers(reference, &rule.pub_keys, &rule.keyless, None).map_err(|signers| {
+        anyhow!(
+            "attestations are not signed by the {} required by {}",
+            signers,
+            rule.image
+        )
+    })
+}
+
+/// Verifies the signatures of `image` against the public keys and the
+/// keyless identities, returning which of them failed. Host errors are
+/// failures too, nothing is trusted without a successful verification.
+fn verify_signers(
+    image: &str,
+    pub_keys: &[String],
+    keyless: &[KeylessInfo],
+    annotations: Option<HashMap<String, String>>,
+) -> Result<(), String> {
+    if !pub_keys.is_empty() {
+        match verify_pub_keys_image(image, pub_keys.to_vec(), annotations.clone()) {
+            Ok(response) if response.is_trusted => {}
+            Ok(_) => return Err("keys".to_string()),
+            Err(e) => return Err(format!("keys ({})", e)),
+        }
+    }
+    if !keyless.is_empty() {
+        match verify_keyless_exact_match(image, keyless.to_vec(), annotations) {
+            Ok(response) if response.is_trusted => {}
+            Ok(_) => return Err("keyless identities".to_string()),
+            Err(e) => return Err(format!("keyless identities ({})", e)),
+        }
+    }
+    Ok(())
+}